@@ -2,7 +2,8 @@ use {
   self::{
     entry::{
       Entry, HeaderValue, InscriptionEntry, InscriptionEntryValue, InscriptionIdValue,
-      OutPointValue, RuneEntryValue, RuneIdValue, SatPointValue, SatRange, TxidValue,
+      OutPointValue, RuneActivityEntry, RuneActivityEntryValue, RuneActivityKind, RuneEntryValue,
+      RuneIdValue, SatPointValue, SatRange, TxidValue,
     },
     event::Event,
     lot::Lot,
@@ -54,12 +55,13 @@ mod utxo_entry;
 #[cfg(test)]
 pub(crate) mod testing;
 
-const SCHEMA_VERSION: u64 = 30;
+const SCHEMA_VERSION: u64 = 31;
 
 define_multimap_table! { SAT_TO_SEQUENCE_NUMBER, u64, u32 }
 define_multimap_table! { SEQUENCE_NUMBER_TO_CHILDREN, u32, u32 }
 define_multimap_table! { SCRIPT_PUBKEY_TO_OUTPOINT, &[u8], OutPointValue }
 define_multimap_table! { HEIGHT_TO_TRANSFERS, u32, &[u8; 96] }
+define_multimap_table! { HEIGHT_TO_RUNE_ACTIVITY, u32, &RuneActivityEntryValue }
 define_table! { HEIGHT_TO_BLOCK_HEADER, u32, &HeaderValue }
 define_table! { HEIGHT_TO_LAST_SEQUENCE_NUMBER, u32, u32 }
 define_table! { HEIGHT_TO_BLOCK_SIZE, u32, u32 }
@@ -317,6 +319,7 @@ impl Index {
         tx.open_multimap_table(SCRIPT_PUBKEY_TO_OUTPOINT)?;
         tx.open_multimap_table(SEQUENCE_NUMBER_TO_CHILDREN)?;
         tx.open_multimap_table(HEIGHT_TO_TRANSFERS)?;
+        tx.open_multimap_table(HEIGHT_TO_RUNE_ACTIVITY)?;
         tx.open_table(HEIGHT_TO_BLOCK_HEADER)?;
         tx.open_table(HEIGHT_TO_LAST_SEQUENCE_NUMBER)?;
         tx.open_table(HOME_INSCRIPTIONS)?;
@@ -2147,6 +2150,16 @@ impl Index {
     Ok(return_vec)
   }
 
+  pub(crate) fn get_rune_activity_by_block_height(&self, height: u32) -> Result<Vec<RuneActivityEntry>> {
+    let rtx = self.database.begin_read()?;
+    let height_to_rune_activity = rtx.open_multimap_table(HEIGHT_TO_RUNE_ACTIVITY)?;
+    height_to_rune_activity
+      .get(&height)?
+      .map(|result| result.map(|entry| RuneActivityEntry::load(*entry.value())))
+      .collect::<Result<Vec<RuneActivityEntry>, StorageError>>()
+      .map_err(|err| err.into())
+  }
+
   pub(crate) fn get_inscription_entry_by_sequence_number(
     &self,
     sequence_number: u32,
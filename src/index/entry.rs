@@ -488,6 +488,83 @@ impl Entry for Txid {
   }
 }
 
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub(crate) enum RuneActivityKind {
+  Mint,
+  Transfer,
+  Burn,
+  Spend,
+}
+
+impl RuneActivityKind {
+  pub(crate) fn as_str(self) -> &'static str {
+    match self {
+      RuneActivityKind::Mint => "mint",
+      RuneActivityKind::Transfer => "transfer",
+      RuneActivityKind::Burn => "burn",
+      RuneActivityKind::Spend => "spend",
+    }
+  }
+}
+
+// One balance change in a block. For transfers `outpoint` is the output that
+// received the runes, for spends it is the input that released them, and for
+// mints and burns it is null.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub(crate) struct RuneActivityEntry {
+  pub(crate) tx_index: u32,
+  pub(crate) kind: RuneActivityKind,
+  pub(crate) rune_id: RuneId,
+  pub(crate) amount: u128,
+  pub(crate) txid: Txid,
+  pub(crate) outpoint: OutPoint,
+}
+
+pub(super) type RuneActivityEntryValue = [u8; 101];
+
+impl Entry for RuneActivityEntry {
+  type Value = RuneActivityEntryValue;
+
+  fn load(value: Self::Value) -> Self {
+    let kind = match value[4] {
+      0 => RuneActivityKind::Mint,
+      1 => RuneActivityKind::Transfer,
+      2 => RuneActivityKind::Burn,
+      _ => RuneActivityKind::Spend,
+    };
+    Self {
+      tx_index: u32::from_be_bytes(value[0..4].try_into().unwrap()),
+      kind,
+      rune_id: RuneId {
+        block: u64::from_be_bytes(value[5..13].try_into().unwrap()),
+        tx: u32::from_be_bytes(value[13..17].try_into().unwrap()),
+      },
+      amount: u128::from_be_bytes(value[17..33].try_into().unwrap()),
+      txid: Txid::load(value[33..65].try_into().unwrap()),
+      outpoint: OutPoint::load(value[65..101].try_into().unwrap()),
+    }
+  }
+
+  // tx_index is stored big endian first so a block's entries iterate in
+  // transaction order
+  fn store(self) -> Self::Value {
+    let mut value = [0; 101];
+    value[0..4].copy_from_slice(&self.tx_index.to_be_bytes());
+    value[4] = match self.kind {
+      RuneActivityKind::Mint => 0,
+      RuneActivityKind::Transfer => 1,
+      RuneActivityKind::Burn => 2,
+      RuneActivityKind::Spend => 3,
+    };
+    value[5..13].copy_from_slice(&self.rune_id.block.to_be_bytes());
+    value[13..17].copy_from_slice(&self.rune_id.tx.to_be_bytes());
+    value[17..33].copy_from_slice(&self.amount.to_be_bytes());
+    value[33..65].copy_from_slice(&self.txid.store());
+    value[65..101].copy_from_slice(&self.outpoint.store());
+    value
+  }
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -922,4 +999,36 @@ mod tests {
       1001
     );
   }
+
+  #[test]
+  fn rune_activity_entry() {
+    let entry = RuneActivityEntry {
+      tx_index: 3,
+      kind: RuneActivityKind::Transfer,
+      rune_id: RuneId {
+        block: 840000,
+        tx: 1,
+      },
+      amount: u128::MAX,
+      txid: txid(1),
+      outpoint: OutPoint {
+        txid: txid(1),
+        vout: 2,
+      },
+    };
+
+    let value = entry.store();
+
+    assert_eq!(&value[0..4], &[0, 0, 0, 3]);
+    assert_eq!(value[4], 1);
+    assert_eq!(RuneActivityEntry::load(value), entry);
+
+    let spend = RuneActivityEntry {
+      kind: RuneActivityKind::Spend,
+      outpoint: OutPoint::null(),
+      ..entry
+    };
+
+    assert_eq!(RuneActivityEntry::load(spend.store()), spend);
+  }
 }
@@ -358,6 +358,7 @@ impl Updater<'_> {
       let mut rune_to_rune_id = wtx.open_table(RUNE_TO_RUNE_ID)?;
       let mut sequence_number_to_rune_id = wtx.open_table(SEQUENCE_NUMBER_TO_RUNE_ID)?;
       let mut transaction_id_to_rune = wtx.open_table(TRANSACTION_ID_TO_RUNE)?;
+      let mut height_to_rune_activity = wtx.open_multimap_table(HEIGHT_TO_RUNE_ACTIVITY)?;
 
       let runes = statistic_to_count
         .get(&Statistic::Runes.into())?
@@ -370,6 +371,7 @@ impl Updater<'_> {
         burned: HashMap::new(),
         client: &self.index.client,
         height: self.height,
+        height_to_rune_activity: &mut height_to_rune_activity,
         id_to_entry: &mut rune_id_to_rune_entry,
         inscription_id_to_sequence_number: &mut inscription_id_to_sequence_number,
         minimum: Rune::minimum_at_height(
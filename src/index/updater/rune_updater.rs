@@ -6,6 +6,8 @@ pub(super) struct RuneUpdater<'a, 'tx, 'client> {
   pub(super) client: &'client Client,
   pub(super) event_sender: Option<&'a mpsc::Sender<Event>>,
   pub(super) height: u32,
+  pub(super) height_to_rune_activity:
+    &'a mut MultimapTable<'tx, u32, &'static RuneActivityEntryValue>,
   pub(super) id_to_entry: &'a mut Table<'tx, RuneIdValue, RuneEntryValue>,
   pub(super) inscription_id_to_sequence_number: &'a Table<'tx, InscriptionIdValue, u32>,
   pub(super) minimum: Rune,
@@ -21,7 +23,7 @@ impl RuneUpdater<'_, '_, '_> {
   pub(super) fn index_runes(&mut self, tx_index: u32, tx: &Transaction, txid: Txid) -> Result<()> {
     let artifact = Runestone::decipher(tx);
 
-    let mut unallocated = self.unallocated(tx)?;
+    let mut unallocated = self.unallocated(tx_index, tx, txid)?;
 
     let mut allocated: Vec<HashMap<RuneId, Lot>> = vec![HashMap::new(); tx.output.len()];
 
@@ -30,6 +32,15 @@ impl RuneUpdater<'_, '_, '_> {
         if let Some(amount) = self.mint(id)? {
           *unallocated.entry(id).or_default() += amount;
 
+          self.record_activity(
+            tx_index,
+            RuneActivityKind::Mint,
+            id,
+            amount.n(),
+            txid,
+            OutPoint::null(),
+          )?;
+
           if let Some(sender) = self.event_sender {
             sender.blocking_send(Event::RuneMinted {
               block_height: self.height,
@@ -198,6 +209,15 @@ impl RuneUpdater<'_, '_, '_> {
       for (id, balance) in balances {
         Index::encode_rune_balance(id, balance.n(), &mut buffer);
 
+        self.record_activity(
+          tx_index,
+          RuneActivityKind::Transfer,
+          id,
+          balance.n(),
+          txid,
+          outpoint,
+        )?;
+
         if let Some(sender) = self.event_sender {
           sender.blocking_send(Event::RuneTransferred {
             outpoint,
@@ -218,6 +238,15 @@ impl RuneUpdater<'_, '_, '_> {
     for (id, amount) in burned {
       *self.burned.entry(id).or_default() += amount;
 
+      self.record_activity(
+        tx_index,
+        RuneActivityKind::Burn,
+        id,
+        amount.n(),
+        txid,
+        OutPoint::null(),
+      )?;
+
       if let Some(sender) = self.event_sender {
         sender.blocking_send(Event::RuneBurned {
           block_height: self.height,
@@ -466,12 +495,41 @@ impl RuneUpdater<'_, '_, '_> {
     Ok(false)
   }
 
-  fn unallocated(&mut self, tx: &Transaction) -> Result<HashMap<RuneId, Lot>> {
+  fn record_activity(
+    &mut self,
+    tx_index: u32,
+    kind: RuneActivityKind,
+    rune_id: RuneId,
+    amount: u128,
+    txid: Txid,
+    outpoint: OutPoint,
+  ) -> Result {
+    let entry = RuneActivityEntry {
+      tx_index,
+      kind,
+      rune_id,
+      amount,
+      txid,
+      outpoint,
+    };
+    self
+      .height_to_rune_activity
+      .insert(&self.height, &entry.store())?;
+    Ok(())
+  }
+
+  fn unallocated(
+    &mut self,
+    tx_index: u32,
+    tx: &Transaction,
+    txid: Txid,
+  ) -> Result<HashMap<RuneId, Lot>> {
     // map of rune ID to un-allocated balance of that rune
     let mut unallocated: HashMap<RuneId, Lot> = HashMap::new();
 
     // increment unallocated runes with the runes in tx inputs
     for input in &tx.input {
+      let mut spent = Vec::new();
       if let Some(guard) = self
         .outpoint_to_balances
         .remove(&input.previous_output.store())?
@@ -482,8 +540,19 @@ impl RuneUpdater<'_, '_, '_> {
           let ((id, balance), len) = Index::decode_rune_balance(&buffer[i..]).unwrap();
           i += len;
           *unallocated.entry(id).or_default() += balance;
+          spent.push((id, balance));
         }
       }
+      for (id, balance) in spent {
+        self.record_activity(
+          tx_index,
+          RuneActivityKind::Spend,
+          id,
+          balance,
+          txid,
+          input.previous_output,
+        )?;
+      }
     }
 
     Ok(unallocated)
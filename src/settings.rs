@@ -34,8 +34,29 @@ pub struct Settings {
   db_name: Option<String>,
   db_user: Option<String>,
   db_password: Option<String>,
+  db_connection_string: Option<String>,
+  db_port: Option<u16>,
+  db_sslmode: Option<String>,
+  db_ssl_root_cert: Option<PathBuf>,
+  db_pool_max_size: Option<usize>,
+  db_statement_timeout_ms: Option<u64>,
   magiceden_api_key: Option<String>,
+  magiceden_api_url: Option<String>,
+  magiceden_stats_url: Option<String>,
+  magiceden_timeout_ms: Option<u64>,
+  magiceden_requests_per_minute: Option<u32>,
+  magiceden_max_retries: Option<u32>,
   access_token_secret: Option<String>,
+  admin_api_key: Option<String>,
+  content_store: Option<String>,
+  content_store_path: Option<PathBuf>,
+  s3_bucket_name: Option<String>,
+  s3_endpoint: Option<String>,
+  s3_region: Option<String>,
+  s3_access_key_id: Option<String>,
+  s3_secret_access_key: Option<String>,
+  s3_upload_start_number: Option<u64>,
+  s3_head_check: bool,
 }
 
 impl Settings {
@@ -155,8 +176,31 @@ impl Settings {
       db_name: self.db_name.or(source.db_name),
       db_user: self.db_user.or(source.db_user),
       db_password: self.db_password.or(source.db_password),
+      db_connection_string: self.db_connection_string.or(source.db_connection_string),
+      db_port: self.db_port.or(source.db_port),
+      db_sslmode: self.db_sslmode.or(source.db_sslmode),
+      db_ssl_root_cert: self.db_ssl_root_cert.or(source.db_ssl_root_cert),
+      db_pool_max_size: self.db_pool_max_size.or(source.db_pool_max_size),
+      db_statement_timeout_ms: self.db_statement_timeout_ms.or(source.db_statement_timeout_ms),
       magiceden_api_key: self.magiceden_api_key.or(source.magiceden_api_key),
+      magiceden_api_url: self.magiceden_api_url.or(source.magiceden_api_url),
+      magiceden_stats_url: self.magiceden_stats_url.or(source.magiceden_stats_url),
+      magiceden_timeout_ms: self.magiceden_timeout_ms.or(source.magiceden_timeout_ms),
+      magiceden_requests_per_minute: self.magiceden_requests_per_minute.or(source.magiceden_requests_per_minute),
+      magiceden_max_retries: self.magiceden_max_retries.or(source.magiceden_max_retries),
       access_token_secret: self.access_token_secret.or(source.access_token_secret),
+      admin_api_key: self.admin_api_key.or(source.admin_api_key),
+      content_store: self.content_store.or(source.content_store),
+      content_store_path: self.content_store_path.or(source.content_store_path),
+      s3_bucket_name: self.s3_bucket_name.or(source.s3_bucket_name),
+      s3_endpoint: self.s3_endpoint.or(source.s3_endpoint),
+      s3_region: self.s3_region.or(source.s3_region),
+      s3_access_key_id: self.s3_access_key_id.or(source.s3_access_key_id),
+      s3_secret_access_key: self.s3_secret_access_key.or(source.s3_secret_access_key),
+      s3_upload_start_number: self
+        .s3_upload_start_number
+        .or(source.s3_upload_start_number),
+      s3_head_check: self.s3_head_check || source.s3_head_check,
     }
   }
 
@@ -199,8 +243,29 @@ impl Settings {
       db_name: None,
       db_user: None,
       db_password: None,
+      db_connection_string: None,
+      db_port: None,
+      db_sslmode: None,
+      db_ssl_root_cert: None,
+      db_pool_max_size: None,
+      db_statement_timeout_ms: None,
       magiceden_api_key: None,
+      magiceden_api_url: None,
+      magiceden_stats_url: None,
+      magiceden_timeout_ms: None,
+      magiceden_requests_per_minute: None,
+      magiceden_max_retries: None,
       access_token_secret: None,
+      admin_api_key: None,
+      content_store: None,
+      content_store_path: None,
+      s3_bucket_name: None,
+      s3_endpoint: None,
+      s3_region: None,
+      s3_access_key_id: None,
+      s3_secret_access_key: None,
+      s3_upload_start_number: None,
+      s3_head_check: false,
     }
   }
 
@@ -255,6 +320,14 @@ impl Settings {
         .with_context(|| format!("failed to parse environment variable ORD_{key} as u32"))
     };
 
+    let get_u64 = |key| {
+      env
+        .get(key)
+        .map(|int| int.parse::<u64>())
+        .transpose()
+        .with_context(|| format!("failed to parse environment variable ORD_{key} as u64"))
+    };
+
     let get_usize = |key| {
       env
         .get(key)
@@ -295,8 +368,29 @@ impl Settings {
       db_name: get_string("DB_NAME"),
       db_user: get_string("DB_USER"),
       db_password: get_string("DB_PASSWORD"),
+      db_connection_string: get_string("DB_CONNECTION_STRING"),
+      db_port: get_u16("DB_PORT")?,
+      db_sslmode: get_string("DB_SSLMODE"),
+      db_ssl_root_cert: get_path("DB_SSL_ROOT_CERT"),
+      db_pool_max_size: get_usize("DB_POOL_MAX_SIZE")?,
+      db_statement_timeout_ms: get_u64("DB_STATEMENT_TIMEOUT_MS")?,
       magiceden_api_key: get_string("MAGICEDEN_API_KEY"),
+      magiceden_api_url: get_string("MAGICEDEN_API_URL"),
+      magiceden_stats_url: get_string("MAGICEDEN_STATS_URL"),
+      magiceden_timeout_ms: get_u64("MAGICEDEN_TIMEOUT_MS")?,
+      magiceden_requests_per_minute: get_u32("MAGICEDEN_REQUESTS_PER_MINUTE")?,
+      magiceden_max_retries: get_u32("MAGICEDEN_MAX_RETRIES")?,
       access_token_secret: get_string("ACCESS_TOKEN_SECRET"),
+      admin_api_key: get_string("ADMIN_API_KEY"),
+      content_store: get_string("CONTENT_STORE"),
+      content_store_path: get_path("CONTENT_STORE_PATH"),
+      s3_bucket_name: get_string("S3_BUCKET_NAME"),
+      s3_endpoint: get_string("S3_ENDPOINT"),
+      s3_region: get_string("S3_REGION"),
+      s3_access_key_id: get_string("S3_ACCESS_KEY_ID"),
+      s3_secret_access_key: get_string("S3_SECRET_ACCESS_KEY"),
+      s3_upload_start_number: get_u64("S3_UPLOAD_START_NUMBER")?,
+      s3_head_check: get_bool("S3_HEAD_CHECK"),
     })
   }
 
@@ -333,8 +427,29 @@ impl Settings {
       db_name: None,
       db_user: None,
       db_password: None,
+      db_connection_string: None,
+      db_port: None,
+      db_sslmode: None,
+      db_ssl_root_cert: None,
+      db_pool_max_size: None,
+      db_statement_timeout_ms: None,
       magiceden_api_key: None,
+      magiceden_api_url: None,
+      magiceden_stats_url: None,
+      magiceden_timeout_ms: None,
+      magiceden_requests_per_minute: None,
+      magiceden_max_retries: None,
       access_token_secret: None,
+      admin_api_key: None,
+      content_store: None,
+      content_store_path: None,
+      s3_bucket_name: None,
+      s3_endpoint: None,
+      s3_region: None,
+      s3_access_key_id: None,
+      s3_secret_access_key: None,
+      s3_upload_start_number: None,
+      s3_head_check: false,
     }
   }
 
@@ -415,8 +530,29 @@ impl Settings {
       db_name: self.db_name,
       db_user: self.db_user,
       db_password: self.db_password,
+      db_connection_string: self.db_connection_string,
+      db_port: self.db_port,
+      db_sslmode: self.db_sslmode,
+      db_ssl_root_cert: self.db_ssl_root_cert,
+      db_pool_max_size: self.db_pool_max_size,
+      db_statement_timeout_ms: self.db_statement_timeout_ms,
       magiceden_api_key: self.magiceden_api_key,
+      magiceden_api_url: self.magiceden_api_url,
+      magiceden_stats_url: self.magiceden_stats_url,
+      magiceden_timeout_ms: self.magiceden_timeout_ms,
+      magiceden_requests_per_minute: self.magiceden_requests_per_minute,
+      magiceden_max_retries: self.magiceden_max_retries,
       access_token_secret: self.access_token_secret,
+      admin_api_key: self.admin_api_key,
+      content_store: self.content_store,
+      content_store_path: self.content_store_path,
+      s3_bucket_name: self.s3_bucket_name,
+      s3_endpoint: self.s3_endpoint,
+      s3_region: self.s3_region,
+      s3_access_key_id: self.s3_access_key_id,
+      s3_secret_access_key: self.s3_secret_access_key,
+      s3_upload_start_number: self.s3_upload_start_number,
+      s3_head_check: self.s3_head_check,
     })
   }
 
@@ -612,6 +748,10 @@ impl Settings {
     self.integration_test
   }
 
+  pub fn hidden(&self) -> impl Iterator<Item = &InscriptionId> {
+    self.hidden.iter().flatten()
+  }
+
   pub fn is_hidden(&self, inscription_id: InscriptionId) -> bool {
     self
       .hidden
@@ -652,13 +792,97 @@ impl Settings {
     self.db_password.as_deref()
   }
 
+  pub fn db_connection_string(&self) -> Option<&str> {
+    self.db_connection_string.as_deref()
+  }
+
+  pub fn db_port(&self) -> Option<u16> {
+    self.db_port
+  }
+
+  pub fn db_sslmode(&self) -> Option<&str> {
+    self.db_sslmode.as_deref()
+  }
+
+  pub fn db_ssl_root_cert(&self) -> Option<&Path> {
+    self.db_ssl_root_cert.as_deref()
+  }
+
+  pub fn db_pool_max_size(&self) -> Option<usize> {
+    self.db_pool_max_size
+  }
+
+  pub fn db_statement_timeout_ms(&self) -> Option<u64> {
+    self.db_statement_timeout_ms
+  }
+
   pub fn magiceden_api_key(&self) -> Option<&str> {
     self.magiceden_api_key.as_deref()
   }
 
+  pub fn magiceden_api_url(&self) -> Option<&str> {
+    self.magiceden_api_url.as_deref()
+  }
+
+  pub fn magiceden_stats_url(&self) -> Option<&str> {
+    self.magiceden_stats_url.as_deref()
+  }
+
+  pub fn magiceden_timeout_ms(&self) -> Option<u64> {
+    self.magiceden_timeout_ms
+  }
+
+  pub fn magiceden_requests_per_minute(&self) -> Option<u32> {
+    self.magiceden_requests_per_minute
+  }
+
+  pub fn magiceden_max_retries(&self) -> Option<u32> {
+    self.magiceden_max_retries
+  }
+
   pub fn access_token_secret(&self) -> Option<&str> {
     self.access_token_secret.as_deref()
   }
+
+  pub fn admin_api_key(&self) -> Option<&str> {
+    self.admin_api_key.as_deref()
+  }
+
+  pub fn content_store(&self) -> Option<&str> {
+    self.content_store.as_deref()
+  }
+
+  pub fn content_store_path(&self) -> Option<&Path> {
+    self.content_store_path.as_deref()
+  }
+
+  pub fn s3_bucket_name(&self) -> Option<&str> {
+    self.s3_bucket_name.as_deref()
+  }
+
+  pub fn s3_endpoint(&self) -> Option<&str> {
+    self.s3_endpoint.as_deref()
+  }
+
+  pub fn s3_region(&self) -> Option<&str> {
+    self.s3_region.as_deref()
+  }
+
+  pub fn s3_access_key_id(&self) -> Option<&str> {
+    self.s3_access_key_id.as_deref()
+  }
+
+  pub fn s3_secret_access_key(&self) -> Option<&str> {
+    self.s3_secret_access_key.as_deref()
+  }
+
+  pub fn s3_upload_start_number(&self) -> u64 {
+    self.s3_upload_start_number.unwrap_or_default()
+  }
+
+  pub fn s3_head_check(&self) -> bool {
+    self.s3_head_check
+  }
 }
 
 #[cfg(test)]
@@ -1197,8 +1421,29 @@ mod tests {
         db_name: None,
         db_user: None,
         db_password: None,
+        db_connection_string: None,
+        db_port: None,
+        db_sslmode: None,
+        db_ssl_root_cert: None,
+        db_pool_max_size: None,
+        db_statement_timeout_ms: None,
         magiceden_api_key: None,
+        magiceden_api_url: None,
+        magiceden_stats_url: None,
+        magiceden_timeout_ms: None,
+        magiceden_requests_per_minute: None,
+        magiceden_max_retries: None,
         access_token_secret: None,
+        admin_api_key: None,
+        content_store: None,
+        content_store_path: None,
+        s3_bucket_name: None,
+        s3_endpoint: None,
+        s3_region: None,
+        s3_access_key_id: None,
+        s3_secret_access_key: None,
+        s3_upload_start_number: None,
+        s3_head_check: false,
       }
     );
   }
@@ -1268,8 +1513,29 @@ mod tests {
         db_name: None,
         db_user: None,
         db_password: None,
+        db_connection_string: None,
+        db_port: None,
+        db_sslmode: None,
+        db_ssl_root_cert: None,
+        db_pool_max_size: None,
+        db_statement_timeout_ms: None,
         magiceden_api_key: None,
+        magiceden_api_url: None,
+        magiceden_stats_url: None,
+        magiceden_timeout_ms: None,
+        magiceden_requests_per_minute: None,
+        magiceden_max_retries: None,
         access_token_secret: None,
+        admin_api_key: None,
+        content_store: None,
+        content_store_path: None,
+        s3_bucket_name: None,
+        s3_endpoint: None,
+        s3_region: None,
+        s3_access_key_id: None,
+        s3_secret_access_key: None,
+        s3_upload_start_number: None,
+        s3_head_check: false,
       }
     );
   }
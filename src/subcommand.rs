@@ -61,7 +61,7 @@ pub(crate) enum Subcommand {
   Wallets,
   #[command(about = "Run the vermilion server")]
   Vermilion(vermilion::Vermilion),
-  #[command(about = "Run the migration")]
+  #[command(about = "Apply vermilion database schema migrations")]
   Migrate(migrate::Migrator),
 }
 
@@ -96,10 +96,7 @@ impl Subcommand {
         println!("Vermilion exited");
         result
       },
-      Self::Migrate(migrator) => {
-        let index = Arc::new(Index::open(&settings)?);
-        migrator.run(settings, index)
-      }
+      Self::Migrate(migrator) => migrator.run(settings),
     }
   }
 }
@@ -1,23 +1,46 @@
-use super::*;
+use {
+  super::*,
+  vermilion::{migrations, Vermilion},
+};
 
 #[derive(Debug, Parser, Clone)]
+#[clap(
+group(
+  ArgGroup::new("action")
+    .required(true)
+    .args(&["status", "up", "to"]))
+)]
 pub(crate) struct Migrator {
-  #[arg(
-    long,
-    help = "Which migration to run. If not specified, all migrations will be run."
-  )]
-  pub(crate) script_number: u16,
+  #[arg(long, help = "Show which vermilion schema migrations have been applied.")]
+  status: bool,
+  #[arg(long, help = "Apply all pending vermilion schema migrations.")]
+  up: bool,
+  #[arg(long, help = "Apply pending vermilion schema migrations up to and including <TO>.")]
+  to: Option<u32>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Output {
+  pub applied: Vec<u32>,
+  pub migrations: Vec<migrations::MigrationStatus>,
 }
 
 impl Migrator {
-  pub(crate) fn run(&self, _settings: Settings, _index: Arc<Index>) -> SubcommandResult {
-    if self.script_number == 1 {
-      println!("Running test migration 1");
-      //CODE GOES HERE
-      Ok(None)
-    } else {
-      Ok(None)
-    }
+  pub(crate) fn run(&self, settings: Settings) -> SubcommandResult {
+    let rt = tokio::runtime::Builder::new_current_thread()
+      .enable_all()
+      .build()?;
+    rt.block_on(async {
+      let pool = Vermilion::get_deadpool(settings).await?;
+      let applied = if self.status {
+        Vec::new()
+      } else {
+        migrations::migrate(pool.clone(), self.to).await?
+      };
+      Ok(Some(Box::new(Output {
+        applied,
+        migrations: migrations::status(pool).await?,
+      }) as Box<dyn subcommand::Output>))
+    })
   }
-
-}
\ No newline at end of file
+}
@@ -1,17 +1,36 @@
 use super::*;
 use axum_server::Handle;
-use rune_indexer::process_runes;
+use rune_indexer::{extract_runes, insert_runes, RuneBlock};
 use rune_indexer::initialize_runes_tables;
+use rune_indexer::rollback_rune_activity;
+use rune_indexer::{RuneSummary, RuneHolder, RuneActivity};
+use content_store::ContentStore;
+use block_pipeline::BlockPipeline;
 use social::initialize_social_tables;
 use social_api::social_router;
+use social_auth::parse_address;
+use events::{events_router, EventHub};
+use metrics::{metrics_router, track_request, METRICS};
+use hidden::{admin_router, admin_security_scheme, HiddenInscriptions, HiddenParams, VISIBLE_SQL};
+use sessions::{SessionPgPool, SESSIONS_TABLE};
+use pagination::{deserialize_cursor, Keyset, KeyType, PageRequest, Paginated, SqlParams};
+use text_search::{ParsedSearchTextParams, SearchTextParams, TextSearchResult};
+use collection_sources::{CollectionSource, ImportCollections};
+use magic_eden::{MagicEdenClient, RetryPolicy};
+use sales::{Sale, SalesOf, SalesQueryParams, SoldInput};
+use collection_history::{CollectionHistory, CollectionHistoryParams, HistoryOf};
+use snapshot::{Snapshot, SnapshotParams, SnapshotResponse};
+use provenance::{CollectorHistoryParams, HoldingPeriod, ParsedCollectorHistoryParams};
+use brc20::{Brc20Balance, Brc20Event, Brc20List, Brc20PageParams, Brc20Ticker};
+use bitmaps::{Bitmap, BitmapAvailability, BitmapsInAddressParams, ParsedBitmapsInAddressParams};
 use crate::subcommand::server;
 use crate::index::fetcher::Fetcher;
 use crate::subcommand::vermilion::api::{
   TxidParam, serve_openapi, serve_scalar, ApiError, ContentResponse,
   InscriptionNumber, BlockNumber, SatNumber, Sha256Hash,
-  BitcoinAddress, CollectionSymbol, ParentList, SearchQuery,
-  SatributeType, CharmType, ContentType, InscriptionSortBy, CollectionSortBy, GallerySortBy, BlockSortBy,
-  set_comma_separated_arrays
+  BitcoinAddress, CollectionSymbol, ParentList, SearchQuery, SpacedRuneName, RuneIdentifier, Brc20Tick, BitmapNumber,
+  SatributeType, CharmType, ContentType, InscriptionSortBy, CollectionSortBy, GallerySortBy, BlockSortBy, SaleSortBy, HistoryInterval,
+  set_comma_separated_arrays, set_comma_separated_content_types
 };
 use crate::Charm;
 
@@ -34,7 +53,7 @@ use axum::{
   Extension,
 };
 use axum_extra::extract::Query;
-use axum_session::{Session, SessionNullPool, SessionConfig, SessionStore, SessionLayer};
+use axum_session::{Session, SessionConfig, SessionStore, SessionLayer};
 use aide::{
   axum::{
     routing::{get, get_with},
@@ -66,7 +85,6 @@ use base64::engine::general_purpose::STANDARD as BASE64;
 use serde_aux::prelude::*;
 
 use deadpool_postgres::{ManagerConfig, Pool as deadpool, RecyclingMethod};
-use tokio_postgres::NoTls;
 use tokio_postgres::binary_copy::BinaryCopyInWriter;
 use tokio_postgres::types::{ToSql, Type};
 use futures::pin_mut;
@@ -81,9 +99,29 @@ use bitcoin::{
 use csv;
 
 mod rune_indexer;
+pub(crate) mod migrations;
+mod content_store;
+mod undo_journal;
+mod block_pipeline;
 mod database;
+mod postgres_tls;
 mod social;
 mod social_api;
+mod social_auth;
+mod events;
+mod metrics;
+mod hidden;
+mod sessions;
+mod pagination;
+mod text_search;
+mod collection_sources;
+mod magic_eden;
+mod sales;
+mod collection_history;
+mod snapshot;
+mod provenance;
+mod brc20;
+mod bitmaps;
 mod api;
 
 #[derive(Debug, Parser, Clone)]
@@ -153,6 +191,32 @@ pub(crate) struct Vermilion {
   pub(crate) run_api_server_only: bool,
   #[arg(long, help = "Run migration script. [default: false].")]
   pub(crate) run_migration_script: bool,
+  #[arg(
+    long,
+    default_value = "1",
+    help = "Extract up to <PIPELINE_DEPTH> blocks ahead of the block being committed. [default: 1]."
+  )]
+  pub(crate) pipeline_depth: usize,
+  #[arg(
+    long,
+    help = "Index off-chain collections from the .json and .csv manifests in <COLLECTION_MANIFEST_DIR>, as well as from Magic Eden if magiceden_api_key is set."
+  )]
+  pub(crate) collection_manifest_dir: Option<PathBuf>,
+  #[arg(
+    long,
+    help = "Index BRC-20 deploys, mints and transfers into the brc20 tables. Needs to be set before the indexer passes the first BRC-20 block, or balances will be incomplete. [default: false]."
+  )]
+  pub(crate) index_brc20: bool,
+  #[command(subcommand)]
+  pub(crate) subcommand: Option<VermilionSubcommand>,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub(crate) enum VermilionSubcommand {
+  #[command(about = "Import off-chain collections from local manifests")]
+  ImportCollections(ImportCollections),
+  #[command(about = "Print the holders of a collection, inscriptions or a rune at a past block height")]
+  Snapshot(Snapshot),
 }
 
 #[derive(Clone, Serialize)]
@@ -279,6 +343,25 @@ pub struct BlockStats {
   average_fee: Option<i64>
 }
 
+// Rows for one block read from the index and bitcoin rpc. Nothing here depends on postgres,
+// so blocks can be extracted ahead of the one being committed.
+pub struct ExtractedBlock {
+  block_hash: BlockHash,
+  blockstats: BlockStats,
+  runes: Option<RuneBlock>,
+  inscriptions: Option<InscriptionBlock>,
+  transfers: Option<(Vec<Transfer>, Vec<Sale>)>,
+}
+
+#[derive(Default)]
+pub struct InscriptionBlock {
+  metadata: Vec<Metadata>,
+  sat_metadata: Vec<SatMetadata>,
+  satributes: Vec<Satribute>,
+  galleries: Vec<GalleryMetadata>,
+  content: Vec<(i64, ContentBlob)>,
+}
+
 #[derive(Clone, Serialize)]
 pub struct InscriptionBlockStats {
   block_number: i64,
@@ -389,6 +472,12 @@ pub struct QueryNumber {
   n: Option<u32>
 }
 
+// Transfers record burned inscriptions, unbound inscriptions and outputs without an address under the pseudo
+// addresses burned and unbound, or the error message from decoding the script. Real addresses never have spaces.
+pub(crate) fn is_holder_address(column: &str) -> String {
+  format!("({column} NOT IN ('burned', 'unbound') AND {column} NOT LIKE '% %')")
+}
+
 fn empty_json_schema(_gen: &mut schemars::SchemaGenerator) -> schemars::Schema {
   schemars::json_schema!({})
 }
@@ -479,6 +568,66 @@ pub struct InscriptionQueryParams {
   #[serde(default, deserialize_with = "deserialize_comma_separated_and_repeated")]
   charms: Vec<CharmType>,
 
+  /// Lowest genesis block height
+  #[schemars(description = "Only inscriptions inscribed at or after this block height", example = "840000")]
+  min_genesis_height: Option<i64>,
+
+  /// Highest genesis block height
+  #[schemars(description = "Only inscriptions inscribed at or before this block height")]
+  max_genesis_height: Option<i64>,
+
+  /// Earliest genesis timestamp
+  #[schemars(description = "Only inscriptions inscribed at or after this unix timestamp, in seconds")]
+  min_timestamp: Option<i64>,
+
+  /// Latest genesis timestamp
+  #[schemars(description = "Only inscriptions inscribed at or before this unix timestamp, in seconds")]
+  max_timestamp: Option<i64>,
+
+  /// Smallest content length
+  #[schemars(description = "Only inscriptions with content of at least this many bytes")]
+  min_content_length: Option<i64>,
+
+  /// Biggest content length
+  #[schemars(description = "Only inscriptions with content of at most this many bytes")]
+  max_content_length: Option<i64>,
+
+  /// Lowest genesis fee
+  #[schemars(description = "Only inscriptions with a genesis fee of at least this many sats")]
+  min_genesis_fee: Option<i64>,
+
+  /// Highest genesis fee
+  #[schemars(description = "Only inscriptions with a genesis fee of at most this many sats")]
+  max_genesis_fee: Option<i64>,
+
+  /// Metaprotocol to filter by
+  #[schemars(description = "Only inscriptions with this metaprotocol", example = "brc-20")]
+  metaprotocol: Option<String>,
+
+  /// Inscriber address to filter by
+  #[schemars(description = "Only inscriptions inscribed by this address")]
+  inscribed_by_address: Option<String>,
+
+  /// Filter by whether inscriptions have a parent
+  #[schemars(description = "true for only inscriptions with at least one parent, false for only inscriptions without parents")]
+  has_parent: Option<bool>,
+
+  /// Filter by whether inscriptions have a delegate
+  #[schemars(description = "true for only delegating inscriptions, false for only inscriptions without a delegate")]
+  has_delegate: Option<bool>,
+
+  /// Filter by whether inscriptions are recursive
+  #[schemars(description = "true for only recursive inscriptions, false for only non-recursive inscriptions")]
+  is_recursive: Option<bool>,
+
+  /// Filter by whether inscriptions are json
+  #[schemars(description = "true for only inscriptions with valid json content, false for only inscriptions without")]
+  is_json: Option<bool>,
+
+  /// Rune to filter by
+  #[schemars(description = "Only inscriptions etching this rune, spaced as in UNCOMMON•GOODS")]
+  spaced_rune: Option<String>,
+
   /// Sort order for the results
   #[schemars(description = "Sort order for inscription results")]
   sort_by: Option<InscriptionSortBy>,
@@ -497,28 +646,153 @@ pub struct InscriptionQueryParams {
     example = "20",
     range(min = 1, max = 100)
   )]
-  page_size: Option<usize>
+  page_size: Option<usize>,
+
+  /// Cursor for keyset pagination
+  #[schemars(description = "Opaque cursor for keyset pagination. Pass an empty cursor for the first page, then the next_cursor of each response. Takes precedence over page_number")]
+  #[serde(default, deserialize_with = "deserialize_cursor")]
+  cursor: Option<String>
 }
 
 pub struct ParsedInscriptionQueryParams {
   content_types: Vec<ContentType>,
   satributes: Vec<String>,
   charms: Vec<String>,
-  sort_by: InscriptionSortBy,
-  page_number: usize,
-  page_size: usize
+  genesis_height: (Option<i64>, Option<i64>),
+  timestamp: (Option<i64>, Option<i64>),
+  content_length: (Option<i64>, Option<i64>),
+  genesis_fee: (Option<i64>, Option<i64>),
+  metaprotocol: Option<String>,
+  inscribed_by_address: Option<String>,
+  has_parent: Option<bool>,
+  has_delegate: Option<bool>,
+  is_recursive: Option<bool>,
+  is_json: Option<bool>,
+  spaced_rune: Option<String>,
+  keyset: Keyset,
+  page: PageRequest
 }
 
-impl From<InscriptionQueryParams> for ParsedInscriptionQueryParams {
-  fn from(params: InscriptionQueryParams) -> Self {
-      Self {
-        content_types: params.content_types,
-        satributes: params.satributes.into_iter().map(|s| s.to_string()).collect(),
-        charms: params.charms.into_iter().map(|c| c.to_string()).collect(),
-        sort_by: params.sort_by.unwrap_or(InscriptionSortBy::Newest),
-        page_number: params.page_number.map_or(0, |v| v),
-        page_size: params.page_size.map_or(10, |v| std::cmp::min(v, 100)),
-      }
+impl InscriptionQueryParams {
+  // Addresses are checked against the chain being served, like address paths are
+  fn parse(self, chain: Chain) -> Result<ParsedInscriptionQueryParams, ApiError> {
+    let keyset = self.sort_by.unwrap_or(InscriptionSortBy::Newest).keyset();
+    let page_size = self.page_size.map_or(10, |v| std::cmp::min(v, 100));
+    let page = PageRequest::new(self.page_number, page_size, self.cursor.as_deref(), &keyset)?;
+    let inscribed_by_address = self.inscribed_by_address
+      .map(|address| parse_address(&address, chain)
+        .map_err(|error| ApiError::BadRequest(format!("Invalid {} address {}: {}", chain, address, error))))
+      .transpose()?;
+    Ok(ParsedInscriptionQueryParams {
+      content_types: self.content_types,
+      satributes: self.satributes.into_iter().map(|s| s.to_string()).collect(),
+      charms: self.charms.into_iter().map(|c| c.to_string()).collect(),
+      genesis_height: (self.min_genesis_height, self.max_genesis_height),
+      timestamp: (self.min_timestamp, self.max_timestamp),
+      content_length: (self.min_content_length, self.max_content_length),
+      genesis_fee: (self.min_genesis_fee, self.max_genesis_fee),
+      metaprotocol: self.metaprotocol,
+      inscribed_by_address,
+      has_parent: self.has_parent,
+      has_delegate: self.has_delegate,
+      is_recursive: self.is_recursive,
+      is_json: self.is_json,
+      spaced_rune: self.spaced_rune,
+      keyset,
+      page,
+    })
+  }
+}
+
+impl InscriptionSortBy {
+  fn keyset(&self) -> Keyset {
+    let (column, descending) = match self {
+      InscriptionSortBy::Newest => ("o.sequence_number", true),
+      InscriptionSortBy::Oldest => ("o.sequence_number", false),
+      InscriptionSortBy::NewestSat => ("o.sat", true),
+      InscriptionSortBy::OldestSat => ("o.sat", false),
+      InscriptionSortBy::RarestSat => ("o.sequence_number", true),  // No sorting implemented yet
+      InscriptionSortBy::CommonestSat => ("o.sequence_number", true),  // No sorting implemented yet
+      InscriptionSortBy::Biggest => ("o.content_length", true),
+      InscriptionSortBy::Smallest => ("o.content_length", false),
+      InscriptionSortBy::HighestFee => ("o.genesis_fee", true),
+      InscriptionSortBy::LowestFee => ("o.genesis_fee", false),
+    };
+    Keyset::new("inscriptions", self, column, descending).tie_break("o.sequence_number", KeyType::Int8)
+  }
+}
+
+impl CollectionSortBy {
+  fn keyset(&self, list: &str, key: &'static str, key_type: KeyType) -> Keyset {
+    let (column, descending) = match self {
+      CollectionSortBy::BiggestOnChainFootprint => ("s.total_on_chain_footprint", true),
+      CollectionSortBy::SmallestOnChainFootprint => ("s.total_on_chain_footprint", false),
+      CollectionSortBy::MostVolume => ("s.total_volume", true),
+      CollectionSortBy::LeastVolume => ("s.total_volume", false),
+      CollectionSortBy::BiggestFileSize => ("s.total_inscription_size", true),
+      CollectionSortBy::SmallestFileSize => ("s.total_inscription_size", false),
+      CollectionSortBy::BiggestCreationFee => ("s.total_inscription_fees", true),
+      CollectionSortBy::SmallestCreationFee => ("s.total_inscription_fees", false),
+      CollectionSortBy::EarliestFirstInscribedDate => ("s.first_inscribed_date", false),
+      CollectionSortBy::LatestFirstInscribedDate => ("s.first_inscribed_date", true),
+      CollectionSortBy::EarliestLastInscribedDate => ("s.last_inscribed_date", false),
+      CollectionSortBy::LatestLastInscribedDate => ("s.last_inscribed_date", true),
+      CollectionSortBy::BiggestSupply => ("s.supply", true),
+      CollectionSortBy::SmallestSupply => ("s.supply", false),
+    };
+    Keyset::new(list, self, column, descending).nulls_last().tie_break(key, key_type)
+  }
+}
+
+impl GallerySortBy {
+  fn keyset(&self, list: &str, key: &'static str) -> Keyset {
+    let (column, descending) = match self {
+      GallerySortBy::BiggestOnChainFootprint => ("s.total_on_chain_footprint", true),
+      GallerySortBy::SmallestOnChainFootprint => ("s.total_on_chain_footprint", false),
+      GallerySortBy::MostVolume => ("s.total_volume", true),
+      GallerySortBy::LeastVolume => ("s.total_volume", false),
+      GallerySortBy::BiggestFileSize => ("s.total_inscription_size", true),
+      GallerySortBy::SmallestFileSize => ("s.total_inscription_size", false),
+      GallerySortBy::BiggestCreationFee => ("s.total_inscription_fees", true),
+      GallerySortBy::SmallestCreationFee => ("s.total_inscription_fees", false),
+      GallerySortBy::EarliestFirstInscribedDate => ("s.first_inscribed_date", false),
+      GallerySortBy::LatestFirstInscribedDate => ("s.first_inscribed_date", true),
+      GallerySortBy::EarliestLastInscribedDate => ("s.last_inscribed_date", false),
+      GallerySortBy::LatestLastInscribedDate => ("s.last_inscribed_date", true),
+      GallerySortBy::BiggestSupply => ("s.supply", true),
+      GallerySortBy::SmallestSupply => ("s.supply", false),
+      GallerySortBy::MostBoosts => ("s.boost_count", true),
+      GallerySortBy::LeastBoosts => ("s.boost_count", false),
+      GallerySortBy::Newest => ("s.gallery_inscribed_date", true),
+      GallerySortBy::Oldest => ("s.gallery_inscribed_date", false),
+    };
+    Keyset::new(list, self, column, descending).nulls_last().tie_break(key, KeyType::Text)
+  }
+}
+
+impl BlockSortBy {
+  fn keyset(&self) -> Keyset {
+    let (column, descending) = match self {
+      BlockSortBy::Newest => ("b.block_number", true),
+      BlockSortBy::Oldest => ("b.block_number", false),
+      BlockSortBy::MostTxs => ("b.block_tx_count", true),
+      BlockSortBy::LeastTxs => ("b.block_tx_count", false),
+      BlockSortBy::MostInscriptions => ("i.block_inscription_count", true),
+      BlockSortBy::LeastInscriptions => ("i.block_inscription_count", false),
+      BlockSortBy::BiggestBlock => ("b.block_size", true),
+      BlockSortBy::SmallestBlock => ("b.block_size", false),
+      BlockSortBy::BiggestTotalInscriptionsSize => ("i.block_inscription_size", true),
+      BlockSortBy::SmallestTotalInscriptionsSize => ("i.block_inscription_size", false),
+      BlockSortBy::HighestTotalFees => ("b.block_fees", true),
+      BlockSortBy::LowestTotalFees => ("b.block_fees", false),
+      BlockSortBy::HighestInscriptionFees => ("i.block_inscription_fees", true),
+      BlockSortBy::LowestInscriptionFees => ("i.block_inscription_fees", false),
+      BlockSortBy::MostVolume => ("i.block_volume", true),
+      BlockSortBy::LeastVolume => ("i.block_volume", false),
+    };
+    let keyset = Keyset::new("blocks", self, column, descending).tie_break("b.block_number", KeyType::Int8);
+    // Inscription stats are left joined, so blocks without inscriptions go last
+    if column.starts_with("i.") { keyset.nulls_last() } else { keyset }
   }
 }
 
@@ -542,7 +816,12 @@ pub struct CollectionQueryParams {
     example = "20",
     range(min = 1, max = 100)
   )]
-  page_size: Option<usize>
+  page_size: Option<usize>,
+
+  /// Cursor for keyset pagination
+  #[schemars(description = "Opaque cursor for keyset pagination. Pass an empty cursor for the first page, then the next_cursor of each response. Takes precedence over page_number")]
+  #[serde(default, deserialize_with = "deserialize_cursor")]
+  cursor: Option<String>
 }
 
 #[derive(Deserialize, JsonSchema)]
@@ -565,7 +844,12 @@ pub struct GalleryQueryParams {
     example = "20",
     range(min = 1, max = 100)
   )]
-  page_size: Option<usize>
+  page_size: Option<usize>,
+
+  /// Cursor for keyset pagination
+  #[schemars(description = "Opaque cursor for keyset pagination. Pass an empty cursor for the first page, then the next_cursor of each response. Takes precedence over page_number")]
+  #[serde(default, deserialize_with = "deserialize_cursor")]
+  cursor: Option<String>
 }
 
 #[derive(Deserialize, JsonSchema)]
@@ -588,7 +872,12 @@ pub struct BlockQueryParams {
     example = "20",
     range(min = 1, max = 100)
   )]
-  page_size: Option<usize>
+  page_size: Option<usize>,
+
+  /// Cursor for keyset pagination
+  #[schemars(description = "Opaque cursor for keyset pagination. Pass an empty cursor for the first page, then the next_cursor of each response. Takes precedence over page_number")]
+  #[serde(default, deserialize_with = "deserialize_cursor")]
+  cursor: Option<String>
 }
 
 #[derive(Deserialize, Clone, JsonSchema)]
@@ -888,7 +1177,17 @@ pub struct SearchResult {
   inscription: Option<FullMetadata>,
   address: Option<String>,
   block: Option<CombinedBlockStats>,
-  sat: Option<SatMetadata>
+  sat: Option<SatMetadata>,
+  rune: Option<RuneSummary>,
+  transaction: Option<TransactionSearchResult>
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct TransactionSearchResult {
+  txid: String,
+  block_number: i64,
+  /// Inscriptions created or moved by the transaction
+  transfers: Vec<Transfer>
 }
 
 #[derive(Clone,PartialEq, PartialOrd, Ord, Eq)]
@@ -921,6 +1220,12 @@ pub struct IndexerTimings {
 pub struct ApiServerConfig {
   deadpool: deadpool,
   bitcoin_rpc_client: Arc<bitcoincore_rpc::Client>,
+  content_store: ContentStore,
+  events: EventHub,
+  hidden: HiddenInscriptions,
+  admin_api_key: Option<String>,
+  access_token_secret: Option<String>,
+  chain: Chain,
 }
 
 impl Vermilion {
@@ -953,6 +1258,12 @@ impl Vermilion {
   }
 
   pub(crate) fn run(self, settings: Settings) -> SubcommandResult {
+    match &self.subcommand {
+      Some(VermilionSubcommand::ImportCollections(import)) => return import.run(settings),
+      Some(VermilionSubcommand::Snapshot(snapshot)) => return snapshot.run(settings),
+      None => {},
+    }
+
     //1. Run Vermilion Server
     println!("Vermilion Server Starting");
     let vermilion_server_clone = self.clone();
@@ -1048,15 +1359,49 @@ impl Vermilion {
           }
         };
 
+        let content_store = match ContentStore::from_settings(&settings) {
+          Ok(content_store) => content_store,
+          Err(err) => {
+            println!("Error creating content store: {:?}", err);
+            return;
+          }
+        };
+
+        METRICS.register_pool("api", deadpool.clone());
+        // Inscriptions hidden through the admin routes must never be served, so the server doesn't start without them
+        let hidden = HiddenInscriptions::new(&settings);
+        if let Err(err) = hidden.reload(&deadpool).await {
+          println!("Error loading hidden inscriptions: {:?}", err);
+          return;
+        }
+        hidden.poll(deadpool.clone());
+        let events = EventHub::start(settings.clone(), deadpool.clone(), hidden.clone());
+
         let server_config = ApiServerConfig {
           deadpool: deadpool,
           bitcoin_rpc_client: bitcoin_rpc_client.clone(),
+          content_store,
+          events,
+          hidden,
+          admin_api_key: settings.admin_api_key().map(str::to_string),
+          access_token_secret: settings.access_token_secret().map(str::to_string),
+          chain: settings.chain(),
         };
 
         let session_config = SessionConfig::default()
           .with_cookie_path("/api") // Used to have it only for /random_inscriptions. Can't remember why. Setting it to /api for now.
-          .with_table_name("sessions_table");
-        let session_store = SessionStore::<SessionNullPool>::new(None, session_config).await.unwrap();
+          .with_table_name(SESSIONS_TABLE)
+          // Always read sessions back from postgres so replicas behind a load balancer agree on what a visitor has seen
+          .with_memory_lifetime(chrono::Duration::zero())
+          .with_lifetime(chrono::Duration::days(1))
+          .with_purge_database_update(chrono::Duration::hours(1));
+        let session_store = match SessionStore::new(Some(SessionPgPool::new(server_config.deadpool.clone())), session_config).await {
+          Ok(session_store) => session_store,
+          Err(err) => {
+            println!("Error creating session store: {:?}", err);
+            return;
+          }
+        };
 
         let mut api = OpenApi::default();
 
@@ -1099,6 +1444,10 @@ impl Vermilion {
           .api_route("/inscription_last_transfer_number/{number}", get(Self::inscription_last_transfer_number))
           .api_route("/inscription_transfers/{inscription_id}", get(Self::inscription_transfers))
           .api_route("/inscription_transfers_number/{number}", get(Self::inscription_transfers_number))
+          .api_route("/inscription_provenance/{inscription_id}", get(Self::inscription_provenance))
+          .api_route("/inscription_sales/{inscription_id}", get(Self::inscription_sales))
+          .api_route("/address_sales/{address}", get(Self::address_sales))
+          .api_route("/collector_history/{address}", get(Self::collector_history))
           .api_route("/inscriptions_in_address/{address}", get_with(Self::inscriptions_in_address, set_comma_separated_arrays))
           .api_route("/inscriptions_on_sat/{sat}", get(Self::inscriptions_on_sat))
           .api_route("/inscriptions_in_sat_block/{block}", get_with(Self::inscriptions_in_sat_block, set_comma_separated_arrays))
@@ -1112,10 +1461,14 @@ impl Vermilion {
           .api_route("/collections", get(Self::collections))
           .api_route("/collection_summary/{collection_symbol}", get(Self::collection_summary))
           .api_route("/collection_holders/{collection_symbol}", get(Self::collection_holders))
+          .api_route("/collection_sales/{collection_symbol}", get(Self::collection_sales))
+          .api_route("/collection_history/{collection_symbol}", get(Self::collection_history))
+          .api_route("/holder_snapshot", get(Self::holder_snapshot))
           .api_route("/inscriptions_in_collection/{collection_symbol}", get_with(Self::inscriptions_in_collection, set_comma_separated_arrays))
           .api_route("/on_chain_collections", get(Self::on_chain_collections))
           .api_route("/on_chain_collection_summary/{parents}", get(Self::on_chain_collection_summary))
           .api_route("/on_chain_collection_holders/{parents}", get(Self::on_chain_collection_holders))
+          .api_route("/on_chain_collection_history/{parents}", get(Self::on_chain_collection_history))
           .api_route("/inscriptions_in_on_chain_collection/{parents}", get_with(Self::inscriptions_in_on_chain_collection, set_comma_separated_arrays))
           .api_route("/gallery_inscriptions", get(Self::gallery_inscriptions))
           .api_route("/galleries_summary", get(Self::galleries_summary))
@@ -1123,14 +1476,32 @@ impl Vermilion {
           .api_route("/gallery_holders/{gallery_id}", get(Self::gallery_holders))
           .api_route("/inscriptions_in_gallery/{gallery_id}", get_with(Self::inscriptions_in_gallery, set_comma_separated_arrays))
           .api_route("/search/{search_by_query}", get(Self::search_by_query))
+          .api_route("/search_text", get_with(Self::search_text, set_comma_separated_content_types))
           .api_route("/block_icon/{block}", get(Self::block_icon))
           .api_route("/sat_block_icon/{block}", get(Self::sat_block_icon))
           .api_route("/block_transfers/{block}", get(Self::block_transfers))
+          .api_route("/runes", get(Self::runes))
+          .api_route("/rune/{spaced_rune}", get(Self::rune))
+          .api_route("/rune_holders/{rune}", get(Self::rune_holders))
+          .api_route("/rune_activity/{rune}", get(Self::rune_activity))
+          .api_route("/runes_in_block/{block}", get(Self::runes_in_block))
+          .api_route("/brc20_tickers", get(Self::brc20_tickers))
+          .api_route("/brc20_ticker/{tick}", get(Self::brc20_ticker))
+          .api_route("/brc20_holders/{tick}", get(Self::brc20_holders))
+          .api_route("/brc20_activity/{tick}", get(Self::brc20_activity))
+          .api_route("/address_brc20_balances/{address}", get(Self::address_brc20_balances))
+          .api_route("/bitmap/{bitmap}", get(Self::bitmap))
+          .api_route("/bitmaps_in_address/{address}", get(Self::bitmaps_in_address))
+          .api_route("/bitmap_availability/{bitmap}", get(Self::bitmap_availability))
           .api_route("/submit_package", post(Self::submit_package))
           .api_route("/get_raw_transaction/{txid}", get(Self::get_raw_transaction))
           .api_route("/api.json", get(serve_openapi))
           .api_route("/docs", get(serve_scalar))
           .merge(social_router())
+          .merge(events_router())
+          .merge(metrics_router())
+          .merge(admin_router())
+          .route_layer(axum::middleware::from_fn(track_request))
           .layer(map_response(Self::set_header))
           .layer(
             TraceLayer::new_for_http()
@@ -1152,7 +1523,7 @@ impl Vermilion {
               .allow_origin(Any),
           )
           .with_state(server_config)
-          .finish_api(&mut api)
+          .finish_api_with(&mut api, admin_security_scheme)
           .layer(Extension(api));
 
         let addr = SocketAddr::from(([127, 0, 0, 1], self.api_http_port.unwrap_or(81)));
@@ -1215,38 +1586,58 @@ impl Vermilion {
             return;
           }
         };
+        METRICS.register_pool("collection_indexer", pool.clone());
         let init_result = Self::initialize_collection_tables(pool.clone()).await;
         if init_result.is_err() {
           println!("Error initializing collection tables: {:?}", init_result.unwrap_err());
           return;
         }
+        let mut sources = match CollectionSource::configured(&settings, self.collection_manifest_dir.clone()) {
+          Ok(sources) => sources,
+          Err(err) => {
+            println!("Error configuring collection sources: {:?}", err);
+            return;
+          }
+        };
+        if sources.is_empty() {
+          println!("Collection indexer: No collection sources, set magiceden_api_key or --collection-manifest-dir");
+          return;
+        }
         let mut last_update = std::time::Instant::now() - std::time::Duration::from_secs(86401); // Force initial update
-        loop {
+        'indexer: loop {
           let t0 = Instant::now();
           // break if ctrl-c is received
           if SHUTTING_DOWN.load(atomic::Ordering::Relaxed) {
             break;
           }
           let force_update = t0.duration_since(last_update) >= std::time::Duration::from_secs(86400);
-          match Self::update_all_tokens(pool.clone(), settings.clone(), force_update).await {
-            Ok(update) => {
-              let t1 = Instant::now();
-              if update {
-                last_update = t1;
-                log::info!("Collection indexer: Updated all tokens in {:?}, forced: {}", t1.duration_since(t0), force_update);
-              } else {
-                log::info!("Collection indexer: No updates needed");
-              }
-            },
-            Err(err) => {
-              if err.to_string() == "Shutting down" {
-                break;
+          let mut failed = false;
+          for source in sources.iter_mut() {
+            match source.update(pool.clone(), force_update).await {
+              Ok(update) => {
+                let t1 = Instant::now();
+                if update {
+                  log::info!("Collection indexer: Updated {} collections in {:?}, forced: {}", source.name(), t1.duration_since(t0), force_update);
+                } else {
+                  log::info!("Collection indexer: No updates needed from {}", source.name());
+                }
+              },
+              Err(err) => {
+                if err.to_string() == "Shutting down" {
+                  break 'indexer;
+                }
+                log::warn!("Error updating {} collections: {:?}", source.name(), err);
+                failed = true;
               }
-              log::warn!("Error updating all tokens: {:?}", err);
-              tokio::time::sleep(Duration::from_secs(60)).await;
-              continue;
+            };
+          }
+          // A forced update is retried until every source has succeeded
+          if !failed {
+            METRICS.collection_indexer_succeeded();
+            if force_update {
+              last_update = Instant::now();
             }
-          };
+          }
           tokio::time::sleep(Duration::from_secs(60)).await;
         }
         println!("Collection indexer stopped");
@@ -1270,17 +1661,8 @@ impl Vermilion {
           }
         };
 
-        // Build connection string for LISTEN/NOTIFY
-        let conn_str = format!(
-          "host={} dbname={} user={} password={}",
-          settings.db_host().unwrap_or("localhost"),
-          settings.db_name().unwrap_or("vermilion"),
-          settings.db_user().unwrap_or("postgres"),
-          settings.db_password().unwrap_or("")
-        );
-
         // Set up dedicated listener connection for notifications
-        let (client, mut connection) = match tokio_postgres::connect(&conn_str, NoTls).await {
+        let (client, mut connection) = match database::connect(&settings).await {
           Ok((client, connection)) => (client, connection),
           Err(err) => {
             println!("Error creating postgres connection for postgres notification listener: {:?}", err);
@@ -1381,18 +1763,23 @@ impl Vermilion {
       _ => return false,
     };
 
-    // Get latest block from postgres indexer using fast blockstats query
-    let conn = match pool.get().await {
-      Ok(conn) => conn,
+    let postgres_height = match Self::get_postgres_height(pool).await {
+      Ok(height) => height,
       Err(_) => return false,
     };
 
-    let postgres_height = match conn.query_one("SELECT MAX(block_number) as latest_block FROM blockstats", &[]).await {
-      Ok(row) => row.get::<_, Option<i64>>("latest_block").unwrap_or(0),
-      Err(_) => return false,
-    };
+    Self::is_at_chain_tip(index_height, postgres_height)
+  }
 
-    // Consider "at tip" if within 2 blocks of main index
+  // Get latest block from postgres indexer using fast blockstats query
+  async fn get_postgres_height(pool: &deadpool_postgres::Pool) -> anyhow::Result<i64> {
+    let conn = pool.get().await?;
+    let row = conn.query_one("SELECT MAX(block_number) as latest_block FROM blockstats", &[]).await?;
+    Ok(row.get::<_, Option<i64>>("latest_block").unwrap_or(0))
+  }
+
+  // Consider "at tip" if within 2 blocks of main index
+  fn is_at_chain_tip(index_height: i64, postgres_height: i64) -> bool {
     let blocks_behind = index_height.saturating_sub(postgres_height);
     blocks_behind <= 2
   }
@@ -1410,8 +1797,6 @@ impl Vermilion {
         .build()
         .unwrap();
       rt.block_on(async move {
-        let first_inscription_height = settings.first_inscription_height();
-        let first_rune_height = settings.first_rune_height();
         let deadpool = match Self::get_deadpool(settings.clone()).await {
           Ok(deadpool) => deadpool,
           Err(err) => {
@@ -1419,6 +1804,7 @@ impl Vermilion {
             return;
           }
         };
+        METRICS.register_pool("indexer", deadpool.clone());
         let init_result = Self::initialize_db_tables(deadpool.clone()).await;
         if init_result.is_err() {
           println!("Error initializing db tables: {:?}", init_result.unwrap_err());
@@ -1431,13 +1817,30 @@ impl Vermilion {
             return;
           }
         };
+        if self.index_brc20 && block_number > brc20::first_brc20_height(&settings) {
+          match brc20::has_history(deadpool.clone()).await {
+            Ok(true) => {},
+            Ok(false) => log::warn!("BRC-20 indexing enabled at block {:?}, after the first BRC-20 block {:?}. Balances will be incomplete", block_number, brc20::first_brc20_height(&settings)),
+            Err(err) => log::info!("Error checking BRC-20 history: {:?}, continuing", err),
+          }
+        }
         let fetcher = match Fetcher::new(&settings.clone()) {
-          Ok(fetcher) => fetcher,
+          Ok(fetcher) => Arc::new(fetcher),
           Err(err) => {
             log::info!("Error creating fetcher: {:?}, exiting", err);
             return;
           }
         };
+        let content_store = match ContentStore::from_settings(&settings) {
+          Ok(content_store) => content_store,
+          Err(err) => {
+            log::info!("Error creating content store: {:?}, exiting", err);
+            return;
+          }
+        };
+        // Blocks are extracted ahead on worker tasks and handed back in order. Whenever the block being
+        // committed doesn't get committed, the pipeline is reset so it restarts at that block.
+        let mut pipeline = BlockPipeline::new(self.pipeline_depth, block_number);
         loop {
           // 0. break if ctrl-c is received
           let t0 = Instant::now();
@@ -1446,22 +1849,39 @@ impl Vermilion {
           }
           // 1. make sure block is indexed before requesting transfers
           let indexed_height = match index.get_blocks_indexed() {
-            Ok(indexed_height) => indexed_height,
+            Ok(indexed_height) => {
+              METRICS.set_index_height(indexed_height);
+              indexed_height
+            },
             Err(err) => {
               log::info!("Error getting blocks indexed: {:?}, waiting a minute", err);
               tokio::time::sleep(Duration::from_secs(60)).await;
               continue;
             }
           };
-          if block_number > indexed_height {
+          pipeline.fill(indexed_height, |number| {
+            Self::extract_block(index.clone(), settings.clone(), fetcher.clone(), number)
+          });
+          let Some(extracted) = pipeline.next().await else {
             log::info!("Waiting for blocks to be indexed, current block: {:?}, only indexed up to: {:?}", block_number, indexed_height);
             tokio::time::sleep(Duration::from_secs(60)).await;
             continue;
-          }
+          };
+          let extracted = match extracted {
+            Ok(extracted) => extracted,
+            Err(err) => {
+              log::info!("Error extracting block {:?}: {:?}, waiting a minute", block_number, err);
+              pipeline.reset(block_number);
+              tokio::time::sleep(Duration::from_secs(60)).await;
+              continue;
+            }
+          };
+          let t1 = Instant::now();
           let mut conn = match deadpool.get().await {
             Ok(conn) => conn,
             Err(err) => {
               log::info!("Error getting db connection: {:?}, exiting", err);
+              pipeline.reset(block_number);
               tokio::time::sleep(Duration::from_secs(60)).await;
               continue;
             }
@@ -1470,6 +1890,7 @@ impl Vermilion {
             Ok(tx) => tx,
             Err(err) => {
               log::info!("Error starting db transaction: {:?}, waiting a minute", err);
+              pipeline.reset(block_number);
               tokio::time::sleep(Duration::from_secs(60)).await;
               continue;
             }
@@ -1479,6 +1900,7 @@ impl Vermilion {
             Ok(last_consistent_block) => last_consistent_block,
             Err(err) => {
               log::info!("Error detecting last consistent block: {:?}, waiting a minute", err);
+              pipeline.reset(block_number);
               tokio::time::sleep(Duration::from_secs(60)).await;
               continue;
             }
@@ -1491,77 +1913,127 @@ impl Vermilion {
               },
               Err(err) => {
                 log::error!("CRITICAL Error handling reorg: {:?}, waiting a minute", err);
+                pipeline.reset(block_number);
                 tokio::time::sleep(Duration::from_secs(60)).await;
                 continue;
               }
             }
             block_number = last_consistent_block + 1;
+            pipeline.reset(block_number);
             continue;
           }
-          let t1 = Instant::now();
-          // 2. Process block stats
-          match Self::process_blockstats(index.clone(), &deadpool_tx, block_number).await {
+          //1c. Make sure the block wasn't reorged out of the index after it was extracted
+          match index.block_hash(Some(block_number)) {
+            Ok(Some(block_hash)) if block_hash == extracted.block_hash => {},
+            Ok(_) => {
+              log::warn!("Block {:?} changed since it was extracted, extracting again", block_number);
+              pipeline.reset(block_number);
+              continue;
+            },
+            Err(err) => {
+              log::info!("Error getting block hash for block {:?}: {:?}, waiting a minute", block_number, err);
+              pipeline.reset(block_number);
+              tokio::time::sleep(Duration::from_secs(60)).await;
+              continue;
+            }
+          }
+          if let Err(err) = undo_journal::start_block(&deadpool_tx, block_number, indexed_height).await {
+            log::info!("Error starting undo journal for block {:?}: {:?}, waiting a minute", block_number, err);
+            pipeline.reset(block_number);
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            continue;
+          }
+          let t2 = Instant::now();
+          // 2. Insert block stats
+          match Self::bulk_insert_blockstats(&deadpool_tx, vec![extracted.blockstats]).await {
             Ok(_) => {},
             Err(err) => {
-              log::info!("Error processing block stats for block {:?}: {:?}, waiting a minute", block_number, err);
+              log::info!("Error inserting block stats for block {:?}: {:?}, waiting a minute", block_number, err);
+              pipeline.reset(block_number);
               tokio::time::sleep(Duration::from_secs(60)).await;
               continue;
             }
           };
-          let t2 = Instant::now();
+          let t3 = Instant::now();
 
-          // 3. Process runes
-          if block_number >= first_rune_height || block_number == 0 {
-            match process_runes(index.clone(), &deadpool_tx, block_number).await {
+          // 3. Insert runes
+          if let Some(runes) = extracted.runes {
+            match insert_runes(&deadpool_tx, runes, block_number).await {
               Ok(_) => {},
               Err(err) => {
-                log::info!("Error processing runes for block {:?}: {:?}, waiting a minute", block_number, err);
+                log::info!("Error inserting runes for block {:?}: {:?}, waiting a minute", block_number, err);
+                pipeline.reset(block_number);
                 tokio::time::sleep(Duration::from_secs(60)).await;
                 continue;
               }
             };
           }
-          let t3 = Instant::now();
+          let t4 = Instant::now();
 
-          // 4. Process inscriptions
-          if block_number >= first_inscription_height {
-            match Self::process_inscriptions(index.clone(), &deadpool_tx, settings.clone(), block_number).await {
+          // 4. Insert inscriptions
+          if let Some(inscriptions) = extracted.inscriptions {
+            match Self::insert_inscriptions(&deadpool_tx, &content_store, inscriptions, block_number).await {
               Ok(_) => {},
               Err(err) => {
-                log::info!("Error processing inscriptions for block {:?}: {:?}, waiting a minute", block_number, err);
+                log::info!("Error inserting inscriptions for block {:?}: {:?}, waiting a minute", block_number, err);
+                pipeline.reset(block_number);
                 tokio::time::sleep(Duration::from_secs(60)).await;
                 continue;
               }
             };
           }
-          let t4 = Instant::now();
+          let t5 = Instant::now();
 
-          // 5. Process transfers
-          if block_number >= first_inscription_height {
-            match Self::process_transfers(index.clone(), &deadpool_tx, settings.clone(), &fetcher, block_number).await {
+          // 5. Insert transfers
+          if let Some((transfers, sales)) = extracted.transfers {
+            match Self::insert_transfers(&deadpool_tx, transfers, sales, block_number).await {
               Ok(_) => {},
               Err(err) => {
-                log::info!("Error processing transfers for block {:?}: {:?}, waiting a minute", block_number, err);
+                log::info!("Error inserting transfers for block {:?}: {:?}, waiting a minute", block_number, err);
+                pipeline.reset(block_number);
                 tokio::time::sleep(Duration::from_secs(60)).await;
                 continue;
               }
             };
           }
-          let t5 = Instant::now();
+          let t6 = Instant::now();
+
+          // 5b. Apply BRC-20 operations from the inscriptions and transfers just inserted
+          if self.index_brc20 && block_number >= brc20::first_brc20_height(&settings) {
+            match brc20::index_block(&deadpool_tx, block_number).await {
+              Ok(_) => {},
+              Err(err) => {
+                log::info!("Error indexing BRC-20 for block {:?}: {:?}, waiting a minute", block_number, err);
+                pipeline.reset(block_number);
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                continue;
+              }
+            };
+          }
+          let t7 = Instant::now();
 
-          // 6. Commit transaction
+          // 6. Queue a notification for API servers, postgres only delivers it if the transaction commits
+          if let Err(err) = events::notify_block_committed(&deadpool_tx, block_number, extracted.block_hash).await {
+            log::info!("Error queuing block notification for block {:?}: {:?}, waiting a minute", block_number, err);
+            pipeline.reset(block_number);
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            continue;
+          }
+
+          // 7. Commit transaction
           match deadpool_tx.commit().await {
             Ok(_) => {},
             Err(err) => {
               log::info!("Error committing transaction for block {:?}: {:?}, waiting a minute", block_number, err);
+              pipeline.reset(block_number);
               tokio::time::sleep(Duration::from_secs(60)).await;
               continue;
             }
           };
-          let t6 = Instant::now();
+          let t8 = Instant::now();
 
-          // 7. Increment block number
-          log::info!("Indexed block: {:?} - Height check: {:?} - Block stats: {:?} - Runes: {:?} - Inscriptions: {:?} - Transfers: {:?} - Commit: {:?} - Total: {:?}",
+          // 8. Increment block number
+          log::info!("Indexed block: {:?} - Extraction wait: {:?} - Height check: {:?} - Block stats: {:?} - Runes: {:?} - Inscriptions: {:?} - Transfers: {:?} - BRC-20: {:?} - Commit: {:?} - Total: {:?}",
             block_number,
             t1.duration_since(t0),
             t2.duration_since(t1),
@@ -1569,8 +2041,21 @@ impl Vermilion {
             t4.duration_since(t3),
             t5.duration_since(t4),
             t6.duration_since(t5),
-            t6.duration_since(t0)
+            t7.duration_since(t6),
+            t8.duration_since(t7),
+            t8.duration_since(t0)
           );
+          METRICS.block_indexed(&[
+            ("extraction_wait", t1.duration_since(t0)),
+            ("height_check", t2.duration_since(t1)),
+            ("block_stats", t3.duration_since(t2)),
+            ("runes", t4.duration_since(t3)),
+            ("inscriptions", t5.duration_since(t4)),
+            ("transfers", t6.duration_since(t5)),
+            ("brc20", t7.duration_since(t6)),
+            ("commit", t8.duration_since(t7)),
+            ("total", t8.duration_since(t0)),
+          ]);
           let trigger_timings = match Self::get_trigger_timing_log(deadpool.clone()).await {
             Ok(timings) => timings,
             Err(err) => {
@@ -1581,6 +2066,7 @@ impl Vermilion {
           // Convert trigger timings to format for condensed logging
           let trigger_timing_vec: Vec<(String, Duration)> = trigger_timings.into_iter().map(|timing| {
             let duration = Duration::from_micros(timing.5 as u64);
+            METRICS.observe_trigger_step(&timing.0, &timing.2, duration);
             let name = format!("{}.{}: {}", timing.0, timing.1, timing.2);
             (name, duration)
           }).collect();
@@ -1604,7 +2090,36 @@ impl Vermilion {
     return block_indexer_thread;
   }
 
-  async fn process_blockstats(index: Arc<Index>, tx: &deadpool_postgres::Transaction<'_>, block_number: u32) -> anyhow::Result<()> {
+  // Reads everything needed to index a block without touching postgres. The block hash is taken first,
+  // so a reorg in the index while extracting shows up as a hash mismatch before commit.
+  async fn extract_block(index: Arc<Index>, settings: Settings, fetcher: Arc<Fetcher>, block_number: u32) -> anyhow::Result<ExtractedBlock> {
+    let block_hash = index.block_hash(Some(block_number))
+      .with_context(|| format!("Failed to get block hash for {}", block_number))?
+      .ok_or_else(|| anyhow::anyhow!("No block hash found for block {}", block_number))?;
+    let blockstats = Self::extract_blockstats(index.clone(), block_number)?;
+    let runes = if block_number >= settings.first_rune_height() || block_number == 0 {
+      Some(extract_runes(index.clone(), settings.clone(), block_number)?)
+    } else {
+      None
+    };
+    let (inscriptions, transfers) = if block_number >= settings.first_inscription_height() {
+      (
+        Some(Self::extract_inscriptions(index.clone(), settings.clone(), block_number)?),
+        Some(Self::extract_transfers(index, settings, &fetcher, block_number).await?),
+      )
+    } else {
+      (None, None)
+    };
+    Ok(ExtractedBlock {
+      block_hash,
+      blockstats,
+      runes,
+      inscriptions,
+      transfers,
+    })
+  }
+
+  fn extract_blockstats(index: Arc<Index>, block_number: u32) -> anyhow::Result<BlockStats> {
     let blockstat_result = index.get_block_stats(block_number as u64)
       .with_context(|| format!("Failed to get blockstats for {}", block_number))?
       .ok_or_else(|| anyhow::anyhow!("No blockstats found for block {}", block_number))?;
@@ -1627,11 +2142,7 @@ impl Vermilion {
       max_fee: blockstat_result.max_fee_rate.map(|y| y.to_sat() as i64),
       average_fee: blockstat_result.fee_rate_percentiles.map(|y| y.fr_50th.to_sat() as i64),
     };
-    let blockstats = vec![blockstat];
-    Self::bulk_insert_blockstats(&tx, blockstats.clone()).await
-      .map_err(|err| anyhow::anyhow!("Failed to insert blockstats for block {}: {}",block_number, err))?;
-
-    Ok(())
+    Ok(blockstat)
   }
 
   async fn find_last_consistent_block(index: Arc<Index>, pool: deadpool_postgres::Pool<>, block_number: u32) -> anyhow::Result<u32> {
@@ -1666,47 +2177,121 @@ impl Vermilion {
   }
 
   async fn handle_reorg(pool: deadpool_postgres::Pool<>, last_good_block: u32) -> anyhow::Result<()> {
-    // 1. tables to rollback: rest should be fine
+    // 1. Blocks near the tip are rolled back from the undo journal, which restores every table
+    // written in the block transaction (including trigger maintained totals and summaries) exactly.
+    let mut conn = pool.get().await?;
+    let tx = conn.transaction().await?;
+    let undone = undo_journal::rollback(&tx, last_good_block).await?;
+    log::info!("Undo journal reverted {} row changes after block {}", undone, last_good_block);
+    // 2. Anything left above last_good_block was indexed without a journal (too deep at the time, or before
+    // the journal existed), so fall back to deleting by height and let the triggers reverse what they can:
     // ordinals
     // - editions
     // - sat_metadata (SKIP - this data is immutable)
     // - satributes (SKIP - this data is immutable)
     // - inscription_galleries
+    // - bitmaps
     // ordinals_full_t
     // runes
+    // rune_balances (spends are reverted, not deleted)
+    // rune_transfers
     // transfers
+    // sales
+    // collection_history and collection_history_days (days are rolled up again from the remaining blocks)
+    // collection_holdings and collection_holder_counts (recounted from the restored addresses)
+    // brc20_events (brc20_tickers and brc20_balances are rebuilt from the remaining events)
     // inscription_blockstats
     // blockstats
     // collections (SKIP - ME is the source of truth)
-    // 2. aggregate tables to refresh:
+    let unjournaled = tx.query_one("SELECT EXISTS (SELECT 1 FROM blockstats WHERE block_number > $1)", &[&(last_good_block as i64)]).await?;
+    if unjournaled.get::<_, bool>(0) {
+      log::warn!("Blocks after {} were indexed without an undo journal, rolling back by height", last_good_block);
+      tx.execute("DELETE FROM blockstats WHERE block_number > $1", &[&(last_good_block as i64)]).await?;
+      tx.execute("DELETE FROM inscription_blockstats WHERE block_number > $1", &[&(last_good_block as i64)]).await?;
+      tx.execute("DELETE FROM runes WHERE block > $1", &[&(last_good_block as i64)]).await?;
+      rollback_rune_activity(&tx, last_good_block).await?;
+      tx.execute("DELETE FROM ordinals_full_t WHERE genesis_height > $1", &[&(last_good_block as i64)]).await?;
+      tx.execute("DELETE FROM transfers WHERE block_number > $1", &[&(last_good_block as i64)]).await?;
+      tx.execute("DELETE FROM sales WHERE block_number > $1", &[&i64::from(last_good_block)]).await?;
+      tx.execute("DELETE FROM collection_history WHERE block_number > $1", &[&i64::from(last_good_block)]).await?;
+      brc20::rollback_brc20(&tx, last_good_block).await?;
+      tx.execute("DELETE FROM editions WHERE id IN (SELECT id from ordinals WHERE genesis_height > $1)", &[&(last_good_block as i64)]).await?;
+      tx.execute("DELETE FROM inscription_galleries WHERE gallery_id IN (SELECT id from ordinals WHERE genesis_height > $1)", &[&(last_good_block as i64)]).await?;
+      tx.execute("DELETE FROM bitmaps WHERE genesis_height > $1", &[&i64::from(last_good_block)]).await?;
+      tx.execute("DELETE FROM ordinals WHERE genesis_height > $1", &[&(last_good_block as i64)]).await?;
+      collection_history::rebuild_collection_holders(&tx, None).await?;
+      collection_history::roll_up_collection_days(&tx, None).await?;
+    }
+    // 3. Weights are recomputed from the restored tables
     // update_trending_weights
-    // update_discover_weights (skipped - too long to recalculate)
+    // update_discover_weights (after commit, it rebuilds the table and swaps it in, so it doesn't need to hold the reorg open)
     // update_collection_summary (skipped - ME is the source of truth)
-    let mut conn = pool.get().await?;
-    let tx = conn.transaction().await?;
-    tx.execute("DELETE FROM blockstats WHERE block_number > $1", &[&(last_good_block as i64)]).await?;
-    tx.execute("DELETE FROM inscription_blockstats WHERE block_number > $1", &[&(last_good_block as i64)]).await?;
-    tx.execute("DELETE FROM runes WHERE block > $1", &[&(last_good_block as i64)]).await?;
-    tx.execute("DELETE FROM ordinals_full_t WHERE genesis_height > $1", &[&(last_good_block as i64)]).await?;
-    tx.execute("DELETE FROM transfers WHERE block_number > $1", &[&(last_good_block as i64)]).await?;
-    tx.execute("DELETE FROM editions WHERE id IN (SELECT id from ordinals WHERE genesis_height > $1)", &[&(last_good_block as i64)]).await?;
-    tx.execute("DELETE FROM inscription_galleries WHERE gallery_id IN (SELECT id from ordinals WHERE genesis_height > $1)", &[&(last_good_block as i64)]).await?;
-    tx.execute("DELETE FROM ordinals WHERE genesis_height > $1", &[&(last_good_block as i64)]).await?;
     tx.execute("CALL update_trending_weights()", &[]).await?;
+    events::notify_reorg(&tx, last_good_block).await?;
     tx.commit().await?;
+    let start = Instant::now();
+    match conn.execute("CALL update_discover_weights()", &[]).await {
+      Ok(_) => log::info!("Discover weights update after reorg completed in {:?}", start.elapsed()),
+      // The reorg itself is committed, stale weights only skew discover until the next update
+      Err(err) => log::warn!("Error updating discover weights after reorg: {:?}", err),
+    }
     Ok(())
   }
 
-  async fn process_transfers(index: Arc<Index>, deadpool_tx: &deadpool_postgres::Transaction<'_>, settings: Settings, fetcher: &Fetcher, block_number: u32) -> anyhow::Result<()> {
+  fn get_sighash_byte(txin: &TxIn) -> Option<u8> {
+    if txin.witness.is_empty() {
+      // legacy inputs carry the signature as the first push of the script_sig
+      return match txin.script_sig.instructions().next() {
+        Some(Ok(instruction)) => instruction.push_bytes().and_then(|bytes| bytes.as_bytes().last().cloned()),
+        _ => None,
+      };
+    }
+    // segwit inputs (including p2sh wrapped ones) carry it in the witness, ignoring a trailing taproot annex
+    let mut elements: Vec<&[u8]> = txin.witness.iter().collect();
+    if elements.len() > 1 && elements.last().and_then(|element| element.first()) == Some(&0x50) {
+      elements.pop();
+    }
+    match elements.as_slice() {
+      // p2tr key path: 64 byte schnorr sig is SIGHASH_DEFAULT, 65 bytes has an explicit sighash byte
+      [sig] if sig.len() == 64 => Some(0x00),
+      [sig] if sig.len() == 65 => sig.last().cloned(),
+      // p2wpkh and p2sh-p2wpkh: DER signature followed by a compressed pubkey
+      [sig, pubkey] if pubkey.len() == 33 && sig.len() >= 9 && sig.len() <= 73 && sig[0] == 0x30 => sig.last().cloned(),
+      _ => None,
+    }
+  }
+
+  // Index of the output paying the seller of the inscription in input_index, if it was sold
+  fn get_sale_output(tx: &Transaction, input_index: usize, prev_postage: u64) -> Option<usize> {
+    // Splitting an ordinal off a large UTXO isn't a sale
+    if prev_postage > 20000 {
+      return None;
+    }
+    let vout = match Self::get_sighash_byte(&tx.input[input_index]) {
+      // IF SIG_SINGLE|ANYONECANPAY (0x83), Then price is on same output index as the ordinal's input index
+      Some(0x83) => input_index,
+      // IF SIG_ALL|ANYONECANPAY (0x81), Then price is on second output index (me snipe protection buys)
+      Some(0x81) => 1,
+      // SIG_ALL (0x01) gives shoddy data as it is the default (offers) - ignore for now
+      _ => return None,
+    };
+    (vout < tx.output.len()).then_some(vout)
+  }
+
+  fn get_sale_price(tx: &Transaction, input_index: usize, prev_postage: u64) -> u64 {
+    Self::get_sale_output(tx, input_index, prev_postage)
+      .map(|vout| tx.output[vout].value.to_sat())
+      .unwrap_or(0)
+  }
+
+  async fn extract_transfers(index: Arc<Index>, settings: Settings, fetcher: &Fetcher, block_number: u32) -> anyhow::Result<(Vec<Transfer>, Vec<Sale>)> {
     let t1 = Instant::now();
     let transfers = index.get_transfers_by_block_height(block_number)
       .with_context(|| format!("Failed to get transfers for block {}", block_number))?;
 
     if transfers.len() == 0 {
       log::debug!("No transfers found for block height: {:?}, skipping", block_number);
-      Self::bulk_insert_inscription_blockstats(&deadpool_tx, block_number as i64).await
-        .map_err(|err| anyhow::anyhow!("Failed to insert inscription blockstats for block {}: {}", block_number, err))?;
-      return Ok(());
+      return Ok((Vec::new(), Vec::new()));
     }
     let t2 = Instant::now();
     let mut tx_id_list = transfers.clone().into_iter().map(|(_id, _tx_offset, _,satpoint)| satpoint.outpoint.txid).collect::<Vec<_>>();
@@ -1756,6 +2341,7 @@ impl Vermilion {
 
     let t3 = Instant::now();
     let mut seq_point_transfer_details = Vec::new();
+    let mut sold_inputs = Vec::new();
     for (sequence_number, tx_offset, old_satpoint, satpoint) in transfers {
       //1. Get ordinal receive address
       let (address, prev_address, price, tx_fee, tx_size, burn_metadata) = if satpoint.outpoint == unbound_outpoint() && (old_satpoint.outpoint == unbound_outpoint() || old_satpoint.outpoint.is_null()) {
@@ -1843,60 +2429,12 @@ impl Vermilion {
         let mut price = 0;
         for (input_index, txin) in tx.input.iter().enumerate() {
           if txin.previous_output == old_satpoint.outpoint {
-            // sig is typically the first instruction in the script_sig
-            // but can also be in the witness (where it is also first)
-            let first_script_instruction = txin.script_sig.instructions().next();
-            let last_sig_byte = match first_script_instruction {
-              Some(Ok(first_script_instruction)) => {
-                first_script_instruction.push_bytes().and_then(|x| x.as_bytes().last().cloned())
-              },
-              Some(Err(_)) => None,
-              None => txin.witness.nth(0).and_then(|bytes| bytes.last().cloned())
-            };
-            price = match last_sig_byte {
-              Some(last_sig_byte) => {
-                // IF SIG_SINGLE|ANYONECANPAY (0x83), Then price is on same output index as the ordinal's input index
-                if last_sig_byte == 0x83 {
-                  price = match tx.output.get(input_index) {
-                    Some(output) => {
-                      //Check previous tx postage value to see if it's splitting off an ordinal within a large UTXO
-                      let prev_tx_value = prev_tx.output.get(old_satpoint.outpoint.vout as usize).unwrap().value;
-                      if prev_tx_value.to_sat() > 20000 {
-                        0
-                      } else {
-                        output.value.to_sat()
-                      }
-                    },
-                    None => 0
-                  };
-                }
-                // IF SIG_ALL|ANYONECANPAY (0x81), Then price is on second output index (me snipe protection buys)
-                else if last_sig_byte == 0x81 {
-                  price = match tx.output.get(1) {
-                    Some(output) => {
-                      //Checking postage value is less than 20k sats, just for a sanity check (not necessary like in the 0x83 case)
-                      let prev_tx_value = prev_tx.output.get(old_satpoint.outpoint.vout as usize).unwrap().value;
-                      if prev_tx_value.to_sat() > 20000 {
-                        0
-                      } else {
-                        output.value.to_sat()
-                      }
-                    },
-                    None => 0
-                  };
-                }
-                // This gives shoddy data as sig_all is default - ignore for now
-                // IF SIG_ALL (0x01), Then price is on second output index (for offers)
-                // } else if last_sig_byte == &0x01 {
-                //   price = match tx.output.clone().into_iter().nth(1) {
-                //     Some(output) => output.value,
-                //     None => 0
-                //   };
-                // }
-                price
-              },
-              None => 0
-            };
+            //Check previous tx postage value to see if it's splitting off an ordinal within a large UTXO
+            let prev_postage = prev_tx.output.get(old_satpoint.outpoint.vout as usize).unwrap().value.to_sat();
+            price = Self::get_sale_price(&tx, input_index, prev_postage);
+            if price > 0 {
+              sold_inputs.push(SoldInput { transfer: seq_point_transfer_details.len(), input_index });
+            }
           }
         }
 
@@ -1940,36 +2478,57 @@ impl Vermilion {
       transfer_vec.push(transfer);
     }
     let t5 = Instant::now();
-    Self::bulk_insert_transfers(&deadpool_tx, transfer_vec.clone()).await
-      .map_err(|err| anyhow::anyhow!("Failed to insert transfers for block {}: {}", block_number, err))?;
+    let sales = sales::extract_sales(settings.chain(), fetcher, &tx_map, &transfer_vec, sold_inputs).await
+      .with_context(|| format!("Failed to get sales for block {}", block_number))?;
     let t6 = Instant::now();
-    Self::bulk_insert_addresses(&deadpool_tx, transfer_vec).await
-      .map_err(|err| anyhow::anyhow!("Failed to insert addresses for block {}: {}", block_number, err))?;
-    let t7 = Instant::now();
-    Self::bulk_insert_inscription_blockstats(&deadpool_tx, block_number as i64).await
-      .map_err(|err| anyhow::anyhow!("Failed to insert inscription blockstats for block {}: {}", block_number, err))?;
-    let t8 = Instant::now();
-    log::info!("Transfer indexer: Indexed block: {:?}", block_number);
-    Self::log_timings_condensed("Transfer processing", vec![
+    Self::log_timings_condensed("Transfer extraction", vec![
       ("Get transfers", t2.duration_since(t1)),
       ("Get txs", t3.duration_since(t2)),
       ("Get addresses", t4.duration_since(t3)),
       ("Create Vec", t5.duration_since(t4)),
-      ("Insert transfers", t6.duration_since(t5)),
-      ("Insert addresses", t7.duration_since(t6)),
-      ("Insert blockstats", t8.duration_since(t7))
+      ("Get sales", t6.duration_since(t5))
+    ], Duration::from_secs(1));
+    Ok((transfer_vec, sales))
+  }
+
+  async fn insert_transfers(deadpool_tx: &deadpool_postgres::Transaction<'_>, transfer_vec: Vec<Transfer>, sales: Vec<Sale>, block_number: u32) -> anyhow::Result<()> {
+    let t1 = Instant::now();
+    if !transfer_vec.is_empty() {
+      Self::bulk_insert_transfers(&deadpool_tx, transfer_vec.clone()).await
+        .map_err(|err| anyhow::anyhow!("Failed to insert transfers for block {}: {}", block_number, err))?;
+    }
+    if !sales.is_empty() {
+      sales::bulk_insert_sales(&deadpool_tx, sales).await
+        .with_context(|| format!("Failed to insert sales for block {}", block_number))?;
+    }
+    let t2 = Instant::now();
+    if !transfer_vec.is_empty() {
+      Self::bulk_insert_addresses(&deadpool_tx, transfer_vec).await
+        .map_err(|err| anyhow::anyhow!("Failed to insert addresses for block {}: {}", block_number, err))?;
+      collection_history::update_collection_history(&deadpool_tx, i64::from(block_number)).await
+        .with_context(|| format!("Failed to update collection history for block {}", block_number))?;
+    }
+    let t3 = Instant::now();
+    Self::bulk_insert_inscription_blockstats(&deadpool_tx, block_number as i64).await
+      .map_err(|err| anyhow::anyhow!("Failed to insert inscription blockstats for block {}: {}", block_number, err))?;
+    let t4 = Instant::now();
+    log::info!("Transfer indexer: Indexed block: {:?}", block_number);
+    Self::log_timings_condensed("Transfer processing", vec![
+      ("Insert transfers", t2.duration_since(t1)),
+      ("Insert addresses", t3.duration_since(t2)),
+      ("Insert blockstats", t4.duration_since(t3))
     ], Duration::from_secs(1));
     Ok(())
   }
 
-  async fn process_inscriptions(index: Arc<Index>, deadpool_tx: &deadpool_postgres::Transaction<'_>, settings: Settings, block_number: u32) -> anyhow::Result<()> {
+  fn extract_inscriptions(index: Arc<Index>, settings: Settings, block_number: u32) -> anyhow::Result<InscriptionBlock> {
     // 1. Get ids
     let t0 = Instant::now();
     let inscription_ids = index.get_inscriptions_in_block(block_number)
       .with_context(|| format!("Failed to get inscriptions for block {}", block_number))?;
     if inscription_ids.is_empty() {
       log::info!("No inscriptions found for block height: {:?}, skipping", block_number);
-      return Ok(());
+      return Ok(InscriptionBlock::default());
     }
 
     //2. Get inscriptions
@@ -2043,23 +2602,7 @@ impl Vermilion {
       gallery_vec.append(&mut gallery);
     }
 
-    //4. Insert metadata
-    let t3 = Instant::now();
-    Self::bulk_insert_metadata(&deadpool_tx, metadata_vec.clone()).await
-      .map_err(|err| anyhow::anyhow!("Failed to insert metadata for block {}: {}", block_number, err))?;
-
-    //5. Insert editions
-    let t4 = Instant::now();
-    Self::bulk_insert_editions(&deadpool_tx, metadata_vec.clone()).await
-      .map_err(|err| anyhow::anyhow!("Failed to insert editions for block {}: {}", block_number, err))?;
-
-    //6. Insert sat metadata
-    let t5 = Instant::now();
-    Self::bulk_insert_sat_metadata(&deadpool_tx, sat_metadata_vec.clone()).await
-      .map_err(|err| anyhow::anyhow!("Failed to insert sat metadata for block {}: {}", block_number, err))?;
-
-    //7. Insert satributes
-    let t6 = Instant::now();
+    //4. Get satributes
     let mut satributes_vec = Vec::new();
     for sat_metadata in sat_metadata_vec.iter() {
       let sat = Sat(sat_metadata.sat as u64);
@@ -2079,16 +2622,9 @@ impl Vermilion {
         satributes_vec.push(rarity);
       }
     }
-    Self::bulk_insert_satributes(&deadpool_tx, satributes_vec).await
-      .map_err(|err| anyhow::anyhow!("Failed to insert satributes for block {}: {}", block_number, err))?;
 
-    //8. Insert galleries
-    let t7 = Instant::now();
-    Self::bulk_insert_gallery_metadata(&deadpool_tx, gallery_vec).await
-      .map_err(|err| anyhow::anyhow!("Failed to insert galleries for block {}: {}", block_number, err))?;
-
-    //9. Upload content to db
-    let t8 = Instant::now();
+    //5. Get content
+    let t3 = Instant::now();
     let sequence_numbers = metadata_vec.iter().map(|m| m.sequence_number).collect::<Vec<_>>();
     let number_inscriptions: Vec<_> = sequence_numbers.clone().into_iter()
       .zip(inscriptions.into_iter())
@@ -2111,24 +2647,76 @@ impl Vermilion {
         content_vec.push((number as i64, content_blob));
       }
     }
-    Self::bulk_insert_content(&deadpool_tx, content_vec).await
-      .map_err(|err| anyhow::anyhow!("Failed to insert content for block {}: {}", block_number, err))?;
 
-    //10. Log timings
-    let t9 = Instant::now();
-    let first_number = sequence_numbers.first().unwrap_or(&0);
-    let last_number = sequence_numbers.last().unwrap_or(&0);
-    log::info!("Inscription indexer: Indexed block: {:?}, Sequence numbers: {}-{}", block_number, first_number, last_number);
-    Self::log_timings_condensed("Inscription processing", vec![
+    //6. Log timings
+    let t4 = Instant::now();
+    Self::log_timings_condensed("Inscription extraction", vec![
       ("Get ids", t1.duration_since(t0)),
       ("Get inscriptions", t2.duration_since(t1)),
       ("Get metadata", t3.duration_since(t2)),
-      ("Insert metadata", t4.duration_since(t3)),
-      ("Insert editions", t5.duration_since(t4)),
-      ("Insert sat metadata", t6.duration_since(t5)),
-      ("Insert satributes", t7.duration_since(t6)),
-      ("Insert galleries", t8.duration_since(t7)),
-      ("Upload content", t9.duration_since(t8))
+      ("Get content", t4.duration_since(t3))
+    ], Duration::from_secs(1));
+    Ok(InscriptionBlock {
+      metadata: metadata_vec,
+      sat_metadata: sat_metadata_vec,
+      satributes: satributes_vec,
+      galleries: gallery_vec,
+      content: content_vec,
+    })
+  }
+
+  async fn insert_inscriptions(deadpool_tx: &deadpool_postgres::Transaction<'_>, content_store: &ContentStore, inscriptions: InscriptionBlock, block_number: u32) -> anyhow::Result<()> {
+    if inscriptions.metadata.is_empty() {
+      return Ok(());
+    }
+    //1. Insert metadata
+    let t0 = Instant::now();
+    Self::bulk_insert_metadata(&deadpool_tx, inscriptions.metadata.clone()).await
+      .map_err(|err| anyhow::anyhow!("Failed to insert metadata for block {}: {}", block_number, err))?;
+
+    //2. Insert editions
+    let t1 = Instant::now();
+    Self::bulk_insert_editions(&deadpool_tx, inscriptions.metadata.clone()).await
+      .map_err(|err| anyhow::anyhow!("Failed to insert editions for block {}: {}", block_number, err))?;
+
+    //3. Insert sat metadata
+    let t2 = Instant::now();
+    Self::bulk_insert_sat_metadata(&deadpool_tx, inscriptions.sat_metadata).await
+      .map_err(|err| anyhow::anyhow!("Failed to insert sat metadata for block {}: {}", block_number, err))?;
+
+    //4. Insert satributes
+    let t3 = Instant::now();
+    Self::bulk_insert_satributes(&deadpool_tx, inscriptions.satributes).await
+      .map_err(|err| anyhow::anyhow!("Failed to insert satributes for block {}: {}", block_number, err))?;
+
+    //5. Insert galleries
+    let t4 = Instant::now();
+    Self::bulk_insert_gallery_metadata(&deadpool_tx, inscriptions.galleries).await
+      .map_err(|err| anyhow::anyhow!("Failed to insert galleries for block {}: {}", block_number, err))?;
+
+    //6. Upload content to db
+    let t5 = Instant::now();
+    Self::bulk_insert_content(&deadpool_tx, content_store, inscriptions.content).await
+      .map_err(|err| anyhow::anyhow!("Failed to insert content for block {}: {}", block_number, err))?;
+
+    //7. Register bitmap claims
+    let t6 = Instant::now();
+    bitmaps::register_bitmaps(&deadpool_tx, block_number).await
+      .map_err(|err| anyhow::anyhow!("Failed to register bitmaps for block {}: {}", block_number, err))?;
+
+    //8. Log timings
+    let t7 = Instant::now();
+    let first_number = inscriptions.metadata.first().map(|m| m.sequence_number).unwrap_or(0);
+    let last_number = inscriptions.metadata.last().map(|m| m.sequence_number).unwrap_or(0);
+    log::info!("Inscription indexer: Indexed block: {:?}, Sequence numbers: {}-{}", block_number, first_number, last_number);
+    Self::log_timings_condensed("Inscription processing", vec![
+      ("Insert metadata", t1.duration_since(t0)),
+      ("Insert editions", t2.duration_since(t1)),
+      ("Insert sat metadata", t3.duration_since(t2)),
+      ("Insert satributes", t4.duration_since(t3)),
+      ("Insert galleries", t5.duration_since(t4)),
+      ("Upload content", t6.duration_since(t5)),
+      ("Register bitmaps", t7.duration_since(t6))
     ], Duration::from_secs(1));
     Ok(())
   }
@@ -2145,100 +2733,57 @@ impl Vermilion {
     Ok(collections)
 }
 
-  async fn get_all_collection_metadata(settings: Settings) -> Result<Vec<CollectionMetadata>, Box<dyn std::error::Error>> {
+  async fn get_all_collection_metadata(client: &MagicEdenClient) -> Result<Vec<CollectionMetadata>, Box<dyn std::error::Error>> {
     let mut collections = Vec::new();
 
-    let url = format!("https://api-mainnet.magiceden.dev/v2/ord/btc/collections");
-    let client = reqwest::Client::new();
-    let mut headers = reqwest::header::HeaderMap::new();
-    let token = settings.magiceden_api_key().map(|s| s.to_string()).ok_or("No Magic Eden Api key found")?;
-    headers.insert(reqwest::header::AUTHORIZATION, format!("Bearer {}", token).parse().unwrap());
-
-    for attempt in 0..10 {
-      // break if ctrl-c is received
-      if SHUTTING_DOWN.load(atomic::Ordering::Relaxed) {
-        return Err("Shutting down".into());
-      }
-      let response = client.get(&url).headers(headers.clone()).send().await?;
-
-      if response.status() == 429 {
-        log::info!("Rate limit exceeded getting all collections: {}, retrying in {} minutes", response.status(), attempt + 1);
-        log::info!("{}", response.text().await?);
-        tokio::time::sleep(std::time::Duration::from_secs(60*(attempt+1))).await;
-        continue;
-      }
-
-      if response.status() != 200 {
-        if attempt == 2 {
-          return Err(format!("Failed to fetch all collection metadata after 3 attempts").into());
+    // Fails over to recently traded collections after a few attempts, rather than backing off for the full retry policy
+    let client = client.with_retry(RetryPolicy { max_retries: 2, ..client.retry().clone() });
+    let data: Vec<JsonValue> = serde_json::from_value(client.api_get("v2/ord/btc/collections", &[], "all collections").await?)?;
+    for item in data {
+      if let Some(symbol) = item["symbol"].as_str() {
+        if symbol.starts_with("domain_dot") {
+          continue;
         }
-        println!("Error getting all collections: {}, retrying in {} minutes", response.status(), attempt + 1);
-        println!("{}", response.text().await?);
-        tokio::time::sleep(std::time::Duration::from_secs(60*(attempt+1))).await;
-        continue;
-      }
-
-      let data: Vec<JsonValue> = response.json().await?;
-
-      for item in data {
-        if let Some(symbol) = item["symbol"].as_str() {
-          if symbol.starts_with("domain_dot") {
-            continue;
-          }
-          if symbol.starts_with("brc20_") {
-            continue;
-          }
-          if symbol == "btc-name" {
-            continue;
-          }
-          if symbol == "rare-sats" || symbol == "uncommons" || symbol == "cursed" {
-            continue;
-          }
-          if symbol == "sub-100" || symbol == "sub-1k" || symbol == "sub-5k"|| symbol == "sub-10k" || symbol == "sub-100k"  {
-            continue;
-          }
-          let metadata: CollectionMetadata = serde_json::from_value(item.clone())?;
-          collections.push(metadata);
+        if symbol.starts_with("brc20_") {
+          continue;
+        }
+        if symbol == "btc-name" {
+          continue;
+        }
+        if symbol == "rare-sats" || symbol == "uncommons" || symbol == "cursed" {
+          continue;
+        }
+        if symbol == "sub-100" || symbol == "sub-1k" || symbol == "sub-5k"|| symbol == "sub-10k" || symbol == "sub-100k"  {
+          continue;
         }
+        let metadata: CollectionMetadata = serde_json::from_value(item.clone())?;
+        collections.push(metadata);
       }
-      log::info!("{} collections detected", collections.len());
-      return Ok(collections);
     }
-
-    Err(format!("Failed to fetch all collection metadata after 10 attempts").into())
+    log::info!("{} collections detected", collections.len());
+    Ok(collections)
   }
 
-  async fn get_recently_traded_collections() -> Result<Vec<String>, Box<dyn std::error::Error>> {
+  async fn get_recently_traded_collections(client: &MagicEdenClient) -> Result<Vec<String>, Box<dyn std::error::Error>> {
     let mut collections = Vec::new();
     let mut offset = 0;
 
-    for _attempt in 0..10  {
+    for _page in 0..10  {
       // break if ctrl-c is received
       if SHUTTING_DOWN.load(atomic::Ordering::Relaxed) {
         return Err("Shutting down".into());
       }
-      let url = format!(
-        "https://stats-mainnet.magiceden.io/collection_stats/search/bitcoin?window=30d&limit=1000&offset={}&sort=totalVolume&direction=desc",
-        offset
-      );
-
-      let response = reqwest::get(&url).await?;
-
-      if response.status() == 429 {
-        log::info!("Error getting recently traded collections: {}", response.status());
-        log::info!("{}", response.text().await?);
-        log::info!("Rate limit exceeded, retrying in 2 minutes");
-        tokio::time::sleep(std::time::Duration::from_secs(120)).await;
-        continue;
-      }
-
-      if response.status() != 200 {
-        println!("Error getting recently traded collections: {}", response.status());
-        println!("{}", response.text().await?);
-        break;
-      }
-
-      let data: Vec<JsonValue> = response.json().await?;
+      let offset_param = offset.to_string();
+      let query = [
+        ("window", "30d"),
+        ("limit", "1000"),
+        ("offset", offset_param.as_str()),
+        ("sort", "totalVolume"),
+        ("direction", "desc"),
+      ];
+      let response = client.stats_get("collection_stats/search/bitcoin", &query, "recently traded collections").await?;
+
+      let data: Vec<JsonValue> = serde_json::from_value(response)?;
       if data.is_empty() {
         break;
       }
@@ -2272,74 +2817,34 @@ impl Vermilion {
     Ok(collections)
 }
 
-  async fn get_collection_metadata(settings: Settings, symbol: &str) -> Result<Option<CollectionMetadata>, Box<dyn std::error::Error>> {
-    let url = format!(
-        "https://api-mainnet.magiceden.dev/v2/ord/btc/tokens?limit=20&offset=0&collectionSymbol={}",
-        symbol
-    );
-    let client = reqwest::Client::new();
-    let mut headers = reqwest::header::HeaderMap::new();
-    let token = settings.magiceden_api_key().map(|s| s.to_string()).ok_or("No Magic Eden Api key found")?;
-    headers.insert(reqwest::header::AUTHORIZATION, format!("Bearer {}", token).parse().unwrap());
-
-    for attempt in 0..10 {
-      // break if ctrl-c is received
-      if SHUTTING_DOWN.load(atomic::Ordering::Relaxed) {
-        return Err("Shutting down".into());
-      }
-      let request_start_time = Instant::now();
-      let response = client.get(&url).headers(headers.clone()).send().await?;
-      log::debug!(
-        "Got metadata for {} in {:.2} seconds",
-        symbol,
-        request_start_time.elapsed().as_secs_f64()
-      );
-
-      if response.status() == 429 {
-        log::info!("Rate limit exceeded getting collection metadata for {}: {}", symbol, response.status());
-        log::info!("{}", response.text().await?);
-        log::info!("Retrying in {} minutes", attempt+1);
-        tokio::time::sleep(std::time::Duration::from_secs(60*(attempt+1))).await;
-        continue;
-      }
-
-      if response.status() != 200 {
-        log::info!("Error getting collection metadata for {}: {}", symbol, response.status());
-        log::info!("{}", response.text().await?);
-        log::info!("Retrying in {} minutes", attempt+1);
-        tokio::time::sleep(std::time::Duration::from_secs(60*(attempt+1))).await;
-        continue;
-      }
-
-      let data: JsonValue = response.json().await?;
-      if let Some(tokens) = data["tokens"].as_array() {
-        if let Some(first_token) = tokens.first() {
-          if let Some(item_type) = first_token.get("itemType") {
-            if item_type.as_str() == Some("UTXO") {//skip rare-sats
-              return Ok(None);
-            }
-          }
-          if let Some(_domain) = first_token.get("domain") { //Skip domain collections
-            log::info!("Skipping domain collection: {}", symbol);
+  async fn get_collection_metadata(client: &MagicEdenClient, symbol: &str) -> Result<Option<CollectionMetadata>, Box<dyn std::error::Error>> {
+    let query = [("limit", "20"), ("offset", "0"), ("collectionSymbol", symbol)];
+    let data = client.api_get("v2/ord/btc/tokens", &query, &format!("collection metadata for {}", symbol)).await?;
+    if let Some(tokens) = data["tokens"].as_array() {
+      if let Some(first_token) = tokens.first() {
+        if let Some(item_type) = first_token.get("itemType") {
+          if item_type.as_str() == Some("UTXO") {//skip rare-sats
             return Ok(None);
           }
-          if let Some(collection) = first_token.get("collection") {
-            if let Some(_brc20) = collection.get("brc20") { //Skip brc20 collections
-              return Ok(None);
-            } else {
-              let metadata: CollectionMetadata = serde_json::from_value(collection.clone())?;
-              return Ok(Some(metadata));
-            }
+        }
+        if let Some(_domain) = first_token.get("domain") { //Skip domain collections
+          log::info!("Skipping domain collection: {}", symbol);
+          return Ok(None);
+        }
+        if let Some(collection) = first_token.get("collection") {
+          if let Some(_brc20) = collection.get("brc20") { //Skip brc20 collections
+            return Ok(None);
+          } else {
+            let metadata: CollectionMetadata = serde_json::from_value(collection.clone())?;
+            return Ok(Some(metadata));
           }
         }
       }
-      return Ok(None);
     }
-
-    Err(format!("Failed to fetch metadata after 5 attempts for {}", symbol).into())
+    Ok(None)
   }
 
-  async fn get_bulk_collection_metadata(settings: Settings, collections: Vec<String>, cached_collection_metadata: Vec<CollectionMetadata>) -> Result<Vec<CollectionMetadata>, Box<dyn std::error::Error>> {
+  async fn get_bulk_collection_metadata(client: &MagicEdenClient, collections: Vec<String>, cached_collection_metadata: Vec<CollectionMetadata>) -> Result<Vec<CollectionMetadata>, Box<dyn std::error::Error>> {
     let mut traded_metadata = Vec::new();
     println!("Getting metadata for traded collections: {}", collections.len());
     let mut t0 = Instant::now();
@@ -2352,7 +2857,7 @@ impl Vermilion {
         traded_metadata.push(metadata.clone());
         continue;
       }
-      if let Some(metadata) = Self::get_collection_metadata(settings.clone(), symbol).await? {
+      if let Some(metadata) = Self::get_collection_metadata(client, symbol).await? {
         traded_metadata.push(metadata);
       }
       if i % 100 == 0 {
@@ -2389,7 +2894,7 @@ impl Vermilion {
     Ok(collections)
   }
 
-  async fn get_new_collection_symbols(pool: deadpool_postgres::Pool, settings: Settings, traded_collections: Vec<CollectionMetadata>) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+  async fn get_new_collection_symbols(pool: deadpool_postgres::Pool, client: &MagicEdenClient, traded_collections: Vec<CollectionMetadata>) -> Result<Vec<String>, Box<dyn std::error::Error>> {
     let stored_collections = Self::get_stored_collection_metadata(pool.clone()).await?;
     //let traded_collections = Self::get_recently_traded_collection_metadata(settings.clone()).await?;
     let mut update_symbols = Vec::new();
@@ -2408,7 +2913,7 @@ impl Vermilion {
         } else {
           //compare actual supplies - update if different
           let stored_supply = Self::get_stored_collection_supply(pool.clone(), traded_collection.collection_symbol.clone()).await?;
-          if Self::is_me_supply_larger(settings.clone(), &traded_collection.collection_symbol, stored_supply as u64).await? {
+          if Self::is_me_supply_larger(client, &traded_collection.collection_symbol, stored_supply as u64).await? {
             log::info!("Detected supply larger than {} on ME for Symbol {}, {}/{} checked",stored_supply, traded_collection.collection_symbol, count, total);
             update_symbols.push(traded_collection.collection_symbol.clone());
           }
@@ -2422,36 +2927,29 @@ impl Vermilion {
     Ok(update_symbols)
   }
 
-  async fn get_tokens(settings: Settings, symbol: &str) -> Result<Vec<Collection>, Box<dyn std::error::Error>> {
-    let mut tokens = Vec::new();
-    let mut offset = 0;
+  // Pages are saved to collection_token_pages as they arrive, so an interrupted collection resumes from its last page
+  async fn get_tokens(pool: deadpool, client: &MagicEdenClient, symbol: &str) -> Result<Vec<Collection>, Box<dyn std::error::Error>> {
     let start_time = Instant::now();
-    let client = reqwest::Client::new();
-    let mut retry_count = 0;
+    let mut offset = Self::get_token_progress(pool.clone(), symbol).await?;
+    if offset > 0 {
+      log::info!("Resuming tokens for {} at offset {}", symbol, offset);
+    }
+    let mut too_many = false;
 
     loop {
       // break if ctrl-c is received
       if SHUTTING_DOWN.load(atomic::Ordering::Relaxed) {
         return Err("Shutting down".into());
       }
-      let url = format!(
-        "https://api-mainnet.magiceden.dev/v2/ord/btc/tokens?limit=100&offset={}&sortBy=inscriptionNumberAsc&collectionSymbol={}",
-        offset, symbol
-      );
-      let mut headers = reqwest::header::HeaderMap::new();
-      let token = settings.magiceden_api_key().map(|s| s.to_string()).ok_or("No Magic Eden Api key found")?;
-      headers.insert(reqwest::header::AUTHORIZATION, format!("Bearer {}", token).parse().unwrap());
-
+      let offset_param = offset.to_string();
+      let query = [
+        ("limit", "100"),
+        ("offset", offset_param.as_str()),
+        ("sortBy", "inscriptionNumberAsc"),
+        ("collectionSymbol", symbol),
+      ];
       let request_start_time = Instant::now();
-      let response = match client.get(&url).headers(headers).send().await {
-        Ok(resp) => resp,
-        Err(e) => {
-          println!("Error occurred: {}", e);
-          tokio::time::sleep(Duration::from_secs(5)).await;
-          continue;
-        }
-      };
-
+      let data = client.api_get("v2/ord/btc/tokens", &query, &format!("tokens for {}", symbol)).await?;
       log::info!(
         "Got 100 tokens for {} at offset {} in {:.2} seconds",
         symbol,
@@ -2459,59 +2957,40 @@ impl Vermilion {
         request_start_time.elapsed().as_secs_f64()
       );
 
-      if response.status() == 429 {
-        retry_count += 1;
-        log::info!("Error getting tokens for {}: {}", symbol, response.status());
-        log::info!("{}", response.text().await?);
-        log::info!("Rate limit exceeded, retrying in {} minutes", retry_count);
-        tokio::time::sleep(std::time::Duration::from_secs(60*(retry_count))).await;
-        continue;
-      }
-
-      if response.status() != 200 {
-        retry_count += 1;
-        if retry_count < 10 {
-          log::info!("Error getting tokens for {}: {}", symbol, response.status());
-          log::info!("{}", response.text().await?);
-          log::info!("Retrying in {} minutes", retry_count);
-          tokio::time::sleep(std::time::Duration::from_secs(60*(retry_count))).await;
-          continue;
-        }
-        log::info!("Error getting tokens for {}: {}", symbol, response.status());
-        log::info!("{}", response.text().await?);
-        return Err("Error occurred, 200 not returned".into());
-      }
-
-      let data: JsonValue = response.json().await?;
-      if let Some(tokens_data) = data.get("tokens") {
-        let new_tokens: Vec<Collection> = match tokens_data.as_array()
-          .ok_or("tokens is not an array")?
-          .iter()
-          .map(|token| serde_json::from_value(token.clone()))
-          .collect::<Result<_, _>>() {
-          Ok(tokens) => tokens,
-          Err(e) => {
-            log::info!("Failed to parse tokens response for {}: {}, response: {:?}", symbol, e, data);
-            return Err(format!("Failed to parse tokens for {}: {}", symbol, e).into());
-          }
-        };
-        tokens.extend(new_tokens.clone());
-        if new_tokens.len() < 100 {
-          break;
+      let Some(tokens_data) = data.get("tokens") else {
+        break;
+      };
+      let new_tokens: Vec<Collection> = match tokens_data.as_array()
+        .ok_or("tokens is not an array")?
+        .iter()
+        .map(|token| serde_json::from_value(token.clone()))
+        .collect::<Result<_, _>>() {
+        Ok(tokens) => tokens,
+        Err(e) => {
+          log::info!("Failed to parse tokens response for {}: {}, response: {:?}", symbol, e, data);
+          return Err(format!("Failed to parse tokens for {}: {}", symbol, e).into());
         }
-      } else {
+      };
+      offset += 100;
+      Self::insert_token_page(pool.clone(), symbol, &new_tokens, offset).await?;
+      if new_tokens.len() < 100 {
         break;
       }
-
-      retry_count = 0; // reset retry count on successful response
-      offset += 100;
       if offset > 25000 {
-        tokens = Vec::new();
         log::info!(">25k tokens for {} - returning nothing", symbol);
+        too_many = true;
         break;
       }
     }
 
+    let tokens = if too_many { Vec::new() } else { Self::get_token_pages(pool.clone(), symbol).await? };
+    // Nothing gets stored for these, so the next update starts from scratch
+    if tokens.is_empty() {
+      let mut conn = pool.get().await?;
+      let tx = conn.transaction().await?;
+      Self::remove_token_progress(&tx, symbol).await?;
+      tx.commit().await?;
+    }
     log::info!(
       "Got {} tokens for {} in {:.2} seconds",
       tokens.len(),
@@ -2521,57 +3000,18 @@ impl Vermilion {
     Ok(tokens)
   }
 
-  async fn is_me_supply_larger(settings: Settings, symbol: &str, stored_supply: u64) -> Result<bool, Box<dyn std::error::Error>> {
+  async fn is_me_supply_larger(client: &MagicEdenClient, symbol: &str, stored_supply: u64) -> Result<bool, Box<dyn std::error::Error>> {
     let expected_remainder = stored_supply % 20; // magic eden requires multiples of 20
-    let offset = stored_supply - expected_remainder;
-    let url = format!(
-      "https://api-mainnet.magiceden.dev/v2/ord/btc/tokens?limit=20&offset={}&collectionSymbol={}",
-      offset,
-      symbol
-    );
-    let mut headers = reqwest::header::HeaderMap::new();
-    let token = settings.magiceden_api_key().map(|s| s.to_string()).ok_or("No Magic Eden Api key found")?;
-    headers.insert(reqwest::header::AUTHORIZATION, format!("Bearer {}", token).parse().unwrap());
-    let client = reqwest::Client::new();
-    let mut retry_count = 0;
-    let response = loop {
-      let response = client.get(&url).headers(headers.clone()).send().await?;
-      if response.status() == 429 {
-        retry_count += 1;
-        log::info!("Rate limit exceeded getting supply for {}: {}", symbol, response.status());
-        log::info!("Rate limit exceeded, pausing for {} minutes", retry_count);
-        tokio::time::sleep(std::time::Duration::from_secs(retry_count*60)).await;
-        continue;
-      }
-      if response.status() != 200 {
-        retry_count += 1;
-        if retry_count < 10 {
-          log::info!("Error getting supply for {}: {}", symbol, response.status());
-          log::info!("{}", response.text().await?);
-          log::info!("Retrying in {} minutes", retry_count);
-          tokio::time::sleep(std::time::Duration::from_secs(60*(retry_count))).await;
-          continue;
-        }
-        println!("Error getting supply for {}: {}", symbol, response.status());
-        println!("{}", response.text().await?);
-        return Err("Error getting supply occurred, 200 not returned".into());
-      }
-
-      break response
-    };
-
-    let data: JsonValue = response.json().await?;
+    let offset = (stored_supply - expected_remainder).to_string();
+    let query = [("limit", "20"), ("offset", offset.as_str()), ("collectionSymbol", symbol)];
+    let data = client.api_get("v2/ord/btc/tokens", &query, &format!("supply for {}", symbol)).await?;
     let tokens_array = data.get("tokens").ok_or("tokens not found")?;
     let remainder_length = tokens_array.as_array().ok_or("tokens is not an array")?.len();
-    if remainder_length > expected_remainder as usize {
-      return Ok(true);
-    } else {
-      return Ok(false);
-    }
+    Ok(remainder_length > expected_remainder as usize)
   }
 
-  async fn update_all_tokens(pool: deadpool_postgres::Pool, settings: Settings, force_update: bool) -> Result<bool, Box<dyn std::error::Error>> {
-    let (all_collections, all_collection_metadata) =  match Self::get_all_collection_metadata(settings.clone()).await {
+  async fn update_all_tokens(pool: deadpool_postgres::Pool, client: &MagicEdenClient, force_update: bool) -> Result<bool, Box<dyn std::error::Error>> {
+    let (all_collections, all_collection_metadata) =  match Self::get_all_collection_metadata(client).await {
       Ok(collections) => {
         let all_collections = collections.clone().into_iter()
           .map(|item| item.collection_symbol)
@@ -2580,7 +3020,7 @@ impl Vermilion {
       },
       Err(e) => { // Sometimes this magiceden endpoint fails, so we fallback to recently traded collections
         log::error!("Failed to get all collections, getting recently traded collections instead: {}", e);
-        let recently_traded_collections = Self::get_recently_traded_collections().await?;
+        let recently_traded_collections = Self::get_recently_traded_collections(client).await?;
         (recently_traded_collections, Vec::new())
       }
     };
@@ -2603,14 +3043,14 @@ impl Vermilion {
       return Ok(false);
     }
 
-    let collections_to_update_metadata = Self::get_bulk_collection_metadata(settings.clone(), collections_to_update.clone(), all_collection_metadata).await?;
-    let new_symbols = Self::get_new_collection_symbols(pool.clone(), settings.clone(), collections_to_update_metadata.clone()).await?;
+    let collections_to_update_metadata = Self::get_bulk_collection_metadata(client, collections_to_update.clone(), all_collection_metadata).await?;
+    let new_symbols = Self::get_new_collection_symbols(pool.clone(), client, collections_to_update_metadata.clone()).await?;
     for (i, symbol) in new_symbols.iter().enumerate() {
       // break if ctrl-c is received
       if SHUTTING_DOWN.load(atomic::Ordering::Relaxed) {
         return Err("Shutting down".into());
       }
-      let new_tokens = match Self::get_tokens(settings.clone(), &symbol).await {
+      let new_tokens = match Self::get_tokens(pool.clone(), client, &symbol).await {
         Ok(tokens) => tokens,
         Err(e) => {
           log::info!("Failed to get tokens for {}, skipping: {}", symbol, e);
@@ -2627,6 +3067,8 @@ impl Vermilion {
       Self::insert_collection_list(&tx, vec![collection_metadata.clone()]).await?;
       Self::remove_collection_symbol(&tx, symbol.clone()).await?;
       Self::insert_collections(&tx, new_tokens).await?;
+      collection_history::rebuild_collection_holders(&tx, Some(&symbol)).await?;
+      Self::remove_token_progress(&tx, symbol).await?;
       tx.commit().await?;
       log::info!("Inserted tokens for {} in db. {} of {} updated", symbol, i+1, new_symbols.len());
     }
@@ -3007,6 +3449,8 @@ impl Vermilion {
   }
 
   pub(crate) async fn initialize_db_tables(pool: deadpool_postgres::Pool) -> anyhow::Result<()> {
+    // Bring existing tables up to date first, the create functions below assume the latest columns
+    migrations::migrate(pool.clone(), None).await.context("Failed to apply schema migrations")?;
     Self::create_metadata_table(pool.clone()).await.context("Failed to create metadata table")?;
     Self::create_full_metadata_table(pool.clone()).await.context("Failed to create full metadata table")?;
     Self::create_sat_table(pool.clone()).await.context("Failed to create sat table")?;
@@ -3044,6 +3488,10 @@ impl Vermilion {
 
     Self::initialize_transfer_tables(pool.clone()).await.context("Failed to initialize transfer tables")?;
     initialize_runes_tables(pool.clone()).await.context("Failed to create runes tables")?;
+    sales::initialize_sales_tables(pool.clone()).await.context("Failed to create sales tables")?;
+    collection_history::initialize_collection_history_table(pool.clone()).await.context("Failed to create collection history table")?;
+    brc20::initialize_brc20_tables(pool.clone()).await.context("Failed to create brc20 tables")?;
+    bitmaps::initialize_bitmaps_table(pool.clone()).await.context("Failed to create bitmaps table")?;
 
     Self::create_edition_insert_trigger(pool.clone()).await.context("Failed to create edition trigger")?;
     Self::create_metadata_insert_trigger(pool.clone()).await.context("Failed to create metadata trigger")?;
@@ -3058,6 +3506,8 @@ impl Vermilion {
     Self::create_transfer_delete_trigger(pool.clone()).await.context("Failed to create transfer delete trigger")?;
 
     Self::create_ordinals_full_view(pool.clone()).await.context("Failed to create ordinals full view")?;
+    undo_journal::initialize_undo_journal(pool.clone()).await.context("Failed to create undo journal")?;
+    hidden::initialize_hidden_inscriptions(pool.clone()).await.context("Failed to create hidden inscriptions table")?;
 
     initialize_social_tables(pool.clone()).await.context("Failed to create social tables")?;
     Ok(())
@@ -3068,6 +3518,8 @@ impl Vermilion {
     Self::create_collections_table(pool.clone()).await.context("Failed to create collections table")?;
     Self::create_collections_summary_table(pool.clone()).await.context("Failed to create collections summary table")?;
     Self::create_recently_stored_collection_table(pool.clone()).await.context("Failed to create recently traded collection table")?;
+    Self::create_collection_token_progress_tables(pool.clone()).await.context("Failed to create collection token progress tables")?;
+    collection_sources::initialize_manifest_collections_table(pool.clone()).await.context("Failed to create manifest collections table")?;
     Ok(())
   }
 
@@ -3268,7 +3720,8 @@ impl Vermilion {
         sha256 varchar(64) NOT NULL PRIMARY KEY,
         content bytea,
         content_type text,
-        content_encoding text
+        content_encoding text,
+        store varchar(20)
       )").await?;
     conn.simple_query(r"
       CREATE INDEX IF NOT EXISTS index_content_content_id ON content (content_id);
@@ -3509,6 +3962,26 @@ impl Vermilion {
     Ok(())
   }
 
+  // Tokens fetched so far for collections the magic eden indexer hasn't finished
+  pub(crate) async fn create_collection_token_progress_tables(pool: deadpool_postgres::Pool<>) -> anyhow::Result<()> {
+    let conn = pool.get().await?;
+    conn.simple_query(
+      r"CREATE TABLE IF NOT EXISTS collection_token_progress (
+        collection_symbol varchar(50) not null primary key,
+        next_offset bigint not null,
+        updated_at timestamptz not null default now()
+      )").await?;
+    conn.simple_query(
+      r"CREATE TABLE IF NOT EXISTS collection_token_pages (
+        collection_symbol varchar(50) not null,
+        id varchar(80) not null,
+        number bigint,
+        off_chain_metadata jsonb,
+        CONSTRAINT collection_token_page_key PRIMARY KEY (collection_symbol, id)
+      )").await?;
+    Ok(())
+  }
+
   //on chain collections
   pub(crate) async fn create_on_chain_collection_summary_table(pool: deadpool_postgres::Pool<>) -> anyhow::Result<()> {
     let conn = pool.get().await?;
@@ -3746,38 +4219,51 @@ impl Vermilion {
     Ok(())
   }
 
-  async fn bulk_insert_content(tx: &deadpool_postgres::Transaction<'_>, data: Vec<(i64, ContentBlob)>) -> anyhow::Result<()> {
+  async fn bulk_insert_content(tx: &deadpool_postgres::Transaction<'_>, content_store: &ContentStore, data: Vec<(i64, ContentBlob)>) -> anyhow::Result<()> {
+    // sha256 is the primary key, so only the first copy of each content would be kept anyway. Dropping the rest
+    // up front also keeps concurrent uploads of the same bytes from racing each other.
+    let data: Vec<(i64, ContentBlob)> = data.into_iter().unique_by(|(_, content)| content.sha256.clone()).collect();
+    // Push bytes to the external store first, only the store name is kept in postgres for those rows
+    let stores: Vec<&str> = stream::iter(data.iter())
+      .map(|(sequence_number, content)| content_store.put(*sequence_number, &content.sha256, &content.content))
+      .buffered(16)
+      .try_collect()
+      .await?;
     tx.simple_query("CREATE TEMP TABLE inserts_content ON COMMIT DROP AS TABLE content WITH NO DATA").await?;
     let copy_stm = r#"COPY inserts_content (
       content_id,
       sha256,
       content,
       content_type,
-      content_encoding
+      content_encoding,
+      store
     ) FROM STDIN BINARY"#;
     let col_types = vec![
       Type::INT8,
       Type::VARCHAR,
       Type::BYTEA,
       Type::TEXT,
-      Type::TEXT
+      Type::TEXT,
+      Type::VARCHAR
     ];
     let sink = tx.copy_in(copy_stm).await?;
     let writer = BinaryCopyInWriter::new(sink, &col_types);
     pin_mut!(writer);
-    for (sequence_number, content) in data {
+    for ((sequence_number, content), store) in data.into_iter().zip(stores) {
       let mut row: Vec<&'_ (dyn ToSql + Sync)> = Vec::new();
       row.push(&sequence_number);
       row.push(&content.sha256);
-      row.push(&content.content);
+      let inline_content = (store == "postgres").then_some(content.content);
+      row.push(&inline_content);
       let clean_type = &content.content_type.replace("\0", "");
       row.push(clean_type);
       let clean_encoding = &content.content_encoding.map(|s| s.replace("\0", ""));
       row.push(clean_encoding);
+      row.push(&store);
       writer.as_mut().write(&row).await?;
     }
     writer.finish().await?;
-    tx.simple_query("INSERT INTO content SELECT content_id, sha256, content, content_type, content_encoding FROM inserts_content ON CONFLICT DO NOTHING").await?;
+    tx.simple_query("INSERT INTO content SELECT content_id, sha256, content, content_type, content_encoding, store FROM inserts_content ON CONFLICT DO NOTHING").await?;
     Ok(())
   }
 
@@ -3883,6 +4369,8 @@ impl Vermilion {
     conn.simple_query(r"
       CREATE INDEX IF NOT EXISTS index_transfers_id ON transfers (id);
       CREATE INDEX IF NOT EXISTS index_transfers_block ON transfers (block_number);
+      CREATE INDEX IF NOT EXISTS index_transfers_address ON transfers (address);
+      CREATE INDEX IF NOT EXISTS index_transfers_transaction ON transfers (transaction);
     ").await?;
     Ok(())
   }
@@ -4138,6 +4626,9 @@ impl Vermilion {
         max_fee bigint,
         average_fee bigint
       )").await?;
+    conn.simple_query(r"
+      CREATE INDEX IF NOT EXISTS index_blockstats_hash ON blockstats (block_hash);
+    ").await?;
     Ok(())
   }
 
@@ -4164,13 +4655,15 @@ impl Vermilion {
 
 
   async fn home(State(server_config): State<ApiServerConfig>) -> Result<impl IntoApiResponse, ApiError> {
-    let content_blob = match Self::get_ordinal_content(server_config.deadpool,  "6fb976ab49dcec017f1e201e84395983204ae1a7c2abf7ced0a85d692e442799i0".to_string()).await {
+    let inscription_id = "6fb976ab49dcec017f1e201e84395983204ae1a7c2abf7ced0a85d692e442799i0";
+    let content_blob = match Self::get_ordinal_content(server_config.deadpool, &server_config.content_store,  inscription_id.to_string()).await {
       Ok(content_blob) => content_blob,
       Err(error) => {
         log::warn!("Error getting /home: {}", error);
-        return Err(ApiError::InternalServerError(format!("Error retrieving 6fb976ab49dcec017f1e201e84395983204ae1a7c2abf7ced0a85d692e442799i0")));
+        return Err(ApiError::InternalServerError(format!("Error retrieving {}", inscription_id)));
       }
     };
+    server_config.hidden.check_content(Some(inscription_id), &content_blob.sha256)?;
     let bytes = content_blob.content;
     let content_type = content_blob.content_type;
     Ok((
@@ -4185,7 +4678,7 @@ impl Vermilion {
   }
 
   async fn inscription(Path(inscription_id): Path<InscriptionId>, State(server_config): State<ApiServerConfig>) -> Result<ContentResponse, ApiError> {
-    let content_blob = match Self::get_ordinal_content(server_config.deadpool, inscription_id.to_string()).await {
+    let content_blob = match Self::get_ordinal_content(server_config.deadpool, &server_config.content_store, inscription_id.to_string()).await {
       Ok(content_blob) => content_blob,
       Err(error) => {
         log::warn!("Error getting /inscription: {}", error);
@@ -4196,6 +4689,7 @@ impl Vermilion {
         }
       }
     };
+    server_config.hidden.check_content(Some(&inscription_id.to_string()), &content_blob.sha256)?;
     let bytes = content_blob.content;
     let content_type = content_blob.content_type;
     let content_encoding = content_blob.content_encoding;
@@ -4216,13 +4710,14 @@ impl Vermilion {
   }
 
   async fn inscription_number(Path(InscriptionNumber(number)): Path<InscriptionNumber>, State(server_config): State<ApiServerConfig>) -> Result<ContentResponse, ApiError> {
-    let content_blob = match Self::get_ordinal_content_by_number(server_config.deadpool, number).await {
+    let content_blob = match Self::get_ordinal_content_by_number(server_config.deadpool, &server_config.content_store, number).await {
       Ok(content_blob) => content_blob,
       Err(error) => {
         log::warn!("Error getting /inscription_number: {}", error);
         return Err(ApiError::InternalServerError(format!("Error retrieving {}", number)));
       }
     };
+    server_config.hidden.check_content(None, &content_blob.sha256)?;
     let bytes = content_blob.content;
     let content_type = content_blob.content_type;
     let content_encoding = content_blob.content_encoding;
@@ -4243,7 +4738,8 @@ impl Vermilion {
   }
 
   async fn inscription_sha256(Path(Sha256Hash(sha256)): Path<Sha256Hash>, State(server_config): State<ApiServerConfig>) -> Result<ContentResponse, ApiError> {
-    let content_blob = match Self::get_ordinal_content_by_sha256(server_config.deadpool, sha256.clone(), None, None).await {
+    server_config.hidden.check_content(None, &sha256)?;
+    let content_blob = match Self::get_ordinal_content_by_sha256(server_config.deadpool, &server_config.content_store, sha256.clone(), None, None).await {
       Ok(content_blob) => content_blob,
       Err(error) => {
         log::warn!("Error getting /inscription_sha256: {}", error);
@@ -4274,7 +4770,7 @@ impl Vermilion {
       log::warn!("Error getting /inscription_metadata: {}", error);
       ApiError::InternalServerError(format!("Error retrieving metadata for {}", inscription_id.to_string()))
     })?;
-    Ok(Json(metadata))
+    Ok(Json(server_config.hidden.check(metadata)?))
   }
 
   async fn inscription_metadata_number(Path(InscriptionNumber(number)): Path<InscriptionNumber>, State(server_config): State<ApiServerConfig>) -> Result<Json<FullMetadata>, ApiError> {
@@ -4282,7 +4778,7 @@ impl Vermilion {
       log::warn!("Error getting /inscription_metadata_number: {}", error);
       ApiError::InternalServerError(format!("Error retrieving metadata for {}", number))
     })?;
-    Ok(Json(metadata))
+    Ok(Json(server_config.hidden.check(metadata)?))
   }
 
   async fn inscription_edition(Path(inscription_id): Path<InscriptionId>, State(server_config): State<ApiServerConfig>) -> Result<Json<InscriptionNumberEdition>, ApiError> {
@@ -4290,7 +4786,7 @@ impl Vermilion {
       log::warn!("Error getting /inscription_edition: {}", error);
       ApiError::InternalServerError(format!("Error retrieving edition for {}", inscription_id.to_string()))
     })?;
-    Ok(Json(edition))
+    Ok(Json(server_config.hidden.check(edition)?))
   }
 
   async fn inscription_edition_number(Path(InscriptionNumber(number)): Path<InscriptionNumber>, State(server_config): State<ApiServerConfig>) -> Result<Json<InscriptionNumberEdition>, ApiError> {
@@ -4298,47 +4794,48 @@ impl Vermilion {
       log::warn!("Error getting /inscription_edition_number: {}", error);
       ApiError::InternalServerError(format!("Error retrieving edition for {}", number))
     })?;
-    Ok(Json(edition))
+    Ok(Json(server_config.hidden.check(edition)?))
   }
 
   async fn inscription_editions_sha256(Path(Sha256Hash(sha256)): Path<Sha256Hash>, params: Query<PaginationParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Vec<InscriptionNumberEdition>>, ApiError> {
-    let editions = Self::get_matching_inscriptions_by_sha256(server_config.deadpool, sha256.clone(), params.0).await.map_err(|error| {
+    server_config.hidden.check_content(None, &sha256)?;
+    let editions = Self::get_matching_inscriptions_by_sha256(server_config.deadpool, sha256.clone(), params.0, &server_config.hidden.params()).await.map_err(|error| {
       log::warn!("Error getting /inscription_editions_sha256: {}", error);
       ApiError::InternalServerError(format!("Error retrieving editions for {}", sha256))
     })?;
     Ok(Json(editions))
   }
 
-  async fn inscription_children(Path(inscription_id): Path<InscriptionId>, params: Query<InscriptionQueryParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Vec<FullMetadata>>, ApiError> {
-    let parsed_params = ParsedInscriptionQueryParams::from(params.0);
-    let editions = Self::get_inscription_children(server_config.deadpool, inscription_id.to_string(), parsed_params).await.map_err(|error| {
+  async fn inscription_children(Path(inscription_id): Path<InscriptionId>, params: Query<InscriptionQueryParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Paginated<FullMetadata>>, ApiError> {
+    let parsed_params = params.0.parse(server_config.chain)?;
+    let editions = Self::get_inscription_children(server_config.deadpool, inscription_id.to_string(), parsed_params, &server_config.hidden.params()).await.map_err(|error| {
       log::warn!("Error getting /inscription_children: {}", error);
       ApiError::InternalServerError(format!("Error retrieving children for {}", inscription_id.to_string()))
     })?;
     Ok(Json(editions))
   }
 
-  async fn inscription_children_number(Path(InscriptionNumber(number)): Path<InscriptionNumber>, params: Query<InscriptionQueryParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Vec<FullMetadata>>, ApiError> {
-    let parsed_params = ParsedInscriptionQueryParams::from(params.0);
-    let editions = Self::get_inscription_children_by_number(server_config.deadpool, number, parsed_params).await.map_err(|error| {
+  async fn inscription_children_number(Path(InscriptionNumber(number)): Path<InscriptionNumber>, params: Query<InscriptionQueryParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Paginated<FullMetadata>>, ApiError> {
+    let parsed_params = params.0.parse(server_config.chain)?;
+    let editions = Self::get_inscription_children_by_number(server_config.deadpool, number, parsed_params, &server_config.hidden.params()).await.map_err(|error| {
       log::warn!("Error getting /inscription_children_number: {}", error);
       ApiError::InternalServerError(format!("Error retrieving children for {}", number))
     })?;
     Ok(Json(editions))
   }
 
-  async fn inscription_referenced_by(Path(inscription_id): Path<InscriptionId>, params: Query<InscriptionQueryParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Vec<FullMetadata>>, ApiError> {
-    let parsed_params = ParsedInscriptionQueryParams::from(params.0);
-    let referenced_by = Self::get_inscription_referenced_by(server_config.deadpool, inscription_id.to_string(), parsed_params).await.map_err(|error| {
+  async fn inscription_referenced_by(Path(inscription_id): Path<InscriptionId>, params: Query<InscriptionQueryParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Paginated<FullMetadata>>, ApiError> {
+    let parsed_params = params.0.parse(server_config.chain)?;
+    let referenced_by = Self::get_inscription_referenced_by(server_config.deadpool, inscription_id.to_string(), parsed_params, &server_config.hidden.params()).await.map_err(|error| {
       log::warn!("Error getting /inscription_referenced_by: {}", error);
       ApiError::InternalServerError(format!("Error retrieving referenced by for {}", inscription_id.to_string()))
     })?;
     Ok(Json(referenced_by))
   }
 
-  async fn inscription_referenced_by_number(Path(InscriptionNumber(number)): Path<InscriptionNumber>, params: Query<InscriptionQueryParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Vec<FullMetadata>>, ApiError> {
-    let parsed_params = ParsedInscriptionQueryParams::from(params.0);
-    let referenced_by = Self::get_inscription_referenced_by_number(server_config.deadpool, number, parsed_params).await.map_err(|error| {
+  async fn inscription_referenced_by_number(Path(InscriptionNumber(number)): Path<InscriptionNumber>, params: Query<InscriptionQueryParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Paginated<FullMetadata>>, ApiError> {
+    let parsed_params = params.0.parse(server_config.chain)?;
+    let referenced_by = Self::get_inscription_referenced_by_number(server_config.deadpool, number, parsed_params, &server_config.hidden.params()).await.map_err(|error| {
       log::warn!("Error getting /inscription_referenced_by_number: {}", error);
       ApiError::InternalServerError(format!("Error retrieving referenced by for {}", number))
     })?;
@@ -4346,7 +4843,7 @@ impl Vermilion {
   }
 
   async fn inscription_bootlegs(Path(inscription_id): Path<InscriptionId>, params: Query<PaginationParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Vec<BootlegEdition>>, ApiError> {
-    let delegates = Self::get_inscription_bootlegs(server_config.deadpool, inscription_id.to_string(), params.0).await.map_err(|error| {
+    let delegates = Self::get_inscription_bootlegs(server_config.deadpool, inscription_id.to_string(), params.0, &server_config.hidden.params()).await.map_err(|error| {
       log::warn!("Error getting /inscription_bootlegs: {}", error);
       ApiError::InternalServerError(format!("Error retrieving bootlegs for {}", inscription_id.to_string()))
     })?;
@@ -4354,7 +4851,7 @@ impl Vermilion {
   }
 
   async fn inscription_bootlegs_number(Path(InscriptionNumber(number)): Path<InscriptionNumber>, params: Query<PaginationParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Vec<BootlegEdition>>, ApiError> {
-    let delegates = Self::get_inscription_bootlegs_by_number(server_config.deadpool, number, params.0).await.map_err(|error| {
+    let delegates = Self::get_inscription_bootlegs_by_number(server_config.deadpool, number, params.0, &server_config.hidden.params()).await.map_err(|error| {
       log::warn!("Error getting /inscription_bootlegs_number: {}", error);
       ApiError::InternalServerError(format!("Error retrieving bootlegs for {}", number))
     })?;
@@ -4366,7 +4863,7 @@ impl Vermilion {
       log::warn!("Error getting /bootleg_edition: {}", error);
       ApiError::InternalServerError(format!("Error retrieving bootleg edition for {}", inscription_id.to_string()))
     })?;
-    Ok(Json(edition))
+    Ok(Json(server_config.hidden.check(edition)?))
   }
 
   async fn bootleg_edition_number(Path(InscriptionNumber(number)): Path<InscriptionNumber>, State(server_config): State<ApiServerConfig>) -> Result<Json<BootlegEdition>, ApiError> {
@@ -4374,11 +4871,11 @@ impl Vermilion {
       log::warn!("Error getting /bootleg_edition_number: {}", error);
       ApiError::InternalServerError(format!("Error retrieving bootleg edition for {}", number))
     })?;
-    Ok(Json(edition))
+    Ok(Json(server_config.hidden.check(edition)?))
   }
 
   async fn inscription_comments(Path(inscription_id): Path<InscriptionId>, params: Query<PaginationParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Vec<CommentEdition>>, ApiError> {
-    let delegates = Self::get_inscription_comments(server_config.deadpool, inscription_id.to_string(), params.0).await.map_err(|error| {
+    let delegates = Self::get_inscription_comments(server_config.deadpool, inscription_id.to_string(), params.0, &server_config.hidden.params()).await.map_err(|error| {
       log::warn!("Error getting /inscription_comments: {}", error);
       ApiError::InternalServerError(format!("Error retrieving comments for {}", inscription_id.to_string()))
     })?;
@@ -4386,7 +4883,7 @@ impl Vermilion {
   }
 
   async fn inscription_comments_number(Path(InscriptionNumber(number)): Path<InscriptionNumber>, params: Query<PaginationParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Vec<CommentEdition>>, ApiError> {
-    let delegates = Self::get_inscription_comments_by_number(server_config.deadpool, number, params.0).await.map_err(|error| {
+    let delegates = Self::get_inscription_comments_by_number(server_config.deadpool, number, params.0, &server_config.hidden.params()).await.map_err(|error| {
       log::warn!("Error getting /inscription_comments_number: {}", error);
       ApiError::InternalServerError(format!("Error retrieving comments for {}", number))
     })?;
@@ -4394,7 +4891,7 @@ impl Vermilion {
   }
 
   async fn comment(Path(inscription_id): Path<InscriptionId>, State(server_config): State<ApiServerConfig>) -> Result<ContentResponse, ApiError> {
-    let content_blob = Self::get_ordinal_comment(server_config.deadpool, inscription_id.to_string()).await
+    let content_blob = Self::get_ordinal_comment(server_config.deadpool, &server_config.content_store, inscription_id.to_string()).await
       .map_err(|error| {
         log::warn!("Error getting /comment: {}", error);
         if error.to_string().contains("unexpected number of rows") || error.to_string().contains("not found") {
@@ -4403,6 +4900,7 @@ impl Vermilion {
           ApiError::InternalServerError(format!("Error retrieving {}", inscription_id.to_string()))
         }
       })?;
+    server_config.hidden.check_content(Some(&inscription_id.to_string()), &content_blob.sha256)?;
     let bytes = content_blob.content;
     let content_type = content_blob.content_type;
     let content_encoding = content_blob.content_encoding;
@@ -4423,7 +4921,7 @@ impl Vermilion {
   }
 
   async fn comment_number(Path(InscriptionNumber(number)): Path<InscriptionNumber>, State(server_config): State<ApiServerConfig>) -> Result<ContentResponse, ApiError> {
-    let content_blob = Self::get_ordinal_comment_by_number(server_config.deadpool, number).await
+    let content_blob = Self::get_ordinal_comment_by_number(server_config.deadpool, &server_config.content_store, number).await
       .map_err(|error| {
         log::warn!("Error getting /comment_number: {}", error);
         if error.to_string().contains("unexpected number of rows") || error.to_string().contains("not found") {
@@ -4432,6 +4930,7 @@ impl Vermilion {
           ApiError::InternalServerError(format!("Error retrieving {}", number))
         }
       })?;
+    server_config.hidden.check_content(None, &content_blob.sha256)?;
     let bytes = content_blob.content;
     let content_type = content_blob.content_type;
     let content_encoding = content_blob.content_encoding;
@@ -4456,7 +4955,7 @@ impl Vermilion {
       log::warn!("Error getting /inscription_satribute_editions: {}", error);
       ApiError::InternalServerError(format!("Error retrieving satribute editions for {}", inscription_id.to_string()))
     })?;
-    Ok(Json(editions))
+    Ok(Json(server_config.hidden.retain(editions)))
   }
 
   async fn inscription_satribute_editions_number(Path(InscriptionNumber(number)): Path<InscriptionNumber>, State(server_config): State<ApiServerConfig>) -> Result<Json<Vec<SatributeEdition>>, ApiError> {
@@ -4464,12 +4963,12 @@ impl Vermilion {
       log::warn!("Error getting /inscription_satribute_editions_number: {}", error);
       ApiError::InternalServerError(format!("Error retrieving satribute editions for {}", number))
     })?;
-    Ok(Json(editions))
+    Ok(Json(server_config.hidden.retain(editions)))
   }
 
-  async fn inscriptions_in_block(Path(BlockNumber(block)): Path<BlockNumber>, params: Query<InscriptionQueryParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Vec<FullMetadata>>, ApiError> {
-    let parsed_params = ParsedInscriptionQueryParams::from(params.0);
-    let inscriptions = Self::get_inscriptions_within_block(server_config.deadpool, block, parsed_params).await.map_err(|error| {
+  async fn inscriptions_in_block(Path(BlockNumber(block)): Path<BlockNumber>, params: Query<InscriptionQueryParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Paginated<FullMetadata>>, ApiError> {
+    let parsed_params = params.0.parse(server_config.chain)?;
+    let inscriptions = Self::get_inscriptions_within_block(server_config.deadpool, block, parsed_params, &server_config.hidden.params()).await.map_err(|error| {
       log::warn!("Error getting /inscriptions_in_block: {}", error);
       ApiError::InternalServerError(format!("Error retrieving inscriptions for block {}", block))
     })?;
@@ -4484,13 +4983,13 @@ impl Vermilion {
         log::warn!("Error getting /random_inscription: {}", error);
         ApiError::InternalServerError("Error retrieving random inscription".to_string())
       })?;
-    Ok(Json(inscription_number))
+    Ok(Json(server_config.hidden.check(inscription_number)?))
   }
 
   async fn random_inscriptions(
     n: Query<QueryNumber>,
     State(server_config): State<ApiServerConfig>,
-    NoApi(session): NoApi<Session<SessionNullPool>>
+    NoApi(session): NoApi<Session<SessionPgPool>>
   ) -> Result<Json<Vec<FullMetadata>>, ApiError> {
     let bands: Vec<(f64, f64)> = session.get("bands_seen").unwrap_or(Vec::new());
     for band in bands.iter() {
@@ -4508,7 +5007,7 @@ impl Vermilion {
     })?;
 
     session.set("bands_seen", new_bands);
-    Ok(Json(inscription_numbers))
+    Ok(Json(server_config.hidden.retain(inscription_numbers)))
   }
 
   async fn recent_inscriptions(n: Query<QueryNumber>, State(server_config): State<ApiServerConfig>) -> Result<Json<Vec<FullMetadata>>, ApiError> {
@@ -4517,7 +5016,7 @@ impl Vermilion {
       log::warn!("Error getting /recent_inscriptions: {}", error);
       ApiError::InternalServerError("Error retrieving recent inscriptions".to_string())
     })?;
-    Ok(Json(inscriptions))
+    Ok(Json(server_config.hidden.retain(inscriptions)))
   }
 
   async fn recent_boosts(n: Query<QueryNumber>, State(server_config): State<ApiServerConfig>) -> Result<Json<Vec<BoostFullMetadata>>, ApiError> {
@@ -4526,7 +5025,7 @@ impl Vermilion {
       log::warn!("Error getting /recent_boosts: {}", error);
       ApiError::InternalServerError("Error retrieving recent boosts".to_string())
     })?;
-    Ok(Json(boosts))
+    Ok(Json(server_config.hidden.retain(boosts)))
   }
 
   async fn boost_leaderboard(State(server_config): State<ApiServerConfig>) -> Result<Json<Vec<LeaderboardEntry>>, ApiError> {
@@ -4537,13 +5036,13 @@ impl Vermilion {
     Ok(Json(leaderboard))
   }
 
-  async fn trending_feed(n: Query<QueryNumber>, State(server_config): State<ApiServerConfig>, NoApi(session): NoApi<Session<SessionNullPool>>) -> Result<Json<Vec<TrendingItem>>, ApiError> {
+  async fn trending_feed(n: Query<QueryNumber>, State(server_config): State<ApiServerConfig>, NoApi(session): NoApi<Session<SessionPgPool>>) -> Result<Json<Vec<TrendingItem>>, ApiError> {
     let mut bands_seen: Vec<i64> = session.get("trending_bands_seen").unwrap_or(Vec::new());
     for band in bands_seen.iter() {
       log::debug!("Trending Band: {:?}", band);
     }
     let n = n.0.n.unwrap_or(20);
-    let trending_items = Self::get_trending_feed_items(server_config.deadpool, n, bands_seen.clone()).await
+    let trending_items = Self::get_trending_feed_items(server_config.deadpool, n, bands_seen.clone(), server_config.hidden.params()).await
       .map_err(|error| {
         log::warn!("Error getting /trending_feed: {}", error);
         ApiError::InternalServerError("Error retrieving trending feed".to_string())
@@ -4554,16 +5053,16 @@ impl Vermilion {
       .collect();
     bands_seen.append(&mut band_ids);
     session.set("trending_bands_seen", bands_seen);
-    Ok(Json(trending_items))
+    Ok(Json(server_config.hidden.retain(trending_items)))
   }
 
-  async fn discover_feed(n: Query<QueryNumber>, State(server_config): State<ApiServerConfig>, NoApi(session): NoApi<Session<SessionNullPool>>) -> Result<Json<Vec<DiscoverItem>>, ApiError> {
+  async fn discover_feed(n: Query<QueryNumber>, State(server_config): State<ApiServerConfig>, NoApi(session): NoApi<Session<SessionPgPool>>) -> Result<Json<Vec<DiscoverItem>>, ApiError> {
     let mut bands_seen: Vec<(f64, f64)> = session.get("discover_bands_seen").unwrap_or(Vec::new());
     for band in bands_seen.iter() {
       log::debug!("Discover Band: {:?}", band);
     }
     let n = n.0.n.unwrap_or(20);
-    let discover_items = Self::get_discover_feed_items(server_config.deadpool, n, bands_seen.clone()).await
+    let discover_items = Self::get_discover_feed_items(server_config.deadpool, n, bands_seen.clone(), server_config.hidden.params()).await
       .map_err(|error| {
         log::warn!("Error getting /discover_feed: {}", error);
         ApiError::InternalServerError("Error retrieving discover feed".to_string())
@@ -4574,12 +5073,12 @@ impl Vermilion {
       .collect();
     bands_seen.append(&mut band_tuples);
     session.set("discover_bands_seen", bands_seen);
-    Ok(Json(discover_items))
+    Ok(Json(server_config.hidden.retain(discover_items)))
   }
 
-  async fn inscriptions(params: Query<InscriptionQueryParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Vec<FullMetadata>>, ApiError> {
-    let params = ParsedInscriptionQueryParams::from(params.0);
-    let inscriptions = Self::get_inscriptions(server_config.deadpool, params).await
+  async fn inscriptions(params: Query<InscriptionQueryParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Paginated<FullMetadata>>, ApiError> {
+    let params = params.0.parse(server_config.chain)?;
+    let inscriptions = Self::get_inscriptions(server_config.deadpool, params, &server_config.hidden.params()).await
       .map_err(|error| {
         log::warn!("Error getting /inscriptions: {}", error);
         ApiError::InternalServerError("Error retrieving inscriptions".to_string())
@@ -4592,7 +5091,7 @@ impl Vermilion {
       log::warn!("Error getting /inscription_last_transfer: {}", error);
       ApiError::InternalServerError(format!("Error retrieving last transfer for {}", inscription_id.to_string()))
     })?;
-    Ok(Json(transfer))
+    Ok(Json(server_config.hidden.check(transfer)?))
   }
 
   async fn inscription_last_transfer_number(Path(InscriptionNumber(number)): Path<InscriptionNumber>, State(server_config): State<ApiServerConfig>) -> Result<Json<Transfer>, ApiError> {
@@ -4600,7 +5099,7 @@ impl Vermilion {
       log::warn!("Error getting /inscription_last_transfer_number: {}", error);
       ApiError::InternalServerError(format!("Error retrieving last transfer for {}", number))
     })?;
-    Ok(Json(transfer))
+    Ok(Json(server_config.hidden.check(transfer)?))
   }
 
   async fn inscription_transfers(Path(inscription_id): Path<InscriptionId>, State(server_config): State<ApiServerConfig>) -> Result<Json<Vec<Transfer>>, ApiError> {
@@ -4608,7 +5107,7 @@ impl Vermilion {
       log::warn!("Error getting /inscription_transfers: {}", error);
       ApiError::InternalServerError(format!("Error retrieving transfers for {}", inscription_id.to_string()))
     })?;
-    Ok(Json(transfers))
+    Ok(Json(server_config.hidden.retain(transfers)))
   }
 
   async fn inscription_transfers_number(Path(InscriptionNumber(number)): Path<InscriptionNumber>, State(server_config): State<ApiServerConfig>) -> Result<Json<Vec<Transfer>>, ApiError> {
@@ -4616,12 +5115,53 @@ impl Vermilion {
       log::warn!("Error getting /inscription_transfers_number: {}", error);
       ApiError::InternalServerError(format!("Error retrieving transfers for {}", number))
     })?;
-    Ok(Json(transfers))
+    Ok(Json(server_config.hidden.retain(transfers)))
+  }
+
+  async fn inscription_provenance(Path(inscription_id): Path<InscriptionId>, State(server_config): State<ApiServerConfig>) -> Result<Json<Vec<HoldingPeriod>>, ApiError> {
+    let provenance = provenance::get_inscription_provenance(server_config.deadpool, inscription_id.to_string()).await.map_err(|error| {
+      log::warn!("Error getting /inscription_provenance: {}", error);
+      ApiError::InternalServerError(format!("Error retrieving provenance for {}", inscription_id.to_string()))
+    })?;
+    Ok(Json(server_config.hidden.retain(provenance)))
+  }
+
+  async fn inscription_sales(Path(inscription_id): Path<InscriptionId>, params: Query<SalesQueryParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Paginated<Sale>>, ApiError> {
+    let params = params.0.parse("inscription_sales")?;
+    let sales = sales::get_sales(server_config.deadpool, SalesOf::Inscription(inscription_id.to_string()), params, &server_config.hidden.params()).await.map_err(|error| {
+      log::warn!("Error getting /inscription_sales: {}", error);
+      ApiError::InternalServerError(format!("Error retrieving sales for {}", inscription_id.to_string()))
+    })?;
+    Ok(Json(sales))
+  }
+
+  async fn address_sales(Path(BitcoinAddress(address)): Path<BitcoinAddress>, params: Query<SalesQueryParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Paginated<Sale>>, ApiError> {
+    let address = parse_address(&address, server_config.chain)
+      .map_err(|error| ApiError::BadRequest(format!("Invalid {} address {}: {}", server_config.chain, address, error)))?;
+    let params = params.0.parse("address_sales")?;
+    let sales = sales::get_sales(server_config.deadpool, SalesOf::Address(address.to_string()), params, &server_config.hidden.params()).await.map_err(|error| {
+      log::warn!("Error getting /address_sales: {}", error);
+      ApiError::InternalServerError(format!("Error retrieving sales for {}", &*address))
+    })?;
+    Ok(Json(sales))
+  }
+
+  async fn collector_history(Path(BitcoinAddress(address)): Path<BitcoinAddress>, params: Query<CollectorHistoryParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Paginated<HoldingPeriod>>, ApiError> {
+    let address = parse_address(&address, server_config.chain)
+      .map_err(|error| ApiError::BadRequest(format!("Invalid {} address {}: {}", server_config.chain, address, error)))?;
+    let params = ParsedCollectorHistoryParams::try_from(params.0)?;
+    let history = provenance::get_collector_history(server_config.deadpool, address.to_string(), params, &server_config.hidden.params()).await.map_err(|error| {
+      log::warn!("Error getting /collector_history: {}", error);
+      ApiError::InternalServerError(format!("Error retrieving collector history for {}", &*address))
+    })?;
+    Ok(Json(history))
   }
 
-  async fn inscriptions_in_address(Path(BitcoinAddress(address)): Path<BitcoinAddress>, params: Query<InscriptionQueryParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Vec<FullMetadata>>, ApiError> {
-    let parsed_params = ParsedInscriptionQueryParams::from(params.0);
-    let inscriptions = Self::get_inscriptions_by_address(server_config.deadpool, address.clone(), parsed_params).await.map_err(|error| {
+  async fn inscriptions_in_address(Path(BitcoinAddress(address)): Path<BitcoinAddress>, params: Query<InscriptionQueryParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Paginated<FullMetadata>>, ApiError> {
+    let address = parse_address(&address, server_config.chain)
+      .map_err(|error| ApiError::BadRequest(format!("Invalid {} address {}: {}", server_config.chain, address, error)))?;
+    let parsed_params = params.0.parse(server_config.chain)?;
+    let inscriptions = Self::get_inscriptions_by_address(server_config.deadpool, address.clone(), parsed_params, &server_config.hidden.params()).await.map_err(|error| {
       log::warn!("Error getting /inscriptions_in_address: {}", error);
       ApiError::InternalServerError(format!("Error retrieving inscriptions for {}", &*address))
     })?;
@@ -4633,12 +5173,12 @@ impl Vermilion {
       log::warn!("Error getting /inscriptions_on_sat: {}", error);
       ApiError::InternalServerError(format!("Error retrieving inscriptions for {}", sat))
     })?;
-    Ok(Json(inscriptions))
+    Ok(Json(server_config.hidden.retain(inscriptions)))
   }
 
-  async fn inscriptions_in_sat_block(Path(BlockNumber(block)): Path<BlockNumber>, params: Query<InscriptionQueryParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Vec<FullMetadata>>, ApiError> {
-    let parsed_params = ParsedInscriptionQueryParams::from(params.0);
-    let inscriptions = Self::get_inscriptions_in_sat_block(server_config.deadpool, block, parsed_params).await.map_err(|error| {
+  async fn inscriptions_in_sat_block(Path(BlockNumber(block)): Path<BlockNumber>, params: Query<InscriptionQueryParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Paginated<FullMetadata>>, ApiError> {
+    let parsed_params = params.0.parse(server_config.chain)?;
+    let inscriptions = Self::get_inscriptions_in_sat_block(server_config.deadpool, block, parsed_params, &server_config.hidden.params()).await.map_err(|error| {
       log::warn!("Error getting /inscriptions_in_sat_block: {}", error);
       ApiError::InternalServerError(format!("Error retrieving inscriptions for {}", block))
     })?;
@@ -4661,9 +5201,11 @@ impl Vermilion {
     Ok(Json(satributes))
   }
 
-  async fn collections(params: Query<CollectionQueryParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Vec<CollectionSummary>>, ApiError> {
+  async fn collections(params: Query<CollectionQueryParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Paginated<CollectionSummary>>, ApiError> {
     let params = params.0;
-    let collections = Self::get_collections(server_config.deadpool, params).await
+    let keyset = params.sort_by.unwrap_or(CollectionSortBy::BiggestOnChainFootprint).keyset("collections", "l.collection_symbol", KeyType::Text);
+    let page = PageRequest::new(params.page_number, std::cmp::min(params.page_size.unwrap_or(20), 100), params.cursor.as_deref(), &keyset)?;
+    let collections = Self::get_collections(server_config.deadpool, keyset, page).await
       .map_err(|error| {
         log::warn!("Error getting /collections: {}", error);
         ApiError::InternalServerError("Error retrieving collections".to_string())
@@ -4687,13 +5229,49 @@ impl Vermilion {
     Ok(Json(collection_holders))
   }
 
+  async fn collection_sales(Path(CollectionSymbol(collection_symbol)): Path<CollectionSymbol>, params: Query<SalesQueryParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Paginated<Sale>>, ApiError> {
+    let params = params.0.parse("collection_sales")?;
+    let sales = sales::get_sales(server_config.deadpool, SalesOf::Collection(collection_symbol.clone()), params, &server_config.hidden.params()).await.map_err(|error| {
+      log::warn!("Error getting /collection_sales: {}", error);
+      ApiError::InternalServerError(format!("Error retrieving sales for {}", collection_symbol))
+    })?;
+    Ok(Json(sales))
+  }
+
+  async fn collection_history(Path(CollectionSymbol(collection_symbol)): Path<CollectionSymbol>, params: Query<CollectionHistoryParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Paginated<CollectionHistory>>, ApiError> {
+    let params = params.0.parse("collection_history")?;
+    let history = collection_history::get_collection_history(server_config.deadpool, HistoryOf::Collection(collection_symbol.clone()), params).await.map_err(|error| {
+      log::warn!("Error getting /collection_history: {}", error);
+      ApiError::InternalServerError(format!("Error retrieving collection history for {}", collection_symbol))
+    })?;
+    Ok(Json(history))
+  }
+
+  async fn holder_snapshot(params: Query<SnapshotParams>, State(server_config): State<ApiServerConfig>) -> Result<SnapshotResponse, ApiError> {
+    let target = params.target()?;
+    let indexed_height = snapshot::get_indexed_height(&server_config.deadpool).await.map_err(|error| {
+      log::warn!("Error getting indexed height for /holder_snapshot: {}", error);
+      ApiError::InternalServerError("Error retrieving indexed height".to_string())
+    })?;
+    let height = params.height(indexed_height)?;
+    let snapshot = snapshot::get_holder_snapshot(server_config.deadpool, target, height, &server_config.hidden.params()).await.map_err(|error| {
+      log::warn!("Error getting /holder_snapshot: {}", error);
+      ApiError::InternalServerError(format!("Error retrieving holder snapshot at height {}", height))
+    })?;
+    if !params.is_csv() {
+      return Ok(SnapshotResponse::Json(snapshot));
+    }
+    let csv = snapshot.to_csv().map_err(|error| ApiError::InternalServerError(format!("Error writing holder snapshot csv: {}", error)))?;
+    Ok(SnapshotResponse::Csv(csv))
+  }
+
   async fn inscription_collection_data(Path(inscription_id): Path<InscriptionId>, State(server_config): State<ApiServerConfig>) -> Result<Json<Vec<InscriptionCollectionData>>, ApiError> {
     let collection_data = Self::get_inscription_collection_data(server_config.deadpool, inscription_id.to_string()).await
       .map_err(|error| {
         log::warn!("Error getting /collection_data_by_inscription_id: {}", error);
         ApiError::InternalServerError(format!("Error retrieving collection data for {}", inscription_id.to_string()))
       })?;
-    Ok(Json(collection_data))
+    Ok(Json(server_config.hidden.retain(collection_data)))
   }
 
   async fn inscription_collection_data_number(Path(InscriptionNumber(number)): Path<InscriptionNumber>, State(server_config): State<ApiServerConfig>) -> Result<Json<Vec<InscriptionCollectionData>>, ApiError> {
@@ -4702,12 +5280,12 @@ impl Vermilion {
         log::warn!("Error getting /collection_data_by_inscription_number: {}", error);
         ApiError::InternalServerError(format!("Error retrieving collection data for {}", number))
       })?;
-    Ok(Json(collection_data))
+    Ok(Json(server_config.hidden.retain(collection_data)))
   }
 
-  async fn inscriptions_in_collection(Path(CollectionSymbol(collection_symbol)): Path<CollectionSymbol>, params: Query<InscriptionQueryParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Vec<FullMetadata>>, ApiError> {
-    let parsed_params = ParsedInscriptionQueryParams::from(params.0);
-    let inscriptions = Self::get_inscriptions_in_collection(server_config.deadpool, collection_symbol.clone(), parsed_params).await
+  async fn inscriptions_in_collection(Path(CollectionSymbol(collection_symbol)): Path<CollectionSymbol>, params: Query<InscriptionQueryParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Paginated<FullMetadata>>, ApiError> {
+    let parsed_params = params.0.parse(server_config.chain)?;
+    let inscriptions = Self::get_inscriptions_in_collection(server_config.deadpool, collection_symbol.clone(), parsed_params, &server_config.hidden.params()).await
       .map_err(|error| {
         log::warn!("Error getting /inscriptions_in_collection: {}", error);
         ApiError::InternalServerError("Error retrieving inscriptions in collection".to_string())
@@ -4715,9 +5293,11 @@ impl Vermilion {
     Ok(Json(inscriptions))
   }
 
-  async fn on_chain_collections(params: Query<CollectionQueryParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Vec<OnChainCollectionSummary>>, ApiError> {
+  async fn on_chain_collections(params: Query<CollectionQueryParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Paginated<OnChainCollectionSummary>>, ApiError> {
     let params = params.0;
-    let collections = Self::get_on_chain_collections(server_config.deadpool, params).await
+    let keyset = params.sort_by.unwrap_or(CollectionSortBy::BiggestOnChainFootprint).keyset("on_chain_collections", "s.parents_hash", KeyType::Int4);
+    let page = PageRequest::new(params.page_number, std::cmp::min(params.page_size.unwrap_or(20), 100), params.cursor.as_deref(), &keyset)?;
+    let collections = Self::get_on_chain_collections(server_config.deadpool, keyset, page).await
       .map_err(|error| {
         log::warn!("Error getting /on_chain_collections: {}", error);
         ApiError::InternalServerError(format!("Error retrieving on chain collections"))
@@ -4745,10 +5325,21 @@ impl Vermilion {
     Ok(Json(collection_holders))
   }
 
-  async fn inscriptions_in_on_chain_collection(Path(ParentList(parents)): Path<ParentList>, params: Query<InscriptionQueryParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Vec<FullMetadata>>, ApiError> {
+  async fn on_chain_collection_history(Path(ParentList(parents)): Path<ParentList>, params: Query<CollectionHistoryParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Paginated<CollectionHistory>>, ApiError> {
+    let parents_vec: Vec<String> = parents.split(",").map(|s| s.to_string()).collect();
+    let params = params.0.parse("on_chain_collection_history")?;
+    let history = collection_history::get_collection_history(server_config.deadpool, HistoryOf::OnChainCollection(parents_vec), params).await
+      .map_err(|error| {
+        log::warn!("Error getting /on_chain_collection_history: {}", error);
+        ApiError::InternalServerError(format!("Error retrieving on chain collection history for {}", parents))
+      })?;
+    Ok(Json(history))
+  }
+
+  async fn inscriptions_in_on_chain_collection(Path(ParentList(parents)): Path<ParentList>, params: Query<InscriptionQueryParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Paginated<FullMetadata>>, ApiError> {
     let parents_vec: Vec<String> = parents.split(",").map(|s| s.to_string()).collect();
-    let parsed_params = ParsedInscriptionQueryParams::from(params.0);
-    let inscriptions = Self::get_inscriptions_in_on_chain_collection(server_config.deadpool, parents_vec, parsed_params).await
+    let parsed_params = params.0.parse(server_config.chain)?;
+    let inscriptions = Self::get_inscriptions_in_on_chain_collection(server_config.deadpool, parents_vec, parsed_params, &server_config.hidden.params()).await
       .map_err(|error| {
         log::warn!("Error getting /inscriptions_in_on_chain_collection: {}", error);
         ApiError::InternalServerError("Error retrieving inscriptions in on chain collection".to_string())
@@ -4756,9 +5347,11 @@ impl Vermilion {
     Ok(Json(inscriptions))
   }
 
-  async fn galleries_summary(params: Query<GalleryQueryParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Vec<GallerySummary>>, ApiError> {
+  async fn galleries_summary(params: Query<GalleryQueryParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Paginated<GallerySummary>>, ApiError> {
     let params = params.0;
-    let galleries = Self::get_galleries_summary(server_config.deadpool, params).await
+    let keyset = params.sort_by.unwrap_or(GallerySortBy::BiggestOnChainFootprint).keyset("galleries", "s.gallery_id");
+    let page = PageRequest::new(params.page_number, std::cmp::min(params.page_size.unwrap_or(20), 100), params.cursor.as_deref(), &keyset)?;
+    let galleries = Self::get_galleries_summary(server_config.deadpool, keyset, page).await
       .map_err(|error| {
         log::warn!("Error getting /galleries_summary: {}", error);
         ApiError::InternalServerError(format!("Error retrieving galleries summary"))
@@ -4766,8 +5359,11 @@ impl Vermilion {
     Ok(Json(galleries))
   }
 
-  async fn gallery_inscriptions(params: Query<GalleryQueryParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Vec<FullMetadata>>, ApiError> {
-    let inscriptions = Self::get_gallery_inscriptions(server_config.deadpool, params.0).await
+  async fn gallery_inscriptions(params: Query<GalleryQueryParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Paginated<FullMetadata>>, ApiError> {
+    let params = params.0;
+    let keyset = params.sort_by.unwrap_or(GallerySortBy::BiggestOnChainFootprint).keyset("gallery_inscriptions", "g.gallery_id");
+    let page = PageRequest::new(params.page_number, std::cmp::min(params.page_size.unwrap_or(20), 100), params.cursor.as_deref(), &keyset)?;
+    let inscriptions = Self::get_gallery_inscriptions(server_config.deadpool, keyset, page, &server_config.hidden.params()).await
       .map_err(|error| {
         log::warn!("Error getting /gallery_inscriptions: {}", error);
         ApiError::InternalServerError(format!("Error retrieving gallery inscriptions"))
@@ -4775,9 +5371,9 @@ impl Vermilion {
     Ok(Json(inscriptions))
   }
 
-  async fn inscriptions_in_gallery(Path(gallery_id): Path<String>, params: Query<InscriptionQueryParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Vec<FullMetadata>>, ApiError> {
-    let parsed_params = ParsedInscriptionQueryParams::from(params.0);
-    let inscriptions = Self::get_inscriptions_in_gallery(server_config.deadpool, gallery_id.clone(), parsed_params).await
+  async fn inscriptions_in_gallery(Path(gallery_id): Path<String>, params: Query<InscriptionQueryParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Paginated<FullMetadata>>, ApiError> {
+    let parsed_params = params.0.parse(server_config.chain)?;
+    let inscriptions = Self::get_inscriptions_in_gallery(server_config.deadpool, gallery_id.clone(), parsed_params, &server_config.hidden.params()).await
       .map_err(|error| {
         log::warn!("Error getting /inscriptions_in_gallery: {}", error);
         ApiError::InternalServerError(format!("Error retrieving inscriptions in gallery {}", gallery_id))
@@ -4820,9 +5416,11 @@ impl Vermilion {
     Ok(Json(block_stats))
   }
 
-  async fn blocks(params: Query<BlockQueryParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Vec<CombinedBlockStats>>, ApiError> {
+  async fn blocks(params: Query<BlockQueryParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Paginated<CombinedBlockStats>>, ApiError> {
     let params = params.0;
-    let blocks = Self::get_blocks(server_config.deadpool, params).await
+    let sort_by = params.sort_by.unwrap_or(BlockSortBy::Newest);
+    let page = PageRequest::new(params.page_number, std::cmp::min(params.page_size.unwrap_or(20), 100), params.cursor.as_deref(), &sort_by.keyset())?;
+    let blocks = Self::get_blocks(server_config.deadpool, sort_by, page).await
       .map_err(|error| {
         log::warn!("Error getting /blocks: {}", error);
         ApiError::InternalServerError("Error retrieving blocks".to_string())
@@ -4831,20 +5429,31 @@ impl Vermilion {
   }
 
   async fn search_by_query(Path(SearchQuery(search_query)): Path<SearchQuery>, State(server_config): State<ApiServerConfig>) -> Result<Json<SearchResult>, ApiError> {
-    let search_result = Self::get_search_result(server_config.deadpool, search_query.clone()).await
+    let search_result = Self::get_search_result(server_config.deadpool, server_config.chain, search_query.clone()).await
       .map_err(|error| {
         log::warn!("Error getting /search_by_query: {}", error);
         ApiError::InternalServerError(format!("Error retrieving search results for {}", search_query))
       })?;
-    Ok(Json(search_result))
+    Ok(Json(server_config.hidden.check(search_result)?))
+  }
+
+  async fn search_text(params: Query<SearchTextParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Paginated<TextSearchResult>>, ApiError> {
+    let params = ParsedSearchTextParams::try_from(params.0)?;
+    let results = text_search::search_text(server_config.deadpool, params, &server_config.hidden.params()).await
+      .map_err(|error| {
+        log::warn!("Error getting /search_text: {}", error);
+        ApiError::InternalServerError("Error searching inscription text".to_string())
+      })?;
+    Ok(Json(results))
   }
 
   async fn block_icon(Path(BlockNumber(block)): Path<BlockNumber>, State(server_config): State<ApiServerConfig>) -> Result<ContentResponse, ApiError> {
-    let content_blob = Self::get_block_icon(server_config.deadpool, block).await
+    let content_blob = Self::get_block_icon(server_config.deadpool, &server_config.content_store, block).await
       .map_err(|error| {
         log::warn!("Error getting /block_icon: {}", error);
         ApiError::InternalServerError(format!("Error retrieving block icon {}", block.to_string()))
       })?;
+    server_config.hidden.check_content(None, &content_blob.sha256)?;
     let bytes = content_blob.content;
     let content_type = content_blob.content_type;
     let mut header_map = HeaderMap::new();
@@ -4854,11 +5463,12 @@ impl Vermilion {
   }
 
   async fn sat_block_icon(Path(BlockNumber(block)): Path<BlockNumber>, State(server_config): State<ApiServerConfig>) -> Result<ContentResponse, ApiError> {
-    let content_blob = Self::get_sat_block_icon(server_config.deadpool, block).await
+    let content_blob = Self::get_sat_block_icon(server_config.deadpool, &server_config.content_store, block).await
       .map_err(|error| {
         log::warn!("Error getting /block_icon: {}", error);
         ApiError::InternalServerError(format!("Error retrieving block icon {}", block.to_string()))
       })?;
+    server_config.hidden.check_content(None, &content_blob.sha256)?;
     let bytes = content_blob.content;
     let content_type = content_blob.content_type;
     let mut header_map = HeaderMap::new();
@@ -4873,7 +5483,138 @@ impl Vermilion {
         log::warn!("Error getting /block_transfers: {}", error);
         ApiError::InternalServerError(format!("Error retrieving transfers for block {}", block))
       })?;
-    Ok(Json(transfers))
+    Ok(Json(server_config.hidden.retain(transfers)))
+  }
+
+  async fn runes(params: Query<PaginationParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Vec<RuneSummary>>, ApiError> {
+    let runes = rune_indexer::get_runes(server_config.deadpool, params.0).await
+      .map_err(|error| {
+        log::warn!("Error getting /runes: {}", error);
+        ApiError::InternalServerError("Error retrieving runes".to_string())
+      })?;
+    Ok(Json(runes))
+  }
+
+  async fn rune(Path(SpacedRuneName(spaced_rune)): Path<SpacedRuneName>, State(server_config): State<ApiServerConfig>) -> Result<Json<RuneSummary>, ApiError> {
+    let rune = rune_indexer::get_rune(server_config.deadpool, spaced_rune.clone()).await
+      .map_err(|error| {
+        log::warn!("Error getting /rune: {}", error);
+        if error.to_string().contains("unexpected number of rows") {
+          ApiError::NotFound(format!("Rune not found {}", spaced_rune))
+        } else {
+          ApiError::InternalServerError(format!("Error retrieving rune {}", spaced_rune))
+        }
+      })?;
+    Ok(Json(rune))
+  }
+
+  async fn rune_holders(Path(RuneIdentifier(rune)): Path<RuneIdentifier>, params: Query<PaginationParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Vec<RuneHolder>>, ApiError> {
+    let holders = rune_indexer::get_rune_holders(server_config.deadpool, rune.clone(), params.0).await
+      .map_err(|error| {
+        log::warn!("Error getting /rune_holders: {}", error);
+        ApiError::InternalServerError(format!("Error retrieving holders for rune {}", rune))
+      })?;
+    Ok(Json(holders))
+  }
+
+  async fn rune_activity(Path(RuneIdentifier(rune)): Path<RuneIdentifier>, params: Query<PaginationParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Vec<RuneActivity>>, ApiError> {
+    let activity = rune_indexer::get_rune_activity(server_config.deadpool, rune.clone(), params.0).await
+      .map_err(|error| {
+        log::warn!("Error getting /rune_activity: {}", error);
+        ApiError::InternalServerError(format!("Error retrieving activity for rune {}", rune))
+      })?;
+    Ok(Json(activity))
+  }
+
+  async fn runes_in_block(Path(BlockNumber(block)): Path<BlockNumber>, params: Query<PaginationParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Vec<RuneSummary>>, ApiError> {
+    let runes = rune_indexer::get_runes_in_block(server_config.deadpool, block, params.0).await
+      .map_err(|error| {
+        log::warn!("Error getting /runes_in_block: {}", error);
+        ApiError::InternalServerError(format!("Error retrieving runes in block {}", block))
+      })?;
+    Ok(Json(runes))
+  }
+
+  async fn brc20_tickers(params: Query<Brc20PageParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Paginated<Brc20Ticker>>, ApiError> {
+    let params = params.0.parse(Brc20List::Tickers)?;
+    let tickers = brc20::get_tickers(server_config.deadpool, params).await
+      .map_err(|error| {
+        log::warn!("Error getting /brc20_tickers: {}", error);
+        ApiError::InternalServerError("Error retrieving BRC-20 tickers".to_string())
+      })?;
+    Ok(Json(tickers))
+  }
+
+  async fn brc20_ticker(Path(Brc20Tick(tick)): Path<Brc20Tick>, State(server_config): State<ApiServerConfig>) -> Result<Json<Brc20Ticker>, ApiError> {
+    let ticker = brc20::get_ticker(server_config.deadpool, tick.clone()).await
+      .map_err(|error| {
+        log::warn!("Error getting /brc20_ticker: {}", error);
+        ApiError::InternalServerError(format!("Error retrieving BRC-20 ticker {}", tick))
+      })?
+      .ok_or_else(|| ApiError::NotFound(format!("BRC-20 ticker not found {}", tick)))?;
+    Ok(Json(ticker))
+  }
+
+  async fn brc20_holders(Path(Brc20Tick(tick)): Path<Brc20Tick>, params: Query<Brc20PageParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Paginated<Brc20Balance>>, ApiError> {
+    let params = params.0.parse(Brc20List::Holders)?;
+    let holders = brc20::get_holders(server_config.deadpool, tick.clone(), params).await
+      .map_err(|error| {
+        log::warn!("Error getting /brc20_holders: {}", error);
+        ApiError::InternalServerError(format!("Error retrieving holders for BRC-20 ticker {}", tick))
+      })?;
+    Ok(Json(holders))
+  }
+
+  async fn brc20_activity(Path(Brc20Tick(tick)): Path<Brc20Tick>, params: Query<Brc20PageParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Paginated<Brc20Event>>, ApiError> {
+    let params = params.0.parse(Brc20List::Activity)?;
+    let activity = brc20::get_activity(server_config.deadpool, tick.clone(), params).await
+      .map_err(|error| {
+        log::warn!("Error getting /brc20_activity: {}", error);
+        ApiError::InternalServerError(format!("Error retrieving activity for BRC-20 ticker {}", tick))
+      })?;
+    Ok(Json(activity))
+  }
+
+  async fn address_brc20_balances(Path(BitcoinAddress(address)): Path<BitcoinAddress>, State(server_config): State<ApiServerConfig>) -> Result<Json<Vec<Brc20Balance>>, ApiError> {
+    let address = parse_address(&address, server_config.chain)
+      .map_err(|error| ApiError::BadRequest(format!("Invalid {} address {}: {}", server_config.chain, address, error)))?;
+    let balances = brc20::get_address_balances(server_config.deadpool, address.to_string()).await
+      .map_err(|error| {
+        log::warn!("Error getting /address_brc20_balances: {}", error);
+        ApiError::InternalServerError(format!("Error retrieving BRC-20 balances for {}", &*address))
+      })?;
+    Ok(Json(balances))
+  }
+
+  async fn bitmap(Path(BitmapNumber(bitmap_number)): Path<BitmapNumber>, State(server_config): State<ApiServerConfig>) -> Result<Json<Bitmap>, ApiError> {
+    let bitmap = bitmaps::get_bitmap(server_config.deadpool, bitmap_number).await
+      .map_err(|error| {
+        log::warn!("Error getting /bitmap: {}", error);
+        ApiError::InternalServerError(format!("Error retrieving bitmap {}", bitmap_number))
+      })?
+      .ok_or_else(|| ApiError::NotFound(format!("Bitmap {} has not been claimed", bitmap_number)))?;
+    Ok(Json(server_config.hidden.check(bitmap)?))
+  }
+
+  async fn bitmaps_in_address(Path(BitcoinAddress(address)): Path<BitcoinAddress>, params: Query<BitmapsInAddressParams>, State(server_config): State<ApiServerConfig>) -> Result<Json<Paginated<Bitmap>>, ApiError> {
+    let address = parse_address(&address, server_config.chain)
+      .map_err(|error| ApiError::BadRequest(format!("Invalid {} address {}: {}", server_config.chain, address, error)))?;
+    let params = ParsedBitmapsInAddressParams::try_from(params.0)?;
+    let bitmaps = bitmaps::get_bitmaps_in_address(server_config.deadpool, address.to_string(), params, &server_config.hidden.params()).await
+      .map_err(|error| {
+        log::warn!("Error getting /bitmaps_in_address: {}", error);
+        ApiError::InternalServerError(format!("Error retrieving bitmaps for {}", &*address))
+      })?;
+    Ok(Json(bitmaps))
+  }
+
+  async fn bitmap_availability(Path(BitmapNumber(bitmap_number)): Path<BitmapNumber>, State(server_config): State<ApiServerConfig>) -> Result<Json<BitmapAvailability>, ApiError> {
+    let availability = bitmaps::get_bitmap_availability(server_config.deadpool, bitmap_number).await
+      .map_err(|error| {
+        log::warn!("Error getting /bitmap_availability: {}", error);
+        ApiError::InternalServerError(format!("Error checking availability of bitmap {}", bitmap_number))
+      })?;
+    Ok(Json(availability))
   }
 
   async fn submit_package(State(server_config): State<ApiServerConfig>, Json(payload): Json<Vec<String>>) -> Result<Json<Vec<String>>, ApiError> {
@@ -5016,6 +5757,62 @@ impl Vermilion {
     Ok(())
   }
 
+  // Progress older than a day is dropped, as the collection may have changed since
+  async fn get_token_progress(pool: deadpool, collection_symbol: &str) -> anyhow::Result<i64> {
+    let mut conn = pool.get().await?;
+    let tx = conn.transaction().await?;
+    let row = tx.query_opt(
+      "SELECT next_offset FROM collection_token_progress WHERE collection_symbol=$1 AND updated_at > now() - interval '1 day'",
+      &[&collection_symbol]
+    ).await?;
+    if row.is_none() {
+      Self::remove_token_progress(&tx, collection_symbol).await?;
+    }
+    tx.commit().await?;
+    Ok(row.map_or(0, |row| row.get("next_offset")))
+  }
+
+  async fn insert_token_page(pool: deadpool, collection_symbol: &str, tokens: &[Collection], next_offset: i64) -> anyhow::Result<()> {
+    let ids: Vec<&str> = tokens.iter().map(|token| token.id.as_str()).collect();
+    let numbers: Vec<i64> = tokens.iter().map(|token| token.number).collect();
+    let off_chain_metadata: Vec<&JsonValue> = tokens.iter().map(|token| &token.off_chain_metadata).collect();
+    let mut conn = pool.get().await?;
+    let tx = conn.transaction().await?;
+    tx.execute(
+      "INSERT INTO collection_token_pages (collection_symbol, id, number, off_chain_metadata)
+       SELECT $1, * FROM unnest($2::varchar[], $3::bigint[], $4::jsonb[])
+       ON CONFLICT DO NOTHING",
+      &[&collection_symbol, &ids, &numbers, &off_chain_metadata]
+    ).await?;
+    tx.execute(
+      "INSERT INTO collection_token_progress (collection_symbol, next_offset) VALUES ($1, $2)
+       ON CONFLICT (collection_symbol) DO UPDATE SET next_offset=EXCLUDED.next_offset, updated_at=now()",
+      &[&collection_symbol, &next_offset]
+    ).await?;
+    tx.commit().await?;
+    Ok(())
+  }
+
+  async fn get_token_pages(pool: deadpool, collection_symbol: &str) -> anyhow::Result<Vec<Collection>> {
+    let conn = pool.get().await?;
+    let rows = conn.query(
+      "SELECT id, number, off_chain_metadata FROM collection_token_pages WHERE collection_symbol=$1 ORDER BY number",
+      &[&collection_symbol]
+    ).await?;
+    Ok(rows.into_iter().map(|row| Collection {
+      id: row.get("id"),
+      number: row.get("number"),
+      collection_symbol: collection_symbol.to_string(),
+      off_chain_metadata: row.get::<_, Option<JsonValue>>("off_chain_metadata").unwrap_or(JsonValue::Null),
+    }).collect())
+  }
+
+  async fn remove_token_progress(tx: &deadpool_postgres::Transaction<'_>, collection_symbol: &str) -> anyhow::Result<()> {
+    tx.execute("DELETE FROM collection_token_pages WHERE collection_symbol=$1", &[&collection_symbol]).await?;
+    tx.execute("DELETE FROM collection_token_progress WHERE collection_symbol=$1", &[&collection_symbol]).await?;
+    Ok(())
+  }
+
   async fn insert_recently_stored_collections(pool: deadpool, symbols: Vec<String>) -> anyhow::Result<()> {
     let mut conn = pool.get().await?;
     let tx = conn.transaction().await?;
@@ -5062,7 +5859,7 @@ impl Vermilion {
     Ok(supply)
   }
 
-  async fn get_ordinal_content(pool: deadpool, inscription_id: String) -> anyhow::Result<ContentBlob> {
+  async fn get_ordinal_content(pool: deadpool, content_store: &ContentStore, inscription_id: String) -> anyhow::Result<ContentBlob> {
     let conn = pool.clone().get().await?;
 
     // Get initial values before the loop
@@ -5093,11 +5890,11 @@ impl Vermilion {
     }
 
     let sha256 = sha256.ok_or(anyhow!("No sha256 found"))?;
-    let content = Self::get_ordinal_content_by_sha256(pool, sha256, content_type, content_encoding).await;
+    let content = Self::get_ordinal_content_by_sha256(pool, content_store, sha256, content_type, content_encoding).await;
     content
   }
 
-  async fn get_ordinal_content_by_number(pool: deadpool, number: i64) -> anyhow::Result<ContentBlob> {
+  async fn get_ordinal_content_by_number(pool: deadpool, content_store: &ContentStore, number: i64) -> anyhow::Result<ContentBlob> {
     let conn = pool.clone().get().await?;
 
     // Get initial values before the loop
@@ -5129,11 +5926,11 @@ impl Vermilion {
     }
 
     let sha256 = sha256.ok_or(anyhow!("No sha256 found"))?;
-    let content = Self::get_ordinal_content_by_sha256(pool, sha256, content_type, content_encoding).await;
+    let content = Self::get_ordinal_content_by_sha256(pool, content_store, sha256, content_type, content_encoding).await;
     content
   }
 
-  async fn get_ordinal_comment(pool: deadpool, inscription_id: String) -> anyhow::Result<ContentBlob> {
+  async fn get_ordinal_comment(pool: deadpool, content_store: &ContentStore, inscription_id: String) -> anyhow::Result<ContentBlob> {
     let conn = pool.clone().get().await?;
     let row = conn.query_one(
       "SELECT sha256, content_type, content_encoding, delegate FROM ordinals WHERE id=$1 LIMIT 1",
@@ -5145,11 +5942,11 @@ impl Vermilion {
     let delegate: Option<String> = row.get(3);
     let _delegate = delegate.ok_or(anyhow!("Delegate not found"))?;
     let sha256 = sha256.ok_or(anyhow!("Sha256 not found"))?;
-    let content = Self::get_ordinal_content_by_sha256(pool, sha256, content_type, content_encoding).await;
+    let content = Self::get_ordinal_content_by_sha256(pool, content_store, sha256, content_type, content_encoding).await;
     content
   }
 
-  async fn get_ordinal_comment_by_number(pool: deadpool, number: i64) -> anyhow::Result<ContentBlob> {
+  async fn get_ordinal_comment_by_number(pool: deadpool, content_store: &ContentStore, number: i64) -> anyhow::Result<ContentBlob> {
     let conn = pool.clone().get().await?;
     let row = conn.query_one(
       "SELECT sha256, content_type, content_encoding, delegate FROM ordinals WHERE number=$1 LIMIT 1",
@@ -5161,11 +5958,11 @@ impl Vermilion {
     let delegate: Option<String> = row.get(3);
     let _delegate = delegate.ok_or(anyhow!("Delegate not found"))?;
     let sha256 = sha256.ok_or(anyhow!("Sha256 not found"))?;
-    let content = Self::get_ordinal_content_by_sha256(pool, sha256, content_type, content_encoding).await;
+    let content = Self::get_ordinal_content_by_sha256(pool, content_store, sha256, content_type, content_encoding).await;
     content
   }
 
-  async fn get_ordinal_content_by_sha256(pool: deadpool, sha256: String, content_type_override: Option<String>, content_encoding_override: Option<String>) -> anyhow::Result<ContentBlob> {
+  async fn get_ordinal_content_by_sha256(pool: deadpool, content_store: &ContentStore, sha256: String, content_type_override: Option<String>, content_encoding_override: Option<String>) -> anyhow::Result<ContentBlob> {
     let conn = pool.get().await?;
     let moderation_flag = match conn.query_one(
       r"SELECT coalesce(human_override_moderation_flag, automated_moderation_flag)
@@ -5207,9 +6004,11 @@ impl Vermilion {
               LIMIT 1",
       &[&sha256]
     ).await?;
+    let store: Option<String> = row.get("store");
+    let content = content_store.get(store.as_deref(), &sha256, row.get("content")).await?;
     let mut content_blob = ContentBlob {
       sha256: row.get("sha256"),
-      content: row.get("content"),
+      content,
       content_type: row.get("content_type"),
       content_encoding: row.get("content_encoding")
     };
@@ -5220,25 +6019,25 @@ impl Vermilion {
     Ok(content_blob)
   }
 
-  async fn get_block_icon(pool: deadpool, block: i64) -> anyhow::Result<ContentBlob> {
+  async fn get_block_icon(pool: deadpool, content_store: &ContentStore, block: i64) -> anyhow::Result<ContentBlob> {
     let conn = pool.get().await?;
     let result = conn.query_one(
       "select id from ordinals where genesis_height=$1 and (content_type LIKE 'image%' or content_type LIKE 'text/html%') order by content_length desc nulls last limit 1",
       &[&block]
     ).await?;
     let id = result.get(0);
-    let content = Self::get_ordinal_content(pool, id).await?;
+    let content = Self::get_ordinal_content(pool, content_store, id).await?;
     Ok(content)
   }
 
-  async fn get_sat_block_icon(pool: deadpool, block: i64) -> anyhow::Result<ContentBlob> {
+  async fn get_sat_block_icon(pool: deadpool, content_store: &ContentStore, block: i64) -> anyhow::Result<ContentBlob> {
     let conn = pool.get().await?;
     let result = conn.query_one(
       "select id from ordinals where sat in (select sat from sat where block=$1) and (content_type LIKE 'image%' or content_type LIKE 'text/html%') order by content_length desc nulls last limit 1",
       &[&block]
     ).await?;
     let id = result.get(0);
-    let content = Self::get_ordinal_content(pool, id).await?;
+    let content = Self::get_ordinal_content(pool, content_store, id).await?;
     Ok(content)
   }
 
@@ -5358,11 +6157,16 @@ impl Vermilion {
     Ok(edition)
   }
 
-  async fn get_matching_inscriptions_by_sha256(pool: deadpool, sha256: String, params: PaginationParams) -> anyhow::Result<Vec<InscriptionNumberEdition>> {
+  async fn get_matching_inscriptions_by_sha256(pool: deadpool, sha256: String, params: PaginationParams, hidden: &HiddenParams) -> anyhow::Result<Vec<InscriptionNumberEdition>> {
     let conn = pool.get().await?;
     let page_size = params.page_size.unwrap_or(10);
     let offset = params.page_number.unwrap_or(0) * page_size;
-    let mut query = "SELECT id, number, edition, t.total from (select * from editions where sha256=$1) e inner join editions_total t on t.sha256=e.sha256 order by edition asc".to_string();
+    let mut sql_params = SqlParams::default();
+    let mut query = format!("SELECT id, number, edition, t.total from (select * from editions where sha256={}) e inner join editions_total t on t.sha256=e.sha256", sql_params.push(sha256));
+    if let Some(visible) = hidden.visible_ids(&["e.id"], &mut sql_params) {
+      query.push_str(format!(" WHERE {}", visible).as_str());
+    }
+    query.push_str(" order by edition asc");
     if page_size > 0 {
       query.push_str(format!(" LIMIT {}", page_size).as_str());
     }
@@ -5371,7 +6175,7 @@ impl Vermilion {
     }
     let result = conn.query(
       query.as_str(),
-      &[&sha256]
+      &sql_params.values()
     ).await?;
     let mut editions = Vec::new();
     for row in result {
@@ -5385,22 +6189,19 @@ impl Vermilion {
     Ok(editions)
   }
 
-  async fn get_inscription_children(pool: deadpool, inscription_id: String, params: ParsedInscriptionQueryParams) -> anyhow::Result<Vec<FullMetadata>> {
+  async fn get_inscription_children(pool: deadpool, inscription_id: String, params: ParsedInscriptionQueryParams, hidden: &HiddenParams) -> anyhow::Result<Paginated<FullMetadata>> {
     let conn = pool.get().await?;
-    let base_query = "SELECT * FROM ordinals_full_v o WHERE parents && ARRAY[$1::varchar]".to_string();
-    let full_query = Self::create_inscription_query_string(base_query, params);
+    let mut sql_params = SqlParams::default();
+    let base_query = format!("SELECT * FROM ordinals_full_v o WHERE parents && ARRAY[{}::varchar]", sql_params.push(inscription_id));
+    let full_query = Self::create_inscription_query_string(base_query, &params, hidden, &mut sql_params);
     let result = conn.query(
       full_query.as_str(),
-      &[&inscription_id]
+      &sql_params.values()
     ).await?;
-    let mut inscriptions = Vec::new();
-    for row in result {
-      inscriptions.push(Self::map_row_to_fullmetadata(row));
-    }
-    Ok(inscriptions)
+    Ok(params.page.paginate(&params.keyset, result, Self::map_row_to_fullmetadata))
   }
 
-  async fn get_inscription_children_by_number(pool: deadpool, number: i64, params: ParsedInscriptionQueryParams) -> anyhow::Result<Vec<FullMetadata>> {
+  async fn get_inscription_children_by_number(pool: deadpool, number: i64, params: ParsedInscriptionQueryParams, hidden: &HiddenParams) -> anyhow::Result<Paginated<FullMetadata>> {
     let conn = pool.get().await?;
     let query = "Select id from ordinals where number=$1";
     let result = conn.query_one(
@@ -5408,26 +6209,23 @@ impl Vermilion {
       &[&number]
     ).await?;
     let id: String = result.get(0);
-    let inscriptions = Self::get_inscription_children(pool, id, params).await;
+    let inscriptions = Self::get_inscription_children(pool, id, params, hidden).await;
     inscriptions
   }
 
-  async fn get_inscription_referenced_by(pool: deadpool, inscription_id: String, params: ParsedInscriptionQueryParams) -> anyhow::Result<Vec<FullMetadata>> {
+  async fn get_inscription_referenced_by(pool: deadpool, inscription_id: String, params: ParsedInscriptionQueryParams, hidden: &HiddenParams) -> anyhow::Result<Paginated<FullMetadata>> {
     let conn = pool.get().await?;
-    let base_query = "SELECT * FROM ordinals_full_v o WHERE referenced_ids && ARRAY[$1::varchar]".to_string();
-    let full_query = Self::create_inscription_query_string(base_query, params);
+    let mut sql_params = SqlParams::default();
+    let base_query = format!("SELECT * FROM ordinals_full_v o WHERE referenced_ids && ARRAY[{}::varchar]", sql_params.push(inscription_id));
+    let full_query = Self::create_inscription_query_string(base_query, &params, hidden, &mut sql_params);
     let result = conn.query(
       full_query.as_str(),
-      &[&inscription_id]
+      &sql_params.values()
     ).await?;
-    let mut inscriptions = Vec::new();
-    for row in result {
-      inscriptions.push(Self::map_row_to_fullmetadata(row));
-    }
-    Ok(inscriptions)
+    Ok(params.page.paginate(&params.keyset, result, Self::map_row_to_fullmetadata))
   }
 
-  async fn get_inscription_referenced_by_number(pool: deadpool, number: i64, params: ParsedInscriptionQueryParams) -> anyhow::Result<Vec<FullMetadata>> {
+  async fn get_inscription_referenced_by_number(pool: deadpool, number: i64, params: ParsedInscriptionQueryParams, hidden: &HiddenParams) -> anyhow::Result<Paginated<FullMetadata>> {
     let conn = pool.get().await?;
     let query = "Select id from ordinals where number=$1";
     let result = conn.query_one(
@@ -5435,11 +6233,11 @@ impl Vermilion {
       &[&number]
     ).await?;
     let id: String = result.get(0);
-    let inscriptions = Self::get_inscription_referenced_by(pool, id, params).await;
+    let inscriptions = Self::get_inscription_referenced_by(pool, id, params, hidden).await;
     inscriptions
   }
 
-  async fn get_inscription_bootlegs(pool: deadpool, inscription_id: String, params: PaginationParams) -> anyhow::Result<Vec<BootlegEdition>> {
+  async fn get_inscription_bootlegs(pool: deadpool, inscription_id: String, params: PaginationParams, hidden: &HiddenParams) -> anyhow::Result<Vec<BootlegEdition>> {
     let conn = pool.get().await?;
     let page_size = params.page_size.unwrap_or(10);
     let offset = params.page_number.unwrap_or(0) * page_size;
@@ -5455,6 +6253,11 @@ impl Vermilion {
     left join addresses a on d.bootleg_id=a.id
     left join ordinals o on d.bootleg_id=o.id
     WHERE d.delegate_id=$1"#.to_string();
+    let mut sql_params = SqlParams::default();
+    sql_params.push(inscription_id);
+    if let Some(visible) = hidden.visible_ids(&["d.bootleg_id", "d.delegate_id"], &mut sql_params) {
+      query.push_str(format!(" AND {}", visible).as_str());
+    }
     if page_size > 0 {
       query.push_str(format!(" LIMIT {}", page_size).as_str());
     }
@@ -5463,7 +6266,7 @@ impl Vermilion {
     }
     let result = conn.query(
       query.as_str(),
-      &[&inscription_id]
+      &sql_params.values()
     ).await?;
     let mut bootlegs = Vec::new();
     for row in result {
@@ -5482,7 +6285,7 @@ impl Vermilion {
     Ok(bootlegs)
   }
 
-  async fn get_inscription_bootlegs_by_number(pool: deadpool, number: i64, params: PaginationParams) -> anyhow::Result<Vec<BootlegEdition>> {
+  async fn get_inscription_bootlegs_by_number(pool: deadpool, number: i64, params: PaginationParams, hidden: &HiddenParams) -> anyhow::Result<Vec<BootlegEdition>> {
     let conn = pool.get().await?;
     let page_size = params.page_size.unwrap_or(10);
     let offset = params.page_number.unwrap_or(0) * page_size;
@@ -5498,6 +6301,11 @@ impl Vermilion {
     left join addresses a on d.bootleg_id=a.id
     left join ordinals o on d.bootleg_id=o.id
     WHERE d.delegate_id=(SELECT id FROM ordinals WHERE number=$1 LIMIT 1)"#.to_string();
+    let mut sql_params = SqlParams::default();
+    sql_params.push(number);
+    if let Some(visible) = hidden.visible_ids(&["d.bootleg_id", "d.delegate_id"], &mut sql_params) {
+      query.push_str(format!(" AND {}", visible).as_str());
+    }
     if page_size > 0 {
       query.push_str(format!(" LIMIT {}", page_size).as_str());
     }
@@ -5506,7 +6314,7 @@ impl Vermilion {
     }
     let result = conn.query(
       query.as_str(),
-      &[&number]
+      &sql_params.values()
     ).await?;
     let mut bootlegs = Vec::new();
     for row in result {
@@ -5587,7 +6395,7 @@ impl Vermilion {
     Ok(edition)
   }
 
-  async fn get_inscription_comments(pool: deadpool, inscription_id: String, params: PaginationParams) -> anyhow::Result<Vec<CommentEdition>> {
+  async fn get_inscription_comments(pool: deadpool, inscription_id: String, params: PaginationParams, hidden: &HiddenParams) -> anyhow::Result<Vec<CommentEdition>> {
     let conn = pool.get().await?;
     let page_size = params.page_size.unwrap_or(10);
     let offset = params.page_number.unwrap_or(0) * page_size;
@@ -5603,6 +6411,11 @@ impl Vermilion {
     left join addresses a on c.comment_id=a.id
     left join ordinals o on c.comment_id=o.id
     WHERE c.delegate_id=$1"#.to_string();
+    let mut sql_params = SqlParams::default();
+    sql_params.push(inscription_id);
+    if let Some(visible) = hidden.visible_ids(&["c.comment_id", "c.delegate_id"], &mut sql_params) {
+      query.push_str(format!(" AND {}", visible).as_str());
+    }
     if page_size > 0 {
       query.push_str(format!(" LIMIT {}", page_size).as_str());
     }
@@ -5611,7 +6424,7 @@ impl Vermilion {
     }
     let result = conn.query(
       query.as_str(),
-      &[&inscription_id]
+      &sql_params.values()
     ).await?;
     let mut comments = Vec::new();
     for row in result {
@@ -5630,7 +6443,7 @@ impl Vermilion {
     Ok(comments)
   }
 
-  async fn get_inscription_comments_by_number(pool: deadpool, number: i64, params: PaginationParams) -> anyhow::Result<Vec<CommentEdition>> {
+  async fn get_inscription_comments_by_number(pool: deadpool, number: i64, params: PaginationParams, hidden: &HiddenParams) -> anyhow::Result<Vec<CommentEdition>> {
     let conn = pool.get().await?;
     let page_size = params.page_size.unwrap_or(10);
     let offset = params.page_number.unwrap_or(0) * page_size;
@@ -5646,6 +6459,11 @@ impl Vermilion {
     left join addresses a on c.comment_id=a.id
     left join ordinals o on c.comment_id=o.id
     WHERE c.delegate_id=(SELECT id FROM ordinals WHERE number=$1 LIMIT 1)"#.to_string();
+    let mut sql_params = SqlParams::default();
+    sql_params.push(number);
+    if let Some(visible) = hidden.visible_ids(&["c.comment_id", "c.delegate_id"], &mut sql_params) {
+      query.push_str(format!(" AND {}", visible).as_str());
+    }
     if page_size > 0 {
       query.push_str(format!(" LIMIT {}", page_size).as_str());
     }
@@ -5654,7 +6472,7 @@ impl Vermilion {
     }
     let result = conn.query(
       query.as_str(),
-      &[&number]
+      &sql_params.values()
     ).await?;
     let mut comments = Vec::new();
     for row in result {
@@ -5715,20 +6533,17 @@ impl Vermilion {
     Ok(editions)
   }
 
-  async fn get_inscriptions_within_block(pool: deadpool, block: i64, params: ParsedInscriptionQueryParams) -> anyhow::Result<Vec<FullMetadata>> {
+  async fn get_inscriptions_within_block(pool: deadpool, block: i64, params: ParsedInscriptionQueryParams, hidden: &HiddenParams) -> anyhow::Result<Paginated<FullMetadata>> {
     let conn = pool.get().await?;
-    let base_query = "SELECT * FROM ordinals_full_v o WHERE genesis_height=$1".to_string();
-    let full_query = Self::create_inscription_query_string(base_query, params);
+    let mut sql_params = SqlParams::default();
+    let base_query = format!("SELECT * FROM ordinals_full_v o WHERE genesis_height={}", sql_params.push(block));
+    let full_query = Self::create_inscription_query_string(base_query, &params, hidden, &mut sql_params);
     println!("{}", full_query);
     let result = conn.query(
       full_query.as_str(),
-      &[&block]
+      &sql_params.values()
     ).await?;
-    let mut inscriptions = Vec::new();
-    for row in result {
-      inscriptions.push(Self::map_row_to_fullmetadata(row));
-    }
-    Ok(inscriptions)
+    Ok(params.page.paginate(&params.keyset, result, Self::map_row_to_fullmetadata))
   }
 
   async fn get_random_inscription(pool: deadpool, random_float: f64) -> anyhow::Result<(FullMetadata, (f64, f64))> {
@@ -5862,7 +6677,7 @@ impl Vermilion {
     Ok(leaderboard)
   }
 
-async fn get_trending_feed_items(pool: deadpool, n: u32, mut already_seen_bands: Vec<i64>) -> anyhow::Result<Vec<TrendingItem>> {
+async fn get_trending_feed_items(pool: deadpool, n: u32, mut already_seen_bands: Vec<i64>, hidden: Arc<HiddenParams>) -> anyhow::Result<Vec<TrendingItem>> {
   let n = std::cmp::min(n, 100);
   let all_bands = Self::get_trending_bands(pool.clone()).await?;
   let mut rng = rand::rngs::StdRng::from_entropy();
@@ -5915,7 +6730,7 @@ async fn get_trending_feed_items(pool: deadpool, n: u32, mut already_seen_bands:
   let mut set = JoinSet::new();
   let mut trending_items = Vec::new();
   for i in 0..n {
-    set.spawn(Self::get_trending_feed_item(pool.clone(), random_floats[i as usize]));
+    set.spawn(Self::get_trending_feed_item(pool.clone(), random_floats[i as usize], hidden.clone()));
   }
   while let Some(res) = set.join_next().await {
     let trending_item = res??;
@@ -5925,13 +6740,22 @@ async fn get_trending_feed_items(pool: deadpool, n: u32, mut already_seen_bands:
   Ok(trending_items)
 }
 
-  async fn get_trending_feed_item(pool: deadpool, random_float: f64) -> anyhow::Result<TrendingItem> {
+  async fn get_trending_feed_item(pool: deadpool, random_float: f64, hidden: Arc<HiddenParams>) -> anyhow::Result<TrendingItem> {
     let conn = pool.get().await?;
-    let random_inscription_band = conn.query_one(
-      "SELECT ids, block_age, most_recent_timestamp, children_count, delegate_count, comment_count, band_start, band_end, band_id from trending_summary where band_end>$1 order by band_end limit 1",
-      &[&random_float]
-    ).await?;
-    let trending_item_activity = TrendingItemActivity {
+    // Skips bands with nothing visible left, wrapping around to the first band if every later one is hidden
+    let query = format!(
+      "SELECT ids, block_age, most_recent_timestamp, children_count, delegate_count, comment_count, band_start, band_end, band_id from trending_summary
+       where band_end>$1 and exists (SELECT 1 from ordinals_full_t o where o.id=ANY(ids) AND {VISIBLE_SQL})
+       order by band_end limit 1");
+    let mut random_inscription_band = None;
+    for start in [random_float, 0.0] {
+      random_inscription_band = conn.query_opt(&query, &[&start, &hidden.ids, &hidden.sha256s]).await?;
+      if random_inscription_band.is_some() {
+        break;
+      }
+    }
+    let random_inscription_band = random_inscription_band.ok_or_else(|| anyhow!("No visible trending bands"))?;
+    let mut trending_item_activity = TrendingItemActivity {
       ids: random_inscription_band.get("ids"),
       block_age: random_inscription_band.get("block_age"),
       most_recent_timestamp: random_inscription_band.get("most_recent_timestamp"),
@@ -5943,13 +6767,15 @@ async fn get_trending_feed_items(pool: deadpool, n: u32, mut already_seen_bands:
       band_id: random_inscription_band.get("band_id"),
     };
     let result = conn.query(
-      "SELECT * from ordinals_full_v where id=ANY($1)",
-      &[&trending_item_activity.ids]
+      &format!("SELECT * from ordinals_full_v o where o.id=ANY($1) AND {VISIBLE_SQL}"),
+      &[&trending_item_activity.ids, &hidden.ids, &hidden.sha256s]
     ).await?;
     let mut inscriptions = Vec::new();
     for row in result {
       inscriptions.push(Self::map_row_to_fullmetadata(row));
     }
+    // Only ids with a visible row, which also leaves out inscriptions hidden by their content
+    trending_item_activity.ids.retain(|id| inscriptions.iter().any(|inscription| inscription.id == *id));
     Ok(TrendingItem {
       inscriptions: inscriptions,
       activity: trending_item_activity
@@ -5969,7 +6795,7 @@ async fn get_trending_feed_items(pool: deadpool, n: u32, mut already_seen_bands:
     Ok(bands)
   }
 
-  async fn get_discover_feed_items(pool: deadpool, n: u32, already_seen_bands: Vec<(f64, f64)>) -> anyhow::Result<Vec<DiscoverItem>> {
+  async fn get_discover_feed_items(pool: deadpool, n: u32, already_seen_bands: Vec<(f64, f64)>, hidden: Arc<HiddenParams>) -> anyhow::Result<Vec<DiscoverItem>> {
     let n = std::cmp::min(n, 100);
     let mut rng = rand::rngs::StdRng::from_entropy();
     let mut random_floats = Vec::new();
@@ -5992,7 +6818,7 @@ async fn get_trending_feed_items(pool: deadpool, n: u32, mut already_seen_bands:
     let mut set = JoinSet::new();
     let mut discover_items = Vec::new();
     for i in 0..n {
-      set.spawn(Self::get_discover_feed_item(pool.clone(), random_floats[i as usize]));
+      set.spawn(Self::get_discover_feed_item(pool.clone(), random_floats[i as usize], hidden.clone()));
     }
     while let Some(res) = set.join_next().await {
       let discover_item = res??;
@@ -6001,13 +6827,22 @@ async fn get_trending_feed_items(pool: deadpool, n: u32, mut already_seen_bands:
     Ok(discover_items)
   }
 
-  async fn get_discover_feed_item(pool: deadpool, random_float: f64) -> anyhow::Result<DiscoverItem> {
+  async fn get_discover_feed_item(pool: deadpool, random_float: f64, hidden: Arc<HiddenParams>) -> anyhow::Result<DiscoverItem> {
     let conn = pool.get().await?;
-    let random_inscription_band = conn.query_one(
-      "SELECT ids, children_count, delegate_count, comment_count, edition_count, block_age, most_recent_timestamp, band_start, band_end, class_band_start, class_band_end from discover_weights where band_end>$1 order by band_end limit 1",
-      &[&random_float]
-    ).await?;
-    let discover_item_activity = DiscoverItemActivity {
+    // Skips bands with nothing visible left, wrapping around to the first band if every later one is hidden
+    let query = format!(
+      "SELECT ids, children_count, delegate_count, comment_count, edition_count, block_age, most_recent_timestamp, band_start, band_end, class_band_start, class_band_end from discover_weights
+       where band_end>$1 and exists (SELECT 1 from ordinals_full_t o where o.id=ANY(ids) AND {VISIBLE_SQL})
+       order by band_end limit 1");
+    let mut random_inscription_band = None;
+    for start in [random_float, 0.0] {
+      random_inscription_band = conn.query_opt(&query, &[&start, &hidden.ids, &hidden.sha256s]).await?;
+      if random_inscription_band.is_some() {
+        break;
+      }
+    }
+    let random_inscription_band = random_inscription_band.ok_or_else(|| anyhow!("No visible discover bands"))?;
+    let mut discover_item_activity = DiscoverItemActivity {
       ids: random_inscription_band.get("ids"),
       block_age: random_inscription_band.get("block_age"),
       most_recent_timestamp: random_inscription_band.get("most_recent_timestamp"),
@@ -6021,21 +6856,22 @@ async fn get_trending_feed_items(pool: deadpool, n: u32, mut already_seen_bands:
       class_band_end: random_inscription_band.get("class_band_end")
     };
     let result = conn.query(
-      "SELECT * from ordinals_full_v where id=ANY($1)",
-      &[&discover_item_activity.ids]
+      &format!("SELECT * from ordinals_full_v o where o.id=ANY($1) AND {VISIBLE_SQL}"),
+      &[&discover_item_activity.ids, &hidden.ids, &hidden.sha256s]
     ).await?;
     let mut inscriptions = Vec::new();
     for row in result {
       inscriptions.push(Self::map_row_to_fullmetadata(row));
     }
+    // Only ids with a visible row, which also leaves out inscriptions hidden by their content
+    discover_item_activity.ids.retain(|id| inscriptions.iter().any(|inscription| inscription.id == *id));
     Ok(DiscoverItem {
       inscriptions: inscriptions,
       activity: discover_item_activity
     })
   }
 
-  fn create_inscription_query_string(base_query: String, params: ParsedInscriptionQueryParams) -> String {
-    let mut query = base_query;
+  fn push_inscription_filters(query: &mut String, params: &ParsedInscriptionQueryParams, hidden: &HiddenParams, sql_params: &mut SqlParams) {
     if params.content_types.len() > 0 {
       query.push_str(" AND (");
       for (i, content_type) in params.content_types.iter().enumerate() {
@@ -6063,99 +6899,71 @@ async fn get_trending_feed_items(pool: deadpool, n: u32, mut already_seen_bands:
     if params.charms.len() > 0 {
       query.push_str(format!(" AND (o.charms && array['{}'::varchar])", params.charms.join("'::varchar,'")).as_str());
     }
-    let order_clause = match params.sort_by {
-      InscriptionSortBy::Newest => " ORDER BY o.sequence_number DESC",
-      InscriptionSortBy::Oldest => " ORDER BY o.sequence_number ASC",
-      InscriptionSortBy::NewestSat => " ORDER BY o.sat DESC",
-      InscriptionSortBy::OldestSat => " ORDER BY o.sat ASC",
-      InscriptionSortBy::RarestSat => "",  // No sorting implemented yet
-      InscriptionSortBy::CommonestSat => "",  // No sorting implemented yet
-      InscriptionSortBy::Biggest => " ORDER BY o.content_length DESC",
-      InscriptionSortBy::Smallest => " ORDER BY o.content_length ASC",
-      InscriptionSortBy::HighestFee => " ORDER BY o.genesis_fee DESC",
-      InscriptionSortBy::LowestFee => " ORDER BY o.genesis_fee ASC",
-    };
-    query.push_str(order_clause);
-    if params.page_size > 0 {
-      query.push_str(format!(" LIMIT {}", params.page_size).as_str());
-    }
-    if params.page_number > 0 {
-      query.push_str(format!(" OFFSET {}", params.page_number * params.page_size).as_str());
-    }
-    query
-  }
-
-  async fn get_inscriptions(pool: deadpool, params: ParsedInscriptionQueryParams) -> anyhow::Result<Vec<FullMetadata>> {
-    let conn = pool.get().await?;
-    //1. build query
-    let mut query = "SELECT o.* FROM ordinals_full_v o WHERE 1=1".to_string();
-    if params.content_types.len() > 0 {
-      query.push_str(" AND (");
-      for (i, content_type) in params.content_types.iter().enumerate() {
-        let category = match content_type {
-          ContentType::Text => "o.content_category = 'text'",
-          ContentType::Image => "o.content_category = 'image'",
-          ContentType::Gif => "o.content_category = 'gif'",
-          ContentType::Audio => "o.content_category = 'audio'",
-          ContentType::Video => "o.content_category = 'video'",
-          ContentType::Html => "o.content_category = 'html'",
-          ContentType::Json => "o.content_category = 'json'",
-          ContentType::Namespace => "o.content_category = 'namespace'",
-          ContentType::Javascript => "o.content_category = 'javascript'",
-        };
-        query.push_str(category);
-        if i < params.content_types.len() - 1 {
-          query.push_str(" OR ");
-        }
+    for (column, (min, max)) in [
+      ("o.genesis_height", params.genesis_height),
+      ("o.timestamp", params.timestamp),
+      ("o.content_length", params.content_length),
+      ("o.genesis_fee", params.genesis_fee),
+    ] {
+      if let Some(min) = min {
+        query.push_str(format!(" AND {} >= {}", column, min).as_str());
+      }
+      if let Some(max) = max {
+        query.push_str(format!(" AND {} <= {}", column, max).as_str());
       }
-      query.push_str(")");
     }
-    if params.satributes.len() > 0 {
-      query.push_str(format!(" AND (o.satributes && array['{}'::varchar])", params.satributes.join("'::varchar,'")).as_str());
+    for (column, value) in [
+      ("o.metaprotocol", &params.metaprotocol),
+      ("o.inscribed_by_address", &params.inscribed_by_address),
+      ("o.spaced_rune", &params.spaced_rune),
+    ] {
+      if let Some(value) = value {
+        query.push_str(format!(" AND {} = {}", column, sql_params.push(value.clone())).as_str());
+      }
     }
-    if params.charms.len() > 0 {
-      query.push_str(format!(" AND (o.charms && array['{}'::varchar])", params.charms.join("'::varchar,'")).as_str());
+    // parents is an empty array rather than null for inscriptions without parents
+    for (filter, (present, absent)) in [
+      (params.has_parent, ("cardinality(o.parents) > 0", "coalesce(cardinality(o.parents), 0) = 0")),
+      (params.has_delegate, ("o.delegate IS NOT NULL", "o.delegate IS NULL")),
+      (params.is_recursive, ("o.is_recursive", "o.is_recursive IS NOT TRUE")),
+      (params.is_json, ("o.is_json", "o.is_json IS NOT TRUE")),
+    ] {
+      match filter {
+        Some(true) => query.push_str(format!(" AND {}", present).as_str()),
+        Some(false) => query.push_str(format!(" AND {}", absent).as_str()),
+        None => {}
+      }
     }
-    let order_clause = match params.sort_by {
-      InscriptionSortBy::Newest => " ORDER BY o.sequence_number DESC",
-      InscriptionSortBy::Oldest => " ORDER BY o.sequence_number ASC",
-      InscriptionSortBy::NewestSat => " ORDER BY o.sat DESC",
-      InscriptionSortBy::OldestSat => " ORDER BY o.sat ASC",
-      InscriptionSortBy::RarestSat => "",  // No sorting implemented yet
-      InscriptionSortBy::CommonestSat => "",  // No sorting implemented yet
-      InscriptionSortBy::Biggest => " ORDER BY o.content_length DESC",
-      InscriptionSortBy::Smallest => " ORDER BY o.content_length ASC",
-      InscriptionSortBy::HighestFee => " ORDER BY o.genesis_fee DESC",
-      InscriptionSortBy::LowestFee => " ORDER BY o.genesis_fee ASC",
-    };
-    query.push_str(order_clause);
-    if params.page_size > 0 {
-      query.push_str(format!(" LIMIT {}", params.page_size).as_str());
+    if let Some(visible) = hidden.visible(sql_params) {
+      query.push_str(format!(" AND {}", visible).as_str());
     }
-    if params.page_number > 0 {
-      query.push_str(format!(" OFFSET {}", params.page_number * params.page_size).as_str());
+    if let Some(after) = params.page.filter(&params.keyset, sql_params) {
+      query.push_str(format!(" AND {}", after).as_str());
     }
+  }
+
+  fn create_inscription_query_string(base_query: String, params: &ParsedInscriptionQueryParams, hidden: &HiddenParams, sql_params: &mut SqlParams) -> String {
+    let mut query = base_query;
+    Self::push_inscription_filters(&mut query, params, hidden, sql_params);
+    query.push_str(&params.keyset.order_by());
+    query.push_str(&params.page.limit());
+    query
+  }
+
+  async fn get_inscriptions(pool: deadpool, params: ParsedInscriptionQueryParams, hidden: &HiddenParams) -> anyhow::Result<Paginated<FullMetadata>> {
+    let conn = pool.get().await?;
+    let mut sql_params = SqlParams::default();
+    let query = Self::create_inscription_query_string("SELECT o.* FROM ordinals_full_v o WHERE 1=1".to_string(), &params, hidden, &mut sql_params);
     println!("Query: {}", query);
     let result = conn.query(
       query.as_str(),
-      &[]
+      &sql_params.values()
     ).await?;
-    let mut inscriptions = Vec::new();
-    for row in result {
-      inscriptions.push(Self::map_row_to_fullmetadata(row));
-    }
-    Ok(inscriptions)
+    Ok(params.page.paginate(&params.keyset, result, Self::map_row_to_fullmetadata))
   }
 
-  async fn get_deadpool(settings: Settings) -> anyhow::Result<deadpool> {
-    let mut deadpool_cfg = deadpool_postgres::Config::new();
-    deadpool_cfg.host = settings.db_host().map(|s| s.to_string());
-    deadpool_cfg.dbname = settings.db_name().map(|s| s.to_string());
-    deadpool_cfg.user = settings.db_user().map(|s| s.to_string());
-    deadpool_cfg.password = settings.db_password().map(|s| s.to_string());
-    deadpool_cfg.manager = Some(ManagerConfig { recycling_method: RecyclingMethod::Fast });
-    let deadpool = deadpool_cfg.create_pool(Some(deadpool_postgres::Runtime::Tokio1), NoTls)?;
-    Ok(deadpool)
+  pub(crate) async fn get_deadpool(settings: Settings) -> anyhow::Result<deadpool> {
+    database::create_pool(&settings)
   }
 
   async fn get_last_ordinal_transfer(pool: deadpool, inscription_id: String) -> anyhow::Result<Transfer> {
@@ -6268,19 +7076,16 @@ async fn get_trending_feed_items(pool: deadpool, n: u32, mut already_seen_bands:
     Ok(transfers)
   }
 
-  async fn get_inscriptions_by_address(pool: deadpool, address: String, params: ParsedInscriptionQueryParams) -> anyhow::Result<Vec<FullMetadata>> {
+  async fn get_inscriptions_by_address(pool: deadpool, address: String, params: ParsedInscriptionQueryParams, hidden: &HiddenParams) -> anyhow::Result<Paginated<FullMetadata>> {
     let conn = pool.get().await?;
-    let base_query = " SELECT o.* FROM addresses a LEFT JOIN ordinals_full_v o ON a.id=o.id WHERE a.address=$1".to_string();
-    let full_query = Self::create_inscription_query_string(base_query, params);
+    let mut sql_params = SqlParams::default();
+    let base_query = format!(" SELECT o.* FROM addresses a LEFT JOIN ordinals_full_v o ON a.id=o.id WHERE a.address={}", sql_params.push(address));
+    let full_query = Self::create_inscription_query_string(base_query, &params, hidden, &mut sql_params);
     let result = conn.query(
       full_query.as_str(),
-      &[&address]
+      &sql_params.values()
     ).await?;
-    let mut inscriptions = Vec::new();
-    for row in result {
-      inscriptions.push(Self::map_row_to_fullmetadata(row));
-    }
-    Ok(inscriptions)
+    Ok(params.page.paginate(&params.keyset, result, Self::map_row_to_fullmetadata))
   }
 
   async fn get_inscriptions_on_sat(pool: deadpool, sat: i64) -> anyhow::Result<Vec<FullMetadata>> {
@@ -6296,19 +7101,16 @@ async fn get_trending_feed_items(pool: deadpool, n: u32, mut already_seen_bands:
     Ok(inscriptions)
   }
 
-  async fn get_inscriptions_in_sat_block(pool: deadpool, block: i64, params: ParsedInscriptionQueryParams) -> anyhow::Result<Vec<FullMetadata>> {
+  async fn get_inscriptions_in_sat_block(pool: deadpool, block: i64, params: ParsedInscriptionQueryParams, hidden: &HiddenParams) -> anyhow::Result<Paginated<FullMetadata>> {
     let conn = pool.get().await?;
-    let base_query = "select o.* from ordinals_full_v o where o.sat_block=$1".to_string();
-    let full_query = Self::create_inscription_query_string(base_query, params);
+    let mut sql_params = SqlParams::default();
+    let base_query = format!("select o.* from ordinals_full_v o where o.sat_block={}", sql_params.push(block));
+    let full_query = Self::create_inscription_query_string(base_query, &params, hidden, &mut sql_params);
     let result = conn.query(
       full_query.as_str(),
-      &[&block]
+      &sql_params.values()
     ).await?;
-    let mut inscriptions = Vec::new();
-    for row in result {
-      inscriptions.push(Self::map_row_to_fullmetadata(row));
-    }
-    Ok(inscriptions)
+    Ok(params.page.paginate(&params.keyset, result, Self::map_row_to_fullmetadata))
   }
 
   async fn get_sat_metadata(pool: deadpool, sat: i64) -> anyhow::Result<SatMetadata> {
@@ -6395,11 +7197,8 @@ async fn get_trending_feed_items(pool: deadpool, n: u32, mut already_seen_bands:
     Ok(satributes)
   }
 
-  async fn get_collections(pool: deadpool, params: CollectionQueryParams) -> anyhow::Result<Vec<CollectionSummary>> {
+  async fn get_collections(pool: deadpool, keyset: Keyset, page: PageRequest) -> anyhow::Result<Paginated<CollectionSummary>> {
     let conn = pool.get().await?;
-    let sort_by = params.sort_by.unwrap_or(CollectionSortBy::BiggestOnChainFootprint);
-    let page_size = std::cmp::min(params.page_size.unwrap_or(20), 100);
-    let page_number = params.page_number.unwrap_or(0);
     //1. build query
     let mut query = r"
       SELECT
@@ -6413,64 +7212,42 @@ async fn get_trending_feed_items(pool: deadpool, n: u32, mut already_seen_bands:
         s.range_end,
         s.total_volume,
         s.transfer_fees,
-        s.transfer_footprint,
-        s.total_fees,
-        s.total_on_chain_footprint
-      from collection_list l left join collection_summary s on l.collection_symbol=s.collection_symbol where l.name!=''".to_string();
-
-    let order_clause = match sort_by {
-      CollectionSortBy::BiggestOnChainFootprint => " ORDER BY s.total_on_chain_footprint DESC NULLS LAST",
-      CollectionSortBy::SmallestOnChainFootprint => " ORDER BY s.total_on_chain_footprint ASC",
-      CollectionSortBy::MostVolume => " ORDER BY s.total_volume DESC NULLS LAST",
-      CollectionSortBy::LeastVolume => " ORDER BY s.total_volume ASC",
-      CollectionSortBy::BiggestFileSize => " ORDER BY s.total_inscription_size DESC NULLS LAST",
-      CollectionSortBy::SmallestFileSize => " ORDER BY s.total_inscription_size ASC",
-      CollectionSortBy::BiggestCreationFee => " ORDER BY s.total_inscription_fees DESC NULLS LAST",
-      CollectionSortBy::SmallestCreationFee => " ORDER BY s.total_inscription_fees ASC",
-      CollectionSortBy::EarliestFirstInscribedDate => " ORDER BY s.first_inscribed_date ASC",
-      CollectionSortBy::LatestFirstInscribedDate => " ORDER BY s.first_inscribed_date DESC NULLS LAST",
-      CollectionSortBy::EarliestLastInscribedDate => " ORDER BY s.last_inscribed_date ASC",
-      CollectionSortBy::LatestLastInscribedDate => " ORDER BY s.last_inscribed_date DESC NULLS LAST",
-      CollectionSortBy::BiggestSupply => " ORDER BY s.supply DESC NULLS LAST",
-      CollectionSortBy::SmallestSupply => " ORDER BY s.supply ASC",
-    };
-    query.push_str(order_clause);
-    if page_size > 0 {
-      query.push_str(format!(" LIMIT {}", page_size).as_str());
-    }
-    if page_number > 0 {
-      query.push_str(format!(" OFFSET {}", page_number * page_size).as_str());
-    }
-    println!("Query: {}", query);
-    let result = conn.query(
-      query.as_str(),
-      &[]
-    ).await?;
-    let mut collections = Vec::new();
-    for row in result {
-      let collection = CollectionSummary {
-        collection_symbol: row.get("collection_symbol"),
-        name: row.get("name"),
-        description: row.get("description"),
-        twitter: row.get("twitter"),
-        discord: row.get("discord"),
-        website: row.get("website"),
-        total_inscription_fees: row.get("total_inscription_fees"),
-        total_inscription_size: row.get("total_inscription_size"),
-        first_inscribed_date: row.get("first_inscribed_date"),
-        last_inscribed_date: row.get("last_inscribed_date"),
-        supply: row.get("supply"),
-        range_start: row.get("range_start"),
-        range_end: row.get("range_end"),
-        total_volume: row.get("total_volume"),
-        transfer_fees: row.get("transfer_fees"),
-        transfer_footprint: row.get("transfer_footprint"),
-        total_fees: row.get("total_fees"),
-        total_on_chain_footprint: row.get("total_on_chain_footprint")
-      };
-      collections.push(collection);
+        s.transfer_footprint,
+        s.total_fees,
+        s.total_on_chain_footprint
+      from collection_list l left join collection_summary s on l.collection_symbol=s.collection_symbol where l.name!=''".to_string();
+
+    let mut sql_params = SqlParams::default();
+    if let Some(after) = page.filter(&keyset, &mut sql_params) {
+      query.push_str(format!(" AND {}", after).as_str());
     }
-    Ok(collections)
+    query.push_str(&keyset.order_by());
+    query.push_str(&page.limit());
+    println!("Query: {}", query);
+    let result = conn.query(
+      query.as_str(),
+      &sql_params.values()
+    ).await?;
+    Ok(page.paginate(&keyset, result, |row| CollectionSummary {
+      collection_symbol: row.get("collection_symbol"),
+      name: row.get("name"),
+      description: row.get("description"),
+      twitter: row.get("twitter"),
+      discord: row.get("discord"),
+      website: row.get("website"),
+      total_inscription_fees: row.get("total_inscription_fees"),
+      total_inscription_size: row.get("total_inscription_size"),
+      first_inscribed_date: row.get("first_inscribed_date"),
+      last_inscribed_date: row.get("last_inscribed_date"),
+      supply: row.get("supply"),
+      range_start: row.get("range_start"),
+      range_end: row.get("range_end"),
+      total_volume: row.get("total_volume"),
+      transfer_fees: row.get("transfer_fees"),
+      transfer_footprint: row.get("transfer_footprint"),
+      total_fees: row.get("total_fees"),
+      total_on_chain_footprint: row.get("total_on_chain_footprint")
+    }))
   }
 
   async fn get_collection_summary(pool: deadpool, collection_symbol: String) -> anyhow::Result<CollectionSummary> {
@@ -6558,68 +7335,21 @@ async fn get_trending_feed_items(pool: deadpool, n: u32, mut already_seen_bands:
     Ok(holders)
   }
 
-  async fn get_inscriptions_in_collection(pool: deadpool, collection_symbol: String, params: ParsedInscriptionQueryParams) -> anyhow::Result<Vec<FullMetadata>> {
+  async fn get_inscriptions_in_collection(pool: deadpool, collection_symbol: String, params: ParsedInscriptionQueryParams, hidden: &HiddenParams) -> anyhow::Result<Paginated<FullMetadata>> {
     let conn = pool.get().await?;
     //1. build query
-    let mut query = "with m as MATERIALIZED (SELECT o.* from ordinals_full_v o where o.collection_symbol=$1".to_string();
-    if params.content_types.len() > 0 {
-      query.push_str(" AND (");
-      for (i, content_type) in params.content_types.iter().enumerate() {
-        let category = match content_type {
-          ContentType::Text => "o.content_category = 'text'",
-          ContentType::Image => "o.content_category = 'image'",
-          ContentType::Gif => "o.content_category = 'gif'",
-          ContentType::Audio => "o.content_category = 'audio'",
-          ContentType::Video => "o.content_category = 'video'",
-          ContentType::Html => "o.content_category = 'html'",
-          ContentType::Json => "o.content_category = 'json'",
-          ContentType::Namespace => "o.content_category = 'namespace'",
-          ContentType::Javascript => "o.content_category = 'javascript'",
-        };
-        query.push_str(category);
-        if i < params.content_types.len() - 1 {
-          query.push_str(" OR ");
-        }
-      }
-      query.push_str(")");
-    }
-    if params.satributes.len() > 0 {
-      query.push_str(format!(" AND (o.satributes && array['{}'::varchar])", params.satributes.join("'::varchar,'")).as_str());
-    }
-    if params.charms.len() > 0 {
-      query.push_str(format!(" AND (o.charms && array['{}'::varchar])", params.charms.join("'::varchar,'")).as_str());
-    }
-    let order_clause = match params.sort_by {
-      InscriptionSortBy::Newest => " ORDER BY o.sequence_number DESC",
-      InscriptionSortBy::Oldest => " ORDER BY o.sequence_number ASC",
-      InscriptionSortBy::NewestSat => " ORDER BY o.sat DESC",
-      InscriptionSortBy::OldestSat => " ORDER BY o.sat ASC",
-      InscriptionSortBy::RarestSat => "",  // No sorting implemented yet
-      InscriptionSortBy::CommonestSat => "",  // No sorting implemented yet
-      InscriptionSortBy::Biggest => " ORDER BY o.content_length DESC",
-      InscriptionSortBy::Smallest => " ORDER BY o.content_length ASC",
-      InscriptionSortBy::HighestFee => " ORDER BY o.genesis_fee DESC",
-      InscriptionSortBy::LowestFee => " ORDER BY o.genesis_fee ASC",
-    };
-    query.push_str(order_clause);
+    let mut sql_params = SqlParams::default();
+    let mut query = format!("with m as MATERIALIZED (SELECT o.* from ordinals_full_v o where o.collection_symbol={}", sql_params.push(collection_symbol));
+    Self::push_inscription_filters(&mut query, &params, hidden, &mut sql_params);
+    query.push_str(&params.keyset.order_by());
     query.push_str(") SELECT * from m");
-    if params.page_size > 0 {
-      query.push_str(format!(" LIMIT {}", params.page_size).as_str());
-    }
-    if params.page_number > 0 {
-      query.push_str(format!(" OFFSET {}", params.page_number * params.page_size).as_str());
-    }
+    query.push_str(&params.page.limit());
     println!("Query: {}", query);
     let result = conn.query(
       query.as_str(),
-      &[&collection_symbol]
+      &sql_params.values()
     ).await?;
-    let mut inscriptions = Vec::new();
-    for row in result {
-      let inscription = Self::map_row_to_fullmetadata(row);
-      inscriptions.push(inscription);
-    }
-    Ok(inscriptions)
+    Ok(params.page.paginate(&params.keyset, result, Self::map_row_to_fullmetadata))
   }
 
   async fn get_inscription_collection_data(pool: deadpool, inscription_id: String) -> anyhow::Result<Vec<InscriptionCollectionData>> {
@@ -6680,11 +7410,8 @@ async fn get_trending_feed_items(pool: deadpool, n: u32, mut already_seen_bands:
     Ok(collection_data)
   }
 
-  async fn get_on_chain_collections(pool: deadpool, params: CollectionQueryParams) -> anyhow::Result<Vec<OnChainCollectionSummary>> {
+  async fn get_on_chain_collections(pool: deadpool, keyset: Keyset, page: PageRequest) -> anyhow::Result<Paginated<OnChainCollectionSummary>> {
     let conn = pool.get().await?;
-    let sort_by = params.sort_by.unwrap_or(CollectionSortBy::BiggestOnChainFootprint);
-    let page_size = std::cmp::min(params.page_size.unwrap_or(20), 100);
-    let page_number = params.page_number.unwrap_or(0);
     //1. build query
     let mut query = r"
       SELECT
@@ -6705,58 +7432,37 @@ async fn get_trending_feed_items(pool: deadpool, n: u32, mut already_seen_bands:
         s.transfer_fees,
         s.transfer_footprint,
         s.total_fees,
-        s.total_on_chain_footprint
+        s.total_on_chain_footprint,
+        s.parents_hash
       from on_chain_collection_summary s".to_string();
 
-    let order_clause = match sort_by {
-      CollectionSortBy::BiggestOnChainFootprint => " ORDER BY s.total_on_chain_footprint DESC NULLS LAST",
-      CollectionSortBy::SmallestOnChainFootprint => " ORDER BY s.total_on_chain_footprint ASC",
-      CollectionSortBy::MostVolume => " ORDER BY s.total_volume DESC NULLS LAST",
-      CollectionSortBy::LeastVolume => " ORDER BY s.total_volume ASC",
-      CollectionSortBy::BiggestFileSize => " ORDER BY s.total_inscription_size DESC NULLS LAST",
-      CollectionSortBy::SmallestFileSize => " ORDER BY s.total_inscription_size ASC",
-      CollectionSortBy::BiggestCreationFee => " ORDER BY s.total_inscription_fees DESC NULLS LAST",
-      CollectionSortBy::SmallestCreationFee => " ORDER BY s.total_inscription_fees ASC",
-      CollectionSortBy::EarliestFirstInscribedDate => " ORDER BY s.first_inscribed_date ASC",
-      CollectionSortBy::LatestFirstInscribedDate => " ORDER BY s.first_inscribed_date DESC NULLS LAST",
-      CollectionSortBy::EarliestLastInscribedDate => " ORDER BY s.last_inscribed_date ASC",
-      CollectionSortBy::LatestLastInscribedDate => " ORDER BY s.last_inscribed_date DESC NULLS LAST",
-      CollectionSortBy::BiggestSupply => " ORDER BY s.supply DESC NULLS LAST",
-      CollectionSortBy::SmallestSupply => " ORDER BY s.supply ASC",
-    };
-    query.push_str(order_clause);
-    if page_size > 0 {
-      query.push_str(format!(" LIMIT {}", page_size).as_str());
-    }
-    if page_number > 0 {
-      query.push_str(format!(" OFFSET {}", page_number * page_size).as_str());
+    let mut sql_params = SqlParams::default();
+    if let Some(after) = page.filter(&keyset, &mut sql_params) {
+      query.push_str(format!(" WHERE {}", after).as_str());
     }
+    query.push_str(&keyset.order_by());
+    query.push_str(&page.limit());
     println!("Query: {}", query);
     let result = conn.query(
       query.as_str(),
-      &[]
+      &sql_params.values()
     ).await?;
-    let mut collections = Vec::new();
-    for row in result {
-      let collection = OnChainCollectionSummary {
-        parents: row.get("parents"),
-        parent_numbers: row.get("parent_numbers"),
-        total_inscription_fees: row.get("total_inscription_fees"),
-        total_inscription_size: row.get("total_inscription_size"),
-        first_inscribed_date: row.get("first_inscribed_date"),
-        last_inscribed_date: row.get("last_inscribed_date"),
-        supply: row.get("supply"),
-        range_start: row.get("range_start"),
-        range_end: row.get("range_end"),
-        total_volume: row.get("total_volume"),
-        transfer_fees: row.get("transfer_fees"),
-        transfer_footprint: row.get("transfer_footprint"),
-        total_fees: row.get("total_fees"),
-        total_on_chain_footprint: row.get("total_on_chain_footprint")
-      };
-      collections.push(collection);
-    }
-    Ok(collections)
+    Ok(page.paginate(&keyset, result, |row| OnChainCollectionSummary {
+      parents: row.get("parents"),
+      parent_numbers: row.get("parent_numbers"),
+      total_inscription_fees: row.get("total_inscription_fees"),
+      total_inscription_size: row.get("total_inscription_size"),
+      first_inscribed_date: row.get("first_inscribed_date"),
+      last_inscribed_date: row.get("last_inscribed_date"),
+      supply: row.get("supply"),
+      range_start: row.get("range_start"),
+      range_end: row.get("range_end"),
+      total_volume: row.get("total_volume"),
+      transfer_fees: row.get("transfer_fees"),
+      transfer_footprint: row.get("transfer_footprint"),
+      total_fees: row.get("total_fees"),
+      total_on_chain_footprint: row.get("total_on_chain_footprint")
+    }))
   }
 
   async fn get_on_chain_collection_summary(pool: deadpool, parents: Vec<String>) -> anyhow::Result<OnChainCollectionSummary> {
@@ -6871,75 +7577,25 @@ async fn get_trending_feed_items(pool: deadpool, n: u32, mut already_seen_bands:
     Ok(holders)
   }
 
-  async fn get_inscriptions_in_on_chain_collection(pool: deadpool, parents: Vec<String>, params: ParsedInscriptionQueryParams) -> anyhow::Result<Vec<FullMetadata>> {
+  async fn get_inscriptions_in_on_chain_collection(pool: deadpool, parents: Vec<String>, params: ParsedInscriptionQueryParams, hidden: &HiddenParams) -> anyhow::Result<Paginated<FullMetadata>> {
     let conn = pool.get().await?;
     //1. build query
-    let mut query = "with m as MATERIALIZED (SELECT o.* from ordinals_full_v o where o.parents=$1".to_string();
-    if params.content_types.len() > 0 {
-      query.push_str(" AND (");
-      for (i, content_type) in params.content_types.iter().enumerate() {
-        let category = match content_type {
-          ContentType::Text => "o.content_category = 'text'",
-          ContentType::Image => "o.content_category = 'image'",
-          ContentType::Gif => "o.content_category = 'gif'",
-          ContentType::Audio => "o.content_category = 'audio'",
-          ContentType::Video => "o.content_category = 'video'",
-          ContentType::Html => "o.content_category = 'html'",
-          ContentType::Json => "o.content_category = 'json'",
-          ContentType::Namespace => "o.content_category = 'namespace'",
-          ContentType::Javascript => "o.content_category = 'javascript'",
-        };
-        query.push_str(category);
-        if i < params.content_types.len() - 1 {
-          query.push_str(" OR ");
-        }
-      }
-      query.push_str(")");
-    }
-    if params.satributes.len() > 0 {
-      query.push_str(format!(" AND (o.satributes && array['{}'::varchar])", params.satributes.join("'::varchar,'")).as_str());
-    }
-    if params.charms.len() > 0 {
-      query.push_str(format!(" AND (o.charms && array['{}'::varchar])", params.charms.join("'::varchar,'")).as_str());
-    }
-    let order_clause = match params.sort_by {
-      InscriptionSortBy::Newest => " ORDER BY o.sequence_number DESC",
-      InscriptionSortBy::Oldest => " ORDER BY o.sequence_number ASC",
-      InscriptionSortBy::NewestSat => " ORDER BY o.sat DESC",
-      InscriptionSortBy::OldestSat => " ORDER BY o.sat ASC",
-      InscriptionSortBy::RarestSat => "",  // No sorting implemented yet
-      InscriptionSortBy::CommonestSat => "",  // No sorting implemented yet
-      InscriptionSortBy::Biggest => " ORDER BY o.content_length DESC",
-      InscriptionSortBy::Smallest => " ORDER BY o.content_length ASC",
-      InscriptionSortBy::HighestFee => " ORDER BY o.genesis_fee DESC",
-      InscriptionSortBy::LowestFee => " ORDER BY o.genesis_fee ASC",
-    };
-    query.push_str(order_clause);
+    let mut sql_params = SqlParams::default();
+    let mut query = format!("with m as MATERIALIZED (SELECT o.* from ordinals_full_v o where o.parents={}", sql_params.push(parents));
+    Self::push_inscription_filters(&mut query, &params, hidden, &mut sql_params);
+    query.push_str(&params.keyset.order_by());
     query.push_str(") SELECT * from m");
-    if params.page_size > 0 {
-      query.push_str(format!(" LIMIT {}", params.page_size).as_str());
-    }
-    if params.page_number > 0 {
-      query.push_str(format!(" OFFSET {}", params.page_number * params.page_size).as_str());
-    }
+    query.push_str(&params.page.limit());
     println!("Query: {}", query);
     let result = conn.query(
       query.as_str(),
-      &[&parents]
+      &sql_params.values()
     ).await?;
-    let mut inscriptions = Vec::new();
-    for row in result {
-      let inscription = Self::map_row_to_fullmetadata(row);
-      inscriptions.push(inscription);
-    }
-    Ok(inscriptions)
+    Ok(params.page.paginate(&params.keyset, result, Self::map_row_to_fullmetadata))
   }
 
-  async fn get_galleries_summary(pool: deadpool, params: GalleryQueryParams) -> anyhow::Result<Vec<GallerySummary>> {
+  async fn get_galleries_summary(pool: deadpool, keyset: Keyset, page: PageRequest) -> anyhow::Result<Paginated<GallerySummary>> {
     let conn = pool.get().await?;
-    let sort_by = params.sort_by.unwrap_or(GallerySortBy::BiggestOnChainFootprint);
-    let page_size = std::cmp::min(params.page_size.unwrap_or(20), 100);
-    let page_number = params.page_number.unwrap_or(0);
 
     let mut query = r"
       SELECT
@@ -6948,6 +7604,7 @@ async fn get_trending_feed_items(pool: deadpool, n: u32, mut already_seen_bands:
         s.total_inscription_size,
         s.first_inscribed_date,
         s.last_inscribed_date,
+        s.gallery_inscribed_date,
         s.supply,
         s.range_start,
         s.range_end,
@@ -6959,125 +7616,75 @@ async fn get_trending_feed_items(pool: deadpool, n: u32, mut already_seen_bands:
         s.boost_count
       from gallery_summary s".to_string();
 
-    let order_clause = match sort_by {
-      GallerySortBy::BiggestOnChainFootprint => " ORDER BY s.total_on_chain_footprint DESC NULLS LAST",
-      GallerySortBy::SmallestOnChainFootprint => " ORDER BY s.total_on_chain_footprint ASC",
-      GallerySortBy::MostVolume => " ORDER BY s.total_volume DESC NULLS LAST",
-      GallerySortBy::LeastVolume => " ORDER BY s.total_volume ASC",
-      GallerySortBy::BiggestFileSize => " ORDER BY s.total_inscription_size DESC NULLS LAST",
-      GallerySortBy::SmallestFileSize => " ORDER BY s.total_inscription_size ASC",
-      GallerySortBy::BiggestCreationFee => " ORDER BY s.total_inscription_fees DESC NULLS LAST",
-      GallerySortBy::SmallestCreationFee => " ORDER BY s.total_inscription_fees ASC",
-      GallerySortBy::EarliestFirstInscribedDate => " ORDER BY s.first_inscribed_date ASC",
-      GallerySortBy::LatestFirstInscribedDate => " ORDER BY s.first_inscribed_date DESC NULLS LAST",
-      GallerySortBy::EarliestLastInscribedDate => " ORDER BY s.last_inscribed_date ASC",
-      GallerySortBy::LatestLastInscribedDate => " ORDER BY s.last_inscribed_date DESC NULLS LAST",
-      GallerySortBy::BiggestSupply => " ORDER BY s.supply DESC NULLS LAST",
-      GallerySortBy::SmallestSupply => " ORDER BY s.supply ASC",
-      GallerySortBy::MostBoosts => " ORDER BY s.boost_count DESC NULLS LAST",
-      GallerySortBy::LeastBoosts => " ORDER BY s.boost_count ASC",
-      GallerySortBy::Newest => " ORDER BY s.gallery_inscribed_date DESC NULLS LAST",
-      GallerySortBy::Oldest => " ORDER BY s.gallery_inscribed_date ASC",
-    };
-    query.push_str(order_clause);
-
-    query.push_str(format!(" LIMIT {} OFFSET {}", page_size, page_number * page_size).as_str());
-
-    let result = conn.query(query.as_str(), &[]).await?;
-    let mut galleries = Vec::new();
-
-    for row in result {
-      let gallery = GallerySummary {
-        gallery_id: row.get("gallery_id"),
-        total_inscription_fees: row.get("total_inscription_fees"),
-        total_inscription_size: row.get("total_inscription_size"),
-        first_inscribed_date: row.get("first_inscribed_date"),
-        last_inscribed_date: row.get("last_inscribed_date"),
-        gallery_inscribed_date: row.get("gallery_inscribed_date"),
-        supply: row.get("supply"),
-        range_start: row.get("range_start"),
-        range_end: row.get("range_end"),
-        total_volume: row.get("total_volume"),
-        transfer_fees: row.get("transfer_fees"),
-        transfer_footprint: row.get("transfer_footprint"),
-        total_fees: row.get("total_fees"),
-        total_on_chain_footprint: row.get("total_on_chain_footprint"),
-        boost_count: row.get("boost_count"),
-      };
-      galleries.push(gallery);
-    }
-    Ok(galleries)
-  }
-
-  async fn get_gallery_inscriptions(pool: deadpool, params: GalleryQueryParams) -> anyhow::Result<Vec<FullMetadata>> {
+    let mut sql_params = SqlParams::default();
+    if let Some(after) = page.filter(&keyset, &mut sql_params) {
+      query.push_str(format!(" WHERE {}", after).as_str());
+    }
+    query.push_str(&keyset.order_by());
+    query.push_str(&page.limit());
+
+    let result = conn.query(query.as_str(), &sql_params.values()).await?;
+    Ok(page.paginate(&keyset, result, |row| GallerySummary {
+      gallery_id: row.get("gallery_id"),
+      total_inscription_fees: row.get("total_inscription_fees"),
+      total_inscription_size: row.get("total_inscription_size"),
+      first_inscribed_date: row.get("first_inscribed_date"),
+      last_inscribed_date: row.get("last_inscribed_date"),
+      gallery_inscribed_date: row.get("gallery_inscribed_date"),
+      supply: row.get("supply"),
+      range_start: row.get("range_start"),
+      range_end: row.get("range_end"),
+      total_volume: row.get("total_volume"),
+      transfer_fees: row.get("transfer_fees"),
+      transfer_footprint: row.get("transfer_footprint"),
+      total_fees: row.get("total_fees"),
+      total_on_chain_footprint: row.get("total_on_chain_footprint"),
+      boost_count: row.get("boost_count"),
+    }))
+  }
+
+  async fn get_gallery_inscriptions(pool: deadpool, keyset: Keyset, page: PageRequest, hidden: &HiddenParams) -> anyhow::Result<Paginated<FullMetadata>> {
     let conn = pool.get().await?;
-    let sort_by = params.sort_by.unwrap_or(GallerySortBy::BiggestOnChainFootprint);
-    let page_size = std::cmp::min(params.page_size.unwrap_or(20), 100);
-    let page_number = params.page_number.unwrap_or(0);
 
     let mut query = r"
       WITH gallery_ids AS (
         SELECT DISTINCT gallery_id
         FROM inscription_galleries
       )
-      SELECT o.*, s.boost_count, s.total_volume, s.total_fees, s.total_on_chain_footprint, s.supply, s.total_inscription_size, s.total_inscription_fees, s.first_inscribed_date, s.last_inscribed_date
+      SELECT o.*, g.gallery_id, s.boost_count, s.total_volume, s.total_fees, s.total_on_chain_footprint, s.supply, s.total_inscription_size, s.total_inscription_fees, s.first_inscribed_date, s.last_inscribed_date, s.gallery_inscribed_date
       FROM gallery_ids g
       LEFT JOIN ordinals_full_v o ON g.gallery_id=o.id
       LEFT JOIN gallery_summary s ON g.gallery_id=s.gallery_id
     ".to_string();
 
-    let order_clause = match sort_by {
-      GallerySortBy::BiggestOnChainFootprint => " ORDER BY s.total_on_chain_footprint DESC NULLS LAST",
-      GallerySortBy::SmallestOnChainFootprint => " ORDER BY s.total_on_chain_footprint ASC",
-      GallerySortBy::MostVolume => " ORDER BY s.total_volume DESC NULLS LAST",
-      GallerySortBy::LeastVolume => " ORDER BY s.total_volume ASC",
-      GallerySortBy::BiggestFileSize => " ORDER BY s.total_inscription_size DESC NULLS LAST",
-      GallerySortBy::SmallestFileSize => " ORDER BY s.total_inscription_size ASC",
-      GallerySortBy::BiggestCreationFee => " ORDER BY s.total_inscription_fees DESC NULLS LAST",
-      GallerySortBy::SmallestCreationFee => " ORDER BY s.total_inscription_fees ASC",
-      GallerySortBy::EarliestFirstInscribedDate => " ORDER BY s.first_inscribed_date ASC",
-      GallerySortBy::LatestFirstInscribedDate => " ORDER BY s.first_inscribed_date DESC NULLS LAST",
-      GallerySortBy::EarliestLastInscribedDate => " ORDER BY s.last_inscribed_date ASC",
-      GallerySortBy::LatestLastInscribedDate => " ORDER BY s.last_inscribed_date DESC NULLS LAST",
-      GallerySortBy::BiggestSupply => " ORDER BY s.supply DESC NULLS LAST",
-      GallerySortBy::SmallestSupply => " ORDER BY s.supply ASC",
-      GallerySortBy::MostBoosts => " ORDER BY s.boost_count DESC NULLS LAST",
-      GallerySortBy::LeastBoosts => " ORDER BY s.boost_count ASC",
-      GallerySortBy::Newest => " ORDER BY s.gallery_inscribed_date DESC NULLS LAST",
-      GallerySortBy::Oldest => " ORDER BY s.gallery_inscribed_date ASC",
-    };
-    query.push_str(order_clause);
-
-    query.push_str(format!(" LIMIT {} OFFSET {}", page_size, page_number * page_size).as_str());
-
-    let result = conn.query(query.as_str(), &[]).await?;
-    let mut inscriptions = Vec::new();
-
-    for row in result {
-      let inscription = Self::map_row_to_fullmetadata(row);
-      inscriptions.push(inscription);
+    let mut sql_params = SqlParams::default();
+    query.push_str(" WHERE 1=1");
+    if let Some(visible) = hidden.visible(&mut sql_params) {
+      query.push_str(format!(" AND {}", visible).as_str());
     }
-    Ok(inscriptions)
+    if let Some(after) = page.filter(&keyset, &mut sql_params) {
+      query.push_str(format!(" AND {}", after).as_str());
+    }
+    query.push_str(&keyset.order_by());
+    query.push_str(&page.limit());
+
+    let result = conn.query(query.as_str(), &sql_params.values()).await?;
+    Ok(page.paginate(&keyset, result, Self::map_row_to_fullmetadata))
   }
 
-  async fn get_inscriptions_in_gallery(pool: deadpool, gallery_id: String, params: ParsedInscriptionQueryParams) -> anyhow::Result<Vec<FullMetadata>> {
+  async fn get_inscriptions_in_gallery(pool: deadpool, gallery_id: String, params: ParsedInscriptionQueryParams, hidden: &HiddenParams) -> anyhow::Result<Paginated<FullMetadata>> {
     let conn = pool.get().await?;
-    let base_query = "
+    let mut sql_params = SqlParams::default();
+    let base_query = format!("
       SELECT o.*
       FROM inscription_galleries ig
       LEFT JOIN ordinals_full_v o
       ON ig.inscription_id=o.id
-      WHERE ig.gallery_id=$1
-    ".to_string();
-let full_query = Self::create_inscription_query_string(base_query, params);
-    let result = conn.query(full_query.as_str(), &[&gallery_id]).await?;
-    let mut inscriptions = Vec::new();
-
-    for row in result {
-      let inscription = Self::map_row_to_fullmetadata(row);
-      inscriptions.push(inscription);
-    }
-    Ok(inscriptions)
+      WHERE ig.gallery_id={}
+    ", sql_params.push(gallery_id));
+    let full_query = Self::create_inscription_query_string(base_query, &params, hidden, &mut sql_params);
+    let result = conn.query(full_query.as_str(), &sql_params.values()).await?;
+    Ok(params.page.paginate(&params.keyset, result, Self::map_row_to_fullmetadata))
   }
 
   async fn get_gallery_summary(pool: deadpool, gallery_id: String) -> anyhow::Result<GallerySummary> {
@@ -7210,11 +7817,8 @@ let full_query = Self::create_inscription_query_string(base_query, params);
     Ok(sat_block_stats)
   }
 
-  async fn get_blocks(pool: deadpool, params: BlockQueryParams) -> anyhow::Result<Vec<CombinedBlockStats>> {
+  async fn get_blocks(pool: deadpool, sort_by: BlockSortBy, page: PageRequest) -> anyhow::Result<Paginated<CombinedBlockStats>> {
     let conn = pool.get().await?;
-    let sort_by = params.sort_by.unwrap_or(BlockSortBy::Newest);
-    let page_size = std::cmp::min(params.page_size.unwrap_or(20), 100);
-    let page_number = params.page_number.unwrap_or(0);
     //1. build query
     let mut query = r"
       select b.*,
@@ -7226,62 +7830,46 @@ let full_query = Self::create_inscription_query_string(base_query, params);
       i.block_transfer_fees,
       i.block_volume from blockstats b
       left join inscription_blockstats i
-      on b.block_number=i.block_number".to_string();
-
-    let order_clause = match sort_by {
-      BlockSortBy::Newest => " ORDER BY b.block_number DESC",
-      BlockSortBy::Oldest => " ORDER BY b.block_number ASC",
-      BlockSortBy::MostTxs => " ORDER BY b.block_tx_count DESC",
-      BlockSortBy::LeastTxs => " ORDER BY b.block_tx_count ASC",
-      BlockSortBy::MostInscriptions => " ORDER BY i.block_inscription_count DESC NULLS LAST",
-      BlockSortBy::LeastInscriptions => " WHERE i.block_inscription_count > 0 ORDER BY i.block_inscription_count ASC",
-      BlockSortBy::BiggestBlock => " ORDER BY b.block_size DESC",
-      BlockSortBy::SmallestBlock => " ORDER BY b.block_size ASC",
-      BlockSortBy::BiggestTotalInscriptionsSize => " ORDER BY i.block_inscription_size DESC NULLS LAST",
-      BlockSortBy::SmallestTotalInscriptionsSize => " WHERE i.block_inscription_size > 0 ORDER BY i.block_inscription_size ASC",
-      BlockSortBy::HighestTotalFees => " ORDER BY b.block_fees DESC",
-      BlockSortBy::LowestTotalFees => " ORDER BY b.block_fees ASC",
-      BlockSortBy::HighestInscriptionFees => " ORDER BY i.block_inscription_fees DESC NULLS LAST",
-      BlockSortBy::LowestInscriptionFees => " WHERE i.block_inscription_fees > 0 ORDER BY i.block_inscription_fees ASC",
-      BlockSortBy::MostVolume => " ORDER BY i.block_volume DESC NULLS LAST",
-      BlockSortBy::LeastVolume => " WHERE i.block_volume > 0 ORDER BY i.block_volume ASC",
-    };
-    query.push_str(order_clause);
-
-    if page_size > 0 {
-      query.push_str(format!(" LIMIT {}", page_size).as_str());
-    }
-    if page_number > 0 {
-      query.push_str(format!(" OFFSET {}", page_number * page_size).as_str());
-    }
+      on b.block_number=i.block_number WHERE 1=1".to_string();
+
+    // Ascending inscription sorts skip blocks without inscriptions
+    match sort_by {
+      BlockSortBy::LeastInscriptions => query.push_str(" AND i.block_inscription_count > 0"),
+      BlockSortBy::SmallestTotalInscriptionsSize => query.push_str(" AND i.block_inscription_size > 0"),
+      BlockSortBy::LowestInscriptionFees => query.push_str(" AND i.block_inscription_fees > 0"),
+      BlockSortBy::LeastVolume => query.push_str(" AND i.block_volume > 0"),
+      _ => {}
+    }
+    let keyset = sort_by.keyset();
+    let mut sql_params = SqlParams::default();
+    if let Some(after) = page.filter(&keyset, &mut sql_params) {
+      query.push_str(format!(" AND {}", after).as_str());
+    }
+    query.push_str(&keyset.order_by());
+    query.push_str(&page.limit());
     println!("Query: {}", query);
     let result = conn.query(
       query.as_str(),
-      &[]
+      &sql_params.values()
     ).await?;
-    let mut blocks = Vec::new();
-    for row in result {
-      let block = CombinedBlockStats {
-        block_number: row.get("block_number"),
-        block_hash: row.get("block_hash"),
-        block_timestamp: row.get("block_timestamp"),
-        block_tx_count: row.get("block_tx_count"),
-        block_size: row.get("block_size"),
-        block_fees: row.get("block_fees"),
-        min_fee: row.get("min_fee"),
-        max_fee: row.get("max_fee"),
-        average_fee: row.get("average_fee"),
-        block_inscription_count: row.get("block_inscription_count"),
-        block_inscription_size: row.get("block_inscription_size"),
-        block_inscription_fees: row.get("block_inscription_fees"),
-        block_transfer_count: row.get("block_transfer_count"),
-        block_transfer_size: row.get("block_transfer_size"),
-        block_transfer_fees: row.get("block_transfer_fees"),
-        block_volume: row.get("block_volume")
-      };
-      blocks.push(block);
-    }
-    Ok(blocks)
+    Ok(page.paginate(&keyset, result, |row| CombinedBlockStats {
+      block_number: row.get("block_number"),
+      block_hash: row.get("block_hash"),
+      block_timestamp: row.get("block_timestamp"),
+      block_tx_count: row.get("block_tx_count"),
+      block_size: row.get("block_size"),
+      block_fees: row.get("block_fees"),
+      min_fee: row.get("min_fee"),
+      max_fee: row.get("max_fee"),
+      average_fee: row.get("average_fee"),
+      block_inscription_count: row.get("block_inscription_count"),
+      block_inscription_size: row.get("block_inscription_size"),
+      block_inscription_fees: row.get("block_inscription_fees"),
+      block_transfer_count: row.get("block_transfer_count"),
+      block_transfer_size: row.get("block_transfer_size"),
+      block_transfer_fees: row.get("block_transfer_fees"),
+      block_volume: row.get("block_volume")
+    }))
   }
 
   async fn get_collection_search_result(pool: deadpool, search_query: String) -> anyhow::Result<Vec<CollectionSummary>> {
@@ -7336,10 +7924,10 @@ let full_query = Self::create_inscription_query_string(base_query, params);
     Ok(collections)
   }
 
-  async fn get_search_result(pool: deadpool, search_query: String) -> anyhow::Result<SearchResult> {
+  async fn get_search_result(pool: deadpool, chain: Chain, search_query: String) -> anyhow::Result<SearchResult> {
     let id: Regex = Regex::new(r"^[[:xdigit:]]{64}i\d+$").unwrap();
-    let address: Regex = Regex::new(r"^(bc1p[a-zA-Z0-9]{58}|bc1q[a-zA-Z0-9]{38}|[13][a-zA-HJ-NP-Z0-9]{25,34})$").unwrap();
-    let number: Regex = Regex::new(r"^-?\d+$").unwrap();
+    let hash: Regex = Regex::new(r"^[[:xdigit:]]{64}$").unwrap();
+    let rune: Regex = Regex::new(r"^[A-Z]+([•.][A-Z]+)*$").unwrap();
 
     let search_query = search_query.trim();
     let mut search_result = SearchResult {
@@ -7348,29 +7936,82 @@ let full_query = Self::create_inscription_query_string(base_query, params);
       address: None,
       block: None,
       sat: None,
+      rune: None,
+      transaction: None,
     };
-    if number.is_match(search_query) {
-      let number = search_query.parse::<i64>().unwrap();
+    // Block hashes and txids look the same, and can be all digits
+    if hash.is_match(search_query) {
+      let hash = search_query.to_lowercase();
+      let potential_block = Self::get_block_statistics_by_hash(pool.clone(), hash.clone()).await;
+      let potential_transaction = Self::get_transaction_search_result(pool, hash).await;
+      search_result.block = potential_block.ok();
+      search_result.transaction = potential_transaction.ok().flatten();
+    } else if let Ok(number) = search_query.parse::<i64>() {
       let potential_inscription = Self::get_ordinal_metadata_by_number(pool.clone(), number).await;
       let potential_block = Self::get_block_statistics(pool.clone(), number).await;
       let potential_sat = Self::get_sat_metadata(pool, number).await;
       search_result.inscription = potential_inscription.ok();
       search_result.block = potential_block.ok();
       search_result.sat = potential_sat.ok();
+    } else if id.is_match(search_query) {
+      let potential_inscription = Self::get_ordinal_metadata(pool, search_query.to_string()).await;
+      search_result.inscription = potential_inscription.ok();
+    } else if let Ok(address) = parse_address(search_query, chain) {
+      search_result.address = Some(address);
     } else {
-      if id.is_match(search_query) {
-        let potential_inscription = Self::get_ordinal_metadata(pool, search_query.to_string()).await;
-        search_result.inscription = potential_inscription.ok();
-      } else if address.is_match(search_query) {
-        search_result.address = Some(search_query.to_string());
-      } else {
-        let potential_collections = Self::get_collection_search_result(pool, search_query.to_string()).await;
-        search_result.collections = potential_collections?;
+      // Sat names, decimal, degree and percentile notation
+      if let Ok(sat) = search_query.parse::<Sat>() {
+        let potential_sat = Self::get_sat_metadata(pool.clone(), sat.n().try_into()?).await;
+        search_result.sat = potential_sat.ok();
+      }
+      if rune.is_match(search_query) {
+        let potential_rune = rune_indexer::get_rune(pool.clone(), search_query.to_string()).await;
+        search_result.rune = potential_rune.ok();
       }
+      let potential_collections = Self::get_collection_search_result(pool, search_query.to_string()).await;
+      search_result.collections = potential_collections?;
     }
     Ok(search_result)
   }
 
+  async fn get_block_statistics_by_hash(pool: deadpool, block_hash: String) -> anyhow::Result<CombinedBlockStats> {
+    let conn = pool.get().await?;
+    let result = conn.query_one("SELECT block_number FROM blockstats WHERE block_hash=$1", &[&block_hash]).await?;
+    Self::get_block_statistics(pool, result.get("block_number")).await
+  }
+
+  async fn get_transaction_search_result(pool: deadpool, txid: String) -> anyhow::Result<Option<TransactionSearchResult>> {
+    let conn = pool.get().await?;
+    let rows = conn.query(
+      "SELECT * FROM transfers WHERE transaction=$1 ORDER BY tx_offset ASC, satpoint_offset ASC",
+      &[&txid]
+    ).await?;
+    let Some(block_number) = rows.first().map(|row| row.get("block_number")) else {
+      return Ok(None);
+    };
+    let mut transfers = Vec::new();
+    for row in rows {
+      transfers.push(Transfer {
+        id: row.get("id"),
+        block_number: row.get("block_number"),
+        block_timestamp: row.get("block_timestamp"),
+        satpoint: row.get("satpoint"),
+        tx_offset: row.get("tx_offset"),
+        transaction: row.get("transaction"),
+        vout: row.get("vout"),
+        offset: row.get("satpoint_offset"),
+        address: row.get("address"),
+        previous_address: row.get("previous_address"),
+        price: row.get("price"),
+        tx_fee: row.get("tx_fee"),
+        tx_size: row.get("tx_size"),
+        is_genesis: row.get("is_genesis"),
+        burn_metadata: row.get("burn_metadata")
+      });
+    }
+    Ok(Some(TransactionSearchResult { txid, block_number, transfers }))
+  }
+
   async fn create_metadata_insert_trigger(pool: deadpool_postgres::Pool<>) -> anyhow::Result<()> {
     let conn = pool.get().await?;
     conn.simple_query(r"CREATE OR REPLACE FUNCTION before_metadata_insert() RETURNS TRIGGER AS $$
@@ -7388,6 +8029,9 @@ let full_query = Self::create_inscription_query_string(base_query, params);
       DECLARE t5 TIMESTAMP;
       DECLARE t6 TIMESTAMP;
       BEGIN
+        IF current_setting('vermilion.undoing', true) = 'on' THEN
+          RETURN NEW;
+        END IF;
         t0 := clock_timestamp();
         -- RAISE NOTICE 'insert_metadata: waiting for lock';
         LOCK TABLE ordinals IN EXCLUSIVE MODE;
@@ -7564,6 +8208,9 @@ let full_query = Self::create_inscription_query_string(base_query, params);
       DECLARE ref_id VARCHAR(80);
       DECLARE inscription_satribute VARCHAR(30);
       BEGIN
+        IF current_setting('vermilion.undoing', true) = 'on' THEN
+          RETURN OLD;
+        END IF;
         LOCK TABLE ordinals IN EXCLUSIVE MODE;
 
         -- 1a. Update delegates (remove bootleg and decrement total)
@@ -7687,6 +8334,9 @@ let full_query = Self::create_inscription_query_string(base_query, params);
       DECLARE t2 TIMESTAMP;
       DECLARE t3 TIMESTAMP;
       BEGIN
+        IF current_setting('vermilion.undoing', true) = 'on' THEN
+          RETURN NULL;
+        END IF;
         t0 := clock_timestamp();
         -- RAISE NOTICE 'insert_transfers: waiting for lock';
         LOCK TABLE transfers IN EXCLUSIVE MODE;
@@ -7770,6 +8420,9 @@ let full_query = Self::create_inscription_query_string(base_query, params);
       DECLARE v_collection_symbol TEXT;
       DECLARE v_parents VARCHAR(80)[];
       BEGIN
+        IF current_setting('vermilion.undoing', true) = 'on' THEN
+          RETURN OLD;
+        END IF;
         -- RAISE NOTICE 'delete_transfer: waiting for lock';
         LOCK TABLE transfers IN EXCLUSIVE MODE;
         -- RAISE NOTICE 'delete_transfer: lock acquired';
@@ -7852,6 +8505,9 @@ let full_query = Self::create_inscription_query_string(base_query, params);
       DECLARE previous_total INTEGER;
       DECLARE new_total INTEGER;
       BEGIN
+        IF current_setting('vermilion.undoing', true) = 'on' THEN
+          RETURN NEW;
+        END IF;
         -- Get the previous total for the same sha256, or default to 0
         SELECT total INTO previous_total FROM editions_total WHERE sha256 = NEW.sha256;
         new_total := COALESCE(previous_total, 0) + 1;
@@ -7879,6 +8535,9 @@ let full_query = Self::create_inscription_query_string(base_query, params);
     conn.simple_query(r#"
       CREATE OR REPLACE FUNCTION before_edition_delete() RETURNS TRIGGER AS $$
       BEGIN
+        IF current_setting('vermilion.undoing', true) = 'on' THEN
+          RETURN OLD;
+        END IF;
         -- Decrement the total in editions_total
         UPDATE editions_total
         SET total = total - 1
@@ -7906,6 +8565,9 @@ let full_query = Self::create_inscription_query_string(base_query, params);
       DECLARE t1 TIMESTAMP;
       DECLARE t2 TIMESTAMP;
       BEGIN
+        IF current_setting('vermilion.undoing', true) = 'on' THEN
+          RETURN NULL;
+        END IF;
         t0 := clock_timestamp();
         INSERT INTO ordinals_full_t (
           sequence_number,
@@ -9014,6 +9676,9 @@ let full_query = Self::create_inscription_query_string(base_query, params);
       DECLARE
         gallery_id_rec RECORD;
       BEGIN
+        IF current_setting('vermilion.undoing', true) = 'on' THEN
+          RETURN NULL;
+        END IF;
         -- Update gallery summary for all galleries affected by this INSERT statement
         FOR gallery_id_rec IN
           SELECT DISTINCT gallery_id FROM inserted_galleries
@@ -9096,4 +9761,508 @@ let full_query = Self::create_inscription_query_string(base_query, params);
     Ok(())
   }
 
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+  use {super::*, bitcoin::script::{self, PushBytesBuf}, tokio_postgres::NoTls};
+
+  fn der_sig(sighash: u8) -> Vec<u8> {
+    let mut sig = vec![0x30, 0x44, 0x02, 0x20];
+    sig.extend([1; 32]);
+    sig.extend([0x02, 0x20]);
+    sig.extend([2; 32]);
+    sig.push(sighash);
+    sig
+  }
+
+  fn schnorr_sig(sighash: Option<u8>) -> Vec<u8> {
+    let mut sig = vec![3; 64];
+    sig.extend(sighash);
+    sig
+  }
+
+  fn sale(script_sig: ScriptBuf, witness: Vec<Vec<u8>>) -> Transaction {
+    Transaction {
+      version: Version(2),
+      lock_time: LockTime::ZERO,
+      input: vec![
+        TxIn {
+          previous_output: OutPoint { txid: txid(1), vout: 0 },
+          script_sig: ScriptBuf::new(),
+          sequence: Sequence::MAX,
+          witness: Witness::from_slice(&[schnorr_sig(Some(0x01))]),
+        },
+        TxIn {
+          previous_output: OutPoint { txid: txid(2), vout: 0 },
+          script_sig,
+          sequence: Sequence::MAX,
+          witness: Witness::from_slice(&witness),
+        },
+      ],
+      output: vec![
+        TxOut { value: Amount::from_sat(546), script_pubkey: ScriptBuf::new() },
+        TxOut { value: Amount::from_sat(150_000), script_pubkey: ScriptBuf::new() },
+      ],
+    }
+  }
+
+  #[test]
+  fn sighash_from_legacy_script_sig() {
+    let script_sig = script::Builder::new()
+      .push_slice(PushBytesBuf::try_from(der_sig(0x83)).unwrap())
+      .push_slice([2; 33])
+      .into_script();
+    let tx = sale(script_sig, Vec::new());
+    assert_eq!(Vermilion::get_sighash_byte(&tx.input[1]), Some(0x83));
+    assert_eq!(Vermilion::get_sale_price(&tx, 1, 546), 150_000);
+  }
+
+  #[test]
+  fn sighash_from_p2wpkh_witness() {
+    let tx = sale(ScriptBuf::new(), vec![der_sig(0x83), vec![2; 33]]);
+    assert_eq!(Vermilion::get_sighash_byte(&tx.input[1]), Some(0x83));
+    assert_eq!(Vermilion::get_sale_price(&tx, 1, 546), 150_000);
+
+    let tx = sale(ScriptBuf::new(), vec![der_sig(0x81), vec![2; 33]]);
+    assert_eq!(Vermilion::get_sighash_byte(&tx.input[1]), Some(0x81));
+    assert_eq!(Vermilion::get_sale_price(&tx, 1, 546), 150_000);
+  }
+
+  #[test]
+  fn sighash_from_p2sh_p2wpkh_witness() {
+    let mut redeem_script = vec![0x00, 0x14];
+    redeem_script.extend([4; 20]);
+    let script_sig = script::Builder::new()
+      .push_slice(PushBytesBuf::try_from(redeem_script).unwrap())
+      .into_script();
+    let tx = sale(script_sig, vec![der_sig(0x83), vec![2; 33]]);
+    assert_eq!(Vermilion::get_sighash_byte(&tx.input[1]), Some(0x83));
+    assert_eq!(Vermilion::get_sale_price(&tx, 1, 546), 150_000);
+  }
+
+  #[test]
+  fn sighash_from_p2tr_key_path_witness() {
+    let tx = sale(ScriptBuf::new(), vec![schnorr_sig(Some(0x83))]);
+    assert_eq!(Vermilion::get_sighash_byte(&tx.input[1]), Some(0x83));
+    assert_eq!(Vermilion::get_sale_price(&tx, 1, 546), 150_000);
+
+    let tx = sale(ScriptBuf::new(), vec![schnorr_sig(Some(0x81))]);
+    assert_eq!(Vermilion::get_sighash_byte(&tx.input[1]), Some(0x81));
+    assert_eq!(Vermilion::get_sale_price(&tx, 1, 546), 150_000);
+  }
+
+  #[test]
+  fn p2tr_annex_is_ignored() {
+    let tx = sale(ScriptBuf::new(), vec![schnorr_sig(Some(0x83)), vec![0x50, 0x01]]);
+    assert_eq!(Vermilion::get_sighash_byte(&tx.input[1]), Some(0x83));
+  }
+
+  #[test]
+  fn p2tr_default_sighash_is_not_a_sale() {
+    let mut sig = schnorr_sig(None);
+    sig[63] = 0x83;
+    let tx = sale(ScriptBuf::new(), vec![sig]);
+    assert_eq!(Vermilion::get_sighash_byte(&tx.input[1]), Some(0x00));
+    assert_eq!(Vermilion::get_sale_price(&tx, 1, 546), 0);
+  }
+
+  #[test]
+  fn large_postage_is_not_a_sale() {
+    let tx = sale(ScriptBuf::new(), vec![der_sig(0x83), vec![2; 33]]);
+    assert_eq!(Vermilion::get_sale_price(&tx, 1, 20_001), 0);
+  }
+
+  #[test]
+  fn sighash_all_is_not_a_sale() {
+    let tx = sale(ScriptBuf::new(), vec![schnorr_sig(Some(0x01))]);
+    assert_eq!(Vermilion::get_sale_price(&tx, 1, 546), 0);
+  }
+
+  #[tokio::test]
+  async fn inscription_filters_are_added_to_the_query() {
+    let uri = "/inscriptions?min_genesis_height=840000&max_timestamp=1700000000&min_genesis_fee=1000&max_genesis_fee=2000\
+      &metaprotocol=brc-20&inscribed_by_address=BC1QAR0SRRR7XFKVY5L643LYDNW9RE59GTZZWF5MDQ&spaced_rune=IT%27S%E2%80%A2RUNE\
+      &has_parent=false&is_recursive=true&page_size=5";
+    let mut parts = http::Request::builder().uri(uri).body(()).unwrap().into_parts().0;
+    let Query(params) = <Query<InscriptionQueryParams> as axum::extract::FromRequestParts<()>>::from_request_parts(&mut parts, &()).await.unwrap();
+    assert!(matches!(params.parse(Chain::Testnet), Err(ApiError::BadRequest(_))));
+    let Query(params) = <Query<InscriptionQueryParams> as axum::extract::FromRequestParts<()>>::from_request_parts(&mut parts, &()).await.unwrap();
+    let Ok(params) = params.parse(Chain::Mainnet) else { panic!("invalid params") };
+    assert_eq!(params.inscribed_by_address.as_deref(), Some("bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq"));
+    let hidden = HiddenParams { ids: vec!["ai0".into()], sha256s: vec!["abc".into()] };
+    let mut sql_params = SqlParams::default();
+    pretty_assert_eq!(
+      Vermilion::create_inscription_query_string("SELECT o.* FROM ordinals_full_v o WHERE 1=1".to_string(), &params, &hidden, &mut sql_params),
+      "SELECT o.* FROM ordinals_full_v o WHERE 1=1 AND o.genesis_height >= 840000 AND o.timestamp <= 1700000000 \
+      AND o.genesis_fee >= 1000 AND o.genesis_fee <= 2000 AND o.metaprotocol = $1 AND o.inscribed_by_address = $2 AND o.spaced_rune = $3 \
+      AND coalesce(cardinality(o.parents), 0) = 0 AND o.is_recursive \
+      AND o.id <> ALL($4) AND (o.delegate IS NULL OR o.delegate <> ALL($4)) AND (o.sha256 IS NULL OR o.sha256 <> ALL($5)) \
+      ORDER BY o.sequence_number DESC LIMIT 5"
+    );
+    assert_eq!(sql_params.values().len(), 5);
+  }
+
+  #[tokio::test]
+  async fn empty_cursor_starts_cursor_paging() {
+    for (uri, cursor) in [("/inscriptions?cursor=", Some("")), ("/inscriptions?cursor=abc", Some("abc")), ("/inscriptions", None)] {
+      let mut parts = http::Request::builder().uri(uri).body(()).unwrap().into_parts().0;
+      let Query(params) = <Query<InscriptionQueryParams> as axum::extract::FromRequestParts<()>>::from_request_parts(&mut parts, &()).await.unwrap();
+      assert_eq!(params.cursor.as_deref(), cursor);
+    }
+  }
+
+  pub(super) fn test_inscription(sequence_number: i64, height: i64, sha256: &str, parents: Vec<String>, delegate: Option<String>) -> Metadata {
+    Metadata {
+      sequence_number,
+      id: format!("{sequence_number:064x}i0"),
+      content_length: Some(4),
+      content_type: Some("text/plain".into()),
+      content_encoding: None,
+      content_category: "text".into(),
+      genesis_fee: 100 * (sequence_number + 1),
+      genesis_height: height,
+      genesis_transaction: format!("{sequence_number:064x}"),
+      pointer: None,
+      number: sequence_number,
+      on_chain_collection_id: (!parents.is_empty()).then(|| digest(parents.join(",").as_bytes())),
+      parents,
+      delegate_content_type: delegate.as_ref().map(|_| "text/plain".into()),
+      delegate,
+      metaprotocol: None,
+      on_chain_metadata: serde_json::Value::Null,
+      sat: Some(1_000_000 * sequence_number),
+      sat_block: Some(0),
+      satributes: vec!["uncommon".into()],
+      charms: Vec::new(),
+      timestamp: height * 600_000,
+      sha256: Some(sha256.into()),
+      text: None,
+      referenced_ids: Vec::new(),
+      is_json: false,
+      is_maybe_json: false,
+      is_bitmap_style: false,
+      is_recursive: false,
+      spaced_rune: None,
+      raw_properties: serde_json::Value::Null,
+      inscribed_by_address: Some("bc1qinscriber".into()),
+    }
+  }
+
+  // Each block's transfers share one transaction, with every inscription on its own sat of the first output
+  pub(super) fn test_transfer(inscription: &Metadata, height: i64, previous_address: &str, address: &str, price: i64) -> Transfer {
+    let transaction = format!("{height:064x}");
+    Transfer {
+      id: inscription.id.clone(),
+      block_number: height,
+      block_timestamp: height * 600_000,
+      satpoint: format!("{transaction}:0:{}", inscription.sequence_number),
+      tx_offset: 1,
+      transaction,
+      vout: 0,
+      offset: inscription.sequence_number,
+      address: address.into(),
+      previous_address: previous_address.into(),
+      price,
+      tx_fee: 250,
+      tx_size: 300,
+      is_genesis: price == 0 && previous_address.is_empty(),
+      burn_metadata: None,
+    }
+  }
+
+  pub(super) struct TestBlock {
+    pub(super) height: i64,
+    pub(super) inscriptions: Vec<Metadata>,
+    pub(super) galleries: Vec<(String, String)>,
+    pub(super) transfers: Vec<Transfer>,
+  }
+
+  // Writes a block through the same insert paths, in the same order, as the block indexer
+  pub(super) async fn index_test_block(pool: &deadpool, block: &TestBlock) {
+    let mut conn = pool.get().await.unwrap();
+    let tx = conn.transaction().await.unwrap();
+    undo_journal::start_block(&tx, block.height as u32, block.height as u32).await.unwrap();
+    Vermilion::bulk_insert_blockstats(&tx, vec![BlockStats {
+      block_number: block.height,
+      block_hash: Some(format!("{:064x}", block.height * 31 + block.inscriptions.len() as i64)),
+      block_timestamp: Some(block.height * 600_000),
+      block_tx_count: Some(10),
+      block_size: Some(1000),
+      block_fees: Some(5000),
+      min_fee: Some(1),
+      max_fee: Some(10),
+      average_fee: Some(5),
+    }]).await.unwrap();
+    Vermilion::bulk_insert_metadata(&tx, block.inscriptions.clone()).await.unwrap();
+    Vermilion::bulk_insert_editions(&tx, block.inscriptions.clone()).await.unwrap();
+    Vermilion::bulk_insert_gallery_metadata(&tx, block.galleries.iter().map(|(gallery_id, inscription_id)| GalleryMetadata {
+      gallery_id: gallery_id.clone(),
+      inscription_id: inscription_id.clone(),
+    }).collect()).await.unwrap();
+    let content = block.inscriptions.iter().map(|inscription| (inscription.sequence_number, ContentBlob {
+      sha256: inscription.sha256.clone().unwrap(),
+      content: inscription.id.as_bytes().to_vec(),
+      content_type: "text/plain".into(),
+      content_encoding: None,
+    })).collect();
+    Vermilion::bulk_insert_content(&tx, &ContentStore::from_settings(&Settings::default()).unwrap(), content).await.unwrap();
+    Vermilion::bulk_insert_transfers(&tx, block.transfers.clone()).await.unwrap();
+    // Priced transfers stand in for single inscription sales
+    for transfer in block.transfers.iter().filter(|transfer| transfer.price > 0) {
+      tx.execute(
+        r"INSERT INTO sales (id, block_number, block_timestamp, transaction, input_index, seller, buyer, price, bundle_price, bundle_size, sweep_size)
+        VALUES ($1, $2, $3, $4, 0, $5, $6, $7, $7, 1, 1)",
+        &[&transfer.id, &transfer.block_number, &transfer.block_timestamp, &transfer.transaction, &transfer.previous_address, &transfer.address, &transfer.price],
+      ).await.unwrap();
+    }
+    Vermilion::bulk_insert_addresses(&tx, block.transfers.clone()).await.unwrap();
+    if !block.transfers.is_empty() {
+      collection_history::update_collection_history(&tx, block.height).await.unwrap();
+    }
+    Vermilion::bulk_insert_inscription_blockstats(&tx, block.height).await.unwrap();
+    tx.commit().await.unwrap();
+  }
+
+  // A scratch database on the postgres server in VERMILION_TEST_DB, for the ignored tests. It's dropped with the guard,
+  // unless the test failed, so the state a test failed on can be inspected. Run them with e.g.
+  // VERMILION_TEST_DB="host=127.0.0.1 user=postgres" cargo test vermilion -- --ignored
+  pub(super) struct TestDatabase {
+    pool: deadpool,
+    config: String,
+    name: &'static str,
+  }
+
+  impl TestDatabase {
+    // A database with the vermilion tables
+    pub(super) async fn new(name: &'static str) -> Self {
+      let database = Self::empty(name).await;
+      Vermilion::initialize_db_tables(database.pool.clone()).await.unwrap();
+      // Weight procedures expect the role, clustering and moderation tables that production maintains outside of ord
+      database.pool.get().await.unwrap().simple_query(r"
+        DO $$ BEGIN CREATE ROLE vermilion_user; EXCEPTION WHEN duplicate_object THEN NULL; END $$;
+        CREATE TABLE IF NOT EXISTS dbscan (sha256 varchar(64), dbscan_class bigint);
+        CREATE TABLE IF NOT EXISTS content_moderation (sha256 varchar(64), automated_moderation_flag varchar(40), human_override_moderation_flag varchar(40));
+        ").await.unwrap();
+      database
+    }
+
+    pub(super) async fn empty(name: &'static str) -> Self {
+      let config = std::env::var("VERMILION_TEST_DB").expect("VERMILION_TEST_DB must be set to a postgres connection string");
+      let (admin, connection) = tokio_postgres::connect(&config, NoTls).await.unwrap();
+      tokio::spawn(connection);
+      admin.simple_query(&format!("DROP DATABASE IF EXISTS {name} WITH (FORCE)")).await.unwrap();
+      admin.simple_query(&format!("CREATE DATABASE {name}")).await.unwrap();
+      let mut pool_config: tokio_postgres::Config = config.parse().unwrap();
+      pool_config.dbname(name);
+      let pool = deadpool_postgres::Pool::builder(deadpool_postgres::Manager::new(pool_config, NoTls)).build().unwrap();
+      Self { pool, config, name }
+    }
+  }
+
+  impl std::ops::Deref for TestDatabase {
+    type Target = deadpool;
+
+    fn deref(&self) -> &deadpool {
+      &self.pool
+    }
+  }
+
+  impl Drop for TestDatabase {
+    // Drop can't await and the test's runtime may be single threaded, so the database is dropped from a thread with
+    // a runtime of its own
+    fn drop(&mut self) {
+      if std::thread::panicking() {
+        return;
+      }
+      self.pool.close();
+      let (config, name) = (self.config.clone(), self.name);
+      std::thread::spawn(move || {
+        tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async {
+          let (admin, connection) = tokio_postgres::connect(&config, NoTls).await.unwrap();
+          tokio::spawn(connection);
+          admin.simple_query(&format!("DROP DATABASE IF EXISTS {name} WITH (FORCE)")).await.unwrap();
+        })
+      })
+      .join()
+      .unwrap();
+    }
+  }
+
+  // An API server config for calling routes in tests, with social login signed by "secret"
+  pub(super) fn test_server_config(pool: &deadpool) -> ApiServerConfig {
+    let settings = Settings::default();
+    let hidden = HiddenInscriptions::new(&settings);
+    ApiServerConfig {
+      deadpool: pool.clone(),
+      bitcoin_rpc_client: Arc::new(bitcoincore_rpc::Client::new("http://127.0.0.1:1", bitcoincore_rpc::Auth::None).unwrap()),
+      content_store: ContentStore::from_settings(&settings).unwrap(),
+      events: EventHub::idle(hidden.clone()),
+      hidden,
+      admin_api_key: None,
+      access_token_secret: Some("secret".into()),
+      chain: Chain::Mainnet,
+    }
+  }
+
+  async fn dump_tables(pool: &deadpool) -> Vec<(&'static str, Vec<String>)> {
+    let conn = pool.get().await.unwrap();
+    let mut tables = Vec::new();
+    for (table, _) in undo_journal::JOURNALED_TABLES {
+      // Serial ids aren't rolled back, so the forked index numbers its rows differently
+      let rows = conn.query(&format!("SELECT (to_jsonb(t) - 'sale_id')::text FROM {table} t ORDER BY 1"), &[]).await.unwrap();
+      tables.push((*table, rows.iter().map(|row| row.get(0)).collect()));
+    }
+    tables
+  }
+
+  // Indexes a two block fork, reorgs it away and checks every journaled table against a clean index of the
+  // winning chain
+  #[tokio::test]
+  #[ignore]
+  async fn reorg_rollback_matches_clean_index() {
+    let parent = test_inscription(0, 1, &"a".repeat(64), Vec::new(), None);
+    let child = test_inscription(1, 1, &"b".repeat(64), vec![parent.id.clone()], None);
+    let common = TestBlock {
+      height: 1,
+      inscriptions: vec![parent.clone(), child.clone()],
+      galleries: Vec::new(),
+      transfers: vec![test_transfer(&parent, 1, "", "bc1qone", 0), test_transfer(&child, 1, "", "bc1qone", 0)],
+    };
+
+    let mut edition = test_inscription(2, 2, &"b".repeat(64), vec![parent.id.clone()], Some(child.id.clone()));
+    edition.referenced_ids = vec![child.id.clone()];
+    let stale_2 = TestBlock {
+      height: 2,
+      inscriptions: vec![edition.clone()],
+      galleries: vec![(edition.id.clone(), child.id.clone())],
+      transfers: vec![test_transfer(&edition, 2, "", "bc1qtwo", 0), test_transfer(&child, 2, "bc1qone", "bc1qtwo", 1000)],
+    };
+    let orphan = test_inscription(3, 3, &"c".repeat(64), Vec::new(), None);
+    let stale_3 = TestBlock {
+      height: 3,
+      inscriptions: vec![orphan.clone()],
+      galleries: Vec::new(),
+      transfers: vec![test_transfer(&orphan, 3, "", "bc1qthree", 0), test_transfer(&edition, 3, "bc1qtwo", "bc1qthree", 2000)],
+    };
+
+    let sibling = test_inscription(2, 2, &"d".repeat(64), vec![parent.id.clone()], None);
+    let winning_2 = TestBlock {
+      height: 2,
+      inscriptions: vec![sibling.clone()],
+      galleries: Vec::new(),
+      transfers: vec![test_transfer(&sibling, 2, "", "bc1qfour", 0), test_transfer(&child, 2, "bc1qone", "bc1qfour", 500)],
+    };
+    let winning_3 = TestBlock {
+      height: 3,
+      inscriptions: Vec::new(),
+      galleries: Vec::new(),
+      transfers: vec![test_transfer(&sibling, 3, "bc1qfour", "bc1qfive", 700)],
+    };
+
+    let forked = TestDatabase::new("vermilion_reorg_test_forked").await;
+    for block in [&common, &stale_2, &stale_3] {
+      index_test_block(&forked, block).await;
+    }
+    Vermilion::handle_reorg(forked.clone(), 1).await.unwrap();
+    for block in [&winning_2, &winning_3] {
+      index_test_block(&forked, block).await;
+    }
+
+    let clean = TestDatabase::new("vermilion_reorg_test_clean").await;
+    for block in [&common, &winning_2, &winning_3] {
+      index_test_block(&clean, block).await;
+    }
+
+    let forked_tables = dump_tables(&forked).await;
+    let clean_tables = dump_tables(&clean).await;
+    for ((table, forked_rows), (_, clean_rows)) in forked_tables.into_iter().zip(clean_tables) {
+      pretty_assert_eq!(forked_rows, clean_rows, "{table} differs after reorg");
+    }
+  }
+
+  #[tokio::test]
+  #[ignore]
+  async fn search_matches_hashes_and_addresses_on_the_served_chain() {
+    let pool = TestDatabase::new("vermilion_search_test").await;
+
+    let inscription = test_inscription(0, 1, &"a".repeat(64), Vec::new(), None);
+    let transfer = test_transfer(&inscription, 1, "", "bc1qone", 0);
+    index_test_block(&pool, &TestBlock {
+      height: 1,
+      inscriptions: vec![inscription.clone()],
+      galleries: Vec::new(),
+      transfers: vec![transfer.clone()],
+    }).await;
+
+    let block = Vermilion::get_search_result(pool.clone(), Chain::Regtest, format!("{:064X}", 32)).await.unwrap();
+    assert_eq!(block.block.map(|block| block.block_number), Some(1));
+    assert!(block.transaction.is_none());
+
+    let transaction = Vermilion::get_search_result(pool.clone(), Chain::Regtest, transfer.transaction.clone()).await.unwrap().transaction.unwrap();
+    assert_eq!((transaction.block_number, transaction.transfers.len()), (1, 1));
+    assert_eq!(transaction.transfers[0].id, inscription.id);
+
+    let regtest = Address::p2wsh(&ScriptBuf::new(), Network::Regtest).to_string();
+    let mainnet = Address::p2wsh(&ScriptBuf::new(), Network::Bitcoin).to_string();
+    for (chain, address, expected) in [
+      (Chain::Regtest, regtest.to_uppercase(), Some(regtest.clone())),
+      (Chain::Regtest, mainnet.clone(), None),
+      (Chain::Mainnet, mainnet.clone(), Some(mainnet.clone())),
+    ] {
+      assert_eq!(Vermilion::get_search_result(pool.clone(), chain, address).await.unwrap().address, expected);
+    }
+
+    let name = Vermilion::get_search_result(pool.clone(), Chain::Regtest, "nvtdijuwxlp".into()).await.unwrap();
+    assert!(name.address.is_none() && name.rune.is_none());
+  }
+
+  #[tokio::test]
+  #[ignore]
+  async fn collection_tokens_resume_from_the_last_saved_page() {
+    let pool = TestDatabase::new("vermilion_collection_tokens_test").await;
+    Vermilion::initialize_collection_tables(pool.clone()).await.unwrap();
+
+    // Serves 250 tokens, failing the third page until told otherwise
+    let failing = Arc::new(AtomicBool::new(true));
+    let offsets = Arc::new(Mutex::new(Vec::new()));
+    let app = Router::new()
+      .route("/v2/ord/btc/tokens", axum::routing::get(
+        |State((failing, offsets)): State<(Arc<AtomicBool>, Arc<Mutex<Vec<usize>>>)>, Query(query): Query<HashMap<String, String>>| async move {
+          let offset: usize = query["offset"].parse().unwrap();
+          offsets.lock().unwrap().push(offset);
+          if offset == 200 && failing.load(atomic::Ordering::Relaxed) {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+          }
+          let tokens: Vec<JsonValue> = (offset..(offset + 100).min(250))
+            .map(|number| serde_json::json!({ "id": format!("{number}i0"), "inscriptionNumber": number, "collectionSymbol": "pixels" }))
+            .collect();
+          Json(serde_json::json!({ "tokens": tokens })).into_response()
+        },
+      ))
+      .with_state((failing.clone(), offsets.clone()));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    let settings = Settings::from_env(
+      [
+        ("MAGICEDEN_API_KEY", "key".to_string()),
+        ("MAGICEDEN_API_URL", format!("http://{address}")),
+        ("MAGICEDEN_REQUESTS_PER_MINUTE", "60000".to_string()),
+        ("MAGICEDEN_MAX_RETRIES", "0".to_string()),
+      ]
+      .into_iter()
+      .map(|(key, value)| (key.to_string(), value))
+      .collect(),
+    )
+    .unwrap();
+    let client = MagicEdenClient::from_settings(&settings).unwrap();
+
+    assert!(Vermilion::get_tokens(pool.clone(), &client, "pixels").await.is_err());
+    assert_eq!(Vermilion::get_token_progress(pool.clone(), "pixels").await.unwrap(), 200);
+
+    failing.store(false, atomic::Ordering::Relaxed);
+    let tokens = Vermilion::get_tokens(pool.clone(), &client, "pixels").await.unwrap();
+    assert_eq!(tokens.iter().map(|token| token.number).collect::<Vec<i64>>(), (0..250).collect::<Vec<i64>>());
+    assert_eq!(*offsets.lock().unwrap(), vec![0, 100, 200, 200]);
+  }
+}
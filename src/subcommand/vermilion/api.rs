@@ -60,6 +60,45 @@ impl OperationOutput for ContentResponse {
   }
 }
 
+// A server-sent events response
+pub struct EventStream(pub Response);
+
+impl IntoResponse for EventStream {
+  fn into_response(self) -> Response {
+    self.0
+  }
+}
+
+impl OperationOutput for EventStream {
+  type Inner = Self;
+
+  fn inferred_responses(
+    _ctx: &mut GenContext,
+    _operation: &mut Operation,
+  ) -> Vec<(Option<u16>, aide::openapi::Response)> {
+    vec![(
+      Some(200),
+      aide::openapi::Response {
+        description: "Stream of events, each named after its type with the event as json data".into(),
+        content: IndexMap::from_iter([(
+          "text/event-stream".into(),
+          MediaType {
+            schema: Some(aide::openapi::SchemaObject {
+              json_schema: json_schema!({
+                "type": "string"
+              }),
+              example: None,
+              external_docs: None,
+            }),
+            ..Default::default()
+          },
+        )]),
+        ..Default::default()
+      },
+    )]
+  }
+}
+
 #[derive(Debug, Deserialize, Serialize, JsonSchema, Display, EnumString)]
 #[serde(rename_all = "snake_case")]
 #[strum(serialize_all = "snake_case")]
@@ -206,6 +245,38 @@ pub enum BlockSortBy {
   LeastVolume,
 }
 
+#[derive(Debug, Deserialize, JsonSchema, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum SaleSortBy {
+  Newest,
+  Oldest,
+  HighestPrice,
+  LowestPrice,
+}
+
+#[derive(Debug, Deserialize, JsonSchema, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum HistoryInterval {
+  Block,
+  Day,
+}
+
+
+// The token of an `Authorization: Bearer <token>` header
+pub(crate) fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+  headers
+    .get(axum::http::header::AUTHORIZATION)
+    .and_then(|value| value.to_str().ok())
+    .and_then(|value| value.strip_prefix("Bearer "))
+    .map(str::trim)
+}
+
+// For comparing secrets, takes as long for a near miss as for a wrong first byte
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+  a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
 
 pub async fn serve_openapi(Extension(api): Extension<OpenApi>) -> impl IntoApiResponse {
   Json(api)
@@ -467,6 +538,31 @@ impl JsonSchema for BlockNumber {
   }
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct BitmapNumber(pub i64);
+
+impl JsonSchema for BitmapNumber {
+  fn schema_name() -> Cow<'static, str> {
+    "BitmapNumber".into()
+  }
+
+  fn json_schema(_gen: &mut SchemaGenerator) -> Schema {
+    json_schema!({
+      "type": "object",
+      "properties": {
+        "bitmap": {
+          "type": "integer",
+          "format": "int64",
+          "description": "Bitmap district, the block number claimed by N.bitmap",
+          "example": 840000
+        }
+      },
+      "required": ["bitmap"]
+    })
+  }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(transparent)]
 pub struct SatNumber(pub i64);
@@ -532,7 +628,7 @@ impl JsonSchema for BitcoinAddress {
       "properties": {
         "address": {
           "type": "string",
-          "description": "Bitcoin address",
+          "description": "Bitcoin address on the chain the API is serving, e.g. tb1 addresses on signet",
           "example": "bc1qxy2kgdygjrsqtzq2n0yrf2493p83kkfjhx0wlh"
         }
       },
@@ -604,7 +700,7 @@ impl JsonSchema for SearchQuery {
       "properties": {
         "search_query": {
           "type": "string",
-          "description": "Search query string",
+          "description": "Inscription id or number, block height or hash, txid, address, sat number, name or decimal/degree notation, rune name or collection name",
           "example": "bitcoin"
         }
       },
@@ -613,11 +709,87 @@ impl JsonSchema for SearchQuery {
   }
 }
 
-pub fn set_comma_separated_arrays(op: aide::transform::TransformOperation) -> aide::transform::TransformOperation {
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct SpacedRuneName(pub String);
+
+impl JsonSchema for SpacedRuneName {
+  fn schema_name() -> Cow<'static, str> {
+    "SpacedRuneName".into()
+  }
+
+  fn json_schema(_gen: &mut SchemaGenerator) -> Schema {
+    json_schema!({
+      "type": "object",
+      "properties": {
+        "spaced_rune": {
+          "type": "string",
+          "description": "Rune name, with or without spacers",
+          "example": "UNCOMMON•GOODS"
+        }
+      },
+      "required": ["spaced_rune"]
+    })
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct RuneIdentifier(pub String);
+
+impl JsonSchema for RuneIdentifier {
+  fn schema_name() -> Cow<'static, str> {
+    "RuneIdentifier".into()
+  }
+
+  fn json_schema(_gen: &mut SchemaGenerator) -> Schema {
+    json_schema!({
+      "type": "object",
+      "properties": {
+        "rune": {
+          "type": "string",
+          "description": "Rune name (with or without spacers) or rune id in BLOCK:TX form",
+          "example": "840000:3"
+        }
+      },
+      "required": ["rune"]
+    })
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Brc20Tick(pub String);
+
+impl JsonSchema for Brc20Tick {
+  fn schema_name() -> Cow<'static, str> {
+    "Brc20Tick".into()
+  }
+
+  fn json_schema(_gen: &mut SchemaGenerator) -> Schema {
+    json_schema!({
+      "type": "object",
+      "properties": {
+        "tick": {
+          "type": "string",
+          "description": "BRC-20 ticker, case insensitive",
+          "example": "ordi"
+        }
+      },
+      "required": ["tick"]
+    })
+  }
+}
+
+pub fn set_comma_separated_content_types(op: aide::transform::TransformOperation) -> aide::transform::TransformOperation {
   op.parameter::<Vec<ContentType>, _>("content_types", |mut param| {
     param.inner_mut().parameter_data_mut().explode = Some(false);
     param
   })
+}
+
+pub fn set_comma_separated_arrays(op: aide::transform::TransformOperation) -> aide::transform::TransformOperation {
+  set_comma_separated_content_types(op)
   .parameter::<Vec<SatributeType>, _>("satributes", |mut param| {
     param.inner_mut().parameter_data_mut().explode = Some(false);
     param
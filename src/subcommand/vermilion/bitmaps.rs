@@ -0,0 +1,261 @@
+use super::*;
+
+// Bitmap district registry. A blessed text/plain inscription whose whole content is `N.bitmap` claims district
+// N, as long as block N had been mined when it was inscribed. The first claim in inscription order wins and
+// later claims of the same district are ignored. Owners aren't stored, they're read from addresses.
+
+#[derive(Clone, Serialize, JsonSchema)]
+pub struct Bitmap {
+  bitmap_number: i64,
+  /// Inscription id of the claim
+  pub(crate) id: String,
+  number: i64,
+  genesis_height: i64,
+  timestamp: i64,
+  /// Current owner. Null if the claim was burned, is unbound or sits in an output without an address, or if it
+  /// was never transferred into the addresses table
+  address: Option<String>,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct BitmapsInAddressParams {
+  /// Page number for pagination (0-based)
+  #[schemars(description = "Page number for pagination, starting from 0", example = "0", range(min = 0))]
+  page_number: Option<usize>,
+
+  /// Number of items per page (max 100)
+  #[schemars(description = "Number of items per page, maximum 100", example = "10", range(min = 1, max = 100))]
+  page_size: Option<usize>,
+
+  /// Cursor for keyset pagination
+  #[schemars(description = "Opaque cursor for keyset pagination. Pass an empty cursor for the first page, then the next_cursor of each response. Takes precedence over page_number")]
+  #[serde(default, deserialize_with = "deserialize_cursor")]
+  cursor: Option<String>,
+}
+
+pub(crate) struct ParsedBitmapsInAddressParams {
+  keyset: Keyset,
+  page: PageRequest,
+}
+
+impl TryFrom<BitmapsInAddressParams> for ParsedBitmapsInAddressParams {
+  type Error = ApiError;
+
+  // Lowest district first
+  fn try_from(params: BitmapsInAddressParams) -> Result<Self, Self::Error> {
+    let keyset = Keyset::new("bitmaps_in_address", "lowest", "b.bitmap_number", false);
+    let page = PageRequest::new(params.page_number, std::cmp::min(params.page_size.unwrap_or(10), 100), params.cursor.as_deref(), &keyset)?;
+    Ok(Self { keyset, page })
+  }
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct BitmapAvailability {
+  bitmap_number: i64,
+  /// Block height the indexer has reached
+  indexed_height: i64,
+  /// False until block bitmap_number is indexed, claims for unmined blocks are invalid
+  claimable: bool,
+  /// Claimable and not yet claimed
+  available: bool,
+  /// Inscription id of the claim, if any
+  claimed_by: Option<String>,
+}
+
+// Digits are capped so the cast can't overflow, no district number has anywhere near that many
+const CLAIMS: &str = r"
+  SELECT DISTINCT ON (substring(o.text FROM '^[0-9]+')::bigint)
+    substring(o.text FROM '^[0-9]+')::bigint AS bitmap_number, o.id, o.number, o.sequence_number, o.genesis_height, o.timestamp
+  FROM ordinals o
+  WHERE o.is_bitmap_style
+  AND o.number >= 0
+  AND o.content_type LIKE 'text/plain%'
+  AND o.text ~ '^(0|[1-9][0-9]{0,17})\.bitmap$'
+  AND substring(o.text FROM '^[0-9]+')::bigint <= o.genesis_height";
+
+const INSERT_CLAIMS: &str = "INSERT INTO bitmaps (bitmap_number, id, number, sequence_number, genesis_height, timestamp)";
+
+pub(crate) async fn initialize_bitmaps_table(pool: deadpool) -> anyhow::Result<()> {
+  let conn = pool.get().await?;
+  let existed: bool = conn.query_one("SELECT to_regclass('bitmaps') IS NOT NULL", &[]).await?.get(0);
+  conn.simple_query(
+    r"CREATE TABLE IF NOT EXISTS bitmaps (
+      bitmap_number bigint not null primary key,
+      id varchar(80) not null,
+      number bigint not null,
+      sequence_number bigint not null,
+      genesis_height bigint not null,
+      timestamp bigint not null
+    )").await?;
+  conn.simple_query(r"
+    CREATE INDEX IF NOT EXISTS index_bitmaps_id ON bitmaps (id);
+    CREATE INDEX IF NOT EXISTS index_bitmaps_height ON bitmaps (genesis_height);
+  ").await?;
+  // Databases indexed before the registry existed are backfilled once from the inscriptions already there
+  if !existed {
+    let started = Instant::now();
+    let claims = conn.execute(
+      format!("{INSERT_CLAIMS} {CLAIMS} ORDER BY 1, o.sequence_number ON CONFLICT DO NOTHING").as_str(),
+      &[]
+    ).await?;
+    log::info!("Backfilled {} bitmap claims in {:?}", claims, started.elapsed());
+  }
+  Ok(())
+}
+
+// Claims inscribed in this block. Districts already claimed in earlier blocks stay with the earlier claim.
+pub(crate) async fn register_bitmaps(tx: &deadpool_postgres::Transaction<'_>, block_number: u32) -> anyhow::Result<u64> {
+  let claims = tx.execute(
+    format!("{INSERT_CLAIMS} {CLAIMS} AND o.genesis_height = $1 ORDER BY 1, o.sequence_number ON CONFLICT DO NOTHING").as_str(),
+    &[&i64::from(block_number)]
+  ).await?;
+  Ok(claims)
+}
+
+fn map_row_to_bitmap(row: tokio_postgres::Row) -> Bitmap {
+  Bitmap {
+    bitmap_number: row.get("bitmap_number"),
+    id: row.get("id"),
+    number: row.get("number"),
+    genesis_height: row.get("genesis_height"),
+    timestamp: row.get("timestamp"),
+    address: row.get("address"),
+  }
+}
+
+// The owner column, null for the pseudo addresses that aren't an owner
+fn owner() -> String {
+  format!("CASE WHEN {} THEN a.address END AS address", is_holder_address("a.address"))
+}
+
+pub(crate) async fn get_bitmap(pool: deadpool, bitmap_number: i64) -> anyhow::Result<Option<Bitmap>> {
+  let conn = pool.get().await?;
+  let query = format!("SELECT b.*, {} FROM bitmaps b LEFT JOIN addresses a ON a.id = b.id WHERE b.bitmap_number = $1", owner());
+  let row = conn.query_opt(query.as_str(), &[&bitmap_number]).await?;
+  Ok(row.map(map_row_to_bitmap))
+}
+
+pub(crate) async fn get_bitmaps_in_address(pool: deadpool, address: String, params: ParsedBitmapsInAddressParams, hidden: &HiddenParams) -> anyhow::Result<Paginated<Bitmap>> {
+  let conn = pool.get().await?;
+  let mut sql_params = SqlParams::default();
+  let mut query = format!("SELECT b.*, a.address FROM addresses a JOIN bitmaps b ON b.id = a.id WHERE a.address = {}", sql_params.push(address));
+  if let Some(visible) = hidden.visible_ids(&["b.id"], &mut sql_params) {
+    query.push_str(&format!(" AND {visible}"));
+  }
+  if let Some(after) = params.page.filter(&params.keyset, &mut sql_params) {
+    query.push_str(&format!(" AND {after}"));
+  }
+  query.push_str(&params.keyset.order_by());
+  query.push_str(&params.page.limit());
+  let rows = conn.query(query.as_str(), &sql_params.values()).await?;
+  Ok(params.page.paginate(&params.keyset, rows, map_row_to_bitmap))
+}
+
+pub(crate) async fn get_bitmap_availability(pool: deadpool, bitmap_number: i64) -> anyhow::Result<BitmapAvailability> {
+  let conn = pool.get().await?;
+  let row = conn.query_one(
+    r"SELECT
+        (SELECT coalesce(max(block_number), -1) FROM blockstats) AS indexed_height,
+        (SELECT id FROM bitmaps WHERE bitmap_number = $1) AS claimed_by",
+    &[&bitmap_number]
+  ).await?;
+  let indexed_height: i64 = row.get("indexed_height");
+  let claimed_by: Option<String> = row.get("claimed_by");
+  let claimable = bitmap_number >= 0 && bitmap_number <= indexed_height;
+  Ok(BitmapAvailability {
+    bitmap_number,
+    indexed_height,
+    claimable,
+    available: claimable && claimed_by.is_none(),
+    claimed_by,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use {
+    super::*,
+    super::super::tests::{index_test_block, TestDatabase, test_inscription, test_transfer, TestBlock},
+  };
+
+  #[tokio::test]
+  #[ignore]
+  async fn first_valid_claim_wins() {
+    let pool = TestDatabase::new("vermilion_bitmaps_test").await;
+
+    let claim = |sequence_number: i64, height: i64, text: &str| {
+      let mut inscription = test_inscription(sequence_number, height, &format!("{sequence_number:064x}"), Vec::new(), None);
+      inscription.text = Some(text.to_string());
+      inscription.is_bitmap_style = Vermilion::is_bitmap_style(text);
+      inscription
+    };
+    let blocks = [
+      (5, vec![
+        claim(0, 5, "3.bitmap"),
+        // Same district later in the block
+        claim(1, 5, "3.bitmap"),
+        // Block 6 hasn't been mined yet
+        claim(2, 5, "6.bitmap"),
+        claim(3, 5, "03.bitmap"),
+        claim(4, 5, "4.Bitmap"),
+        claim(5, 5, "5.bitmap"),
+      ]),
+      (7, vec![claim(6, 7, "5.bitmap"), claim(7, 7, "6.bitmap")]),
+    ];
+    for (height, inscriptions) in blocks {
+      let transfers = inscriptions.iter().map(|inscription| test_transfer(inscription, height, "", "bc1qminter", 0)).collect();
+      index_test_block(&pool, &TestBlock { height, inscriptions, galleries: Vec::new(), transfers }).await;
+      let mut conn = pool.get().await.unwrap();
+      let tx = conn.transaction().await.unwrap();
+      register_bitmaps(&tx, u32::try_from(height).unwrap()).await.unwrap();
+      tx.commit().await.unwrap();
+    }
+    let conn = pool.get().await.unwrap();
+    let claims: Vec<(i64, i64)> = conn
+      .query("SELECT bitmap_number, sequence_number FROM bitmaps ORDER BY bitmap_number", &[])
+      .await
+      .unwrap()
+      .iter()
+      .map(|row| (row.get(0), row.get(1)))
+      .collect();
+    assert_eq!(claims, vec![(3, 0), (5, 5), (6, 7)]);
+
+    let transfer = test_transfer(&claim(0, 5, "3.bitmap"), 8, "bc1qminter", "bc1qbuyer", 0);
+    index_test_block(&pool, &TestBlock { height: 8, inscriptions: Vec::new(), galleries: Vec::new(), transfers: vec![transfer] }).await;
+    let bitmap = get_bitmap(pool.clone(), 3).await.unwrap().unwrap();
+    assert_eq!((bitmap.id.as_str(), bitmap.address.as_deref()), (format!("{:064x}i0", 0).as_str(), Some("bc1qbuyer")));
+    let params = |page_size, cursor| {
+      ParsedBitmapsInAddressParams::try_from(BitmapsInAddressParams { page_number: None, page_size, cursor })
+        .unwrap_or_else(|_| panic!("invalid bitmaps params"))
+    };
+    let Paginated::Items(owned) = get_bitmaps_in_address(pool.clone(), "bc1qminter".into(), params(None, None), &HiddenParams::default()).await.unwrap() else {
+      panic!("expected items")
+    };
+    assert_eq!(owned.iter().map(|bitmap| bitmap.bitmap_number).collect::<Vec<_>>(), vec![5, 6]);
+    let Paginated::Page { items, next_cursor: Some(next_cursor) } =
+      get_bitmaps_in_address(pool.clone(), "bc1qminter".into(), params(Some(1), Some(String::new())), &HiddenParams::default()).await.unwrap()
+    else {
+      panic!("expected a page with a next cursor")
+    };
+    assert_eq!(items[0].bitmap_number, 5);
+    let Paginated::Page { items, next_cursor: None } =
+      get_bitmaps_in_address(pool.clone(), "bc1qminter".into(), params(Some(1), Some(next_cursor)), &HiddenParams::default()).await.unwrap()
+    else {
+      panic!("expected the last page")
+    };
+    assert_eq!(items[0].bitmap_number, 6);
+
+    // A burned claim has no owner
+    let burn = test_transfer(&claim(5, 5, "5.bitmap"), 9, "bc1qminter", "burned", 0);
+    index_test_block(&pool, &TestBlock { height: 9, inscriptions: Vec::new(), galleries: Vec::new(), transfers: vec![burn] }).await;
+    assert_eq!(get_bitmap(pool.clone(), 5).await.unwrap().unwrap().address, None);
+
+    let availability = |bitmap_number| get_bitmap_availability(pool.clone(), bitmap_number);
+    let taken = availability(3).await.unwrap();
+    assert!(taken.claimable && !taken.available);
+    let free = availability(4).await.unwrap();
+    assert!(free.available && free.claimed_by.is_none());
+    let unmined = availability(10).await.unwrap();
+    assert!(!unmined.claimable && !unmined.available);
+  }
+}
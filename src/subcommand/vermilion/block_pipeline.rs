@@ -0,0 +1,102 @@
+use super::*;
+use std::collections::VecDeque;
+use std::future::Future;
+
+// Runs extraction for up to `depth` blocks ahead on blocking threads, as extraction makes synchronous index and
+// RPC calls that would otherwise stall the runtime's workers. Results are handed out strictly in block order
+// regardless of which task finishes first.
+pub(crate) struct BlockPipeline<T> {
+  depth: usize,
+  next_block: u32,
+  in_flight: VecDeque<tokio::task::JoinHandle<anyhow::Result<T>>>,
+}
+
+impl<T: Send + 'static> BlockPipeline<T> {
+  pub(crate) fn new(depth: usize, start_block: u32) -> Self {
+    Self {
+      depth: depth.max(1),
+      next_block: start_block,
+      in_flight: VecDeque::new(),
+    }
+  }
+
+  // Starts extraction of the following blocks, never past indexed_height
+  pub(crate) fn fill<F, Fut>(&mut self, indexed_height: u32, mut extract: F)
+  where
+    F: FnMut(u32) -> Fut,
+    Fut: Future<Output = anyhow::Result<T>> + Send + 'static,
+  {
+    while self.in_flight.len() < self.depth && self.next_block <= indexed_height {
+      let (runtime, extraction) = (tokio::runtime::Handle::current(), extract(self.next_block));
+      self.in_flight.push_back(tokio::task::spawn_blocking(move || runtime.block_on(extraction)));
+      self.next_block += 1;
+    }
+  }
+
+  // Waits for the oldest block in flight, None if nothing is in flight
+  pub(crate) async fn next(&mut self) -> Option<anyhow::Result<T>> {
+    let handle = self.in_flight.pop_front()?;
+    Some(match handle.await {
+      Ok(result) => result,
+      Err(err) => Err(anyhow!("block extraction task failed: {err}")),
+    })
+  }
+
+  // Drops everything in flight, the next block handed out will be block_number. Extractions that already
+  // started can't be aborted, they run to completion and their results are discarded.
+  pub(crate) fn reset(&mut self, block_number: u32) {
+    for handle in self.in_flight.drain(..) {
+      handle.abort();
+    }
+    self.next_block = block_number;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  async fn extract(block_number: u32) -> anyhow::Result<u32> {
+    // Later blocks finish first
+    tokio::time::sleep(Duration::from_millis(u64::from(10 - block_number % 10) * 5)).await;
+    Ok(block_number)
+  }
+
+  #[tokio::test]
+  async fn blocks_are_returned_in_order() {
+    let mut pipeline = BlockPipeline::new(4, 3);
+    let mut committed = Vec::new();
+    while let Some(result) = {
+      pipeline.fill(9, extract);
+      pipeline.next().await
+    } {
+      committed.push(result.unwrap());
+    }
+    assert_eq!(committed, (3..=9).collect::<Vec<u32>>());
+  }
+
+  #[tokio::test]
+  async fn pipeline_is_bounded_by_depth_and_height() {
+    let mut pipeline = BlockPipeline::new(3, 0);
+    pipeline.fill(100, extract);
+    assert_eq!(pipeline.in_flight.len(), 3);
+    pipeline.reset(0);
+    pipeline.fill(1, extract);
+    assert_eq!(pipeline.in_flight.len(), 2);
+    let mut pipeline = BlockPipeline::new(0, 0);
+    pipeline.fill(100, extract);
+    assert_eq!(pipeline.in_flight.len(), 1);
+  }
+
+  #[tokio::test]
+  async fn reset_restarts_at_block() {
+    let mut pipeline = BlockPipeline::new(3, 10);
+    pipeline.fill(20, extract);
+    assert_eq!(pipeline.next().await.unwrap().unwrap(), 10);
+    pipeline.reset(8);
+    assert!(pipeline.in_flight.is_empty());
+    pipeline.fill(20, extract);
+    assert_eq!(pipeline.next().await.unwrap().unwrap(), 8);
+    assert_eq!(pipeline.next().await.unwrap().unwrap(), 9);
+  }
+}
@@ -0,0 +1,940 @@
+use super::*;
+
+// BRC-20 ledger built from the inscriptions and transfers already written for the block. Follows the
+// common indexer rules: the first deploy of a ticker wins, mints are capped by the limit and the remaining
+// supply, and a transfer inscription locks part of its owner's balance until it is first sent, when the
+// locked amount moves to the receiver (or back to the owner if it was spent as fee). Only 4 byte tickers
+// are recognised. Amounts are kept as integers scaled by the ticker's decimals and stored as numeric.
+
+// The first deploy on mainnet, nothing before it can be a BRC-20 operation
+const FIRST_MAINNET_BRC20_HEIGHT: u32 = 779_832;
+const MAX_DECIMALS: u8 = 18;
+
+pub(crate) fn first_brc20_height(settings: &Settings) -> u32 {
+  match settings.chain() {
+    Chain::Mainnet => FIRST_MAINNET_BRC20_HEIGHT,
+    _ => settings.first_inscription_height(),
+  }
+}
+
+#[derive(Clone, Serialize, JsonSchema)]
+pub struct Brc20Ticker {
+  /// Lowercase ticker, tickers are case insensitive
+  tick: String,
+  /// Ticker as deployed
+  display_tick: String,
+  max_supply: String,
+  mint_limit: String,
+  decimals: i32,
+  minted: String,
+  /// Addresses with a non zero balance
+  holders: i64,
+  deploy_inscription_id: String,
+  deploy_number: i64,
+  deploy_block: i64,
+  deploy_timestamp: i64,
+  deployer: String,
+  /// Block the last of the supply was minted in
+  completed_block: Option<i64>,
+}
+
+#[derive(Clone, Serialize, JsonSchema)]
+pub struct Brc20Balance {
+  tick: String,
+  address: String,
+  /// Free to inscribe transfers from
+  available: String,
+  /// Locked in transfer inscriptions that haven't been sent yet
+  transferable: String,
+  /// available + transferable
+  balance: String,
+}
+
+#[derive(Clone, Debug, Serialize, JsonSchema, PartialEq)]
+pub struct Brc20Event {
+  inscription_id: String,
+  inscription_number: i64,
+  /// deploy, mint, inscribe_transfer or transfer
+  event: String,
+  tick: String,
+  /// Null for deploys
+  amount: Option<String>,
+  /// Sender of transfers, null otherwise
+  from_address: Option<String>,
+  /// Deployer, minter, owner of the transfer inscription or receiver of the transfer
+  to_address: String,
+  block_number: i64,
+  block_timestamp: i64,
+  transaction: String,
+  tx_offset: i64,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum Operation {
+  Deploy { tick: String, max_supply: u128, mint_limit: u128, decimals: u8 },
+  Mint { tick: String, amount: String },
+  Transfer { tick: String, amount: String },
+}
+
+impl Operation {
+  fn tick(&self) -> String {
+    match self {
+      Operation::Deploy { tick, .. } | Operation::Mint { tick, .. } | Operation::Transfer { tick, .. } => tick.to_lowercase(),
+    }
+  }
+}
+
+// Parses a decimal string into an integer scaled by 10^decimals. Signs, exponents, spaces, a missing whole
+// or fraction part, more fraction digits than decimals and values above u64::MAX are all invalid.
+pub(crate) fn parse_amount(text: &str, decimals: u8) -> Option<u128> {
+  let (whole, fraction) = match text.split_once('.') {
+    Some((whole, fraction)) if !fraction.is_empty() => (whole, fraction),
+    Some(_) => return None,
+    None => (text, ""),
+  };
+  if whole.is_empty() || fraction.len() > usize::from(decimals) || !whole.bytes().chain(fraction.bytes()).all(|byte| byte.is_ascii_digit()) {
+    return None;
+  }
+  let scale = 10u128.pow(decimals.into());
+  let fraction = if fraction.is_empty() {
+    0
+  } else {
+    fraction.parse::<u128>().ok()? * 10u128.pow((usize::from(decimals) - fraction.len()).try_into().ok()?)
+  };
+  let amount = whole.parse::<u128>().ok()?.checked_mul(scale)?.checked_add(fraction)?;
+  (amount <= u128::from(u64::MAX) * scale).then_some(amount)
+}
+
+pub(crate) fn format_amount(amount: u128, decimals: u8) -> String {
+  let scale = 10u128.pow(decimals.into());
+  let (whole, fraction) = (amount / scale, amount % scale);
+  if fraction == 0 {
+    return whole.to_string();
+  }
+  let fraction = format!("{fraction:0>width$}", width = usize::from(decimals));
+  format!("{whole}.{}", fraction.trim_end_matches('0'))
+}
+
+pub(crate) fn parse_operation(content_type: Option<&str>, text: Option<&str>) -> Option<Operation> {
+  let media_type = content_type?.split(';').next()?.trim();
+  if media_type != "text/plain" && media_type != "application/json" {
+    return None;
+  }
+  let json: serde_json::Value = serde_json::from_str(text?).ok()?;
+  if json.get("p")?.as_str()? != "brc-20" {
+    return None;
+  }
+  let tick = json.get("tick")?.as_str()?.to_string();
+  if tick.len() != 4 {
+    return None;
+  }
+  let field = |name: &str| json.get(name).map(|value| value.as_str().map(str::to_string));
+  match json.get("op")?.as_str()? {
+    "deploy" => {
+      let decimals = match field("dec") {
+        None => MAX_DECIMALS,
+        Some(dec) => {
+          let dec = dec?;
+          if dec.is_empty() || !dec.bytes().all(|byte| byte.is_ascii_digit()) {
+            return None;
+          }
+          dec.parse::<u8>().ok().filter(|dec| *dec <= MAX_DECIMALS)?
+        }
+      };
+      let max_supply = parse_amount(&field("max")??, decimals).filter(|max| *max > 0)?;
+      let mint_limit = match field("lim") {
+        None => max_supply,
+        Some(lim) => parse_amount(&lim?, decimals).filter(|lim| *lim > 0)?,
+      };
+      Some(Operation::Deploy { tick, max_supply, mint_limit, decimals })
+    }
+    "mint" => Some(Operation::Mint { tick, amount: field("amt")?? }),
+    "transfer" => Some(Operation::Transfer { tick, amount: field("amt")?? }),
+    _ => None,
+  }
+}
+
+// A transfer row of the block, with the operation of its inscription when this is the inscription's genesis
+pub(crate) struct BlockItem {
+  id: String,
+  number: i64,
+  transaction: String,
+  tx_offset: i64,
+  block_number: i64,
+  block_timestamp: i64,
+  address: String,
+  operation: Option<Operation>,
+  is_genesis: bool,
+}
+
+pub(crate) struct Ticker {
+  display_tick: String,
+  max_supply: u128,
+  mint_limit: u128,
+  decimals: u8,
+  minted: u128,
+  deploy_inscription_id: String,
+  deploy_number: i64,
+  deploy_block: i64,
+  deploy_timestamp: i64,
+  deployer: String,
+  completed_block: Option<i64>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct Balance {
+  available: u128,
+  transferable: u128,
+}
+
+// An inscribed transfer that hasn't been sent yet
+pub(crate) struct Pending {
+  tick: String,
+  amount: u128,
+  owner: String,
+}
+
+// The part of the ledger a block touches, loaded before the block is applied
+#[derive(Default)]
+pub(crate) struct Ledger {
+  tickers: HashMap<String, Ticker>,
+  balances: HashMap<(String, String), Balance>,
+  pending: HashMap<String, Pending>,
+  events: Vec<Brc20Event>,
+  changed_tickers: BTreeSet<String>,
+  changed_balances: BTreeSet<(String, String)>,
+}
+
+impl Ledger {
+  fn event(&mut self, item: &BlockItem, event: &str, tick: &str, amount: Option<String>, from_address: Option<String>, to_address: String) {
+    self.events.push(Brc20Event {
+      inscription_id: item.id.clone(),
+      inscription_number: item.number,
+      event: event.to_string(),
+      tick: tick.to_string(),
+      amount,
+      from_address,
+      to_address,
+      block_number: item.block_number,
+      block_timestamp: item.block_timestamp,
+      transaction: item.transaction.clone(),
+      tx_offset: item.tx_offset,
+    });
+  }
+
+  fn balance(&mut self, tick: &str, address: &str) -> &mut Balance {
+    let key = (tick.to_string(), address.to_string());
+    self.changed_balances.insert(key.clone());
+    self.balances.entry(key).or_default()
+  }
+
+  pub(crate) fn apply(&mut self, item: &BlockItem) {
+    if !item.is_genesis {
+      self.send(item);
+      return;
+    }
+    let Some(operation) = &item.operation else {
+      return;
+    };
+    let tick = operation.tick();
+    match operation {
+      Operation::Deploy { tick: display_tick, max_supply, mint_limit, decimals } => {
+        if self.tickers.contains_key(&tick) {
+          return;
+        }
+        self.tickers.insert(tick.clone(), Ticker {
+          display_tick: display_tick.clone(),
+          max_supply: *max_supply,
+          mint_limit: *mint_limit,
+          decimals: *decimals,
+          minted: 0,
+          deploy_inscription_id: item.id.clone(),
+          deploy_number: item.number,
+          deploy_block: item.block_number,
+          deploy_timestamp: item.block_timestamp,
+          deployer: item.address.clone(),
+          completed_block: None,
+        });
+        self.changed_tickers.insert(tick.clone());
+        self.event(item, "deploy", &tick, None, None, item.address.clone());
+      }
+      // Nobody can be credited with mints or transfers inscribed straight into the fee
+      Operation::Mint { .. } | Operation::Transfer { .. } if item.address == "unbound" => {}
+      Operation::Mint { amount, .. } => {
+        let Some(ticker) = self.tickers.get_mut(&tick) else {
+          return;
+        };
+        let Some(amount) = parse_amount(amount, ticker.decimals).filter(|amount| *amount > 0 && *amount <= ticker.mint_limit) else {
+          return;
+        };
+        let amount = amount.min(ticker.max_supply - ticker.minted);
+        if amount == 0 {
+          return;
+        }
+        ticker.minted += amount;
+        if ticker.minted == ticker.max_supply {
+          ticker.completed_block = Some(item.block_number);
+        }
+        let decimals = ticker.decimals;
+        self.changed_tickers.insert(tick.clone());
+        self.balance(&tick, &item.address).available += amount;
+        self.event(item, "mint", &tick, Some(format_amount(amount, decimals)), None, item.address.clone());
+      }
+      Operation::Transfer { amount, .. } => {
+        let Some(ticker) = self.tickers.get(&tick) else {
+          return;
+        };
+        let decimals = ticker.decimals;
+        let Some(amount) = parse_amount(amount, decimals).filter(|amount| *amount > 0) else {
+          return;
+        };
+        let balance = self.balance(&tick, &item.address);
+        if balance.available < amount {
+          return;
+        }
+        balance.available -= amount;
+        balance.transferable += amount;
+        self.pending.insert(item.id.clone(), Pending { tick: tick.clone(), amount, owner: item.address.clone() });
+        self.event(item, "inscribe_transfer", &tick, Some(format_amount(amount, decimals)), None, item.address.clone());
+      }
+    }
+  }
+
+  fn send(&mut self, item: &BlockItem) {
+    let Some(pending) = self.pending.remove(&item.id) else {
+      return;
+    };
+    let Some(decimals) = self.tickers.get(&pending.tick).map(|ticker| ticker.decimals) else {
+      return;
+    };
+    let receiver = if item.address == "unbound" { pending.owner.clone() } else { item.address.clone() };
+    self.balance(&pending.tick, &pending.owner).transferable -= pending.amount;
+    self.balance(&pending.tick, &receiver).available += pending.amount;
+    self.event(item, "transfer", &pending.tick, Some(format_amount(pending.amount, decimals)), Some(pending.owner), receiver);
+  }
+}
+
+// Tickers and balances the block items can touch, as (ticks, (tick, address) pairs)
+fn touched_state(items: &[BlockItem], pending: &HashMap<String, (String, String)>) -> (BTreeSet<String>, BTreeSet<(String, String)>) {
+  let mut ticks = BTreeSet::new();
+  let mut balances = BTreeSet::new();
+  let mut inscribed: HashMap<&str, (String, String)> = HashMap::new();
+  for item in items {
+    if let Some(operation) = &item.operation {
+      let tick = operation.tick();
+      ticks.insert(tick.clone());
+      if let Operation::Transfer { .. } = operation {
+        inscribed.insert(&item.id, (tick.clone(), item.address.clone()));
+      }
+      balances.insert((tick, item.address.clone()));
+    } else if let Some((tick, owner)) = pending.get(&item.id).or_else(|| inscribed.get(item.id.as_str())) {
+      ticks.insert(tick.clone());
+      balances.insert((tick.clone(), owner.clone()));
+      balances.insert((tick.clone(), item.address.clone()));
+    }
+  }
+  (ticks, balances)
+}
+
+pub(crate) async fn initialize_brc20_tables(pool: deadpool) -> anyhow::Result<()> {
+  let conn = pool.get().await?;
+  conn.simple_query(
+    r"CREATE TABLE IF NOT EXISTS brc20_tickers (
+      tick varchar(20) not null primary key,
+      display_tick varchar(20) not null,
+      max_supply numeric not null,
+      mint_limit numeric not null,
+      decimals int not null,
+      minted numeric not null,
+      deploy_inscription_id varchar(80) not null,
+      deploy_number bigint not null,
+      deploy_block bigint not null,
+      deploy_timestamp bigint not null,
+      deployer varchar(100) not null,
+      completed_block bigint
+    )").await?;
+  conn.simple_query(
+    r"CREATE TABLE IF NOT EXISTS brc20_balances (
+      tick varchar(20) not null,
+      address varchar(100) not null,
+      available numeric not null,
+      transferable numeric not null,
+      PRIMARY KEY (tick, address)
+    )").await?;
+  conn.simple_query(
+    r"CREATE TABLE IF NOT EXISTS brc20_events (
+      inscription_id varchar(80) not null,
+      inscription_number bigint not null,
+      event varchar(20) not null,
+      tick varchar(20) not null,
+      amount numeric,
+      from_address varchar(100),
+      to_address varchar(100) not null,
+      block_number bigint not null,
+      block_timestamp bigint not null,
+      transaction varchar(80) not null,
+      tx_offset bigint not null,
+      PRIMARY KEY (inscription_id, event)
+    )").await?;
+  conn.simple_query(r"
+    CREATE INDEX IF NOT EXISTS index_brc20_tickers_deploy ON brc20_tickers (deploy_block, deploy_number);
+    CREATE INDEX IF NOT EXISTS index_brc20_balances_address ON brc20_balances (address);
+    CREATE INDEX IF NOT EXISTS index_brc20_events_tick ON brc20_events (tick, block_number, tx_offset);
+    CREATE INDEX IF NOT EXISTS index_brc20_events_block ON brc20_events (block_number);
+  ").await?;
+  Ok(())
+}
+
+// Whether any ticker has been deployed, to warn when indexing is turned on after the first BRC-20 block
+pub(crate) async fn has_history(pool: deadpool) -> anyhow::Result<bool> {
+  let conn = pool.get().await?;
+  let row = conn.query_one("SELECT EXISTS (SELECT 1 FROM brc20_tickers)", &[]).await?;
+  Ok(row.get(0))
+}
+
+pub(crate) async fn index_block(tx: &deadpool_postgres::Transaction<'_>, block_number: u32) -> anyhow::Result<()> {
+  let start_time = Instant::now();
+  // Only inscriptions whose text mentions brc-20 can be operations or unsent transfer inscriptions
+  let rows = tx.query(
+    r"SELECT t.id, o.number, t.transaction, t.tx_offset, t.block_number, t.block_timestamp, t.address, t.is_genesis, o.content_type, o.text
+      FROM transfers t
+      JOIN ordinals o ON o.id = t.id
+      WHERE t.block_number = $1 AND o.number >= 0 AND o.text LIKE '%brc-20%'
+      ORDER BY t.tx_offset, o.sequence_number",
+    &[&i64::from(block_number)]
+  ).await?;
+  if rows.is_empty() {
+    return Ok(());
+  }
+  let items: Vec<BlockItem> = rows.into_iter().map(|row| {
+    let is_genesis: bool = row.get("is_genesis");
+    BlockItem {
+      id: row.get("id"),
+      number: row.get("number"),
+      transaction: row.get("transaction"),
+      tx_offset: row.get("tx_offset"),
+      block_number: row.get("block_number"),
+      block_timestamp: row.get("block_timestamp"),
+      address: row.get("address"),
+      operation: is_genesis.then(|| parse_operation(row.get("content_type"), row.get("text"))).flatten(),
+      is_genesis,
+    }
+  }).collect();
+
+  let sent: Vec<&str> = items.iter().filter(|item| !item.is_genesis).map(|item| item.id.as_str()).collect();
+  let pending_rows = tx.query(
+    r"SELECT e.inscription_id, e.tick, e.amount::text AS amount, e.to_address
+      FROM brc20_events e
+      WHERE e.event = 'inscribe_transfer' AND e.inscription_id = ANY($1)
+      AND NOT EXISTS (SELECT 1 FROM brc20_events s WHERE s.inscription_id = e.inscription_id AND s.event = 'transfer')",
+    &[&sent]
+  ).await?;
+  let pending: HashMap<String, (String, String, String)> = pending_rows
+    .into_iter()
+    .map(|row| (row.get("inscription_id"), (row.get("tick"), row.get("to_address"), row.get("amount"))))
+    .collect();
+  let owners = pending.iter().map(|(id, (tick, owner, _))| (id.clone(), (tick.clone(), owner.clone()))).collect();
+  let (ticks, balance_keys) = touched_state(&items, &owners);
+
+  let mut ledger = Ledger::default();
+  let ticks: Vec<String> = ticks.into_iter().collect();
+  for row in tx.query(
+    r"SELECT tick, display_tick, max_supply::text AS max_supply, mint_limit::text AS mint_limit, decimals, minted::text AS minted,
+        deploy_inscription_id, deploy_number, deploy_block, deploy_timestamp, deployer, completed_block
+      FROM brc20_tickers WHERE tick = ANY($1)",
+    &[&ticks]
+  ).await? {
+    let decimals = u8::try_from(row.get::<_, i32>("decimals"))?;
+    let amount = |column: &str| parse_amount(row.get(column), decimals).ok_or_else(|| anyhow!("Invalid {} stored for ticker {}", column, row.get::<_, String>("tick")));
+    ledger.tickers.insert(row.get("tick"), Ticker {
+      display_tick: row.get("display_tick"),
+      max_supply: amount("max_supply")?,
+      mint_limit: amount("mint_limit")?,
+      decimals,
+      minted: amount("minted")?,
+      deploy_inscription_id: row.get("deploy_inscription_id"),
+      deploy_number: row.get("deploy_number"),
+      deploy_block: row.get("deploy_block"),
+      deploy_timestamp: row.get("deploy_timestamp"),
+      deployer: row.get("deployer"),
+      completed_block: row.get("completed_block"),
+    });
+  }
+  for (id, (tick, owner, amount)) in pending {
+    if let Some(amount) = ledger.tickers.get(&tick).and_then(|ticker| parse_amount(&amount, ticker.decimals)) {
+      ledger.pending.insert(id, Pending { tick, amount, owner });
+    }
+  }
+  let (balance_ticks, balance_addresses): (Vec<String>, Vec<String>) = balance_keys.into_iter().unzip();
+  for row in tx.query(
+    r"SELECT b.tick, b.address, b.available::text AS available, b.transferable::text AS transferable
+      FROM brc20_balances b
+      JOIN unnest($1::varchar[], $2::varchar[]) AS k(tick, address) ON b.tick = k.tick AND b.address = k.address",
+    &[&balance_ticks, &balance_addresses]
+  ).await? {
+    let tick: String = row.get("tick");
+    let Some(decimals) = ledger.tickers.get(&tick).map(|ticker| ticker.decimals) else {
+      continue;
+    };
+    let amount = |column: &str| parse_amount(row.get(column), decimals).ok_or_else(|| anyhow!("Invalid {} balance stored for ticker {}", column, tick));
+    let balance = Balance { available: amount("available")?, transferable: amount("transferable")? };
+    ledger.balances.insert((tick.clone(), row.get("address")), balance);
+  }
+
+  for item in &items {
+    ledger.apply(item);
+  }
+  write_ledger(tx, ledger).await?;
+  log::info!("Block {}: Indexed BRC-20 operations from {} transfers in {:?}", block_number, items.len(), start_time.elapsed());
+  Ok(())
+}
+
+async fn write_ledger(tx: &deadpool_postgres::Transaction<'_>, ledger: Ledger) -> anyhow::Result<()> {
+  for tick in &ledger.changed_tickers {
+    let ticker = &ledger.tickers[tick];
+    tx.execute(
+      r"INSERT INTO brc20_tickers (tick, display_tick, max_supply, mint_limit, decimals, minted, deploy_inscription_id, deploy_number, deploy_block, deploy_timestamp, deployer, completed_block)
+        VALUES ($1, $2, $3::text::numeric, $4::text::numeric, $5, $6::text::numeric, $7, $8, $9, $10, $11, $12)
+        ON CONFLICT (tick) DO UPDATE SET minted = EXCLUDED.minted, completed_block = EXCLUDED.completed_block",
+      &[
+        tick,
+        &ticker.display_tick,
+        &format_amount(ticker.max_supply, ticker.decimals),
+        &format_amount(ticker.mint_limit, ticker.decimals),
+        &i32::from(ticker.decimals),
+        &format_amount(ticker.minted, ticker.decimals),
+        &ticker.deploy_inscription_id,
+        &ticker.deploy_number,
+        &ticker.deploy_block,
+        &ticker.deploy_timestamp,
+        &ticker.deployer,
+        &ticker.completed_block,
+      ]
+    ).await?;
+  }
+
+  let mut ticks = Vec::new();
+  let mut addresses = Vec::new();
+  let mut available = Vec::new();
+  let mut transferable = Vec::new();
+  for (tick, address) in &ledger.changed_balances {
+    let balance = ledger.balances[&(tick.clone(), address.clone())];
+    let decimals = ledger.tickers[tick].decimals;
+    ticks.push(tick.as_str());
+    addresses.push(address.as_str());
+    available.push(format_amount(balance.available, decimals));
+    transferable.push(format_amount(balance.transferable, decimals));
+  }
+  tx.execute(
+    r"INSERT INTO brc20_balances (tick, address, available, transferable)
+      SELECT tick, address, available::numeric, transferable::numeric FROM unnest($1::varchar[], $2::varchar[], $3::text[], $4::text[]) AS b(tick, address, available, transferable)
+      ON CONFLICT (tick, address) DO UPDATE SET available = EXCLUDED.available, transferable = EXCLUDED.transferable",
+    &[&ticks, &addresses, &available, &transferable]
+  ).await?;
+
+  let events = &ledger.events;
+  tx.execute(
+    r"INSERT INTO brc20_events (inscription_id, inscription_number, event, tick, amount, from_address, to_address, block_number, block_timestamp, transaction, tx_offset)
+      SELECT inscription_id, inscription_number, event, tick, amount::numeric, from_address, to_address, block_number, block_timestamp, transaction, tx_offset
+      FROM unnest($1::varchar[], $2::bigint[], $3::varchar[], $4::varchar[], $5::text[], $6::varchar[], $7::varchar[], $8::bigint[], $9::bigint[], $10::varchar[], $11::bigint[])
+        AS e(inscription_id, inscription_number, event, tick, amount, from_address, to_address, block_number, block_timestamp, transaction, tx_offset)",
+    &[
+      &events.iter().map(|event| event.inscription_id.as_str()).collect::<Vec<_>>(),
+      &events.iter().map(|event| event.inscription_number).collect::<Vec<_>>(),
+      &events.iter().map(|event| event.event.as_str()).collect::<Vec<_>>(),
+      &events.iter().map(|event| event.tick.as_str()).collect::<Vec<_>>(),
+      &events.iter().map(|event| event.amount.as_deref()).collect::<Vec<_>>(),
+      &events.iter().map(|event| event.from_address.as_deref()).collect::<Vec<_>>(),
+      &events.iter().map(|event| event.to_address.as_str()).collect::<Vec<_>>(),
+      &events.iter().map(|event| event.block_number).collect::<Vec<_>>(),
+      &events.iter().map(|event| event.block_timestamp).collect::<Vec<_>>(),
+      &events.iter().map(|event| event.transaction.as_str()).collect::<Vec<_>>(),
+      &events.iter().map(|event| event.tx_offset).collect::<Vec<_>>(),
+    ]
+  ).await?;
+  Ok(())
+}
+
+// Fallback for blocks indexed without an undo journal. Events are the source of truth, so tickers and
+// balances are rebuilt from the events left after the rollback.
+pub(crate) async fn rollback_brc20(tx: &deadpool_postgres::Transaction<'_>, last_good_block: u32) -> anyhow::Result<()> {
+  let last_good_block = i64::from(last_good_block);
+  tx.execute("DELETE FROM brc20_events WHERE block_number > $1", &[&last_good_block]).await?;
+  tx.execute("DELETE FROM brc20_tickers WHERE deploy_block > $1", &[&last_good_block]).await?;
+  tx.execute(
+    r"UPDATE brc20_tickers t SET
+        minted = coalesce((SELECT sum(e.amount) FROM brc20_events e WHERE e.tick = t.tick AND e.event = 'mint'), 0),
+        completed_block = CASE WHEN t.completed_block > $1 THEN NULL ELSE t.completed_block END",
+    &[&last_good_block]
+  ).await?;
+  tx.execute("DELETE FROM brc20_balances", &[]).await?;
+  tx.execute(
+    r"INSERT INTO brc20_balances (tick, address, available, transferable)
+      SELECT tick, address, sum(amount) - sum(locked), sum(locked)
+      FROM (
+        SELECT tick, to_address AS address, amount, 0 AS locked FROM brc20_events WHERE event IN ('mint', 'transfer')
+        UNION ALL
+        SELECT tick, from_address, -amount, 0 FROM brc20_events WHERE event = 'transfer'
+        UNION ALL
+        SELECT e.tick, e.to_address, 0, e.amount FROM brc20_events e
+        WHERE e.event = 'inscribe_transfer'
+        AND NOT EXISTS (SELECT 1 FROM brc20_events s WHERE s.inscription_id = e.inscription_id AND s.event = 'transfer')
+      ) b
+      GROUP BY tick, address",
+    &[]
+  ).await?;
+  Ok(())
+}
+
+const TICKER_COLUMNS: &str = r"t.tick, t.display_tick, t.max_supply::text AS max_supply, t.mint_limit::text AS mint_limit, t.decimals,
+  t.minted::text AS minted, t.deploy_inscription_id, t.deploy_number, t.deploy_block, t.deploy_timestamp, t.deployer, t.completed_block,
+  (SELECT count(*) FROM brc20_balances b WHERE b.tick = t.tick AND b.available + b.transferable > 0) AS holders";
+
+fn map_row_to_ticker(row: tokio_postgres::Row) -> Brc20Ticker {
+  Brc20Ticker {
+    tick: row.get("tick"),
+    display_tick: row.get("display_tick"),
+    max_supply: row.get("max_supply"),
+    mint_limit: row.get("mint_limit"),
+    decimals: row.get("decimals"),
+    minted: row.get("minted"),
+    holders: row.get("holders"),
+    deploy_inscription_id: row.get("deploy_inscription_id"),
+    deploy_number: row.get("deploy_number"),
+    deploy_block: row.get("deploy_block"),
+    deploy_timestamp: row.get("deploy_timestamp"),
+    deployer: row.get("deployer"),
+    completed_block: row.get("completed_block"),
+  }
+}
+
+fn map_row_to_balance(row: tokio_postgres::Row) -> Brc20Balance {
+  Brc20Balance {
+    tick: row.get("tick"),
+    address: row.get("address"),
+    available: row.get("available"),
+    transferable: row.get("transferable"),
+    balance: row.get("balance"),
+  }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct Brc20PageParams {
+  /// Page number for pagination (0-based)
+  #[schemars(description = "Page number for pagination, starting from 0", example = "0", range(min = 0))]
+  page_number: Option<usize>,
+
+  /// Number of items per page (max 100)
+  #[schemars(description = "Number of items per page, maximum 100", example = "10", range(min = 1, max = 100))]
+  page_size: Option<usize>,
+
+  /// Cursor for keyset pagination
+  #[schemars(description = "Opaque cursor for keyset pagination. Pass an empty cursor for the first page, then the next_cursor of each response. Takes precedence over page_number")]
+  #[serde(default, deserialize_with = "deserialize_cursor")]
+  cursor: Option<String>,
+}
+
+pub(crate) enum Brc20List {
+  Tickers,
+  Holders,
+  Activity,
+}
+
+pub(crate) struct ParsedBrc20PageParams {
+  keyset: Keyset,
+  page: PageRequest,
+}
+
+impl Brc20PageParams {
+  // Tickers newest deploy first, holders largest balance first and activity newest first. Balances are numeric, so
+  // holders are ordered by a text key, and several events can share a block, so activity ties are broken by one.
+  pub(crate) fn parse(self, list: Brc20List) -> Result<ParsedBrc20PageParams, ApiError> {
+    let keyset = match list {
+      Brc20List::Tickers => Keyset::new("brc20_tickers", "newest", "t.deploy_block", true).tie_break("t.deploy_number", KeyType::Int8),
+      Brc20List::Holders => Keyset::new("brc20_holders", "largest", "b.holder_key", true).tie_break("b.holder_key", KeyType::Text),
+      Brc20List::Activity => Keyset::new("brc20_activity", "newest", "e.block_number", true).tie_break("e.event_key", KeyType::Text),
+    };
+    let page = PageRequest::new(self.page_number, std::cmp::min(self.page_size.unwrap_or(10), 100), self.cursor.as_deref(), &keyset)?;
+    Ok(ParsedBrc20PageParams { keyset, page })
+  }
+}
+
+pub(crate) async fn get_tickers(pool: deadpool, params: ParsedBrc20PageParams) -> anyhow::Result<Paginated<Brc20Ticker>> {
+  let conn = pool.get().await?;
+  let mut sql_params = SqlParams::default();
+  let mut query = format!("SELECT {TICKER_COLUMNS} FROM brc20_tickers t");
+  if let Some(after) = params.page.filter(&params.keyset, &mut sql_params) {
+    query.push_str(&format!(" WHERE {after}"));
+  }
+  query.push_str(&params.keyset.order_by());
+  query.push_str(&params.page.limit());
+  let rows = conn.query(query.as_str(), &sql_params.values()).await?;
+  Ok(params.page.paginate(&params.keyset, rows, map_row_to_ticker))
+}
+
+pub(crate) async fn get_ticker(pool: deadpool, tick: String) -> anyhow::Result<Option<Brc20Ticker>> {
+  let conn = pool.get().await?;
+  let query = format!("SELECT {TICKER_COLUMNS} FROM brc20_tickers t WHERE t.tick = $1");
+  let result = conn.query_opt(query.as_str(), &[&tick.to_lowercase()]).await?;
+  Ok(result.map(map_row_to_ticker))
+}
+
+// The holder key is the balance scaled to an integer by the largest possible decimals and zero padded to the
+// widest possible balance, so it sorts like the balance, followed by the address
+pub(crate) async fn get_holders(pool: deadpool, tick: String, params: ParsedBrc20PageParams) -> anyhow::Result<Paginated<Brc20Balance>> {
+  let conn = pool.get().await?;
+  let mut sql_params = SqlParams::default();
+  let mut query = format!(
+    r"SELECT * FROM (
+        SELECT b.tick, b.address, b.available::text AS available, b.transferable::text AS transferable, (b.available + b.transferable)::text AS balance,
+          lpad(trunc((b.available + b.transferable) * 1e{MAX_DECIMALS})::text, 40, '0') || ':' || b.address AS holder_key
+        FROM brc20_balances b
+        WHERE b.tick = {} AND b.available + b.transferable > 0
+      ) b",
+    sql_params.push(tick.to_lowercase())
+  );
+  if let Some(after) = params.page.filter(&params.keyset, &mut sql_params) {
+    query.push_str(&format!(" WHERE {after}"));
+  }
+  query.push_str(&params.keyset.order_by());
+  query.push_str(&params.page.limit());
+  let rows = conn.query(query.as_str(), &sql_params.values()).await?;
+  Ok(params.page.paginate(&params.keyset, rows, map_row_to_balance))
+}
+
+pub(crate) async fn get_address_balances(pool: deadpool, address: String) -> anyhow::Result<Vec<Brc20Balance>> {
+  let conn = pool.get().await?;
+  let result = conn.query(
+    r"SELECT b.tick, b.address, b.available::text AS available, b.transferable::text AS transferable, (b.available + b.transferable)::text AS balance
+      FROM brc20_balances b
+      WHERE b.address = $1 AND b.available + b.transferable > 0
+      ORDER BY b.tick",
+    &[&address]
+  ).await?;
+  Ok(result.into_iter().map(map_row_to_balance).collect())
+}
+
+// Events in a block are ordered by transaction, then by inscription and event, which identify an event
+pub(crate) async fn get_activity(pool: deadpool, tick: String, params: ParsedBrc20PageParams) -> anyhow::Result<Paginated<Brc20Event>> {
+  let conn = pool.get().await?;
+  let mut sql_params = SqlParams::default();
+  let mut query = format!(
+    r"SELECT * FROM (
+        SELECT e.*, e.amount::text AS amount_text, lpad(e.tx_offset::text, 10, '0') || ':' || e.inscription_id || ':' || e.event AS event_key
+        FROM brc20_events e
+        WHERE e.tick = {}
+      ) e",
+    sql_params.push(tick.to_lowercase())
+  );
+  if let Some(after) = params.page.filter(&params.keyset, &mut sql_params) {
+    query.push_str(&format!(" WHERE {after}"));
+  }
+  query.push_str(&params.keyset.order_by());
+  query.push_str(&params.page.limit());
+  let rows = conn.query(query.as_str(), &sql_params.values()).await?;
+  Ok(params.page.paginate(&params.keyset, rows, |row| Brc20Event {
+    inscription_id: row.get("inscription_id"),
+    inscription_number: row.get("inscription_number"),
+    event: row.get("event"),
+    tick: row.get("tick"),
+    amount: row.get("amount_text"),
+    from_address: row.get("from_address"),
+    to_address: row.get("to_address"),
+    block_number: row.get("block_number"),
+    block_timestamp: row.get("block_timestamp"),
+    transaction: row.get("transaction"),
+    tx_offset: row.get("tx_offset"),
+  }))
+}
+
+#[cfg(test)]
+mod tests {
+  use {
+    super::*,
+    super::super::tests::{index_test_block, TestDatabase, test_inscription, test_transfer, TestBlock},
+  };
+
+  fn item(id: &str, address: &str, text: Option<&str>) -> BlockItem {
+    BlockItem {
+      id: id.into(),
+      number: 0,
+      transaction: String::new(),
+      tx_offset: 0,
+      block_number: 1,
+      block_timestamp: 0,
+      address: address.into(),
+      operation: text.and_then(|text| parse_operation(Some("text/plain;charset=utf-8"), Some(text))),
+      is_genesis: text.is_some(),
+    }
+  }
+
+  #[test]
+  fn amounts() {
+    assert_eq!(parse_amount("1000", 18), Some(1000 * 10u128.pow(18)));
+    assert_eq!(parse_amount("0.5", 1), Some(5));
+    assert_eq!(parse_amount("007.25", 2), Some(725));
+    assert_eq!(parse_amount("18446744073709551615", 0), Some(u128::from(u64::MAX)));
+    for invalid in ["", ".5", "5.", "1.25", "-1", "+1", "1e3", " 1", "1 ", "18446744073709551616"] {
+      assert_eq!(parse_amount(invalid, 1), None, "{invalid}");
+    }
+    assert_eq!(format_amount(725, 2), "7.25");
+    assert_eq!(format_amount(700, 2), "7");
+    assert_eq!(format_amount(5, 18), "0.000000000000000005");
+  }
+
+  #[test]
+  fn operations() {
+    assert_eq!(
+      parse_operation(Some("application/json"), Some(r#"{"p":"brc-20","op":"deploy","tick":"Ordi","max":"21000000","lim":"1000","dec":"2"}"#)),
+      Some(Operation::Deploy { tick: "Ordi".into(), max_supply: 2_100_000_000, mint_limit: 100_000, decimals: 2 })
+    );
+    assert_eq!(
+      parse_operation(Some("text/plain"), Some(r#"{"p":"brc-20","op":"deploy","tick":"ordi","max":"10"}"#)),
+      Some(Operation::Deploy { tick: "ordi".into(), max_supply: 10 * 10u128.pow(18), mint_limit: 10 * 10u128.pow(18), decimals: 18 })
+    );
+    for invalid in [
+      r#"{"p":"brc-20","op":"deploy","tick":"ordi","max":"0"}"#,
+      r#"{"p":"brc-20","op":"deploy","tick":"ordi","max":"10","dec":"19"}"#,
+      r#"{"p":"brc-20","op":"deploy","tick":"ordi","max":10}"#,
+      r#"{"p":"brc-20","op":"mint","tick":"ordinals","amt":"1"}"#,
+      r#"{"p":"BRC-20","op":"mint","tick":"ordi","amt":"1"}"#,
+      r#"{"p":"brc-20","op":"burn","tick":"ordi","amt":"1"}"#,
+      "brc-20",
+    ] {
+      assert_eq!(parse_operation(Some("text/plain"), Some(invalid)), None, "{invalid}");
+    }
+    assert_eq!(parse_operation(Some("image/png"), Some(r#"{"p":"brc-20","op":"mint","tick":"ordi","amt":"1"}"#)), None);
+  }
+
+  #[test]
+  fn ledger_rules() {
+    let mut ledger = Ledger::default();
+    let items = [
+      item("mint_before_deploy", "alice", Some(r#"{"p":"brc-20","op":"mint","tick":"ordi","amt":"5"}"#)),
+      item("deploy", "alice", Some(r#"{"p":"brc-20","op":"deploy","tick":"ordi","max":"12","lim":"5","dec":"0"}"#)),
+      item("deploy_again", "bob", Some(r#"{"p":"brc-20","op":"deploy","tick":"ORDI","max":"100","dec":"0"}"#)),
+      item("over_limit", "alice", Some(r#"{"p":"brc-20","op":"mint","tick":"ordi","amt":"6"}"#)),
+      item("mint_1", "alice", Some(r#"{"p":"brc-20","op":"mint","tick":"OrDi","amt":"5"}"#)),
+      item("mint_2", "bob", Some(r#"{"p":"brc-20","op":"mint","tick":"ordi","amt":"5"}"#)),
+      // Only 2 left to mint
+      item("mint_3", "bob", Some(r#"{"p":"brc-20","op":"mint","tick":"ordi","amt":"5"}"#)),
+      item("sold_out", "bob", Some(r#"{"p":"brc-20","op":"mint","tick":"ordi","amt":"1"}"#)),
+      item("too_much", "alice", Some(r#"{"p":"brc-20","op":"transfer","tick":"ordi","amt":"6"}"#)),
+      item("transfer", "alice", Some(r#"{"p":"brc-20","op":"transfer","tick":"ordi","amt":"3"}"#)),
+      item("fee_transfer", "bob", Some(r#"{"p":"brc-20","op":"transfer","tick":"ordi","amt":"7"}"#)),
+      item("transfer", "carol", None),
+      // Only the first send moves the balance
+      item("transfer", "dave", None),
+      item("fee_transfer", "unbound", None),
+    ];
+    for item in &items {
+      ledger.apply(item);
+    }
+    let ticker = &ledger.tickers["ordi"];
+    assert_eq!((ticker.minted, ticker.completed_block, ticker.deployer.as_str()), (12, Some(1), "alice"));
+    let balance = |address: &str| ledger.balances.get(&("ordi".to_string(), address.to_string())).copied().unwrap_or_default();
+    assert_eq!(balance("alice"), Balance { available: 2, transferable: 0 });
+    assert_eq!(balance("bob"), Balance { available: 7, transferable: 0 });
+    assert_eq!(balance("carol"), Balance { available: 3, transferable: 0 });
+    assert_eq!(balance("dave"), Balance::default());
+    let events: Vec<_> = ledger.events.iter().map(|event| (event.inscription_id.as_str(), event.event.as_str(), event.amount.as_deref(), event.to_address.as_str())).collect();
+    assert_eq!(events, vec![
+      ("deploy", "deploy", None, "alice"),
+      ("mint_1", "mint", Some("5"), "alice"),
+      ("mint_2", "mint", Some("5"), "bob"),
+      ("mint_3", "mint", Some("2"), "bob"),
+      ("transfer", "inscribe_transfer", Some("3"), "alice"),
+      ("fee_transfer", "inscribe_transfer", Some("7"), "bob"),
+      ("transfer", "transfer", Some("3"), "carol"),
+      ("fee_transfer", "transfer", Some("7"), "bob"),
+    ]);
+  }
+
+  #[tokio::test]
+  #[ignore]
+  async fn brc20_ledger_across_blocks_and_rollback() {
+    let pool = TestDatabase::new("vermilion_brc20_test").await;
+
+    let operation = |sequence_number: i64, height: i64, text: &str| {
+      let mut inscription = test_inscription(sequence_number, height, &format!("{sequence_number:064x}"), Vec::new(), None);
+      inscription.text = Some(text.to_string());
+      inscription
+    };
+    let deploy = operation(0, 1, r#"{"p":"brc-20","op":"deploy","tick":"Ordi","max":"1000","lim":"600","dec":"1"}"#);
+    let mint = operation(1, 1, r#"{"p":"brc-20","op":"mint","tick":"ordi","amt":"600"}"#);
+    let transfer = operation(2, 2, r#"{"p":"brc-20","op":"transfer","tick":"ordi","amt":"250.5"}"#);
+    let blocks = [
+      (1, vec![deploy.clone(), mint.clone()], vec![test_transfer(&deploy, 1, "", "bc1qalice", 0), test_transfer(&mint, 1, "", "bc1qalice", 0)]),
+      (2, vec![transfer.clone()], vec![test_transfer(&transfer, 2, "", "bc1qalice", 0)]),
+      (3, Vec::new(), vec![test_transfer(&transfer, 3, "bc1qalice", "bc1qbob", 0)]),
+    ];
+    for (height, inscriptions, transfers) in blocks {
+      index_test_block(&pool, &TestBlock { height, inscriptions, galleries: Vec::new(), transfers }).await;
+      let mut conn = pool.get().await.unwrap();
+      let tx = conn.transaction().await.unwrap();
+      undo_journal::start_block(&tx, u32::try_from(height).unwrap(), u32::try_from(height).unwrap()).await.unwrap();
+      index_block(&tx, u32::try_from(height).unwrap()).await.unwrap();
+      tx.commit().await.unwrap();
+    }
+
+    fn items<T>(page: Paginated<T>) -> Vec<T> {
+      match page {
+        Paginated::Items(items) | Paginated::Page { items, .. } => items,
+      }
+    }
+    let page = |list, page_size, cursor| {
+      Brc20PageParams { page_number: None, page_size, cursor }.parse(list).unwrap_or_else(|_| panic!("invalid page params"))
+    };
+    let balances = |holders: Vec<Brc20Balance>| holders.into_iter().map(|b| (b.address, b.available, b.transferable)).collect::<Vec<_>>();
+    assert_eq!(balances(items(get_holders(pool.clone(), "ORDI".into(), page(Brc20List::Holders, None, None)).await.unwrap())), vec![
+      ("bc1qalice".to_string(), "349.5".to_string(), "0".to_string()),
+      ("bc1qbob".to_string(), "250.5".to_string(), "0".to_string()),
+    ]);
+    let ticker = get_ticker(pool.clone(), "ordi".into()).await.unwrap().unwrap();
+    assert_eq!((ticker.display_tick.as_str(), ticker.minted.as_str(), ticker.holders), ("Ordi", "600", 2));
+    let activity = items(get_activity(pool.clone(), "ordi".into(), page(Brc20List::Activity, None, None)).await.unwrap());
+    assert_eq!(activity.len(), 4);
+
+    // Cursor pages follow the same order, including the balance with decimals and the events sharing a block
+    let Paginated::Page { items: first, next_cursor: Some(next_cursor) } =
+      get_holders(pool.clone(), "ordi".into(), page(Brc20List::Holders, Some(1), Some(String::new()))).await.unwrap()
+    else {
+      panic!("expected a page with a next cursor")
+    };
+    let Paginated::Page { items: second, next_cursor: None } =
+      get_holders(pool.clone(), "ordi".into(), page(Brc20List::Holders, Some(1), Some(next_cursor))).await.unwrap()
+    else {
+      panic!("expected the last page")
+    };
+    assert_eq!((first[0].address.as_str(), second[0].address.as_str()), ("bc1qalice", "bc1qbob"));
+    let mut cursor = Some(String::new());
+    let mut paged = Vec::new();
+    while let Some(after) = cursor.take() {
+      let Paginated::Page { items, next_cursor } = get_activity(pool.clone(), "ordi".into(), page(Brc20List::Activity, Some(1), Some(after))).await.unwrap() else {
+        panic!("expected a page")
+      };
+      paged.extend(items.into_iter().map(|event| (event.inscription_id, event.event)));
+      cursor = next_cursor;
+    }
+    assert_eq!(paged, activity.into_iter().map(|event| (event.inscription_id, event.event)).collect::<Vec<_>>());
+
+    // Journaled rollback of the send restores the locked balance
+    let mut conn = pool.get().await.unwrap();
+    let tx = conn.transaction().await.unwrap();
+    undo_journal::rollback(&tx, 2).await.unwrap();
+    tx.commit().await.unwrap();
+    assert_eq!(balances(get_address_balances(pool.clone(), "bc1qalice".into()).await.unwrap()), vec![
+      ("bc1qalice".to_string(), "349.5".to_string(), "250.5".to_string()),
+    ]);
+
+    // Rebuilding from events after an unjournaled rollback gives the same ledger
+    let tx = conn.transaction().await.unwrap();
+    tx.execute("DELETE FROM brc20_balances", &[]).await.unwrap();
+    rollback_brc20(&tx, 1).await.unwrap();
+    tx.commit().await.unwrap();
+    assert_eq!(balances(get_address_balances(pool.clone(), "bc1qalice".into()).await.unwrap()), vec![
+      ("bc1qalice".to_string(), "600".to_string(), "0".to_string()),
+    ]);
+    assert!(items(get_activity(pool.clone(), "ordi".into(), page(Brc20List::Activity, None, None)).await.unwrap()).iter().all(|event| event.block_number == 1));
+  }
+}
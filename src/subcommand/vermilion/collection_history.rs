@@ -0,0 +1,549 @@
+use super::*;
+
+// Market history of collections. Every block that transfers an inscription of a collection adds a row with the
+// block's sales, from the sales table, and the collection's holder count after the block. Holder counts are kept
+// up to date incrementally in collection_holdings and collection_holder_counts, and rebuilt for a collection when
+// its membership is re-imported. Each block also re-rolls the UTC days it touched into collection_history_days, so
+// day points page like block points.
+
+const DAY_MS: i64 = 86_400_000;
+
+#[derive(Clone, Serialize, JsonSchema)]
+pub struct CollectionHistory {
+  /// Block number for block intervals, or the start of the UTC day in milliseconds for day intervals
+  period_start: i64,
+  /// Last block in the period
+  block_number: i64,
+  block_timestamp: i64,
+  sales: i64,
+  /// Sum of sale prices, in sats
+  volume: i64,
+  /// First sale price in the period, in sats
+  open_price: Option<i64>,
+  high_price: Option<i64>,
+  low_price: Option<i64>,
+  /// Last sale price in the period, in sats
+  close_price: Option<i64>,
+  median_price: Option<i64>,
+  /// Addresses holding the collection at the end of the period
+  holders: i64,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct CollectionHistoryParams {
+  /// Length of each point
+  #[schemars(description = "Aggregate by block or by UTC day, day by default")]
+  interval: Option<HistoryInterval>,
+
+  /// Page number for pagination (0-based)
+  #[schemars(description = "Page number for pagination, starting from 0", example = "0", range(min = 0))]
+  page_number: Option<usize>,
+
+  /// Number of items per page (max 1000)
+  #[schemars(description = "Number of items per page, maximum 1000", example = "100", range(min = 1, max = 1000))]
+  page_size: Option<usize>,
+
+  /// Cursor for keyset pagination
+  #[schemars(description = "Opaque cursor for keyset pagination. Pass an empty cursor for the first page, then the next_cursor of each response. Takes precedence over page_number")]
+  #[serde(default, deserialize_with = "deserialize_cursor")]
+  cursor: Option<String>,
+}
+
+pub(crate) struct ParsedCollectionHistoryParams {
+  interval: HistoryInterval,
+  keyset: Keyset,
+  page: PageRequest,
+}
+
+impl CollectionHistoryParams {
+  // Newest first
+  pub(crate) fn parse(self, list: &str) -> Result<ParsedCollectionHistoryParams, ApiError> {
+    let interval = self.interval.unwrap_or(HistoryInterval::Day);
+    let keyset = Keyset::new(list, &interval, "h.period_start", true);
+    let page = PageRequest::new(self.page_number, std::cmp::min(self.page_size.unwrap_or(100), 1000), self.cursor.as_deref(), &keyset)?;
+    Ok(ParsedCollectionHistoryParams { interval, keyset, page })
+  }
+}
+
+pub(crate) enum HistoryOf {
+  Collection(String),
+  OnChainCollection(Vec<String>),
+}
+
+impl HistoryOf {
+  fn kind(&self) -> &'static str {
+    match self {
+      HistoryOf::Collection(_) => "collection",
+      HistoryOf::OnChainCollection(_) => "on_chain",
+    }
+  }
+
+  fn key(&self) -> String {
+    match self {
+      HistoryOf::Collection(symbol) => symbol.clone(),
+      HistoryOf::OnChainCollection(parents) => parents.join(","),
+    }
+  }
+}
+
+pub(crate) async fn initialize_collection_history_table(pool: deadpool) -> anyhow::Result<()> {
+  let mut conn = pool.get().await?;
+  // prices keeps the block's sale prices in transaction order, for day opens, closes and medians
+  conn.simple_query(
+    r"CREATE TABLE IF NOT EXISTS collection_history (
+      collection_kind varchar(10) not null,
+      collection_key text not null,
+      block_number bigint not null,
+      block_timestamp bigint not null,
+      sales bigint not null,
+      volume bigint not null,
+      open_price bigint,
+      high_price bigint,
+      low_price bigint,
+      close_price bigint,
+      median_price bigint,
+      prices bigint[] not null,
+      holders bigint not null,
+      CONSTRAINT collection_history_key PRIMARY KEY (collection_kind, collection_key, block_number)
+    )").await?;
+  conn.simple_query("CREATE INDEX IF NOT EXISTS index_collection_history_block ON collection_history (block_number)").await?;
+  conn.simple_query("CREATE INDEX IF NOT EXISTS index_collection_history_timestamp ON collection_history (collection_kind, collection_key, block_timestamp)").await?;
+  let days_existed: bool = conn.query_one("SELECT to_regclass('collection_history_days') IS NOT NULL", &[]).await?.get(0);
+  conn.simple_query(
+    r"CREATE TABLE IF NOT EXISTS collection_history_days (
+      collection_kind varchar(10) not null,
+      collection_key text not null,
+      period_start bigint not null,
+      block_number bigint not null,
+      block_timestamp bigint not null,
+      sales bigint not null,
+      volume bigint not null,
+      open_price bigint,
+      high_price bigint,
+      low_price bigint,
+      close_price bigint,
+      median_price bigint,
+      holders bigint not null,
+      CONSTRAINT collection_history_days_key PRIMARY KEY (collection_kind, collection_key, period_start)
+    )").await?;
+  if !days_existed {
+    let started = Instant::now();
+    let tx = conn.transaction().await?;
+    roll_up_collection_days(&tx, None).await?;
+    tx.commit().await?;
+    log::info!("Backfilled collection history days in {:?}", started.elapsed());
+  }
+  let existed: bool = conn.query_one("SELECT to_regclass('collection_holdings') IS NOT NULL", &[]).await?.get(0);
+  // Inscriptions of each collection held per address, and the number of addresses holding any
+  conn.simple_query(
+    r"CREATE TABLE IF NOT EXISTS collection_holdings (
+      collection_kind varchar(10) not null,
+      collection_key text not null,
+      address varchar(100) not null,
+      inscriptions bigint not null,
+      CONSTRAINT collection_holdings_key PRIMARY KEY (collection_kind, collection_key, address)
+    );
+    CREATE TABLE IF NOT EXISTS collection_holder_counts (
+      collection_kind varchar(10) not null,
+      collection_key text not null,
+      holders bigint not null,
+      CONSTRAINT collection_holder_counts_key PRIMARY KEY (collection_kind, collection_key)
+    )").await?;
+  if !existed {
+    let started = Instant::now();
+    let tx = conn.transaction().await?;
+    rebuild_collection_holders(&tx, None).await?;
+    tx.commit().await?;
+    log::info!("Backfilled collection holders in {:?}", started.elapsed());
+  }
+  Ok(())
+}
+
+// Recounts holders from addresses, for one off chain collection after its inscriptions are re-imported, or for
+// every collection. On chain collections never change membership, so they're only recounted with everything else.
+pub(crate) async fn rebuild_collection_holders(tx: &deadpool_postgres::Transaction<'_>, collection_symbol: Option<&str>) -> anyhow::Result<()> {
+  let Some(collection_symbol) = collection_symbol else {
+    tx.simple_query(&format!(
+      r"DELETE FROM collection_holdings;
+      DELETE FROM collection_holder_counts;
+      INSERT INTO collection_holdings (collection_kind, collection_key, address, inscriptions)
+      SELECT 'collection', c.collection_symbol, a.address, count(*)
+      FROM collections c JOIN addresses a ON a.id = c.id
+      WHERE {holder}
+      GROUP BY c.collection_symbol, a.address;
+      INSERT INTO collection_holdings (collection_kind, collection_key, address, inscriptions)
+      SELECT 'on_chain', array_to_string(o.parents, ','), a.address, count(*)
+      FROM ordinals o JOIN addresses a ON a.id = o.id
+      WHERE cardinality(o.parents) > 0 AND {holder}
+      GROUP BY array_to_string(o.parents, ','), a.address;
+      INSERT INTO collection_holder_counts (collection_kind, collection_key, holders)
+      SELECT collection_kind, collection_key, count(*) FROM collection_holdings GROUP BY collection_kind, collection_key",
+      holder = is_holder_address("a.address")
+    )).await?;
+    return Ok(());
+  };
+  tx.execute("DELETE FROM collection_holdings WHERE collection_kind = 'collection' AND collection_key = $1", &[&collection_symbol]).await?;
+  tx.execute(
+    &format!(
+      r"INSERT INTO collection_holdings (collection_kind, collection_key, address, inscriptions)
+      SELECT 'collection', c.collection_symbol, a.address, count(*)
+      FROM collections c JOIN addresses a ON a.id = c.id
+      WHERE c.collection_symbol = $1 AND {}
+      GROUP BY c.collection_symbol, a.address",
+      is_holder_address("a.address")
+    ),
+    &[&collection_symbol]
+  ).await?;
+  tx.execute(
+    r"INSERT INTO collection_holder_counts (collection_kind, collection_key, holders)
+    SELECT 'collection', $1, count(*) FROM collection_holdings WHERE collection_kind = 'collection' AND collection_key = $1
+    ON CONFLICT (collection_kind, collection_key) DO UPDATE SET holders = EXCLUDED.holders",
+    &[&collection_symbol]
+  ).await?;
+  Ok(())
+}
+
+// Adds the rows for collections touched by this block's transfers. Runs after transfers, sales and addresses are written.
+pub(crate) async fn update_collection_history(tx: &deadpool_postgres::Transaction<'_>, block_number: i64) -> anyhow::Result<()> {
+  // Collection inscriptions transferred in this block
+  tx.simple_query(&format!(
+    r"CREATE TEMP TABLE collection_block ON COMMIT DROP AS
+    SELECT DISTINCT 'collection'::varchar(10) AS collection_kind, c.collection_symbol::text AS collection_key, t.id, t.block_timestamp
+    FROM transfers t JOIN collections c ON c.id = t.id
+    WHERE t.block_number = {block_number}
+    UNION
+    SELECT DISTINCT 'on_chain', array_to_string(o.parents, ','), t.id, t.block_timestamp
+    FROM transfers t JOIN ordinals o ON o.id = t.id
+    WHERE t.block_number = {block_number} AND cardinality(o.parents) > 0"
+  )).await?;
+  // Each inscription moves from its holder before the block, its last earlier transfer, to its holder now. Pseudo
+  // addresses like burned aren't holders.
+  tx.simple_query(&format!(
+    r"CREATE TEMP TABLE collection_block_holdings ON COMMIT DROP AS
+    WITH owners AS (
+      SELECT
+        b.collection_kind,
+        b.collection_key,
+        (SELECT t.address FROM transfers t WHERE t.id = b.id AND t.block_number < {block_number}
+          ORDER BY t.block_number DESC, t.tx_offset DESC LIMIT 1) AS before,
+        a.address AS after
+      FROM collection_block b LEFT JOIN addresses a ON a.id = b.id
+    )
+    SELECT collection_kind, collection_key, address, sum(change)::bigint AS change
+    FROM (
+      SELECT collection_kind, collection_key, before AS address, -1 AS change FROM owners WHERE {before}
+      UNION ALL
+      SELECT collection_kind, collection_key, after, 1 FROM owners WHERE {after}
+    ) c
+    GROUP BY collection_kind, collection_key, address
+    HAVING sum(change) <> 0",
+    before = is_holder_address("before"),
+    after = is_holder_address("after")
+  )).await?;
+  tx.simple_query(
+    r"INSERT INTO collection_holder_counts AS n (collection_kind, collection_key, holders)
+    SELECT
+      c.collection_kind,
+      c.collection_key,
+      count(*) FILTER (WHERE coalesce(h.inscriptions, 0) <= 0 AND coalesce(h.inscriptions, 0) + c.change > 0)
+        - count(*) FILTER (WHERE coalesce(h.inscriptions, 0) > 0 AND coalesce(h.inscriptions, 0) + c.change <= 0)
+    FROM collection_block_holdings c
+    LEFT JOIN collection_holdings h USING (collection_kind, collection_key, address)
+    GROUP BY c.collection_kind, c.collection_key
+    ON CONFLICT (collection_kind, collection_key) DO UPDATE SET holders = n.holders + EXCLUDED.holders;
+    INSERT INTO collection_holdings AS h (collection_kind, collection_key, address, inscriptions)
+    SELECT collection_kind, collection_key, address, change FROM collection_block_holdings
+    ON CONFLICT (collection_kind, collection_key, address) DO UPDATE SET inscriptions = h.inscriptions + EXCLUDED.inscriptions;
+    DELETE FROM collection_holdings h USING collection_block_holdings c
+    WHERE h.collection_kind = c.collection_kind AND h.collection_key = c.collection_key AND h.address = c.address
+    AND h.inscriptions <= 0"
+  ).await?;
+  // Sale prices are each inscription's share of its sale
+  tx.execute(
+    r"WITH k AS (
+      SELECT collection_kind, collection_key, max(block_timestamp) AS block_timestamp
+      FROM collection_block
+      GROUP BY collection_kind, collection_key
+    ),
+    s AS (
+      SELECT
+        b.collection_kind,
+        b.collection_key,
+        count(*) AS sales,
+        sum(s.price)::bigint AS volume,
+        max(s.price) AS high_price,
+        min(s.price) AS low_price,
+        percentile_disc(0.5) WITHIN GROUP (ORDER BY s.price) AS median_price,
+        array_agg(s.price ORDER BY s.sale_id) AS prices
+      FROM collection_block b
+      JOIN sales s ON s.id = b.id AND s.block_number = $1
+      GROUP BY b.collection_kind, b.collection_key
+    )
+    INSERT INTO collection_history (collection_kind, collection_key, block_number, block_timestamp, sales, volume,
+      open_price, high_price, low_price, close_price, median_price, prices, holders)
+    SELECT k.collection_kind, k.collection_key, $1, k.block_timestamp, coalesce(s.sales, 0), coalesce(s.volume, 0),
+      s.prices[1], s.high_price, s.low_price, s.prices[cardinality(s.prices)], s.median_price, coalesce(s.prices, '{}'),
+      coalesce(n.holders, 0)
+    FROM k
+    LEFT JOIN s USING (collection_kind, collection_key)
+    LEFT JOIN collection_holder_counts n USING (collection_kind, collection_key)",
+    &[&block_number]
+  ).await?;
+  tx.simple_query("DROP TABLE collection_block; DROP TABLE collection_block_holdings").await?;
+  roll_up_collection_days(tx, Some(block_number)).await?;
+  Ok(())
+}
+
+// Recomputes the days holding a block's rows from every block row in those days, each day closing with the holders
+// of its last block. Without a block every day is rebuilt, for the backfill and for rollbacks without a journal.
+pub(crate) async fn roll_up_collection_days(tx: &deadpool_postgres::Transaction<'_>, block_number: Option<i64>) -> anyhow::Result<()> {
+  let days = match block_number {
+    Some(_) => format!("SELECT DISTINCT collection_kind, collection_key, block_timestamp - block_timestamp % {DAY_MS} AS period_start \
+      FROM collection_history WHERE block_number = $1"),
+    None => {
+      tx.simple_query("DELETE FROM collection_history_days").await?;
+      format!("SELECT DISTINCT collection_kind, collection_key, block_timestamp - block_timestamp % {DAY_MS} AS period_start \
+        FROM collection_history")
+    }
+  };
+  let query = format!(
+    r"WITH days AS ({days}),
+    h AS (
+      SELECT d.period_start, c.*
+      FROM days d JOIN collection_history c
+      ON c.collection_kind = d.collection_kind AND c.collection_key = d.collection_key
+      AND c.block_timestamp >= d.period_start AND c.block_timestamp < d.period_start + {DAY_MS}
+    ),
+    m AS (
+      SELECT collection_kind, collection_key, period_start, percentile_disc(0.5) WITHIN GROUP (ORDER BY p) AS median_price
+      FROM h, unnest(h.prices) p
+      GROUP BY collection_kind, collection_key, period_start
+    )
+    INSERT INTO collection_history_days (collection_kind, collection_key, period_start, block_number, block_timestamp,
+      sales, volume, open_price, high_price, low_price, close_price, median_price, holders)
+    SELECT
+      h.collection_kind,
+      h.collection_key,
+      h.period_start,
+      max(h.block_number),
+      (array_agg(h.block_timestamp ORDER BY h.block_number DESC))[1],
+      sum(h.sales)::bigint,
+      sum(h.volume)::bigint,
+      (array_agg(h.open_price ORDER BY h.block_number) FILTER (WHERE h.sales > 0))[1],
+      max(h.high_price),
+      min(h.low_price),
+      (array_agg(h.close_price ORDER BY h.block_number DESC) FILTER (WHERE h.sales > 0))[1],
+      m.median_price,
+      (array_agg(h.holders ORDER BY h.block_number DESC))[1]
+    FROM h LEFT JOIN m USING (collection_kind, collection_key, period_start)
+    GROUP BY h.collection_kind, h.collection_key, h.period_start, m.median_price
+    ON CONFLICT (collection_kind, collection_key, period_start) DO UPDATE SET
+      block_number = EXCLUDED.block_number,
+      block_timestamp = EXCLUDED.block_timestamp,
+      sales = EXCLUDED.sales,
+      volume = EXCLUDED.volume,
+      open_price = EXCLUDED.open_price,
+      high_price = EXCLUDED.high_price,
+      low_price = EXCLUDED.low_price,
+      close_price = EXCLUDED.close_price,
+      median_price = EXCLUDED.median_price,
+      holders = EXCLUDED.holders"
+  );
+  match block_number {
+    Some(block_number) => tx.execute(query.as_str(), &[&block_number]).await?,
+    None => tx.execute(query.as_str(), &[]).await?,
+  };
+  Ok(())
+}
+
+fn history_query(of: &HistoryOf, params: &ParsedCollectionHistoryParams, sql_params: &mut SqlParams) -> String {
+  let condition = format!(
+    "collection_kind = {} AND collection_key = {}",
+    sql_params.push(of.kind()),
+    sql_params.push(of.key())
+  );
+  let points = match params.interval {
+    HistoryInterval::Block => format!(
+      "SELECT block_number AS period_start, block_number, block_timestamp, sales, volume, open_price, high_price, \
+        low_price, close_price, median_price, holders FROM collection_history WHERE {condition}"
+    ),
+    HistoryInterval::Day => format!(
+      "SELECT period_start, block_number, block_timestamp, sales, volume, open_price, high_price, \
+        low_price, close_price, median_price, holders FROM collection_history_days WHERE {condition}"
+    ),
+  };
+  let mut query = format!("SELECT * FROM ({points}) h");
+  if let Some(after) = params.page.filter(&params.keyset, sql_params) {
+    query.push_str(&format!(" WHERE {after}"));
+  }
+  query.push_str(&params.keyset.order_by());
+  query.push_str(&params.page.limit());
+  query
+}
+
+pub(crate) async fn get_collection_history(pool: deadpool, of: HistoryOf, params: ParsedCollectionHistoryParams) -> anyhow::Result<Paginated<CollectionHistory>> {
+  let conn = pool.get().await?;
+  let mut sql_params = SqlParams::default();
+  let query = history_query(&of, &params, &mut sql_params);
+  let rows = conn.query(query.as_str(), &sql_params.values()).await?;
+  Ok(params.page.paginate(&params.keyset, rows, |row| CollectionHistory {
+    period_start: row.get("period_start"),
+    block_number: row.get("block_number"),
+    block_timestamp: row.get("block_timestamp"),
+    sales: row.get("sales"),
+    volume: row.get("volume"),
+    open_price: row.get("open_price"),
+    high_price: row.get("high_price"),
+    low_price: row.get("low_price"),
+    close_price: row.get("close_price"),
+    median_price: row.get("median_price"),
+    holders: row.get("holders"),
+  }))
+}
+
+#[cfg(test)]
+mod tests {
+  use {
+    super::*,
+    super::super::tests::{index_test_block, TestDatabase, test_inscription, test_transfer, TestBlock},
+  };
+
+  async fn history(pool: &deadpool, of: HistoryOf, interval: HistoryInterval) -> Vec<CollectionHistory> {
+    let params = CollectionHistoryParams { interval: Some(interval), page_number: None, page_size: None, cursor: None }
+      .parse("collection_history")
+      .unwrap_or_else(|_| panic!("invalid history params"));
+    match get_collection_history(pool.clone(), of, params).await.unwrap() {
+      Paginated::Items(items) | Paginated::Page { items, .. } => items,
+    }
+  }
+
+  fn points(history: &[CollectionHistory]) -> Vec<(i64, i64, i64, Option<i64>, Option<i64>, Option<i64>, Option<i64>, Option<i64>, i64)> {
+    history
+      .iter()
+      .map(|point| (
+        point.period_start,
+        point.sales,
+        point.volume,
+        point.open_price,
+        point.high_price,
+        point.low_price,
+        point.close_price,
+        point.median_price,
+        point.holders,
+      ))
+      .collect()
+  }
+
+  #[tokio::test]
+  #[ignore]
+  async fn collection_history_by_block_and_day() {
+    let pool = TestDatabase::new("vermilion_collection_history_test").await;
+
+    let parent = test_inscription(0, 1, &"a".repeat(64), Vec::new(), None);
+    let first = test_inscription(1, 1, &"b".repeat(64), vec![parent.id.clone()], None);
+    let second = test_inscription(2, 1, &"c".repeat(64), vec![parent.id.clone()], None);
+    pool.get().await.unwrap().execute(
+      "INSERT INTO collections (id, collection_symbol) VALUES ($1, 'pixels'), ($2, 'pixels')",
+      &[&first.id, &second.id],
+    ).await.unwrap();
+    index_test_block(&pool, &TestBlock {
+      height: 1,
+      inscriptions: vec![parent.clone(), first.clone(), second.clone()],
+      galleries: Vec::new(),
+      transfers: vec![
+        test_transfer(&parent, 1, "", "bc1qminter", 0),
+        test_transfer(&first, 1, "", "bc1qminter", 0),
+        test_transfer(&second, 1, "", "bc1qminter", 0),
+      ],
+    }).await;
+    index_test_block(&pool, &TestBlock {
+      height: 2,
+      inscriptions: Vec::new(),
+      galleries: Vec::new(),
+      transfers: vec![test_transfer(&first, 2, "bc1qminter", "bc1qone", 1_000)],
+    }).await;
+    index_test_block(&pool, &TestBlock {
+      height: 3,
+      inscriptions: Vec::new(),
+      galleries: Vec::new(),
+      transfers: vec![test_transfer(&second, 3, "bc1qminter", "bc1qtwo", 3_000)],
+    }).await;
+    // bc1qone gives its inscription to bc1qtwo, leaving one holder
+    index_test_block(&pool, &TestBlock {
+      height: 4,
+      inscriptions: Vec::new(),
+      galleries: Vec::new(),
+      transfers: vec![test_transfer(&first, 4, "bc1qone", "bc1qtwo", 0)],
+    }).await;
+
+    // A day later bc1qtwo sells one back to bc1qone
+    index_test_block(&pool, &TestBlock {
+      height: 150,
+      inscriptions: Vec::new(),
+      galleries: Vec::new(),
+      transfers: vec![test_transfer(&second, 150, "bc1qtwo", "bc1qone", 5_000)],
+    }).await;
+
+    let collection = || HistoryOf::Collection("pixels".into());
+    assert_eq!(points(&history(&pool, collection(), HistoryInterval::Block).await), vec![
+      (150, 1, 5_000, Some(5_000), Some(5_000), Some(5_000), Some(5_000), Some(5_000), 2),
+      (4, 0, 0, None, None, None, None, None, 1),
+      (3, 1, 3_000, Some(3_000), Some(3_000), Some(3_000), Some(3_000), Some(3_000), 2),
+      (2, 1, 1_000, Some(1_000), Some(1_000), Some(1_000), Some(1_000), Some(1_000), 2),
+      (1, 0, 0, None, None, None, None, None, 1),
+    ]);
+    assert_eq!(points(&history(&pool, collection(), HistoryInterval::Day).await), vec![
+      (DAY_MS, 1, 5_000, Some(5_000), Some(5_000), Some(5_000), Some(5_000), Some(5_000), 2),
+      (0, 2, 4_000, Some(1_000), Some(3_000), Some(1_000), Some(3_000), Some(1_000), 1),
+    ]);
+    let on_chain = points(&history(&pool, HistoryOf::OnChainCollection(vec![parent.id.clone()]), HistoryInterval::Day).await);
+    assert_eq!(on_chain[1], (0, 2, 4_000, Some(1_000), Some(3_000), Some(1_000), Some(3_000), Some(1_000), 1));
+
+    // Days page by cursor
+    let page = |cursor: String| CollectionHistoryParams { interval: None, page_number: None, page_size: Some(1), cursor: Some(cursor) }
+      .parse("collection_history")
+      .unwrap_or_else(|_| panic!("invalid history params"));
+    let Paginated::Page { items, next_cursor: Some(next_cursor), .. } = get_collection_history(pool.clone(), collection(), page(String::new())).await.unwrap() else { panic!("expected a page") };
+    assert_eq!(items[0].period_start, DAY_MS);
+    let Paginated::Page { items, .. } = get_collection_history(pool.clone(), collection(), page(next_cursor)).await.unwrap() else { panic!("expected a page") };
+    assert_eq!(points(&items), vec![(0, 2, 4_000, Some(1_000), Some(3_000), Some(1_000), Some(3_000), Some(1_000), 1)]);
+
+    Vermilion::handle_reorg(pool.clone(), 2).await.unwrap();
+    assert_eq!(points(&history(&pool, collection(), HistoryInterval::Day).await), vec![
+      (0, 1, 1_000, Some(1_000), Some(1_000), Some(1_000), Some(1_000), Some(1_000), 2),
+    ]);
+
+    // The rolled back counts match a recount from addresses
+    let holders = || async {
+      pool.get().await.unwrap()
+        .query("SELECT to_jsonb(h)::text FROM collection_holdings h UNION ALL SELECT to_jsonb(n)::text FROM collection_holder_counts n ORDER BY 1", &[])
+        .await
+        .unwrap()
+        .iter()
+        .map(|row| row.get::<_, String>(0))
+        .collect::<Vec<_>>()
+    };
+    let incremental = holders().await;
+    let mut conn = pool.get().await.unwrap();
+    let tx = conn.transaction().await.unwrap();
+    rebuild_collection_holders(&tx, None).await.unwrap();
+    tx.commit().await.unwrap();
+    assert_eq!(incremental, holders().await);
+    assert!(incremental.iter().any(|row| row.contains(r#""holders": 2"#) && row.contains("pixels")));
+
+    // Burning an inscription doesn't make burned a holder, incrementally or on a recount
+    index_test_block(&pool, &TestBlock {
+      height: 3,
+      inscriptions: Vec::new(),
+      galleries: Vec::new(),
+      transfers: vec![test_transfer(&second, 3, "bc1qminter", "burned", 0)],
+    }).await;
+    assert_eq!(history(&pool, collection(), HistoryInterval::Block).await[0].holders, 1);
+    let incremental = holders().await;
+    let tx = conn.transaction().await.unwrap();
+    rebuild_collection_holders(&tx, None).await.unwrap();
+    tx.commit().await.unwrap();
+    assert_eq!(incremental, holders().await);
+    assert!(incremental.iter().all(|row| !row.contains("burned")));
+  }
+}
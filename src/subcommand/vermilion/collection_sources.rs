@@ -0,0 +1,445 @@
+use super::*;
+use std::path::Path;
+
+// Where the collection indexer gets off-chain collections from. Every configured source writes into the same
+// collections and collection_list tables.
+pub(crate) enum CollectionSource {
+  // Needs magiceden_api_key
+  MagicEden(MagicEdenClient),
+  // Manifests in a local directory, re-imported whenever a file in it changes. Collections the import left with
+  // listed inscriptions missing are retried on their own as the block indexer moves on.
+  Manifests { dir: PathBuf, last_modified: Option<SystemTime>, retry: Option<MissingRetry> },
+}
+
+// Missing inscriptions are usually only a few blocks away, so collections are retried after 1, 2, 4... blocks and
+// then left alone until the manifests change or the daily forced update
+const MISSING_RETRY_LIMIT: u32 = 10;
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct MissingRetry {
+  symbols: Vec<String>,
+  attempts: u32,
+  next_height: i64,
+}
+
+impl MissingRetry {
+  // What to retry after an import run at height, the attempts-th retry since the manifests last changed
+  fn next(import: &ImportOutput, height: i64, attempts: u32) -> Option<Self> {
+    if import.missing.is_empty() {
+      return None;
+    }
+    if attempts >= MISSING_RETRY_LIMIT {
+      log::warn!("Giving up on {} inscriptions missing from collections {} after {attempts} retries",
+        import.missing.values().map(Vec::len).sum::<usize>(), import.missing.keys().join(", "));
+      return None;
+    }
+    Some(Self { symbols: import.missing.keys().cloned().collect(), attempts: attempts + 1, next_height: height + (1 << attempts) })
+  }
+}
+
+impl CollectionSource {
+  pub(crate) fn configured(settings: &Settings, manifest_dir: Option<PathBuf>) -> anyhow::Result<Vec<Self>> {
+    let mut sources = Vec::new();
+    if settings.magiceden_api_key().is_some() {
+      sources.push(CollectionSource::MagicEden(MagicEdenClient::from_settings(settings)?));
+    }
+    if let Some(dir) = manifest_dir {
+      sources.push(CollectionSource::Manifests { dir, last_modified: None, retry: None });
+    }
+    Ok(sources)
+  }
+
+  pub(crate) fn name(&self) -> &'static str {
+    match self {
+      CollectionSource::MagicEden(_) => "magic eden",
+      CollectionSource::Manifests { .. } => "local manifests",
+    }
+  }
+
+  // Returns whether anything was updated
+  pub(crate) async fn update(&mut self, pool: deadpool, force_update: bool) -> Result<bool, Box<dyn std::error::Error>> {
+    match self {
+      CollectionSource::MagicEden(client) => Vermilion::update_all_tokens(pool, client, force_update).await,
+      CollectionSource::Manifests { dir, last_modified, retry } => {
+        let modified = Some(latest_modification(dir)?);
+        let height = Vermilion::get_postgres_height(&pool).await?;
+        let (manifests, complete, attempts) = if force_update || modified > *last_modified {
+          (read_manifests(dir)?, true, 0)
+        } else if let Some(pending) = retry.take_if(|retry| height >= retry.next_height) {
+          let manifests = read_manifests(dir)?.into_iter().filter(|manifest| pending.symbols.contains(&manifest.symbol)).collect();
+          (manifests, false, pending.attempts)
+        } else {
+          return Ok(false);
+        };
+        let import = import_collections(pool, dir, manifests, complete).await?;
+        log::info!("Imported {} collections with {} inscriptions from {}, removed {}, {} inscriptions not indexed yet",
+          import.collections, import.inscriptions, dir.display(), import.removed.len(), import.missing.values().map(Vec::len).sum::<usize>());
+        *last_modified = modified;
+        *retry = MissingRetry::next(&import, height, attempts);
+        Ok(true)
+      }
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub(crate) struct CollectionManifest {
+  #[serde(alias = "collection_symbol")]
+  symbol: String,
+  name: Option<String>,
+  description: Option<String>,
+  /// Image url, or the id of an inscription to use as the collection icon
+  image: Option<String>,
+  twitter: Option<String>,
+  discord: Option<String>,
+  website: Option<String>,
+  #[serde(alias = "inscriptions")]
+  inscription_ids: Vec<String>,
+}
+
+// One collection per row of a CSV manifest, so rows of the same symbol are merged
+#[derive(Deserialize)]
+struct ManifestRow {
+  symbol: String,
+  name: Option<String>,
+  description: Option<String>,
+  image: Option<String>,
+  inscription_id: String,
+}
+
+// A JSON manifest holds one collection or an array of them
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonManifest {
+  One(CollectionManifest),
+  Many(Vec<CollectionManifest>),
+}
+
+fn parse_csv(contents: &str) -> anyhow::Result<Vec<CollectionManifest>> {
+  let mut manifests: Vec<CollectionManifest> = Vec::new();
+  for row in csv::Reader::from_reader(contents.as_bytes()).deserialize() {
+    let row: ManifestRow = row?;
+    let non_empty = |value: Option<String>| value.filter(|value| !value.is_empty());
+    match manifests.iter_mut().find(|manifest| manifest.symbol == row.symbol) {
+      Some(manifest) => {
+        manifest.name = manifest.name.take().or(non_empty(row.name));
+        manifest.description = manifest.description.take().or(non_empty(row.description));
+        manifest.image = manifest.image.take().or(non_empty(row.image));
+        manifest.inscription_ids.push(row.inscription_id);
+      }
+      None => manifests.push(CollectionManifest {
+        symbol: row.symbol,
+        name: non_empty(row.name),
+        description: non_empty(row.description),
+        image: non_empty(row.image),
+        twitter: None,
+        discord: None,
+        website: None,
+        inscription_ids: vec![row.inscription_id],
+      }),
+    }
+  }
+  Ok(manifests)
+}
+
+pub(crate) fn parse_manifest(path: &Path, contents: &str) -> anyhow::Result<Vec<CollectionManifest>> {
+  let manifests = match path.extension().and_then(|extension| extension.to_str()) {
+    Some("json") => match serde_json::from_str(contents)? {
+      JsonManifest::One(manifest) => vec![manifest],
+      JsonManifest::Many(manifests) => manifests,
+    },
+    Some("csv") => parse_csv(contents)?,
+    _ => bail!("{} is not a .json or .csv manifest", path.display()),
+  };
+  for manifest in &manifests {
+    // collection_list.collection_symbol is a varchar(50)
+    ensure!(
+      !manifest.symbol.is_empty() && manifest.symbol.len() <= 50,
+      "{}: collection symbols must be 1 to 50 characters, got {:?}", path.display(), manifest.symbol
+    );
+  }
+  Ok(manifests)
+}
+
+fn manifest_files(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+  let mut files = Vec::new();
+  for entry in fs::read_dir(dir).with_context(|| format!("Failed to read manifest directory {}", dir.display()))? {
+    let path = entry?.path();
+    if path.is_file() && matches!(path.extension().and_then(|extension| extension.to_str()), Some("json" | "csv")) {
+      files.push(path);
+    }
+  }
+  files.sort();
+  Ok(files)
+}
+
+fn latest_modification(dir: &Path) -> anyhow::Result<SystemTime> {
+  let mut latest = fs::metadata(dir)?.modified()?;
+  for path in manifest_files(dir)? {
+    latest = latest.max(fs::metadata(path)?.modified()?);
+  }
+  Ok(latest)
+}
+
+// Reads a manifest file, or every .json and .csv manifest in a directory
+pub(crate) fn read_manifests(path: &Path) -> anyhow::Result<Vec<CollectionManifest>> {
+  let files = if path.is_dir() { manifest_files(path)? } else { vec![path.to_path_buf()] };
+  let mut manifests = Vec::new();
+  for file in files {
+    let contents = fs::read_to_string(&file).with_context(|| format!("Failed to read manifest {}", file.display()))?;
+    manifests.extend(parse_manifest(&file, &contents)?);
+  }
+  Ok(manifests)
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ImportOutput {
+  pub collections: usize,
+  pub inscriptions: usize,
+  /// Listed inscriptions the indexer hasn't reached yet, by collection symbol. They are picked up by the next import
+  pub missing: BTreeMap<String, Vec<String>>,
+  /// Collections imported from the same path before that its manifests no longer list
+  pub removed: Vec<String>,
+}
+
+// Collections imported from manifests, by the file or directory they came from
+pub(crate) async fn initialize_manifest_collections_table(pool: deadpool) -> anyhow::Result<()> {
+  let conn = pool.get().await?;
+  conn.simple_query(
+    r"CREATE TABLE IF NOT EXISTS manifest_collections (
+      collection_symbol varchar(50) not null primary key,
+      source text not null
+    )").await?;
+  Ok(())
+}
+
+// Replaces the listed collections, like the magic eden indexer does for each updated symbol. When the manifests are
+// everything at source, collections imported from it before that they no longer list are removed.
+pub(crate) async fn import_collections(pool: deadpool, source: &Path, manifests: Vec<CollectionManifest>, complete: bool) -> anyhow::Result<ImportOutput> {
+  let source = fs::canonicalize(source)?.to_string_lossy().into_owned();
+  // A symbol listed by more than one manifest takes the last one
+  let mut by_symbol: Vec<CollectionManifest> = Vec::new();
+  for manifest in manifests {
+    by_symbol.retain(|existing| existing.symbol != manifest.symbol);
+    by_symbol.push(manifest);
+  }
+  let mut output = ImportOutput::default();
+  let mut collection_list = Vec::new();
+  let mut collections = Vec::new();
+  let mut conn = pool.get().await?;
+  let tx = conn.transaction().await?;
+  for manifest in by_symbol {
+    let ids: Vec<String> = manifest.inscription_ids.iter().cloned().unique().collect();
+    let numbers: HashMap<String, i64> = tx
+      .query("SELECT id, number FROM ordinals WHERE id = ANY($1)", &[&ids])
+      .await?
+      .into_iter()
+      .map(|row| (row.get("id"), row.get("number")))
+      .collect();
+    let date_created = tx
+      .query_opt("SELECT date_created FROM collection_list WHERE collection_symbol=$1", &[&manifest.symbol])
+      .await?
+      .and_then(|row| row.get("date_created"))
+      .unwrap_or_else(|| Utc::now().timestamp_millis());
+    for id in &ids {
+      match numbers.get(id) {
+        Some(number) => collections.push(Collection {
+          id: id.clone(),
+          number: *number,
+          collection_symbol: manifest.symbol.clone(),
+          off_chain_metadata: serde_json::Value::Null,
+        }),
+        None => output.missing.entry(manifest.symbol.clone()).or_default().push(id.clone()),
+      }
+    }
+    Vermilion::remove_collection_symbol(&tx, manifest.symbol.clone()).await?;
+    collection_list.push(CollectionMetadata {
+      collection_symbol: manifest.symbol,
+      name: manifest.name,
+      inscription_icon: manifest.image.clone().filter(|image| image.parse::<InscriptionId>().is_ok()),
+      image_uri: manifest.image,
+      description: manifest.description,
+      supply: Some(ids.len().try_into()?),
+      twitter: manifest.twitter,
+      discord: manifest.discord,
+      website: manifest.website,
+      min_inscription_number: numbers.values().min().copied(),
+      max_inscription_number: numbers.values().max().copied(),
+      date_created,
+    });
+  }
+  output.collections = collection_list.len();
+  output.inscriptions = collections.len();
+  let symbols: Vec<String> = collection_list.iter().map(|metadata| metadata.collection_symbol.clone()).collect();
+  if complete {
+    output.removed = tx
+      .query(
+        "DELETE FROM manifest_collections WHERE source = $1 AND collection_symbol <> ALL($2) RETURNING collection_symbol",
+        &[&source, &symbols]
+      )
+      .await?
+      .into_iter()
+      .map(|row| row.get("collection_symbol"))
+      .collect();
+    for symbol in &output.removed {
+      remove_collection(&tx, symbol).await?;
+    }
+  }
+  tx.execute(
+    "INSERT INTO manifest_collections (collection_symbol, source) SELECT unnest($1::text[]), $2 \
+    ON CONFLICT (collection_symbol) DO UPDATE SET source = EXCLUDED.source",
+    &[&symbols, &source]
+  ).await?;
+  Vermilion::insert_collection_list(&tx, collection_list).await?;
+  Vermilion::insert_collections(&tx, collections).await?;
+  for symbol in &symbols {
+    collection_history::rebuild_collection_holders(&tx, Some(symbol)).await?;
+  }
+  tx.commit().await?;
+  Vermilion::update_collection_summary(pool).await?;
+  Ok(output)
+}
+
+async fn remove_collection(tx: &deadpool_postgres::Transaction<'_>, collection_symbol: &str) -> anyhow::Result<()> {
+  tx.execute("DELETE FROM collections WHERE collection_symbol = $1", &[&collection_symbol]).await?;
+  tx.execute("DELETE FROM collection_list WHERE collection_symbol = $1", &[&collection_symbol]).await?;
+  tx.execute("DELETE FROM collection_summary WHERE collection_symbol = $1", &[&collection_symbol]).await?;
+  tx.execute("DELETE FROM collection_holdings WHERE collection_kind = 'collection' AND collection_key = $1", &[&collection_symbol]).await?;
+  tx.execute("DELETE FROM collection_holder_counts WHERE collection_kind = 'collection' AND collection_key = $1", &[&collection_symbol]).await?;
+  Ok(())
+}
+
+#[derive(Debug, Parser, Clone)]
+pub(crate) struct ImportCollections {
+  #[arg(help = "Import collections from the manifest at <PATH>, or from every .json and .csv manifest in the directory at <PATH>. \
+  Collections imported from <PATH> before that its manifests no longer list are removed.")]
+  path: PathBuf,
+}
+
+impl ImportCollections {
+  pub(crate) fn run(&self, settings: Settings) -> SubcommandResult {
+    let manifests = read_manifests(&self.path)?;
+    let rt = tokio::runtime::Builder::new_current_thread()
+      .enable_all()
+      .build()?;
+    rt.block_on(async {
+      let pool = Vermilion::get_deadpool(settings).await?;
+      Vermilion::initialize_collection_tables(pool.clone()).await?;
+      let output = import_collections(pool, &self.path, manifests, true).await?;
+      Ok(Some(Box::new(output) as Box<dyn subcommand::Output>))
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use {
+    super::*,
+    super::super::tests::{index_test_block, TestDatabase, test_inscription, TestBlock},
+  };
+
+  #[test]
+  fn json_manifests() {
+    let one = r#"{"symbol": "pixels", "name": "Pixels", "image": "https://example.com/pixels.png", "inscription_ids": ["a", "b"]}"#;
+    let manifests = parse_manifest(Path::new("pixels.json"), one).unwrap();
+    assert_eq!(manifests.len(), 1);
+    assert_eq!(manifests[0].inscription_ids, vec!["a", "b"]);
+    assert_eq!(manifests[0].description, None);
+
+    let many = r#"[{"collection_symbol": "one", "inscriptions": []}, {"symbol": "two", "inscription_ids": ["c"]}]"#;
+    let symbols: Vec<String> = parse_manifest(Path::new("all.json"), many).unwrap().into_iter().map(|manifest| manifest.symbol).collect();
+    assert_eq!(symbols, vec!["one", "two"]);
+
+    assert!(parse_manifest(Path::new("long.json"), &format!(r#"{{"symbol": "{}", "inscription_ids": []}}"#, "x".repeat(51))).is_err());
+    assert!(parse_manifest(Path::new("pixels.txt"), one).is_err());
+  }
+
+  #[test]
+  fn collections_with_missing_inscriptions_are_retried_with_backoff() {
+    let mut import = ImportOutput::default();
+    assert_eq!(MissingRetry::next(&import, 100, 0), None);
+
+    import.missing.insert("pixels".into(), vec!["a".into()]);
+    let retry = |height, attempts| MissingRetry::next(&import, height, attempts).map(|retry| (retry.symbols, retry.attempts, retry.next_height));
+    assert_eq!(retry(100, 0), Some((vec!["pixels".into()], 1, 101)));
+    assert_eq!(retry(101, 1), Some((vec!["pixels".into()], 2, 103)));
+    assert_eq!(retry(103, 2), Some((vec!["pixels".into()], 3, 107)));
+    assert_eq!(retry(1_000, MISSING_RETRY_LIMIT - 1), Some((vec!["pixels".into()], MISSING_RETRY_LIMIT, 1_512)));
+    assert_eq!(retry(1_512, MISSING_RETRY_LIMIT), None);
+  }
+
+  #[test]
+  fn csv_rows_are_grouped_by_symbol() {
+    let csv = "symbol,name,description,image,inscription_id\n\
+      pixels,Pixels,,,a\n\
+      blocks,,,,c\n\
+      pixels,,Tiny art,https://example.com/pixels.png,b\n";
+    assert_eq!(parse_manifest(Path::new("collections.csv"), csv).unwrap(), vec![
+      CollectionManifest {
+        symbol: "pixels".into(),
+        name: Some("Pixels".into()),
+        description: Some("Tiny art".into()),
+        image: Some("https://example.com/pixels.png".into()),
+        twitter: None,
+        discord: None,
+        website: None,
+        inscription_ids: vec!["a".into(), "b".into()],
+      },
+      CollectionManifest {
+        symbol: "blocks".into(),
+        name: None,
+        description: None,
+        image: None,
+        twitter: None,
+        discord: None,
+        website: None,
+        inscription_ids: vec!["c".into()],
+      },
+    ]);
+  }
+
+  #[tokio::test]
+  #[ignore]
+  async fn manifest_collections_dropped_from_the_manifests_are_removed() {
+    let pool = TestDatabase::new("vermilion_manifest_collections_test").await;
+    Vermilion::initialize_collection_tables(pool.clone()).await.unwrap();
+
+    let first = test_inscription(0, 1, &"a".repeat(64), Vec::new(), None);
+    let second = test_inscription(1, 1, &"b".repeat(64), Vec::new(), None);
+    index_test_block(&pool, &TestBlock { height: 1, inscriptions: vec![first.clone(), second.clone()], galleries: Vec::new(), transfers: Vec::new() }).await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let manifest = |symbol: &str, ids: &[&str]| CollectionManifest {
+      symbol: symbol.into(),
+      name: None,
+      description: None,
+      image: None,
+      twitter: None,
+      discord: None,
+      website: None,
+      inscription_ids: ids.iter().map(|id| id.to_string()).collect(),
+    };
+    let symbols = || async {
+      pool.get().await.unwrap()
+        .query("SELECT l.collection_symbol, count(c.id) FROM collection_list l LEFT JOIN collections c USING (collection_symbol) GROUP BY 1 ORDER BY 1", &[])
+        .await
+        .unwrap()
+        .iter()
+        .map(|row| (row.get::<_, String>(0), row.get::<_, i64>(1)))
+        .collect::<Vec<_>>()
+    };
+
+    let import = import_collections(pool.clone(), dir.path(), vec![manifest("pixels", &[&first.id]), manifest("blocks", &[&second.id, "missingi0"])], true).await.unwrap();
+    assert_eq!(import.missing, BTreeMap::from([("blocks".to_string(), vec!["missingi0".to_string()])]));
+    assert_eq!(symbols().await, vec![("blocks".into(), 1), ("pixels".into(), 1)]);
+
+    // Retrying one collection leaves the others in place
+    let import = import_collections(pool.clone(), dir.path(), vec![manifest("blocks", &[&second.id])], false).await.unwrap();
+    assert!(import.removed.is_empty() && import.missing.is_empty());
+    assert_eq!(symbols().await, vec![("blocks".into(), 1), ("pixels".into(), 1)]);
+
+    let import = import_collections(pool.clone(), dir.path(), vec![manifest("blocks", &[&second.id])], true).await.unwrap();
+    assert_eq!(import.removed, vec!["pixels".to_string()]);
+    assert_eq!(symbols().await, vec![("blocks".into(), 1)]);
+  }
+}
@@ -1,12 +1,99 @@
 use super::*;
+use super::postgres_tls::{PostgresTls, TlsMode, TlsStream};
 
-pub async fn _get_deadpool(settings: Settings) -> anyhow::Result<deadpool> {
+// Connection settings shared by every pool and the LISTEN connection. db_connection_string is the
+// starting point and the individual db_* settings override it. The connection string is unpacked
+// into fields rather than passed as a url, since deadpool appends hosts and ports to the url's own.
+fn deadpool_config(settings: &Settings) -> anyhow::Result<(deadpool_postgres::Config, PostgresTls)> {
+  let connection_string = settings.db_connection_string()
+    .map(|s| s.parse::<tokio_postgres::Config>())
+    .transpose()
+    .context("invalid db_connection_string")?
+    .unwrap_or_default();
   let mut deadpool_cfg = deadpool_postgres::Config::new();
-  deadpool_cfg.host = settings.db_host().map(|s| s.to_string());
-  deadpool_cfg.dbname = settings.db_name().map(|s| s.to_string());
-  deadpool_cfg.user = settings.db_user().map(|s| s.to_string());
-  deadpool_cfg.password = settings.db_password().map(|s| s.to_string());
+  deadpool_cfg.hosts = match settings.db_host() {
+    Some(host) => Some(vec![host.to_string()]),
+    None => Some(
+      connection_string.get_hosts().iter().map(|host| match host {
+        tokio_postgres::config::Host::Tcp(host) => host.clone(),
+        tokio_postgres::config::Host::Unix(path) => path.display().to_string(),
+      }).collect()
+    ),
+  };
+  deadpool_cfg.port = settings.db_port().or(connection_string.get_ports().first().copied());
+  deadpool_cfg.dbname = settings.db_name().or(connection_string.get_dbname()).map(|s| s.to_string());
+  deadpool_cfg.user = settings.db_user().or(connection_string.get_user()).map(|s| s.to_string());
+  deadpool_cfg.password = match settings.db_password() {
+    Some(password) => Some(password.to_string()),
+    None => connection_string.get_password().map(|p| String::from_utf8_lossy(p).into_owned()),
+  };
+  deadpool_cfg.application_name = connection_string.get_application_name().map(|s| s.to_string());
+  deadpool_cfg.connect_timeout = connection_string.get_connect_timeout().copied();
+  // Without an explicit mode, connections stay unencrypted as they always have been unless the connection string asks otherwise
+  let tls_mode = match settings.db_sslmode() {
+    Some(mode) => mode.parse::<TlsMode>().context("invalid db_sslmode")?,
+    None if settings.db_connection_string().is_some() => TlsMode::from(connection_string.get_ssl_mode()),
+    None => TlsMode::Disable,
+  };
+  deadpool_cfg.ssl_mode = Some(tls_mode.ssl_mode());
+  let options = connection_string.get_options().map(|s| s.to_string());
+  deadpool_cfg.options = match settings.db_statement_timeout_ms() {
+    Some(timeout) => Some(
+      options
+        .into_iter()
+        .chain([format!("-c statement_timeout={timeout}")])
+        .join(" ")
+    ),
+    None => options,
+  };
   deadpool_cfg.manager = Some(ManagerConfig { recycling_method: RecyclingMethod::Fast });
-  let deadpool = deadpool_cfg.create_pool(Some(deadpool_postgres::Runtime::Tokio1), NoTls)?;
+  deadpool_cfg.pool = settings.db_pool_max_size().map(deadpool_postgres::PoolConfig::new);
+  let tls = PostgresTls::new(tls_mode, settings.db_ssl_root_cert())?;
+  Ok((deadpool_cfg, tls))
+}
+
+pub(crate) fn create_pool(settings: &Settings) -> anyhow::Result<deadpool> {
+  let (deadpool_cfg, tls) = deadpool_config(settings)?;
+  let deadpool = deadpool_cfg.create_pool(Some(deadpool_postgres::Runtime::Tokio1), tls)?;
   Ok(deadpool)
-}
\ No newline at end of file
+}
+
+// Dedicated connection outside the pool, for LISTEN/NOTIFY
+pub(crate) async fn connect(settings: &Settings) -> anyhow::Result<(tokio_postgres::Client, tokio_postgres::Connection<tokio_postgres::Socket, TlsStream<tokio_postgres::Socket>>)> {
+  let (deadpool_cfg, tls) = deadpool_config(settings)?;
+  let (client, connection) = deadpool_cfg.get_pg_config()?.connect(tls).await?;
+  Ok((client, connection))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn settings(yaml: &str) -> Settings {
+    serde_yaml::from_str::<Settings>(yaml).unwrap()
+  }
+
+  #[test]
+  fn settings_override_connection_string() {
+    let (config, _) = deadpool_config(&settings(
+      "db_connection_string: postgresql://alice@db.example.com:6543/ordinals?sslmode=require\ndb_port: 5433\ndb_statement_timeout_ms: 30000\ndb_pool_max_size: 7",
+    )).unwrap();
+    let pg_config = config.get_pg_config().unwrap();
+    assert_eq!(pg_config.get_hosts(), &[tokio_postgres::config::Host::Tcp("db.example.com".into())]);
+    assert_eq!(pg_config.get_ports(), &[5433]);
+    assert_eq!(pg_config.get_user(), Some("alice"));
+    assert_eq!(pg_config.get_dbname(), Some("ordinals"));
+    assert_eq!(pg_config.get_ssl_mode(), tokio_postgres::config::SslMode::Require);
+    assert_eq!(pg_config.get_options(), Some("-c statement_timeout=30000"));
+    assert_eq!(config.pool.unwrap().max_size, 7);
+  }
+
+  #[test]
+  fn tls_is_disabled_by_default() {
+    let (config, _) = deadpool_config(&settings("db_host: localhost\ndb_name: ordinals")).unwrap();
+    assert_eq!(config.get_pg_config().unwrap().get_ssl_mode(), tokio_postgres::config::SslMode::Disable);
+    let (config, _) = deadpool_config(&settings("db_host: localhost\ndb_name: ordinals\ndb_sslmode: verify-full")).unwrap();
+    assert_eq!(config.get_pg_config().unwrap().get_ssl_mode(), tokio_postgres::config::SslMode::Require);
+    assert!(deadpool_config(&settings("db_sslmode: allow")).is_err());
+  }
+}
@@ -0,0 +1,468 @@
+use super::*;
+use aide::NoApi;
+use axum::{
+  extract::ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade},
+  response::sse::{Event as SseEvent, KeepAlive, Sse},
+};
+use crate::subcommand::vermilion::api::EventStream;
+use std::convert::Infallible;
+use tokio::sync::{broadcast, mpsc};
+
+pub(crate) const EVENTS_CHANNEL: &str = "vermilion_events";
+
+// Enough headroom for the transfers of a busy block. Subscribers that fall further behind are disconnected.
+const EVENT_BUFFER: usize = 65_536;
+
+// Sent by the indexer from inside its transactions, so API servers only hear about committed blocks.
+// HiddenInscriptionsChanged comes from a trigger on hidden_inscriptions.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Notification {
+  BlockCommitted { block_number: i64, block_hash: String },
+  Reorg { last_good_block: i64 },
+  HiddenInscriptionsChanged,
+}
+
+async fn notify(tx: &deadpool_postgres::Transaction<'_>, notification: &Notification) -> anyhow::Result<()> {
+  let payload = serde_json::to_string(notification)?;
+  tx.execute("SELECT pg_notify($1, $2)", &[&EVENTS_CHANNEL, &payload]).await?;
+  Ok(())
+}
+
+pub(crate) async fn notify_block_committed(tx: &deadpool_postgres::Transaction<'_>, block_number: u32, block_hash: BlockHash) -> anyhow::Result<()> {
+  notify(tx, &Notification::BlockCommitted { block_number: block_number.into(), block_hash: block_hash.to_string() }).await
+}
+
+pub(crate) async fn notify_reorg(tx: &deadpool_postgres::Transaction<'_>, last_good_block: u32) -> anyhow::Result<()> {
+  notify(tx, &Notification::Reorg { last_good_block: last_good_block.into() }).await
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+  /// Sent after the inscription events of a block
+  BlockCommitted {
+    block_number: i64,
+    block_hash: String,
+    inscriptions: usize,
+    transfers: usize,
+  },
+  InscriptionCreated(InscriptionCreated),
+  InscriptionTransferred(InscriptionTransferred),
+  /// Everything after last_good_block was rolled back and will be indexed again
+  Reorg {
+    last_good_block: i64,
+  },
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct InscriptionCreated {
+  id: String,
+  number: i64,
+  block_number: i64,
+  content_type: Option<String>,
+  content_category: Option<String>,
+  address: Option<String>,
+  collections: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct InscriptionTransferred {
+  id: String,
+  number: Option<i64>,
+  block_number: i64,
+  transaction: Option<String>,
+  satpoint: String,
+  from_address: Option<String>,
+  to_address: Option<String>,
+  price: Option<i64>,
+  content_category: Option<String>,
+  collections: Vec<String>,
+}
+
+impl Event {
+  fn is_hidden(&self, hidden: &HiddenInscriptions) -> bool {
+    match self {
+      Self::InscriptionCreated(created) => hidden.is_hidden(&created.id),
+      Self::InscriptionTransferred(transferred) => hidden.is_hidden(&transferred.id),
+      Self::BlockCommitted { .. } | Self::Reorg { .. } => false,
+    }
+  }
+
+  fn name(&self) -> &'static str {
+    match self {
+      Self::BlockCommitted { .. } => "block_committed",
+      Self::InscriptionCreated(_) => "inscription_created",
+      Self::InscriptionTransferred(_) => "inscription_transferred",
+      Self::Reorg { .. } => "reorg",
+    }
+  }
+}
+
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct EventFilter {
+  /// Only inscription events for this collection
+  #[schemars(description = "Only send inscription events for inscriptions in this collection")]
+  collection: Option<String>,
+
+  /// Only inscription events involving this address
+  #[schemars(description = "Only send inscription events where this address is the owner, sender or receiver")]
+  address: Option<String>,
+
+  /// Content types to filter by
+  #[schemars(description = "Only send inscription events for these content types")]
+  #[serde(default, deserialize_with = "deserialize_comma_separated_and_repeated")]
+  content_types: Vec<ContentType>,
+}
+
+impl EventFilter {
+  // Events carry canonical addresses, so the filter's address is checked against the chain and canonicalized once
+  // when the subscription opens
+  fn parse(mut self, chain: Chain) -> Result<Self, ApiError> {
+    if let Some(address) = self.address.take() {
+      let parsed = parse_address(&address, chain)
+        .map_err(|error| ApiError::BadRequest(format!("Invalid {} address {}: {}", chain, address, error)))?;
+      self.address = Some(parsed);
+    }
+    Ok(self)
+  }
+
+  // Block and reorg events always pass, clients need them to stay consistent
+  fn matches(&self, event: &Event) -> bool {
+    let (addresses, content_category, collections) = match event {
+      Event::BlockCommitted { .. } | Event::Reorg { .. } => return true,
+      Event::InscriptionCreated(created) => (
+        vec![&created.address],
+        &created.content_category,
+        &created.collections,
+      ),
+      Event::InscriptionTransferred(transferred) => (
+        vec![&transferred.from_address, &transferred.to_address],
+        &transferred.content_category,
+        &transferred.collections,
+      ),
+    };
+    if let Some(collection) = &self.collection {
+      if !collections.contains(collection) {
+        return false;
+      }
+    }
+    if let Some(address) = &self.address {
+      if !addresses.into_iter().flatten().any(|a| a == address) {
+        return false;
+      }
+    }
+    if !self.content_types.is_empty() {
+      let Some(content_category) = content_category else {
+        return false;
+      };
+      if !self.content_types.iter().any(|content_type| content_type.to_string() == *content_category) {
+        return false;
+      }
+    }
+    true
+  }
+}
+
+// Fans committed block events out to every /events subscriber of this API server
+#[derive(Clone)]
+pub(crate) struct EventHub {
+  sender: broadcast::Sender<Arc<Event>>,
+  hidden: HiddenInscriptions,
+}
+
+impl EventHub {
+  // Listens for indexer notifications on a dedicated connection, reconnecting if it drops.
+  // Also keeps the API server's hidden inscriptions up to date.
+  pub(crate) fn start(settings: Settings, pool: deadpool, hidden: HiddenInscriptions) -> Self {
+    let (sender, _) = broadcast::channel(EVENT_BUFFER);
+    let hub = Self { sender, hidden };
+    let listener = hub.clone();
+    tokio::spawn(async move {
+      while !SHUTTING_DOWN.load(atomic::Ordering::Relaxed) {
+        if let Err(err) = listener.listen(&settings, &pool).await {
+          log::warn!("Event listener error: {:?}, reconnecting in 10 seconds", err);
+        }
+        tokio::time::sleep(Duration::from_secs(10)).await;
+      }
+    });
+    hub
+  }
+
+  // A hub that never publishes, for tests of other routes
+  #[cfg(test)]
+  pub(crate) fn idle(hidden: HiddenInscriptions) -> Self {
+    let (sender, _) = broadcast::channel(EVENT_BUFFER);
+    Self { sender, hidden }
+  }
+
+  async fn listen(&self, settings: &Settings, pool: &deadpool) -> anyhow::Result<()> {
+    let (client, mut connection) = database::connect(settings).await?;
+    let (notification_tx, mut notification_rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+      let mut stream = stream::poll_fn(move |cx| connection.poll_message(cx));
+      while let Some(Ok(message)) = stream.next().await {
+        if let tokio_postgres::AsyncMessage::Notification(notification) = message {
+          if notification_tx.send(notification).is_err() {
+            break;
+          }
+        }
+      }
+    });
+    client.batch_execute(&format!("LISTEN {EVENTS_CHANNEL}")).await?;
+    log::info!("Event listener started");
+    // Changes made while we weren't listening would otherwise be missed
+    self.reload_hidden(pool).await;
+    while let Some(notification) = notification_rx.recv().await {
+      let events = match serde_json::from_str::<Notification>(notification.payload()) {
+        Ok(Notification::BlockCommitted { block_number, block_hash }) => {
+          if self.hidden.is_unresolved() {
+            self.reload_hidden(pool).await;
+          }
+          match Self::block_events(pool, block_number, block_hash).await {
+            Ok(events) => events,
+            Err(err) => {
+              log::warn!("Error loading events for block {}: {:?}", block_number, err);
+              continue;
+            }
+          }
+        }
+        Ok(Notification::Reorg { last_good_block }) => vec![Event::Reorg { last_good_block }],
+        Ok(Notification::HiddenInscriptionsChanged) => {
+          self.reload_hidden(pool).await;
+          continue;
+        }
+        Err(err) => {
+          log::warn!("Ignoring malformed event notification {}: {}", notification.payload(), err);
+          continue;
+        }
+      };
+      for event in events {
+        // No subscribers is not an error
+        let _ = self.sender.send(Arc::new(event));
+      }
+    }
+    Err(anyhow!("event listener connection closed"))
+  }
+
+  async fn reload_hidden(&self, pool: &deadpool) {
+    if let Err(err) = self.hidden.reload(pool).await {
+      log::warn!("Error loading hidden inscriptions: {:?}", err);
+    }
+  }
+
+  async fn block_events(pool: &deadpool, block_number: i64, block_hash: String) -> anyhow::Result<Vec<Event>> {
+    let conn = pool.get().await?;
+    let created = conn.query(
+      r"SELECT o.id, o.number, o.content_type, o.content_category, t.address,
+          COALESCE(array_agg(c.collection_symbol) FILTER (WHERE c.collection_symbol IS NOT NULL), '{}') AS collections
+        FROM ordinals o
+        LEFT JOIN transfers t ON t.id = o.id AND t.block_number = o.genesis_height AND t.is_genesis
+        LEFT JOIN collections c ON c.id = o.id
+        WHERE o.genesis_height = $1
+        GROUP BY o.sequence_number, t.address
+        ORDER BY o.sequence_number",
+      &[&block_number],
+    ).await?;
+    let transferred = conn.query(
+      r"SELECT t.id, o.number, t.transaction, t.satpoint, t.previous_address, t.address, t.price, o.content_category,
+          COALESCE(array_agg(c.collection_symbol) FILTER (WHERE c.collection_symbol IS NOT NULL), '{}') AS collections
+        FROM transfers t
+        LEFT JOIN ordinals o ON o.id = t.id
+        LEFT JOIN collections c ON c.id = t.id
+        WHERE t.block_number = $1 AND t.is_genesis IS NOT TRUE
+        GROUP BY t.id, t.block_number, t.satpoint, o.sequence_number
+        ORDER BY t.tx_offset, t.satpoint",
+      &[&block_number],
+    ).await?;
+    let mut events = Vec::with_capacity(created.len() + transferred.len() + 1);
+    let block_committed = Event::BlockCommitted {
+      block_number,
+      block_hash,
+      inscriptions: created.len(),
+      transfers: transferred.len(),
+    };
+    events.extend(created.into_iter().map(|row| Event::InscriptionCreated(InscriptionCreated {
+      id: row.get("id"),
+      number: row.get("number"),
+      block_number,
+      content_type: row.get("content_type"),
+      content_category: row.get("content_category"),
+      address: row.get("address"),
+      collections: row.get("collections"),
+    })));
+    events.extend(transferred.into_iter().map(|row| Event::InscriptionTransferred(InscriptionTransferred {
+      id: row.get("id"),
+      number: row.get("number"),
+      block_number,
+      transaction: row.get("transaction"),
+      satpoint: row.get("satpoint"),
+      from_address: row.get("previous_address"),
+      to_address: row.get("address"),
+      price: row.get("price"),
+      content_category: row.get("content_category"),
+      collections: row.get("collections"),
+    })));
+    events.push(block_committed);
+    Ok(events)
+  }
+
+  // Ends when the subscriber falls behind, since it can't know what it missed it should resync over REST
+  fn subscribe(&self, filter: EventFilter) -> impl futures::Stream<Item = Arc<Event>> + Send + 'static {
+    stream::unfold((self.sender.subscribe(), filter, self.hidden.clone()), |(mut receiver, filter, hidden)| async move {
+      loop {
+        match receiver.recv().await {
+          Ok(event) if filter.matches(&event) && !event.is_hidden(&hidden) => return Some((event, (receiver, filter, hidden))),
+          Ok(_) => continue,
+          Err(broadcast::error::RecvError::Lagged(skipped)) => {
+            log::info!("Disconnecting event subscriber that fell {} events behind", skipped);
+            return None;
+          }
+          Err(broadcast::error::RecvError::Closed) => return None,
+        }
+      }
+    })
+  }
+}
+
+//API
+pub fn events_router() -> ApiRouter<ApiServerConfig> {
+  ApiRouter::new()
+    .api_route("/events", get(events_handler))
+    .api_route("/events_ws", get(events_ws_handler))
+}
+
+async fn events_handler(Query(filter): Query<EventFilter>, State(server_config): State<ApiServerConfig>) -> Result<EventStream, ApiError> {
+  let filter = filter.parse(server_config.chain)?;
+  let stream = server_config.events.subscribe(filter).map(|event| {
+    let data = serde_json::to_string(&*event).unwrap_or_default();
+    Ok::<_, Infallible>(SseEvent::default().event(event.name()).data(data))
+  });
+  Ok(EventStream(Sse::new(stream).keep_alive(KeepAlive::default()).into_response()))
+}
+
+// The upgrade response is documented by WebSocketUpgrade
+async fn events_ws_handler(Query(filter): Query<EventFilter>, State(server_config): State<ApiServerConfig>, upgrade: WebSocketUpgrade) -> Result<NoApi<Response<Body>>, ApiError> {
+  let filter = filter.parse(server_config.chain)?;
+  let events = server_config.events.subscribe(filter);
+  Ok(NoApi(upgrade.on_upgrade(move |socket| send_events(socket, events))))
+}
+
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+
+// Pings keep idle connections open through proxies. Pongs are sent by axum, anything else the client sends is dropped.
+async fn send_events(mut socket: WebSocket, events: impl futures::Stream<Item = Arc<Event>>) {
+  pin_mut!(events);
+  let mut ping = tokio::time::interval_at(tokio::time::Instant::now() + PING_INTERVAL, PING_INTERVAL);
+  ping.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+  loop {
+    let message = tokio::select! {
+      event = events.next() => match event {
+        Some(event) => Message::Text(serde_json::to_string(&*event).unwrap_or_default().into()),
+        // 1008 policy violation, the subscriber fell too far behind
+        None => Message::Close(Some(CloseFrame { code: 1008, reason: "subscriber fell behind".into() })),
+      },
+      received = socket.recv() => match received {
+        Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+        Some(Ok(_)) => continue,
+      },
+      _ = ping.tick() => Message::Ping(Default::default()),
+    };
+    let closing = matches!(message, Message::Close(_));
+    if let Err(err) = socket.send(message).await {
+      log::debug!("Websocket closed with error: {:?}", err);
+      return;
+    }
+    if closing {
+      return;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn created(address: &str, content_category: &str, collections: &[&str]) -> Event {
+    Event::InscriptionCreated(InscriptionCreated {
+      id: format!("{}i0", "a".repeat(64)),
+      number: 1,
+      block_number: 800_000,
+      content_type: Some("image/png".into()),
+      content_category: Some(content_category.into()),
+      address: Some(address.into()),
+      collections: collections.iter().map(|c| c.to_string()).collect(),
+    })
+  }
+
+  fn filter(query: serde_json::Value) -> EventFilter {
+    serde_json::from_value(query).unwrap()
+  }
+
+  #[test]
+  fn filters_apply_to_inscription_events() {
+    let event = created("bc1qone", "image", &["pizza_ninjas"]);
+    assert!(filter(serde_json::json!({})).matches(&event));
+    assert!(filter(serde_json::json!({"collection": "pizza_ninjas", "address": "bc1qone", "content_types": "text,image"})).matches(&event));
+    assert!(!filter(serde_json::json!({"collection": "nodemonkes"})).matches(&event));
+    assert!(!filter(serde_json::json!({"address": "bc1qtwo"})).matches(&event));
+    assert!(!filter(serde_json::json!({"content_types": "text"})).matches(&event));
+    let block = Event::Reorg { last_good_block: 1 };
+    assert!(filter(serde_json::json!({"collection": "nodemonkes", "address": "bc1qtwo", "content_types": ["text"]})).matches(&block));
+  }
+
+  #[test]
+  fn transfers_match_either_address() {
+    let event = Event::InscriptionTransferred(InscriptionTransferred {
+      id: format!("{}i0", "a".repeat(64)),
+      number: Some(1),
+      block_number: 800_001,
+      transaction: None,
+      satpoint: format!("{}:0:0", "b".repeat(64)),
+      from_address: Some("bc1qone".into()),
+      to_address: Some("bc1qtwo".into()),
+      price: None,
+      content_category: None,
+      collections: Vec::new(),
+    });
+    assert!(filter(serde_json::json!({"address": "bc1qone"})).matches(&event));
+    assert!(filter(serde_json::json!({"address": "bc1qtwo"})).matches(&event));
+    assert!(!filter(serde_json::json!({"address": "bc1qthree"})).matches(&event));
+    assert!(!filter(serde_json::json!({"content_types": "image"})).matches(&event));
+  }
+
+  #[test]
+  fn filter_addresses_are_parsed_for_the_chain() {
+    let address = "bc1q9vza2e8x573nczrlzms0wvx3gsqjx7vavgkx0l";
+    let event = created(address, "image", &[]);
+    let parsed = filter(serde_json::json!({"address": address.to_uppercase()})).parse(Chain::Mainnet).unwrap_or_else(|_| panic!("valid address"));
+    assert!(parsed.matches(&event));
+    assert!(matches!(filter(serde_json::json!({"address": address})).parse(Chain::Testnet), Err(ApiError::BadRequest(_))));
+    assert!(matches!(filter(serde_json::json!({"address": "bc1qone"})).parse(Chain::Mainnet), Err(ApiError::BadRequest(_))));
+    assert!(filter(serde_json::json!({})).parse(Chain::Mainnet).is_ok());
+  }
+
+  #[test]
+  fn event_routes_are_documented() {
+    let mut api = aide::openapi::OpenApi::default();
+    let _ = events_router().finish_api(&mut api);
+    let api = serde_json::to_value(&api).unwrap();
+    assert!(api["paths"]["/events"]["get"]["responses"]["200"]["content"]["text/event-stream"].is_object());
+    assert!(api["paths"]["/events_ws"]["get"]["responses"]["101"].is_object());
+    assert!(api["paths"]["/events"]["get"]["responses"]["400"].is_object());
+    let parameters = api["paths"]["/events_ws"]["get"]["parameters"].as_array().unwrap();
+    assert!(parameters.iter().any(|parameter| parameter["name"] == "collection"));
+  }
+
+  #[test]
+  fn notification_payloads() {
+    let payload = serde_json::to_string(&Notification::BlockCommitted { block_number: 5, block_hash: "00".into() }).unwrap();
+    assert_eq!(payload, r#"{"type":"block_committed","block_number":5,"block_hash":"00"}"#);
+    assert_eq!(serde_json::from_str::<Notification>(&payload).unwrap(), Notification::BlockCommitted { block_number: 5, block_hash: "00".into() });
+    // Sent by the hidden_inscriptions trigger
+    assert_eq!(serde_json::from_str::<Notification>(r#"{"type":"hidden_inscriptions_changed"}"#).unwrap(), Notification::HiddenInscriptionsChanged);
+    let event = serde_json::to_value(created("bc1qone", "image", &[])).unwrap();
+    assert_eq!(event["type"], "inscription_created");
+    assert_eq!(event["address"], "bc1qone");
+  }
+}
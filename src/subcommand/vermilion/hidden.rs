@@ -0,0 +1,562 @@
+use super::*;
+use aide::{
+  axum::routing::{get_with, put_with},
+  openapi::SecurityScheme,
+  transform::{TransformOpenApi, TransformOperation},
+  OperationInput,
+};
+use axum::{
+  extract::FromRequestParts,
+  http::request::Parts,
+  response::NoContent,
+};
+use api::{bearer_token, constant_time_eq};
+use events::EVENTS_CHANNEL;
+use std::sync::RwLock;
+
+pub(crate) async fn initialize_hidden_inscriptions(pool: deadpool) -> anyhow::Result<()> {
+  let conn = pool.get().await?;
+  conn.simple_query(
+    r"CREATE TABLE IF NOT EXISTS hidden_inscriptions (
+        id varchar(80) not null primary key,
+        reason text,
+        hidden_at timestamptz not null default now()
+      )").await?;
+  // Statement level so edits made straight from psql reach the API servers too, however many rows they touch
+  conn.simple_query(&format!(
+    r#"CREATE OR REPLACE FUNCTION notify_hidden_inscriptions_changed() RETURNS TRIGGER AS $$
+      BEGIN
+        PERFORM pg_notify('{EVENTS_CHANNEL}', '{{"type":"hidden_inscriptions_changed"}}');
+        RETURN NULL;
+      END;
+      $$ LANGUAGE plpgsql;"#)).await?;
+  conn.simple_query(
+    r"CREATE OR REPLACE TRIGGER hidden_inscriptions_changed
+      AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON hidden_inscriptions
+      FOR EACH STATEMENT
+      EXECUTE PROCEDURE notify_hidden_inscriptions_changed();").await?;
+  Ok(())
+}
+
+// Hiding an inscription also hides anything that shares its content, including inscriptions delegating to it
+#[derive(Default)]
+pub(crate) struct Hidden {
+  ids: HashSet<String>,
+  sha256s: HashSet<String>,
+  // Some hidden ids weren't indexed yet, so their content hashes are still unknown
+  unresolved: bool,
+}
+
+impl Hidden {
+  fn id(&self, id: &str) -> bool {
+    self.ids.contains(id)
+  }
+
+  fn content(&self, sha256: Option<&str>) -> bool {
+    sha256.is_some_and(|sha256| self.sha256s.contains(sha256))
+  }
+}
+
+// The hidden lists as query parameters, for the feeds, which filter in SQL so hidden inscriptions don't leave their pages short
+#[derive(Default)]
+pub(crate) struct HiddenParams {
+  pub(crate) ids: Vec<String>,
+  pub(crate) sha256s: Vec<String>,
+}
+
+// Matches the ordinals_full_t rows (aliased o) that aren't hidden, with HiddenParams bound to $2 and $3
+pub(crate) const VISIBLE_SQL: &str =
+  "o.id <> ALL($2) AND (o.delegate IS NULL OR o.delegate <> ALL($2)) AND (o.sha256 IS NULL OR o.sha256 <> ALL($3))";
+
+// For the paged lists, which filter in SQL too so hidden inscriptions don't leave their pages short. Both return None
+// when nothing is hidden.
+impl HiddenParams {
+  // Like VISIBLE_SQL, for inscription rows aliased o, binding the lists wherever the query has got to
+  pub(crate) fn visible(&self, sql_params: &mut SqlParams) -> Option<String> {
+    if self.ids.is_empty() && self.sha256s.is_empty() {
+      return None;
+    }
+    let ids = sql_params.push(self.ids.clone());
+    let sha256s = sql_params.push(self.sha256s.clone());
+    Some(format!("o.id <> ALL({ids}) AND (o.delegate IS NULL OR o.delegate <> ALL({ids})) AND (o.sha256 IS NULL OR o.sha256 <> ALL({sha256s}))"))
+  }
+
+  // Matches rows none of whose id columns are hidden, like the Redact impls of items that only carry ids
+  pub(crate) fn visible_ids(&self, columns: &[&str], sql_params: &mut SqlParams) -> Option<String> {
+    if self.ids.is_empty() {
+      return None;
+    }
+    let ids = sql_params.push(self.ids.clone());
+    Some(columns.iter().map(|column| format!("{column} <> ALL({ids})")).join(" AND "))
+  }
+}
+
+// Everything the API returns that names inscriptions. Returns None if the whole item should be dropped.
+pub(crate) trait Redact: Sized {
+  fn redact(self, hidden: &Hidden) -> Option<Self>;
+}
+
+impl Redact for FullMetadata {
+  fn redact(self, hidden: &Hidden) -> Option<Self> {
+    let is_hidden = hidden.id(&self.id)
+      || self.delegate.as_deref().is_some_and(|delegate| hidden.id(delegate))
+      || hidden.content(self.sha256.as_deref());
+    (!is_hidden).then_some(self)
+  }
+}
+
+impl Redact for BoostFullMetadata {
+  fn redact(self, hidden: &Hidden) -> Option<Self> {
+    let is_hidden = hidden.id(&self.id)
+      || self.delegate.as_deref().is_some_and(|delegate| hidden.id(delegate))
+      || hidden.content(self.sha256.as_deref());
+    (!is_hidden).then_some(self)
+  }
+}
+
+impl Redact for BootlegEdition {
+  fn redact(self, hidden: &Hidden) -> Option<Self> {
+    (!hidden.id(&self.bootleg_id) && !hidden.id(&self.delegate_id)).then_some(self)
+  }
+}
+
+impl Redact for CommentEdition {
+  fn redact(self, hidden: &Hidden) -> Option<Self> {
+    (!hidden.id(&self.comment_id) && !hidden.id(&self.delegate_id)).then_some(self)
+  }
+}
+
+impl Redact for SatributeEdition {
+  fn redact(self, hidden: &Hidden) -> Option<Self> {
+    (!hidden.id(&self.inscription_id)).then_some(self)
+  }
+}
+
+impl Redact for InscriptionNumberEdition {
+  fn redact(self, hidden: &Hidden) -> Option<Self> {
+    (!hidden.id(&self.id)).then_some(self)
+  }
+}
+
+impl Redact for Transfer {
+  fn redact(self, hidden: &Hidden) -> Option<Self> {
+    (!hidden.id(&self.id)).then_some(self)
+  }
+}
+
+impl Redact for InscriptionCollectionData {
+  fn redact(self, hidden: &Hidden) -> Option<Self> {
+    (!hidden.id(&self.id)).then_some(self)
+  }
+}
+
+impl Redact for TrendingItem {
+  fn redact(mut self, hidden: &Hidden) -> Option<Self> {
+    self.inscriptions = self.inscriptions.into_iter().filter_map(|inscription| inscription.redact(hidden)).collect();
+    self.activity.ids.retain(|id| self.inscriptions.iter().any(|inscription| inscription.id == *id));
+    (!self.inscriptions.is_empty()).then_some(self)
+  }
+}
+
+impl Redact for DiscoverItem {
+  fn redact(mut self, hidden: &Hidden) -> Option<Self> {
+    self.inscriptions = self.inscriptions.into_iter().filter_map(|inscription| inscription.redact(hidden)).collect();
+    self.activity.ids.retain(|id| self.inscriptions.iter().any(|inscription| inscription.id == *id));
+    (!self.inscriptions.is_empty()).then_some(self)
+  }
+}
+
+impl Redact for Sale {
+  fn redact(self, hidden: &Hidden) -> Option<Self> {
+    (!hidden.id(&self.id)).then_some(self)
+  }
+}
+
+impl Redact for HoldingPeriod {
+  fn redact(self, hidden: &Hidden) -> Option<Self> {
+    (!hidden.id(&self.id)).then_some(self)
+  }
+}
+
+impl Redact for Bitmap {
+  fn redact(self, hidden: &Hidden) -> Option<Self> {
+    (!hidden.id(&self.id)).then_some(self)
+  }
+}
+
+impl Redact for TextSearchResult {
+  fn redact(mut self, hidden: &Hidden) -> Option<Self> {
+    self.inscription = self.inscription.redact(hidden)?;
+    Some(self)
+  }
+}
+
+impl Redact for SearchResult {
+  fn redact(mut self, hidden: &Hidden) -> Option<Self> {
+    self.inscription = self.inscription.and_then(|inscription| inscription.redact(hidden));
+    if let Some(transaction) = self.transaction.as_mut() {
+      transaction.transfers = std::mem::take(&mut transaction.transfers).into_iter().filter_map(|transfer| transfer.redact(hidden)).collect();
+    }
+    Some(self)
+  }
+}
+
+// Notifications are lost while the event listener reconnects and never arrive through a pooler in transaction mode,
+// so the list is also polled over the pool
+const HIDDEN_POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+// The hidden list from settings plus the hidden_inscriptions table, shared by every request of an API server.
+// Loaded before the server starts, then reloaded by the event listener whenever the table changes and polled.
+#[derive(Clone)]
+pub(crate) struct HiddenInscriptions {
+  settings_ids: Arc<Vec<String>>,
+  hidden: Arc<RwLock<Hidden>>,
+}
+
+impl HiddenInscriptions {
+  // Settings ids apply straight away, the table is only read by reload
+  pub(crate) fn new(settings: &Settings) -> Self {
+    let settings_ids: Vec<String> = settings.hidden().map(|id| id.to_string()).collect();
+    let hidden = Hidden {
+      ids: settings_ids.iter().cloned().collect(),
+      sha256s: HashSet::new(),
+      unresolved: !settings_ids.is_empty(),
+    };
+    Self {
+      settings_ids: Arc::new(settings_ids),
+      hidden: Arc::new(RwLock::new(hidden)),
+    }
+  }
+
+  pub(crate) async fn reload(&self, pool: &deadpool) -> anyhow::Result<()> {
+    let conn = pool.get().await?;
+    let rows = conn.query(
+      r"SELECT h.id, o.sha256
+        FROM (SELECT id::text FROM hidden_inscriptions UNION SELECT unnest($1::text[])) h
+        LEFT JOIN ordinals o ON o.id = h.id",
+      &[&*self.settings_ids],
+    ).await?;
+    let mut hidden = Hidden::default();
+    for row in rows {
+      let sha256: Option<String> = row.get("sha256");
+      match sha256 {
+        Some(sha256) => {
+          hidden.sha256s.insert(sha256);
+        }
+        None => hidden.unresolved = true,
+      }
+      hidden.ids.insert(row.get("id"));
+    }
+    log::debug!("Loaded {} hidden inscriptions", hidden.ids.len());
+    *self.hidden.write().unwrap() = hidden;
+    Ok(())
+  }
+
+  pub(crate) fn poll(&self, pool: deadpool) {
+    let hidden = self.clone();
+    tokio::spawn(async move {
+      while !SHUTTING_DOWN.load(atomic::Ordering::Relaxed) {
+        tokio::time::sleep(HIDDEN_POLL_INTERVAL).await;
+        if let Err(err) = hidden.reload(&pool).await {
+          log::warn!("Error polling hidden inscriptions: {:?}", err);
+        }
+      }
+    });
+  }
+
+  pub(crate) fn is_unresolved(&self) -> bool {
+    self.hidden.read().unwrap().unresolved
+  }
+
+  pub(crate) fn params(&self) -> Arc<HiddenParams> {
+    let hidden = self.hidden.read().unwrap();
+    Arc::new(HiddenParams {
+      ids: hidden.ids.iter().cloned().collect(),
+      sha256s: hidden.sha256s.iter().cloned().collect(),
+    })
+  }
+
+  pub(crate) fn is_hidden(&self, id: &str) -> bool {
+    self.hidden.read().unwrap().id(id)
+  }
+
+  // For content endpoints, where the id may not be known
+  pub(crate) fn check_content(&self, id: Option<&str>, sha256: &str) -> Result<(), ApiError> {
+    let hidden = self.hidden.read().unwrap();
+    if id.is_some_and(|id| hidden.id(id)) || hidden.content(Some(sha256)) {
+      return Err(ApiError::NotFound("Inscription not found".to_string()));
+    }
+    Ok(())
+  }
+
+  pub(crate) fn check<T: Redact>(&self, item: T) -> Result<T, ApiError> {
+    item.redact(&self.hidden.read().unwrap()).ok_or_else(|| ApiError::NotFound("Inscription not found".to_string()))
+  }
+
+  pub(crate) fn retain<T: Redact>(&self, items: Vec<T>) -> Vec<T> {
+    let hidden = self.hidden.read().unwrap();
+    items.into_iter().filter_map(|item| item.redact(&hidden)).collect()
+  }
+}
+
+// Requires `Authorization: Bearer <admin_api_key>`. The admin routes don't exist unless the key is set.
+pub(crate) struct Admin;
+
+impl FromRequestParts<ApiServerConfig> for Admin {
+  type Rejection = Response<Body>;
+
+  async fn from_request_parts(parts: &mut Parts, server_config: &ApiServerConfig) -> Result<Self, Self::Rejection> {
+    let Some(admin_api_key) = server_config.admin_api_key.as_deref() else {
+      return Err(StatusCode::NOT_FOUND.into_response());
+    };
+    let authorized = bearer_token(&parts.headers)
+      .is_some_and(|token| constant_time_eq(token.as_bytes(), admin_api_key.as_bytes()));
+    if !authorized {
+      return Err((StatusCode::UNAUTHORIZED, Json(serde_json::json!({
+        "error": "Unauthorized",
+        "message": "Missing or invalid admin api key"
+      }))).into_response());
+    }
+    Ok(Admin)
+  }
+}
+
+// Documented through the admin_api_key security requirement on each admin route
+impl OperationInput for Admin {}
+
+// The bearer scheme the admin routes refer to, added to the generated OpenAPI document
+pub(crate) fn admin_security_scheme(api: TransformOpenApi<'_>) -> TransformOpenApi<'_> {
+  api.security_scheme("admin_api_key", SecurityScheme::Http {
+    scheme: "bearer".into(),
+    bearer_format: None,
+    description: Some("The admin_api_key setting. The /admin routes respond 404 unless it is set".into()),
+    extensions: Default::default(),
+  })
+}
+
+fn admin_docs<'a>(operation: TransformOperation<'a>, description: &str) -> TransformOperation<'a> {
+  operation.tag("admin").description(description).security_requirement("admin_api_key")
+}
+
+#[derive(Serialize, JsonSchema)]
+struct HiddenInscription {
+  id: String,
+  reason: Option<String>,
+  /// Unix timestamp in seconds
+  hidden_at: i64,
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct HideRequest {
+  /// Kept with the takedown for the record
+  reason: Option<String>,
+}
+
+//API
+pub fn admin_router() -> ApiRouter<ApiServerConfig> {
+  ApiRouter::new()
+    .api_route("/admin/hidden_inscriptions", get_with(list_hidden_handler, |operation| {
+      admin_docs(operation, "Inscriptions hidden through the admin api, newest first. Doesn't include the hidden setting")
+    }))
+    .api_route(
+      "/admin/hidden_inscriptions/{inscription_id}",
+      put_with(hide_handler, |operation| {
+        admin_docs(operation, "Hides an inscription, and anything sharing its content, from every API server")
+      })
+      .delete_with(unhide_handler, |operation| admin_docs(operation, "Unhides an inscription hidden through the admin api")),
+    )
+}
+
+async fn list_hidden_handler(_: Admin, State(server_config): State<ApiServerConfig>) -> Result<Json<Vec<HiddenInscription>>, ApiError> {
+  let result: anyhow::Result<Vec<HiddenInscription>> = async {
+    let conn = server_config.deadpool.get().await?;
+    let rows = conn.query(
+      "SELECT id, reason, extract(epoch from hidden_at)::bigint AS hidden_at FROM hidden_inscriptions ORDER BY hidden_at DESC",
+      &[],
+    ).await?;
+    Ok(rows.into_iter().map(|row| HiddenInscription {
+      id: row.get("id"),
+      reason: row.get("reason"),
+      hidden_at: row.get("hidden_at"),
+    }).collect())
+  }.await;
+  let hidden = result.map_err(|error| {
+    log::warn!("Error getting /admin/hidden_inscriptions: {}", error);
+    ApiError::InternalServerError("Error retrieving hidden inscriptions".to_string())
+  })?;
+  Ok(Json(hidden))
+}
+
+async fn hide_handler(_: Admin, Path(inscription_id): Path<InscriptionId>, State(server_config): State<ApiServerConfig>, request: Option<Json<HideRequest>>) -> Result<NoContent, ApiError> {
+  let reason = request.and_then(|Json(request)| request.reason);
+  let result: anyhow::Result<()> = async {
+    let conn = server_config.deadpool.get().await?;
+    conn.execute(
+      "INSERT INTO hidden_inscriptions (id, reason) VALUES ($1, $2) ON CONFLICT (id) DO UPDATE SET reason = EXCLUDED.reason",
+      &[&inscription_id.to_string(), &reason],
+    ).await?;
+    // Don't wait for the notification to come back around, the takedown should apply to the next request
+    server_config.hidden.reload(&server_config.deadpool).await
+  }.await;
+  result.map_err(|error| {
+    log::warn!("Error hiding {}: {}", inscription_id, error);
+    ApiError::InternalServerError(format!("Error hiding {}", inscription_id))
+  })?;
+  log::info!("Hid inscription {}", inscription_id);
+  Ok(NoContent)
+}
+
+async fn unhide_handler(_: Admin, Path(inscription_id): Path<InscriptionId>, State(server_config): State<ApiServerConfig>) -> Result<NoContent, ApiError> {
+  let result: anyhow::Result<u64> = async {
+    let conn = server_config.deadpool.get().await?;
+    let deleted = conn.execute("DELETE FROM hidden_inscriptions WHERE id = $1", &[&inscription_id.to_string()]).await?;
+    server_config.hidden.reload(&server_config.deadpool).await?;
+    Ok(deleted)
+  }.await;
+  let deleted = result.map_err(|error| {
+    log::warn!("Error unhiding {}: {}", inscription_id, error);
+    ApiError::InternalServerError(format!("Error unhiding {}", inscription_id))
+  })?;
+  if deleted == 0 {
+    return Err(ApiError::NotFound(format!("{} is not in hidden_inscriptions", inscription_id)));
+  }
+  log::info!("Unhid inscription {}", inscription_id);
+  Ok(NoContent)
+}
+
+#[cfg(test)]
+mod tests {
+  use {
+    super::*,
+    super::super::tests::{index_test_block, TestDatabase, test_inscription, TestBlock},
+  };
+
+  fn hidden(ids: &[&str], sha256s: &[&str]) -> HiddenInscriptions {
+    let hidden = HiddenInscriptions::new(&Settings::default());
+    *hidden.hidden.write().unwrap() = Hidden {
+      ids: ids.iter().map(|id| id.to_string()).collect(),
+      sha256s: sha256s.iter().map(|sha256| sha256.to_string()).collect(),
+      unresolved: false,
+    };
+    hidden
+  }
+
+  fn metadata(id: &str, delegate: Option<&str>, sha256: Option<&str>) -> FullMetadata {
+    FullMetadata {
+      sequence_number: 0,
+      id: id.into(),
+      content_length: None,
+      content_type: None,
+      content_encoding: None,
+      content_category: "image".into(),
+      genesis_fee: 0,
+      genesis_height: 0,
+      genesis_transaction: String::new(),
+      pointer: None,
+      number: 0,
+      parents: Vec::new(),
+      on_chain_collection_id: None,
+      delegate: delegate.map(Into::into),
+      delegate_content_type: None,
+      metaprotocol: None,
+      on_chain_metadata: serde_json::Value::Null,
+      sat: None,
+      sat_block: None,
+      satributes: Vec::new(),
+      charms: Vec::new(),
+      timestamp: 0,
+      sha256: sha256.map(Into::into),
+      text: None,
+      referenced_ids: Vec::new(),
+      is_json: false,
+      is_maybe_json: false,
+      is_bitmap_style: false,
+      is_recursive: false,
+      spaced_rune: None,
+      inscribed_by_address: None,
+      collection_symbol: None,
+      off_chain_metadata: None,
+      collection_name: None,
+    }
+  }
+
+  #[test]
+  fn metadata_is_hidden_by_id_delegate_or_content() {
+    let hidden = hidden(&["ai0"], &["abc"]);
+    assert!(hidden.check(metadata("ai0", None, None)).is_err());
+    assert!(hidden.check(metadata("bi0", Some("ai0"), None)).is_err());
+    assert!(hidden.check(metadata("ci0", None, Some("abc"))).is_err());
+    assert!(hidden.check(metadata("di0", None, Some("def"))).is_ok());
+    let visible = hidden.retain(vec![metadata("ai0", None, None), metadata("di0", None, None)]);
+    assert_eq!(visible.into_iter().map(|metadata| metadata.id).collect::<Vec<_>>(), vec!["di0"]);
+  }
+
+  #[test]
+  fn content_is_hidden_by_id_or_sha256() {
+    let hidden = hidden(&["ai0"], &["abc"]);
+    assert!(hidden.check_content(Some("ai0"), "def").is_err());
+    assert!(hidden.check_content(None, "abc").is_err());
+    assert!(hidden.check_content(Some("bi0"), "def").is_ok());
+  }
+
+  #[test]
+  fn feed_items_drop_hidden_inscriptions() {
+    let hidden = hidden(&["ai0"], &["abc"]);
+    let item = |ids: &[&str]| TrendingItem {
+      activity: TrendingItemActivity {
+        ids: ids.iter().map(|id| id.to_string()).collect(),
+        block_age: 0,
+        most_recent_timestamp: 0,
+        children_count: 0,
+        delegate_count: 0,
+        comment_count: 0,
+        band_start: 0.0,
+        band_end: 0.0,
+        band_id: 0,
+      },
+      inscriptions: ids.iter().map(|id| metadata(id, None, (*id == "ci0").then_some("abc"))).collect(),
+    };
+    // ci0 is hidden by its content
+    let items = hidden.retain(vec![item(&["ai0"]), item(&["ai0", "bi0", "ci0"])]);
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].inscriptions.len(), 1);
+    assert_eq!(items[0].inscriptions[0].id, "bi0");
+    assert_eq!(items[0].activity.ids, vec!["bi0"]);
+  }
+
+  #[test]
+  fn admin_routes_are_documented() {
+    let mut api = OpenApi::default();
+    let _ = admin_router().finish_api_with(&mut api, admin_security_scheme);
+    let paths = api.paths.unwrap().paths;
+    let operation = |path: &str| paths[path].as_item().unwrap().clone();
+    assert!(operation("/admin/hidden_inscriptions").get.unwrap().security[0].contains_key("admin_api_key"));
+    let hide = operation("/admin/hidden_inscriptions/{inscription_id}");
+    assert!(hide.put.is_some() && hide.delete.is_some());
+    assert!(api.components.unwrap().security_schemes.contains_key("admin_api_key"));
+  }
+
+  #[tokio::test]
+  #[ignore]
+  async fn hidden_inscriptions_are_filtered_before_paging() {
+    let pool = TestDatabase::new("vermilion_hidden_test").await;
+
+    // The third inscription shares the hidden second one's content
+    let inscriptions = vec![
+      test_inscription(0, 1, &"a".repeat(64), Vec::new(), None),
+      test_inscription(1, 1, &"b".repeat(64), Vec::new(), None),
+      test_inscription(2, 1, &"b".repeat(64), Vec::new(), None),
+      test_inscription(3, 1, &"d".repeat(64), Vec::new(), None),
+    ];
+    index_test_block(&pool, &TestBlock { height: 1, inscriptions: inscriptions.clone(), galleries: Vec::new(), transfers: Vec::new() }).await;
+    pool.get().await.unwrap().execute("INSERT INTO hidden_inscriptions (id) VALUES ($1)", &[&inscriptions[1].id]).await.unwrap();
+    let hidden = HiddenInscriptions::new(&Settings::default());
+    hidden.reload(&pool).await.unwrap();
+
+    let mut parts = http::Request::builder().uri("/inscriptions?page_size=2").body(()).unwrap().into_parts().0;
+    let Query(params) = <Query<InscriptionQueryParams> as FromRequestParts<()>>::from_request_parts(&mut parts, &()).await.unwrap();
+    let Ok(params) = params.parse(Chain::Mainnet) else { panic!("invalid params") };
+    let Paginated::Items(page) = Vermilion::get_inscriptions(pool.clone(), params, &hidden.params()).await.unwrap() else { panic!("expected items") };
+    assert_eq!(page.into_iter().map(|inscription| inscription.id).collect::<Vec<_>>(), vec![inscriptions[3].id.clone(), inscriptions[0].id.clone()]);
+  }
+}
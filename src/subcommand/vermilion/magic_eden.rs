@@ -0,0 +1,246 @@
+use super::*;
+use reqwest::{StatusCode as HttpStatusCode, Url};
+
+const API_URL: &str = "https://api-mainnet.magiceden.dev";
+const STATS_URL: &str = "https://stats-mainnet.magiceden.io";
+
+// One client is shared by every Magic Eden request the collection indexer makes, so the rate limit
+// covers all of them.
+#[derive(Clone)]
+pub(crate) struct MagicEdenClient {
+  client: reqwest::Client,
+  api_url: Url,
+  stats_url: Url,
+  api_key: Option<String>,
+  retry: RetryPolicy,
+  bucket: Arc<Mutex<TokenBucket>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct RetryPolicy {
+  pub(crate) max_retries: u32,
+  pub(crate) initial_backoff: Duration,
+  pub(crate) max_backoff: Duration,
+}
+
+impl RetryPolicy {
+  // Doubles from the initial backoff on each retry
+  pub(crate) fn backoff(&self, retry: u32) -> Duration {
+    self.initial_backoff.saturating_mul(2u32.saturating_pow(retry)).min(self.max_backoff)
+  }
+}
+
+// Refills at a steady rate up to a burst of one second's worth of requests. Tokens are taken ahead of time, so
+// concurrent requests queue up behind each other instead of all waking at once.
+#[derive(Debug)]
+pub(crate) struct TokenBucket {
+  capacity: f64,
+  per_second: f64,
+  tokens: f64,
+  refilled_at: Instant,
+}
+
+impl TokenBucket {
+  pub(crate) fn new(requests_per_minute: u32, now: Instant) -> Self {
+    let per_second = f64::from(requests_per_minute.max(1)) / 60.0;
+    let capacity = per_second.max(1.0);
+    Self { capacity, per_second, tokens: capacity, refilled_at: now }
+  }
+
+  // Takes a token and returns how long to wait before using it
+  pub(crate) fn take(&mut self, now: Instant) -> Duration {
+    let elapsed = now.saturating_duration_since(self.refilled_at).as_secs_f64();
+    self.tokens = (self.tokens + elapsed * self.per_second).min(self.capacity) - 1.0;
+    self.refilled_at = now;
+    if self.tokens >= 0.0 {
+      Duration::ZERO
+    } else {
+      Duration::from_secs_f64(-self.tokens / self.per_second)
+    }
+  }
+}
+
+impl MagicEdenClient {
+  pub(crate) fn from_settings(settings: &Settings) -> anyhow::Result<Self> {
+    let timeout = Duration::from_millis(settings.magiceden_timeout_ms().unwrap_or(30_000));
+    Ok(Self {
+      client: reqwest::Client::builder()
+        .connect_timeout(timeout.min(Duration::from_secs(10)))
+        .timeout(timeout)
+        .build()?,
+      api_url: Url::parse(settings.magiceden_api_url().unwrap_or(API_URL)).context("Invalid magiceden_api_url")?,
+      stats_url: Url::parse(settings.magiceden_stats_url().unwrap_or(STATS_URL)).context("Invalid magiceden_stats_url")?,
+      api_key: settings.magiceden_api_key().map(|key| key.to_string()),
+      retry: RetryPolicy {
+        max_retries: settings.magiceden_max_retries().unwrap_or(10),
+        initial_backoff: Duration::from_secs(5),
+        max_backoff: Duration::from_secs(600),
+      },
+      bucket: Arc::new(Mutex::new(TokenBucket::new(
+        settings.magiceden_requests_per_minute().unwrap_or(60),
+        Instant::now(),
+      ))),
+    })
+  }
+
+  // Shares the rate limit of this client
+  pub(crate) fn with_retry(&self, retry: RetryPolicy) -> Self {
+    Self { retry, ..self.clone() }
+  }
+
+  pub(crate) fn retry(&self) -> &RetryPolicy {
+    &self.retry
+  }
+
+  // Gets from the ord api, which needs the api key
+  pub(crate) async fn api_get(&self, path: &str, query: &[(&str, &str)], what: &str) -> Result<JsonValue, Box<dyn std::error::Error>> {
+    let api_key = self.api_key.as_deref().ok_or("No Magic Eden Api key found")?;
+    let url = Self::url(&self.api_url, path, query)?;
+    self.get(url, Some(api_key), what).await
+  }
+
+  pub(crate) async fn stats_get(&self, path: &str, query: &[(&str, &str)], what: &str) -> Result<JsonValue, Box<dyn std::error::Error>> {
+    let url = Self::url(&self.stats_url, path, query)?;
+    self.get(url, None, what).await
+  }
+
+  fn url(base: &Url, path: &str, query: &[(&str, &str)]) -> Result<Url, Box<dyn std::error::Error>> {
+    let mut url = base.join(path)?;
+    if !query.is_empty() {
+      url.query_pairs_mut().extend_pairs(query);
+    }
+    Ok(url)
+  }
+
+  async fn get(&self, url: Url, api_key: Option<&str>, what: &str) -> Result<JsonValue, Box<dyn std::error::Error>> {
+    let mut retry = 0;
+    loop {
+      // break if ctrl-c is received
+      if SHUTTING_DOWN.load(atomic::Ordering::Relaxed) {
+        return Err("Shutting down".into());
+      }
+      let wait = self.bucket.lock().unwrap().take(Instant::now());
+      tokio::time::sleep(wait).await;
+
+      let mut request = self.client.get(url.clone());
+      if let Some(api_key) = api_key {
+        request = request.bearer_auth(api_key);
+      }
+      let request_start_time = Instant::now();
+      let error = match request.send().await {
+        Ok(response) if response.status().is_success() => {
+          log::debug!("Got {} in {:.2} seconds", what, request_start_time.elapsed().as_secs_f64());
+          return Ok(response.json().await?);
+        },
+        Ok(response) => {
+          let status = response.status();
+          let body = response.text().await.unwrap_or_default();
+          let error = format!("{} {}", status, body).trim_end().to_string();
+          // Other client errors (bad key, unknown collection) won't go away by retrying
+          if status.is_client_error() && status != HttpStatusCode::TOO_MANY_REQUESTS {
+            return Err(format!("Failed to get {}: {}", what, error).into());
+          }
+          error
+        },
+        Err(err) => err.to_string(),
+      };
+
+      if retry >= self.retry.max_retries {
+        return Err(format!("Failed to get {} after {} attempts: {}", what, retry + 1, error).into());
+      }
+      let backoff = self.retry.backoff(retry);
+      log::info!("Error getting {}: {}, retrying in {:?}", what, error, backoff);
+      tokio::time::sleep(backoff).await;
+      retry += 1;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use {super::*, axum::routing::get as stub_get, std::sync::atomic::AtomicUsize};
+
+  #[test]
+  fn backoff_doubles_up_to_the_cap() {
+    let retry = RetryPolicy {
+      max_retries: 10,
+      initial_backoff: Duration::from_secs(5),
+      max_backoff: Duration::from_secs(600),
+    };
+    let backoffs: Vec<u64> = (0..9).map(|retry_count| retry.backoff(retry_count).as_secs()).collect();
+    assert_eq!(backoffs, vec![5, 10, 20, 40, 80, 160, 320, 600, 600]);
+    assert_eq!(retry.backoff(u32::MAX), Duration::from_secs(600));
+  }
+
+  #[test]
+  fn token_bucket_spreads_out_requests() {
+    let start = Instant::now();
+    let mut bucket = TokenBucket::new(120, start);
+    assert_eq!(bucket.take(start), Duration::ZERO);
+    assert_eq!(bucket.take(start), Duration::ZERO);
+    assert_eq!(bucket.take(start), Duration::from_millis(500));
+    assert_eq!(bucket.take(start), Duration::from_secs(1));
+    // Refilling never saves up more than the burst
+    let later = start + Duration::from_secs(60);
+    assert_eq!(bucket.take(later), Duration::ZERO);
+    assert_eq!(bucket.take(later), Duration::ZERO);
+    assert_eq!(bucket.take(later), Duration::from_millis(500));
+  }
+
+  #[tokio::test]
+  async fn requests_are_retried_against_a_stub_server() {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let app = Router::new()
+      .route(
+        "/v2/ord/btc/tokens",
+        stub_get(|State(requests): State<Arc<AtomicUsize>>, headers: HeaderMap, Query(query): Query<HashMap<String, String>>| async move {
+          if headers.get("authorization").and_then(|value| value.to_str().ok()) != Some("Bearer key") {
+            return StatusCode::FORBIDDEN.into_response();
+          }
+          if requests.fetch_add(1, atomic::Ordering::Relaxed) == 0 {
+            return StatusCode::TOO_MANY_REQUESTS.into_response();
+          }
+          Json(serde_json::json!({ "symbol": query.get("collectionSymbol") })).into_response()
+        }),
+      )
+      .route("/collection_stats/search/bitcoin", stub_get(|| async { StatusCode::INTERNAL_SERVER_ERROR }))
+      .route("/v2/ord/btc/activities", stub_get(|State(requests): State<Arc<AtomicUsize>>| async move {
+        requests.fetch_add(1, atomic::Ordering::Relaxed);
+        StatusCode::NOT_FOUND
+      }))
+      .with_state(requests.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let settings = Settings::from_env(
+      [
+        ("MAGICEDEN_API_KEY", "key".to_string()),
+        ("MAGICEDEN_API_URL", format!("http://{address}")),
+        ("MAGICEDEN_STATS_URL", format!("http://{address}")),
+        ("MAGICEDEN_REQUESTS_PER_MINUTE", "6000".to_string()),
+      ]
+      .into_iter()
+      .map(|(key, value)| (key.to_string(), value))
+      .collect(),
+    )
+    .unwrap();
+    let client = MagicEdenClient::from_settings(&settings).unwrap().with_retry(RetryPolicy {
+      max_retries: 2,
+      initial_backoff: Duration::from_millis(1),
+      max_backoff: Duration::from_millis(10),
+    });
+
+    let data = client.api_get("v2/ord/btc/tokens", &[("collectionSymbol", "pixels")], "tokens").await.unwrap();
+    assert_eq!(data, serde_json::json!({ "symbol": "pixels" }));
+    assert_eq!(requests.load(atomic::Ordering::Relaxed), 2);
+
+    let err = client.stats_get("collection_stats/search/bitcoin", &[], "traded collections").await.unwrap_err();
+    assert!(err.to_string().starts_with("Failed to get traded collections after 3 attempts: 500"), "{err}");
+
+    requests.store(0, atomic::Ordering::Relaxed);
+    let err = client.api_get("v2/ord/btc/activities", &[], "activities").await.unwrap_err();
+    assert!(err.to_string().starts_with("Failed to get activities: 404"), "{err}");
+    assert_eq!(requests.load(atomic::Ordering::Relaxed), 1, "client errors other than 429 aren't retried");
+  }
+}
@@ -0,0 +1,289 @@
+use super::*;
+use axum::{
+  extract::{MatchedPath, Request},
+  middleware::Next,
+  routing::get as axum_get,
+};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::atomic::AtomicU64;
+
+// Shared by every thread of the process, so the API server can report on the indexers running next to it
+pub(crate) static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::default);
+
+const BUCKETS: [f64; 14] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 300.0];
+
+#[derive(Default)]
+struct Histogram {
+  buckets: [u64; BUCKETS.len()],
+  count: u64,
+  sum: f64,
+}
+
+impl Histogram {
+  fn observe(&mut self, duration: Duration) {
+    let seconds = duration.as_secs_f64();
+    for (bucket, le) in self.buckets.iter_mut().zip(BUCKETS) {
+      if seconds <= le {
+        *bucket += 1;
+      }
+    }
+    self.count += 1;
+    self.sum += seconds;
+  }
+
+  fn render(&self, out: &mut String, name: &str, labels: &str) {
+    let separator = if labels.is_empty() { "" } else { "," };
+    for (count, le) in self.buckets.iter().zip(BUCKETS) {
+      let _ = writeln!(out, "{name}_bucket{{{labels}{separator}le=\"{le}\"}} {count}");
+    }
+    let _ = writeln!(out, "{name}_bucket{{{labels}{separator}le=\"+Inf\"}} {}", self.count);
+    let _ = writeln!(out, "{name}_sum{{{labels}}} {}", self.sum);
+    let _ = writeln!(out, "{name}_count{{{labels}}} {}", self.count);
+  }
+}
+
+fn escape(value: &str) -> String {
+  value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[derive(Default)]
+pub(crate) struct Metrics {
+  index_height: Mutex<Option<u32>>,
+  blocks_indexed: AtomicU64,
+  indexer_stages: Mutex<BTreeMap<&'static str, Histogram>>,
+  trigger_steps: Mutex<BTreeMap<(String, String), Histogram>>,
+  // Unix time in seconds, 0 until the first successful run
+  collection_indexer_last_success: AtomicU64,
+  api_requests: Mutex<BTreeMap<(String, String, u16), Histogram>>,
+  pools: Mutex<Vec<(&'static str, deadpool)>>,
+}
+
+impl Metrics {
+  pub(crate) fn set_index_height(&self, height: u32) {
+    *self.index_height.lock().unwrap() = Some(height);
+  }
+
+  // None when the redb index isn't open in this process, e.g. with --run-api-server-only
+  pub(crate) fn index_height(&self) -> Option<u32> {
+    *self.index_height.lock().unwrap()
+  }
+
+  pub(crate) fn block_indexed(&self, stages: &[(&'static str, Duration)]) {
+    self.blocks_indexed.fetch_add(1, atomic::Ordering::Relaxed);
+    let mut histograms = self.indexer_stages.lock().unwrap();
+    for (stage, duration) in stages {
+      histograms.entry(stage).or_default().observe(*duration);
+    }
+  }
+
+  pub(crate) fn observe_trigger_step(&self, trigger: &str, step: &str, duration: Duration) {
+    self.trigger_steps.lock().unwrap()
+      .entry((trigger.to_string(), step.to_string()))
+      .or_default()
+      .observe(duration);
+  }
+
+  pub(crate) fn collection_indexer_succeeded(&self) {
+    let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+    self.collection_indexer_last_success.store(now.as_secs(), atomic::Ordering::Relaxed);
+  }
+
+  fn observe_request(&self, method: &str, route: &str, status: u16, duration: Duration) {
+    self.api_requests.lock().unwrap()
+      .entry((method.to_string(), route.to_string(), status))
+      .or_default()
+      .observe(duration);
+  }
+
+  pub(crate) fn register_pool(&self, name: &'static str, pool: deadpool) {
+    self.pools.lock().unwrap().push((name, pool));
+  }
+
+  // Prometheus text exposition format, version 0.0.4
+  fn render(&self, postgres_height: Option<i64>) -> String {
+    let mut out = String::new();
+    let index_height = self.index_height();
+
+    out.push_str("# HELP vermilion_postgres_up Whether the postgres height could be read.\n# TYPE vermilion_postgres_up gauge\n");
+    let _ = writeln!(out, "vermilion_postgres_up {}", u8::from(postgres_height.is_some()));
+    if let Some(height) = index_height {
+      out.push_str("# HELP vermilion_index_height Height of the redb index.\n# TYPE vermilion_index_height gauge\n");
+      let _ = writeln!(out, "vermilion_index_height {height}");
+    }
+    if let Some(height) = postgres_height {
+      out.push_str("# HELP vermilion_postgres_height Highest block committed to postgres.\n# TYPE vermilion_postgres_height gauge\n");
+      let _ = writeln!(out, "vermilion_postgres_height {height}");
+    }
+    if let (Some(index_height), Some(postgres_height)) = (index_height, postgres_height) {
+      out.push_str("# HELP vermilion_lag_blocks Blocks the postgres indexer is behind the redb index.\n# TYPE vermilion_lag_blocks gauge\n");
+      let _ = writeln!(out, "vermilion_lag_blocks {}", i64::from(index_height) - postgres_height);
+    }
+
+    out.push_str("# HELP vermilion_blocks_indexed_total Blocks committed to postgres by this process.\n# TYPE vermilion_blocks_indexed_total counter\n");
+    let _ = writeln!(out, "vermilion_blocks_indexed_total {}", self.blocks_indexed.load(atomic::Ordering::Relaxed));
+
+    out.push_str("# HELP vermilion_indexer_stage_duration_seconds Time spent in each stage of indexing a block.\n# TYPE vermilion_indexer_stage_duration_seconds histogram\n");
+    for (stage, histogram) in self.indexer_stages.lock().unwrap().iter() {
+      histogram.render(&mut out, "vermilion_indexer_stage_duration_seconds", &format!("stage=\"{stage}\""));
+    }
+
+    out.push_str("# HELP vermilion_trigger_step_duration_seconds Time spent in each step of the postgres triggers, from trigger_timing_log.\n# TYPE vermilion_trigger_step_duration_seconds histogram\n");
+    for ((trigger, step), histogram) in self.trigger_steps.lock().unwrap().iter() {
+      histogram.render(&mut out, "vermilion_trigger_step_duration_seconds", &format!("trigger=\"{}\",step=\"{}\"", escape(trigger), escape(step)));
+    }
+
+    let pools = self.pools.lock().unwrap();
+    out.push_str("# HELP vermilion_db_pool_connections Connections held by each postgres pool.\n# TYPE vermilion_db_pool_connections gauge\n");
+    for (name, pool) in pools.iter() {
+      let status = pool.status();
+      let _ = writeln!(out, "vermilion_db_pool_connections{{pool=\"{name}\",state=\"in_use\"}} {}", status.size.saturating_sub(status.available));
+      let _ = writeln!(out, "vermilion_db_pool_connections{{pool=\"{name}\",state=\"idle\"}} {}", status.available);
+    }
+    out.push_str("# HELP vermilion_db_pool_max_connections Maximum size of each postgres pool.\n# TYPE vermilion_db_pool_max_connections gauge\n");
+    for (name, pool) in pools.iter() {
+      let _ = writeln!(out, "vermilion_db_pool_max_connections{{pool=\"{name}\"}} {}", pool.status().max_size);
+    }
+    out.push_str("# HELP vermilion_db_pool_waiting Tasks waiting for a connection from each postgres pool.\n# TYPE vermilion_db_pool_waiting gauge\n");
+    for (name, pool) in pools.iter() {
+      let _ = writeln!(out, "vermilion_db_pool_waiting{{pool=\"{name}\"}} {}", pool.status().waiting);
+    }
+    drop(pools);
+
+    let last_success = self.collection_indexer_last_success.load(atomic::Ordering::Relaxed);
+    if last_success > 0 {
+      out.push_str("# HELP vermilion_collection_indexer_last_success_timestamp_seconds Unix time of the last successful collection indexer run.\n# TYPE vermilion_collection_indexer_last_success_timestamp_seconds gauge\n");
+      let _ = writeln!(out, "vermilion_collection_indexer_last_success_timestamp_seconds {last_success}");
+    }
+
+    out.push_str("# HELP vermilion_api_request_duration_seconds API latency by route.\n# TYPE vermilion_api_request_duration_seconds histogram\n");
+    for ((method, route, status), histogram) in self.api_requests.lock().unwrap().iter() {
+      histogram.render(&mut out, "vermilion_api_request_duration_seconds", &format!("method=\"{method}\",route=\"{}\",status=\"{status}\"", escape(route)));
+    }
+    out
+  }
+}
+
+// Records latency by route template rather than path, so /inscription/{inscription_id} is one series
+pub(crate) async fn track_request(request: Request, next: Next) -> Response<Body> {
+  let start = Instant::now();
+  let method = request.method().to_string();
+  let route = request.extensions().get::<MatchedPath>().map(|path| path.as_str().to_string());
+  let response = next.run(request).await;
+  if let Some(route) = route {
+    METRICS.observe_request(&method, &route, response.status().as_u16(), start.elapsed());
+  }
+  response
+}
+
+//API
+pub fn metrics_router() -> Router<ApiServerConfig> {
+  Router::new()
+    .route("/metrics", axum_get(metrics_handler))
+    .route("/healthz", axum_get(healthz_handler))
+    .route("/readyz", axum_get(readyz_handler))
+}
+
+async fn metrics_handler(State(server_config): State<ApiServerConfig>) -> impl axum::response::IntoResponse {
+  let postgres_height = Vermilion::get_postgres_height(&server_config.deadpool).await.ok();
+  (
+    [(http::header::CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")],
+    METRICS.render(postgres_height),
+  )
+}
+
+// Liveness only, the process is up and not shutting down
+async fn healthz_handler() -> impl axum::response::IntoResponse {
+  if SHUTTING_DOWN.load(atomic::Ordering::Relaxed) {
+    (StatusCode::SERVICE_UNAVAILABLE, "shutting down")
+  } else {
+    (StatusCode::OK, "ok")
+  }
+}
+
+#[derive(Serialize)]
+struct Readiness {
+  ready: bool,
+  index_height: Option<i64>,
+  postgres_height: Option<i64>,
+  error: Option<String>,
+}
+
+// Ready once postgres is within reach of the chain tip. The tip is the redb index height when the indexer
+// runs in this process, otherwise bitcoind's block count.
+async fn readyz_handler(State(server_config): State<ApiServerConfig>) -> impl axum::response::IntoResponse {
+  let index_height = match METRICS.index_height() {
+    Some(height) => Ok(i64::from(height)),
+    // The rpc client blocks, so it's kept off the runtime's workers
+    None => {
+      let client = server_config.bitcoin_rpc_client.clone();
+      tokio::task::spawn_blocking(move || client.get_block_count())
+        .await
+        .map_err(|err| format!("Error getting block count: {err}"))
+        .and_then(|count| count.map_err(|err| format!("Error getting block count: {err}")))
+        .and_then(|count| i64::try_from(count).map_err(|err| err.to_string()))
+    },
+  };
+  let postgres_height = Vermilion::get_postgres_height(&server_config.deadpool).await
+    .map_err(|err| format!("Error getting postgres height: {err}"));
+  let readiness = match (index_height, postgres_height) {
+    (Ok(index_height), Ok(postgres_height)) => Readiness {
+      ready: Vermilion::is_at_chain_tip(index_height, postgres_height),
+      index_height: Some(index_height),
+      postgres_height: Some(postgres_height),
+      error: None,
+    },
+    (index_height, postgres_height) => Readiness {
+      ready: false,
+      index_height: index_height.as_ref().ok().copied(),
+      postgres_height: postgres_height.as_ref().ok().copied(),
+      error: index_height.err().or(postgres_height.err()),
+    },
+  };
+  let status = if readiness.ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+  (status, Json(readiness))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn histogram_buckets_are_cumulative() {
+    let mut histogram = Histogram::default();
+    histogram.observe(Duration::from_millis(500));
+    histogram.observe(Duration::from_secs(2));
+    histogram.observe(Duration::from_secs(1000));
+    let mut out = String::new();
+    histogram.render(&mut out, "test_seconds", "stage=\"commit\"");
+    assert!(out.contains("test_seconds_bucket{stage=\"commit\",le=\"0.25\"} 0\n"));
+    assert!(out.contains("test_seconds_bucket{stage=\"commit\",le=\"0.5\"} 1\n"));
+    assert!(out.contains("test_seconds_bucket{stage=\"commit\",le=\"2.5\"} 2\n"));
+    assert!(out.contains("test_seconds_bucket{stage=\"commit\",le=\"300\"} 2\n"));
+    assert!(out.contains("test_seconds_bucket{stage=\"commit\",le=\"+Inf\"} 3\n"));
+    assert!(out.contains("test_seconds_sum{stage=\"commit\"} 1002.5\n"));
+    assert!(out.contains("test_seconds_count{stage=\"commit\"} 3\n"));
+  }
+
+  #[test]
+  fn render_includes_heights_lag_and_escaped_labels() {
+    let metrics = Metrics::default();
+    metrics.set_index_height(850_010);
+    metrics.block_indexed(&[("commit", Duration::from_millis(3))]);
+    metrics.observe_trigger_step("before_metadata_insert", "say \"hi\"", Duration::from_millis(1));
+    metrics.observe_request("GET", "/inscription/{inscription_id}", 200, Duration::from_millis(4));
+    let out = metrics.render(Some(850_000));
+    assert!(out.contains("vermilion_postgres_up 1\n"));
+    assert!(out.contains("vermilion_index_height 850010\n"));
+    assert!(out.contains("vermilion_postgres_height 850000\n"));
+    assert!(out.contains("vermilion_lag_blocks 10\n"));
+    assert!(out.contains("vermilion_blocks_indexed_total 1\n"));
+    assert!(out.contains("vermilion_indexer_stage_duration_seconds_count{stage=\"commit\"} 1\n"));
+    assert!(out.contains("trigger=\"before_metadata_insert\",step=\"say \\\"hi\\\"\""));
+    assert!(out.contains("vermilion_api_request_duration_seconds_count{method=\"GET\",route=\"/inscription/{inscription_id}\",status=\"200\"} 1\n"));
+    assert!(!out.contains("vermilion_collection_indexer_last_success_timestamp_seconds "));
+    let out = Metrics::default().render(None);
+    assert!(out.contains("vermilion_postgres_up 0\n"));
+    assert!(!out.contains("vermilion_lag_blocks "));
+  }
+}
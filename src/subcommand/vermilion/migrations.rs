@@ -0,0 +1,141 @@
+use super::*;
+use std::collections::BTreeSet;
+
+// Arbitrary key for pg_advisory_xact_lock, so the indexer, api server and `ord migrate` don't race each other
+const MIGRATION_LOCK: i64 = 0x7665726d696c;
+
+pub(crate) struct Migration {
+  pub(crate) version: u32,
+  pub(crate) name: &'static str,
+  sql: &'static str,
+}
+
+// Append only, in version order. Migrations must be idempotent and must tolerate missing tables,
+// since they run before initialize_db_tables creates the latest schema on a fresh database.
+pub(crate) const MIGRATIONS: &[Migration] = &[
+  Migration {
+    version: 1,
+    name: "inscribed_by_address",
+    sql: include_str!("migrations/0001_inscribed_by_address.sql"),
+  },
+  Migration {
+    version: 2,
+    name: "content_store",
+    sql: include_str!("migrations/0002_content_store.sql"),
+  },
+];
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct MigrationStatus {
+  pub version: u32,
+  pub name: String,
+  pub applied_at: Option<String>,
+}
+
+async fn create_schema_migrations_table(pool: deadpool) -> anyhow::Result<()> {
+  let conn = pool.get().await?;
+  conn.simple_query(
+    r"CREATE TABLE IF NOT EXISTS schema_migrations (
+      version bigint not null primary key,
+      name varchar(100) not null,
+      applied_at timestamptz not null default now()
+    )").await?;
+  Ok(())
+}
+
+pub(crate) async fn status(pool: deadpool) -> anyhow::Result<Vec<MigrationStatus>> {
+  create_schema_migrations_table(pool.clone()).await?;
+  let conn = pool.get().await?;
+  let rows = conn.query("SELECT version, to_char(applied_at, 'YYYY-MM-DD HH24:MI:SS TZ') FROM schema_migrations", &[]).await?;
+  let applied: HashMap<u32, String> = rows
+    .iter()
+    .map(|row| Ok((u32::try_from(row.get::<_, i64>(0))?, row.get(1))))
+    .collect::<anyhow::Result<_>>()?;
+  Ok(
+    MIGRATIONS
+      .iter()
+      .map(|migration| MigrationStatus {
+        version: migration.version,
+        name: migration.name.to_string(),
+        applied_at: applied.get(&migration.version).cloned(),
+      })
+      .collect()
+  )
+}
+
+// Applies pending migrations up to and including `target` (all of them if None), one transaction each.
+// Returns the versions that were applied.
+pub(crate) async fn migrate(pool: deadpool, target: Option<u32>) -> anyhow::Result<Vec<u32>> {
+  create_schema_migrations_table(pool.clone()).await?;
+  let mut conn = pool.get().await?;
+  let mut applied = Vec::new();
+  loop {
+    let tx = conn.transaction().await?;
+    tx.execute("SELECT pg_advisory_xact_lock($1)", &[&MIGRATION_LOCK]).await?;
+    // Re-read under the lock, another process may have applied migrations in the meantime
+    let done: BTreeSet<u32> = tx
+      .query("SELECT version FROM schema_migrations", &[])
+      .await?
+      .iter()
+      .map(|row| u32::try_from(row.get::<_, i64>(0)))
+      .collect::<Result<_, _>>()?;
+    let Some(migration) = pending(&done, target)?.into_iter().next() else {
+      tx.commit().await?;
+      break;
+    };
+    log::info!("Applying schema migration {} ({})", migration.version, migration.name);
+    tx.batch_execute(migration.sql).await
+      .with_context(|| format!("migration {} ({}) failed", migration.version, migration.name))?;
+    tx.execute(
+      "INSERT INTO schema_migrations (version, name) VALUES ($1, $2)",
+      &[&i64::from(migration.version), &migration.name]
+    ).await?;
+    tx.commit().await?;
+    applied.push(migration.version);
+  }
+  Ok(applied)
+}
+
+fn pending(done: &BTreeSet<u32>, target: Option<u32>) -> anyhow::Result<Vec<&'static Migration>> {
+  let latest = MIGRATIONS.last().map(|migration| migration.version).unwrap_or_default();
+  let target = target.unwrap_or(latest);
+  if target > latest {
+    bail!("unknown migration {target}, latest is {latest}");
+  }
+  if let Some(applied) = done.iter().rev().find(|version| **version > target) {
+    bail!("database is already at migration {applied}, down migrations are not supported");
+  }
+  Ok(
+    MIGRATIONS
+      .iter()
+      .filter(|migration| migration.version <= target && !done.contains(&migration.version))
+      .collect()
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn versions(migrations: Vec<&Migration>) -> Vec<u32> {
+    migrations.iter().map(|migration| migration.version).collect()
+  }
+
+  #[test]
+  fn migrations_are_ordered_and_contiguous() {
+    for (i, migration) in MIGRATIONS.iter().enumerate() {
+      assert_eq!(migration.version, u32::try_from(i).unwrap() + 1);
+      assert!(!migration.sql.trim().is_empty());
+    }
+  }
+
+  #[test]
+  fn pending_migrations() {
+    assert_eq!(versions(pending(&BTreeSet::new(), None).unwrap()), vec![1, 2]);
+    assert_eq!(versions(pending(&BTreeSet::new(), Some(1)).unwrap()), vec![1]);
+    assert_eq!(versions(pending(&[1].into(), None).unwrap()), vec![2]);
+    assert!(pending(&[1, 2].into(), None).unwrap().is_empty());
+    assert!(pending(&[1, 2].into(), Some(1)).is_err());
+    assert!(pending(&BTreeSet::new(), Some(3)).is_err());
+  }
+}
@@ -0,0 +1,3 @@
+-- Databases indexed before inscribed_by_address was tracked
+ALTER TABLE IF EXISTS ordinals ADD COLUMN IF NOT EXISTS inscribed_by_address varchar(80);
+ALTER TABLE IF EXISTS ordinals_full_t ADD COLUMN IF NOT EXISTS inscribed_by_address varchar(80);
@@ -0,0 +1,2 @@
+-- Records which content store holds each inscription's bytes, NULL means inline in postgres
+ALTER TABLE IF EXISTS content ADD COLUMN IF NOT EXISTS store varchar(20);
@@ -0,0 +1,292 @@
+use super::*;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use tokio_postgres::{types::ToSql, Row};
+
+// Keyset pagination. A cursor records the sort value and the unique tie breaker of the last row served, and the
+// next page starts strictly after that row. Unlike OFFSET this doesn't slow down with depth, and rows inserted
+// while a client is paging don't shift later pages.
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum CursorKey {
+  Number(i64),
+  Text(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Cursor {
+  sort: String,
+  value: Option<i64>,
+  key: CursorKey,
+}
+
+impl Cursor {
+  pub(crate) fn encode(&self) -> String {
+    URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap())
+  }
+
+  pub(crate) fn decode(cursor: &str) -> Option<Self> {
+    serde_json::from_slice(&URL_SAFE_NO_PAD.decode(cursor).ok()?).ok()
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum KeyType {
+  Int4,
+  Int8,
+  Text,
+}
+
+// The order of a list: a nullable bigint sort column, then a unique column breaking ties in the same direction, or
+// a unique column alone
+#[derive(Debug, Clone)]
+pub(crate) struct Keyset {
+  sort: String,
+  column: &'static str,
+  descending: bool,
+  nulls_last: bool,
+  key: &'static str,
+  key_type: KeyType,
+}
+
+// Values for the $n placeholders of a query that is put together as a string, so nothing a client sent is spliced
+// into the SQL. Placeholders are numbered in the order values are pushed.
+#[derive(Default)]
+pub(crate) struct SqlParams(Vec<Box<dyn ToSql + Sync + Send>>);
+
+impl SqlParams {
+  pub(crate) fn push(&mut self, value: impl ToSql + Sync + Send + 'static) -> String {
+    self.0.push(Box::new(value));
+    format!("${}", self.0.len())
+  }
+
+  pub(crate) fn values(&self) -> Vec<&(dyn ToSql + Sync)> {
+    self.0.iter().map(|value| value.as_ref() as &(dyn ToSql + Sync)).collect()
+  }
+}
+
+// Selected columns are read back from rows by name, without the table alias
+fn column_name(column: &str) -> &str {
+  column.rsplit('.').next().unwrap_or(column)
+}
+
+impl Keyset {
+  // Nulls sort where postgres puts them by default: first when descending, last when ascending
+  pub(crate) fn new(list: &str, sort: impl std::fmt::Display, column: &'static str, descending: bool) -> Self {
+    Self {
+      sort: format!("{list}/{sort}"),
+      column,
+      descending,
+      nulls_last: !descending,
+      key: column,
+      key_type: KeyType::Int8,
+    }
+  }
+
+  pub(crate) fn nulls_last(mut self) -> Self {
+    self.nulls_last = true;
+    self
+  }
+
+  pub(crate) fn tie_break(mut self, key: &'static str, key_type: KeyType) -> Self {
+    self.key = key;
+    self.key_type = key_type;
+    self
+  }
+
+  pub(crate) fn order_by(&self) -> String {
+    let direction = if self.descending { "DESC" } else { "ASC" };
+    if self.column == self.key {
+      return format!(" ORDER BY {} {}", self.column, direction);
+    }
+    let nulls = if self.nulls_last { "NULLS LAST" } else { "NULLS FIRST" };
+    format!(" ORDER BY {} {} {}, {} {}", self.column, direction, nulls, self.key, direction)
+  }
+
+  // A condition matching the rows that sort after the cursor
+  pub(crate) fn after(&self, cursor: &Cursor, params: &mut SqlParams) -> String {
+    let op = if self.descending { "<" } else { ">" };
+    let key = match &cursor.key {
+      CursorKey::Number(number) => number.to_string(),
+      CursorKey::Text(text) => params.push(text.clone()),
+    };
+    if self.column == self.key {
+      return format!("{} {} {}", self.key, op, key);
+    }
+    let (column, after_key) = (self.column, format!("{} {} {}", self.key, op, key));
+    match (cursor.value, self.nulls_last) {
+      (Some(value), false) => format!("({column} {op} {value} OR ({column} = {value} AND {after_key}))"),
+      (Some(value), true) => format!("({column} {op} {value} OR ({column} = {value} AND {after_key}) OR {column} IS NULL)"),
+      (None, false) => format!("({column} IS NOT NULL OR ({column} IS NULL AND {after_key}))"),
+      (None, true) => format!("({column} IS NULL AND {after_key})"),
+    }
+  }
+
+  fn accepts(&self, cursor: &Cursor) -> bool {
+    cursor.sort == self.sort
+      && match cursor.key {
+        CursorKey::Number(_) => self.key_type != KeyType::Text,
+        CursorKey::Text(_) => self.key_type == KeyType::Text,
+      }
+  }
+
+  fn cursor(&self, row: &Row) -> Option<Cursor> {
+    let key = column_name(self.key);
+    let key = match self.key_type {
+      KeyType::Int4 => CursorKey::Number(row.try_get::<_, Option<i32>>(key).ok()??.into()),
+      KeyType::Int8 => CursorKey::Number(row.try_get::<_, Option<i64>>(key).ok()??),
+      KeyType::Text => CursorKey::Text(row.try_get::<_, Option<String>>(key).ok()??),
+    };
+    // A list ordered by its key alone has no sort value, and its key may not be a bigint
+    let value = if self.column == self.key { None } else { row.try_get(column_name(self.column)).ok()? };
+    Some(Cursor {
+      sort: self.sort.clone(),
+      value,
+      key,
+    })
+  }
+}
+
+// Query strings drop empty values of optional params, so an empty cursor has to be read as a plain string
+pub(crate) fn deserialize_cursor<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+  String::deserialize(deserializer).map(Some)
+}
+
+// page_number paging is kept for existing clients, a cursor param (empty for the first page) switches to keyset paging
+#[derive(Debug)]
+pub(crate) enum PageRequest {
+  Offset { page_number: usize, page_size: usize },
+  Cursor { after: Option<Cursor>, page_size: usize },
+}
+
+impl PageRequest {
+  pub(crate) fn new(page_number: Option<usize>, page_size: usize, cursor: Option<&str>, keyset: &Keyset) -> Result<Self, ApiError> {
+    let Some(cursor) = cursor else {
+      return Ok(PageRequest::Offset { page_number: page_number.unwrap_or(0), page_size });
+    };
+    let page_size = page_size.max(1);
+    if cursor.is_empty() {
+      return Ok(PageRequest::Cursor { after: None, page_size });
+    }
+    match Cursor::decode(cursor) {
+      Some(cursor) if keyset.accepts(&cursor) => Ok(PageRequest::Cursor { after: Some(cursor), page_size }),
+      Some(_) => Err(ApiError::BadRequest("Cursor is for a different list or sort order".to_string())),
+      None => Err(ApiError::BadRequest("Invalid cursor".to_string())),
+    }
+  }
+
+  pub(crate) fn filter(&self, keyset: &Keyset, params: &mut SqlParams) -> Option<String> {
+    match self {
+      PageRequest::Cursor { after: Some(cursor), .. } => Some(keyset.after(cursor, params)),
+      _ => None,
+    }
+  }
+
+  // One extra row is fetched in cursor mode to tell whether there is a next page
+  pub(crate) fn limit(&self) -> String {
+    match self {
+      PageRequest::Offset { page_number, page_size } => {
+        let mut limit = String::new();
+        if *page_size > 0 {
+          limit.push_str(&format!(" LIMIT {}", page_size));
+        }
+        if *page_number > 0 {
+          limit.push_str(&format!(" OFFSET {}", page_number * page_size));
+        }
+        limit
+      }
+      PageRequest::Cursor { page_size, .. } => format!(" LIMIT {}", page_size + 1),
+    }
+  }
+
+  pub(crate) fn paginate<T>(&self, keyset: &Keyset, mut rows: Vec<Row>, map: impl FnMut(Row) -> T) -> Paginated<T> {
+    match self {
+      PageRequest::Offset { .. } => Paginated::Items(rows.into_iter().map(map).collect()),
+      PageRequest::Cursor { page_size, .. } => {
+        let next_cursor = if rows.len() > *page_size {
+          rows.truncate(*page_size);
+          rows.last().and_then(|row| keyset.cursor(row)).map(|cursor| cursor.encode())
+        } else {
+          None
+        };
+        Paginated::Page {
+          items: rows.into_iter().map(map).collect(),
+          next_cursor,
+        }
+      }
+    }
+  }
+}
+
+/// A page of results: a plain array when paging by page_number, or an object with the items and the cursor
+/// for the next page when paging by cursor
+#[derive(Serialize, JsonSchema)]
+#[serde(untagged)]
+pub enum Paginated<T> {
+  Items(Vec<T>),
+  Page {
+    items: Vec<T>,
+    /// Pass as cursor to get the next page, null on the last page
+    next_cursor: Option<String>,
+  },
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn cursor(keyset: &Keyset, value: Option<i64>, key: CursorKey) -> Cursor {
+    Cursor { sort: keyset.sort.clone(), value, key }
+  }
+
+  #[test]
+  fn cursors_round_trip() {
+    let keyset = Keyset::new("collections", "most_volume", "s.total_volume", true).nulls_last().tie_break("l.collection_symbol", KeyType::Text);
+    let cursor = cursor(&keyset, None, CursorKey::Text("it's".into()));
+    assert_eq!(Cursor::decode(&cursor.encode()), Some(cursor.clone()));
+    assert!(keyset.accepts(&cursor));
+    assert!(!Keyset::new("galleries", "most_volume", "s.total_volume", true).tie_break("s.gallery_id", KeyType::Text).accepts(&cursor));
+    assert!(!Keyset::new("collections", "most_volume", "s.total_volume", true).accepts(&cursor));
+    assert_eq!(Cursor::decode("not a cursor"), None);
+  }
+
+  #[test]
+  fn page_requests() {
+    let keyset = Keyset::new("inscriptions", "newest", "o.sequence_number", true);
+    assert!(matches!(PageRequest::new(Some(2), 10, None, &keyset), Ok(PageRequest::Offset { page_number: 2, page_size: 10 })));
+    assert!(matches!(PageRequest::new(Some(2), 0, Some(""), &keyset), Ok(PageRequest::Cursor { after: None, page_size: 1 })));
+    let other = Keyset::new("inscriptions", "oldest", "o.sequence_number", false);
+    assert!(PageRequest::new(None, 10, Some(&cursor(&other, Some(1), CursorKey::Number(1)).encode()), &keyset).is_err());
+    assert!(PageRequest::new(None, 10, Some("!"), &keyset).is_err());
+    assert_eq!(PageRequest::Offset { page_number: 2, page_size: 10 }.limit(), " LIMIT 10 OFFSET 20");
+    assert_eq!(PageRequest::Cursor { after: None, page_size: 10 }.limit(), " LIMIT 11");
+  }
+
+  #[test]
+  fn keyset_conditions() {
+    let newest = Keyset::new("inscriptions", "newest", "o.sequence_number", true);
+    assert_eq!(newest.order_by(), " ORDER BY o.sequence_number DESC");
+    assert_eq!(newest.after(&cursor(&newest, Some(5), CursorKey::Number(5)), &mut SqlParams::default()), "o.sequence_number < 5");
+
+    let fee = Keyset::new("inscriptions", "lowest_fee", "o.genesis_fee", false).tie_break("o.sequence_number", KeyType::Int8);
+    assert_eq!(fee.order_by(), " ORDER BY o.genesis_fee ASC NULLS LAST, o.sequence_number ASC");
+    assert_eq!(
+      fee.after(&cursor(&fee, Some(100), CursorKey::Number(7)), &mut SqlParams::default()),
+      "(o.genesis_fee > 100 OR (o.genesis_fee = 100 AND o.sequence_number > 7) OR o.genesis_fee IS NULL)"
+    );
+    assert_eq!(fee.after(&cursor(&fee, None, CursorKey::Number(7)), &mut SqlParams::default()), "(o.genesis_fee IS NULL AND o.sequence_number > 7)");
+
+    let sat = Keyset::new("inscriptions", "newest_sat", "o.sat", true).tie_break("o.sequence_number", KeyType::Int8);
+    assert_eq!(sat.order_by(), " ORDER BY o.sat DESC NULLS FIRST, o.sequence_number DESC");
+    assert_eq!(sat.after(&cursor(&sat, None, CursorKey::Number(7)), &mut SqlParams::default()), "(o.sat IS NOT NULL OR (o.sat IS NULL AND o.sequence_number < 7))");
+
+    let volume = Keyset::new("collections", "most_volume", "s.total_volume", true).nulls_last().tie_break("l.collection_symbol", KeyType::Text);
+    let mut params = SqlParams::default();
+    params.push(1);
+    assert_eq!(
+      volume.after(&cursor(&volume, Some(3), CursorKey::Text("it's'; --".into())), &mut params),
+      "(s.total_volume < 3 OR (s.total_volume = 3 AND l.collection_symbol < $2) OR s.total_volume IS NULL)"
+    );
+    assert_eq!(params.values().len(), 2);
+  }
+}
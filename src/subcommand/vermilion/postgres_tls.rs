@@ -0,0 +1,242 @@
+use super::*;
+use anyhow::Context as _;
+use rustls::{
+  client::{
+    danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    WebPkiServerVerifier,
+  },
+  crypto::{ring, verify_tls12_signature, verify_tls13_signature, WebPkiSupportedAlgorithms},
+  pki_types::{pem::PemObject, CertificateDer, InvalidDnsNameError, ServerName, UnixTime},
+  CertificateError, ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme,
+};
+use std::{
+  future::Future,
+  pin::Pin,
+  task::{Context, Poll},
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_postgres::tls::{ChannelBinding, MakeTlsConnect, TlsConnect};
+use tokio_rustls::TlsConnector;
+
+// libpq sslmode values
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum TlsMode {
+  Disable,
+  Prefer,
+  Require,
+  VerifyCa,
+  VerifyFull,
+}
+
+impl FromStr for TlsMode {
+  type Err = Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    Ok(match s {
+      "disable" => Self::Disable,
+      "prefer" => Self::Prefer,
+      "require" => Self::Require,
+      "verify-ca" => Self::VerifyCa,
+      "verify-full" => Self::VerifyFull,
+      _ => bail!("invalid sslmode `{s}`, expected disable, prefer, require, verify-ca or verify-full"),
+    })
+  }
+}
+
+impl From<tokio_postgres::config::SslMode> for TlsMode {
+  fn from(mode: tokio_postgres::config::SslMode) -> Self {
+    match mode {
+      tokio_postgres::config::SslMode::Disable => Self::Disable,
+      tokio_postgres::config::SslMode::Require => Self::Require,
+      _ => Self::Prefer,
+    }
+  }
+}
+
+impl TlsMode {
+  // tokio-postgres only knows whether to attempt TLS, verification is up to the connector
+  pub(crate) fn ssl_mode(self) -> deadpool_postgres::SslMode {
+    match self {
+      Self::Disable => deadpool_postgres::SslMode::Disable,
+      Self::Prefer => deadpool_postgres::SslMode::Prefer,
+      Self::Require | Self::VerifyCa | Self::VerifyFull => deadpool_postgres::SslMode::Require,
+    }
+  }
+}
+
+// rustls connector for tokio-postgres. As with libpq, prefer and require don't verify the server
+// certificate unless a root certificate bundle is configured, in which case they behave like verify-ca.
+#[derive(Clone)]
+pub(crate) struct PostgresTls {
+  config: Arc<ClientConfig>,
+}
+
+impl PostgresTls {
+  pub(crate) fn new(mode: TlsMode, root_cert: Option<&std::path::Path>) -> Result<Self> {
+    let provider = Arc::new(ring::default_provider());
+    let mut roots = RootCertStore::empty();
+    match root_cert {
+      Some(path) => {
+        let pem = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        for cert in CertificateDer::pem_slice_iter(&pem) {
+          roots.add(cert.with_context(|| format!("failed to parse {}", path.display()))?)?;
+        }
+        ensure!(!roots.is_empty(), "no certificates found in {}", path.display());
+      }
+      None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+    }
+    let verifier = CertVerifier {
+      webpki: WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider.clone()).build()?,
+      verify_chain: mode == TlsMode::VerifyCa || mode == TlsMode::VerifyFull || root_cert.is_some(),
+      verify_name: mode == TlsMode::VerifyFull,
+      algorithms: provider.signature_verification_algorithms,
+    };
+    let config = ClientConfig::builder_with_provider(provider)
+      .with_safe_default_protocol_versions()?
+      .dangerous()
+      .with_custom_certificate_verifier(Arc::new(verifier))
+      .with_no_client_auth();
+    Ok(Self {
+      config: Arc::new(config),
+    })
+  }
+}
+
+#[derive(Debug)]
+struct CertVerifier {
+  webpki: Arc<WebPkiServerVerifier>,
+  verify_chain: bool,
+  verify_name: bool,
+  algorithms: WebPkiSupportedAlgorithms,
+}
+
+impl ServerCertVerifier for CertVerifier {
+  fn verify_server_cert(
+    &self,
+    end_entity: &CertificateDer<'_>,
+    intermediates: &[CertificateDer<'_>],
+    server_name: &ServerName<'_>,
+    ocsp_response: &[u8],
+    now: UnixTime,
+  ) -> Result<ServerCertVerified, rustls::Error> {
+    if !self.verify_chain {
+      return Ok(ServerCertVerified::assertion());
+    }
+    // The chain is checked before the name, so a name mismatch means the chain itself is trusted
+    match self.webpki.verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now) {
+      Err(rustls::Error::InvalidCertificate(
+        CertificateError::NotValidForName | CertificateError::NotValidForNameContext { .. },
+      )) if !self.verify_name => Ok(ServerCertVerified::assertion()),
+      result => result,
+    }
+  }
+
+  fn verify_tls12_signature(
+    &self,
+    message: &[u8],
+    cert: &CertificateDer<'_>,
+    dss: &DigitallySignedStruct,
+  ) -> Result<HandshakeSignatureValid, rustls::Error> {
+    verify_tls12_signature(message, cert, dss, &self.algorithms)
+  }
+
+  fn verify_tls13_signature(
+    &self,
+    message: &[u8],
+    cert: &CertificateDer<'_>,
+    dss: &DigitallySignedStruct,
+  ) -> Result<HandshakeSignatureValid, rustls::Error> {
+    verify_tls13_signature(message, cert, dss, &self.algorithms)
+  }
+
+  fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+    self.algorithms.supported_schemes()
+  }
+}
+
+impl<S> MakeTlsConnect<S> for PostgresTls
+where
+  S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+  type Stream = TlsStream<S>;
+  type TlsConnect = RustlsConnect;
+  type Error = InvalidDnsNameError;
+
+  fn make_tls_connect(&mut self, domain: &str) -> Result<RustlsConnect, InvalidDnsNameError> {
+    Ok(RustlsConnect {
+      connector: TlsConnector::from(self.config.clone()),
+      server_name: ServerName::try_from(domain.to_string())?,
+    })
+  }
+}
+
+pub(crate) struct RustlsConnect {
+  connector: TlsConnector,
+  server_name: ServerName<'static>,
+}
+
+impl<S> TlsConnect<S> for RustlsConnect
+where
+  S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+  type Stream = TlsStream<S>;
+  type Error = io::Error;
+  type Future = Pin<Box<dyn Future<Output = io::Result<TlsStream<S>>> + Send>>;
+
+  fn connect(self, stream: S) -> Self::Future {
+    Box::pin(async move {
+      self.connector.connect(self.server_name, stream).await.map(TlsStream)
+    })
+  }
+}
+
+pub(crate) struct TlsStream<S>(tokio_rustls::client::TlsStream<S>);
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for TlsStream<S> {
+  fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+    Pin::new(&mut self.0).poll_read(cx, buf)
+  }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for TlsStream<S> {
+  fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+    Pin::new(&mut self.0).poll_write(cx, buf)
+  }
+
+  fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+    Pin::new(&mut self.0).poll_flush(cx)
+  }
+
+  fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+    Pin::new(&mut self.0).poll_shutdown(cx)
+  }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> tokio_postgres::tls::TlsStream for TlsStream<S> {
+  // SCRAM channel binding isn't offered, authentication falls back to plain SCRAM-SHA-256
+  fn channel_binding(&self) -> ChannelBinding {
+    ChannelBinding::none()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parse_tls_mode() {
+    assert_eq!("disable".parse::<TlsMode>().unwrap(), TlsMode::Disable);
+    assert_eq!("verify-full".parse::<TlsMode>().unwrap(), TlsMode::VerifyFull);
+    assert_eq!("verify-ca".parse::<TlsMode>().unwrap().ssl_mode(), deadpool_postgres::SslMode::Require);
+    assert!("allow".parse::<TlsMode>().is_err());
+  }
+
+  #[test]
+  fn root_cert_must_contain_certificates() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("ca.pem");
+    fs::write(&path, "not a certificate").unwrap();
+    assert!(PostgresTls::new(TlsMode::VerifyFull, Some(&path)).is_err());
+    assert!(PostgresTls::new(TlsMode::VerifyFull, None).is_ok());
+  }
+}
@@ -0,0 +1,248 @@
+use super::*;
+
+// Ownership history derived from transfers. Consecutive transfers to the same address, like consolidating
+// utxos, are one holding period. A period is a sale when the sales table has the transfer, or for blocks
+// indexed before sales were recorded, when the transfer has a price.
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Acquisition {
+  Mint,
+  Sale,
+  Transfer,
+  Burn,
+}
+
+#[derive(Clone, Serialize, JsonSchema)]
+pub struct HoldingPeriod {
+  /// Inscription id
+  pub(crate) id: String,
+  number: Option<i64>,
+  address: String,
+  acquired_by: Acquisition,
+  /// Previous holder, unbound for mints
+  acquired_from: String,
+  /// This inscription's share of the sale price in sats, null unless acquired_by is sale
+  price: Option<i64>,
+  acquired_block: i64,
+  acquired_timestamp: i64,
+  acquired_transaction: String,
+  /// Null while still held
+  released_block: Option<i64>,
+  released_timestamp: Option<i64>,
+  released_transaction: Option<String>,
+  /// Blocks between acquiring and releasing, null while still held
+  held_blocks: Option<i64>,
+  /// Milliseconds between acquiring and releasing, null while still held
+  held_ms: Option<i64>,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct CollectorHistoryParams {
+  /// Page number for pagination (0-based)
+  #[schemars(description = "Page number for pagination, starting from 0", example = "0", range(min = 0))]
+  page_number: Option<usize>,
+
+  /// Number of items per page (max 100)
+  #[schemars(description = "Number of items per page, maximum 100", example = "20", range(min = 1, max = 100))]
+  page_size: Option<usize>,
+
+  /// Cursor for keyset pagination
+  #[schemars(description = "Opaque cursor for keyset pagination. Pass an empty cursor for the first page, then the next_cursor of each response. Takes precedence over page_number")]
+  #[serde(default, deserialize_with = "deserialize_cursor")]
+  cursor: Option<String>,
+}
+
+pub(crate) struct ParsedCollectorHistoryParams {
+  keyset: Keyset,
+  page: PageRequest,
+}
+
+impl TryFrom<CollectorHistoryParams> for ParsedCollectorHistoryParams {
+  type Error = ApiError;
+
+  // Most recently acquired first. An address can acquire several inscriptions in a block, and the same inscription
+  // more than once, so ties are broken by the transaction's position in the block and then the inscription id.
+  fn try_from(params: CollectorHistoryParams) -> Result<Self, Self::Error> {
+    let keyset = Keyset::new("collector_history", "newest", "p.block_number", true).tie_break("p.period_key", KeyType::Text);
+    let page = PageRequest::new(params.page_number, std::cmp::min(params.page_size.unwrap_or(20), 100), params.cursor.as_deref(), &keyset)?;
+    Ok(Self { keyset, page })
+  }
+}
+
+// Holding periods starting with a transfer that matches acquisitions, a condition on the transfers t that may refer to
+// $1. A transfer starts a period when the inscription's previous transfer went to another address, and the period ends
+// with the next transfer to another address. Both are looked up by the (id, block_number) primary key for each
+// matching transfer, instead of running window functions over whole histories, so the cost follows the number of
+// transfers matching acquisitions and conditions on p, like a keyset bound, are applied before the lookups.
+fn holding_periods(acquisitions: &str) -> String {
+  format!(
+    r"SELECT p.*, o.number FROM (
+      SELECT
+        t.id, t.block_number, t.block_timestamp, t.tx_offset, t.transaction, t.address, t.previous_address, t.is_genesis,
+        coalesce(s.price, nullif(t.price, 0)) AS price,
+        released.block_number AS released_block,
+        released.block_timestamp AS released_timestamp,
+        released.transaction AS released_transaction,
+        lpad(coalesce(t.tx_offset, 0)::text, 10, '0') || ':' || t.id AS period_key
+      FROM transfers t
+      LEFT JOIN sales s ON s.id = t.id AND s.transaction = t.transaction
+      LEFT JOIN LATERAL (
+        SELECT b.address FROM transfers b
+        WHERE b.id = t.id AND (b.block_number, b.tx_offset) < (t.block_number, t.tx_offset)
+        ORDER BY b.block_number DESC, b.tx_offset DESC
+        LIMIT 1
+      ) prior ON true
+      LEFT JOIN LATERAL (
+        SELECT a.block_number, a.block_timestamp, a.transaction FROM transfers a
+        WHERE a.id = t.id AND (a.block_number, a.tx_offset) > (t.block_number, t.tx_offset) AND a.address IS DISTINCT FROM t.address
+        ORDER BY a.block_number, a.tx_offset
+        LIMIT 1
+      ) released ON true
+      WHERE ({acquisitions}) AND prior.address IS DISTINCT FROM t.address
+    ) p
+    LEFT JOIN ordinals o ON o.id = p.id"
+  )
+}
+
+fn holding_period(row: tokio_postgres::Row) -> HoldingPeriod {
+  let address: String = row.get("address");
+  let price: Option<i64> = row.get("price");
+  let is_genesis: Option<bool> = row.get("is_genesis");
+  let acquired_by = if is_genesis == Some(true) {
+    Acquisition::Mint
+  } else if address == "burned" {
+    Acquisition::Burn
+  } else if price.is_some() {
+    Acquisition::Sale
+  } else {
+    Acquisition::Transfer
+  };
+  let acquired_block: i64 = row.get("block_number");
+  let acquired_timestamp: i64 = row.get("block_timestamp");
+  let released_block: Option<i64> = row.get("released_block");
+  let released_timestamp: Option<i64> = row.get("released_timestamp");
+  HoldingPeriod {
+    id: row.get("id"),
+    number: row.get("number"),
+    address,
+    price: if acquired_by == Acquisition::Sale { price } else { None },
+    acquired_by,
+    acquired_from: row.get("previous_address"),
+    acquired_block,
+    acquired_timestamp,
+    acquired_transaction: row.get("transaction"),
+    released_block,
+    released_timestamp,
+    released_transaction: row.get("released_transaction"),
+    held_blocks: released_block.map(|block| block - acquired_block),
+    held_ms: released_timestamp.map(|timestamp| timestamp - acquired_timestamp),
+  }
+}
+
+pub(crate) async fn get_inscription_provenance(pool: deadpool, inscription_id: String) -> anyhow::Result<Vec<HoldingPeriod>> {
+  let conn = pool.get().await?;
+  let query = format!("{} ORDER BY p.block_number, p.tx_offset", holding_periods("t.id = $1"));
+  let rows = conn.query(query.as_str(), &[&inscription_id]).await?;
+  Ok(rows.into_iter().map(holding_period).collect())
+}
+
+pub(crate) async fn get_collector_history(pool: deadpool, address: String, params: ParsedCollectorHistoryParams, hidden: &HiddenParams) -> anyhow::Result<Paginated<HoldingPeriod>> {
+  let conn = pool.get().await?;
+  let mut sql_params = SqlParams::default();
+  let address = sql_params.push(address);
+  let mut acquisitions = format!("t.address = {address}");
+  if let Some(visible) = hidden.visible_ids(&["t.id"], &mut sql_params) {
+    acquisitions.push_str(&format!(" AND {visible}"));
+  }
+  let mut query = format!("{} WHERE 1=1", holding_periods(&acquisitions));
+  if let Some(after) = params.page.filter(&params.keyset, &mut sql_params) {
+    query.push_str(&format!(" AND {after}"));
+  }
+  query.push_str(&params.keyset.order_by());
+  query.push_str(&params.page.limit());
+  let rows = conn.query(query.as_str(), &sql_params.values()).await?;
+  Ok(params.page.paginate(&params.keyset, rows, holding_period))
+}
+
+#[cfg(test)]
+mod tests {
+  use {
+    super::*,
+    super::super::tests::{index_test_block, TestDatabase, test_inscription, test_transfer, TestBlock},
+  };
+
+  #[tokio::test]
+  #[ignore]
+  async fn provenance_and_collector_history() {
+    let pool = TestDatabase::new("vermilion_provenance_test").await;
+
+    let inscription = test_inscription(0, 1, &"a".repeat(64), Vec::new(), None);
+    let other = test_inscription(1, 1, &"b".repeat(64), Vec::new(), None);
+    let mut mint = test_transfer(&inscription, 1, "", "bc1qone", 0);
+    mint.previous_address = "unbound".into();
+    let blocks = [
+      (1, vec![mint, test_transfer(&other, 1, "", "bc1qtwo", 0)]),
+      // Consolidating utxos doesn't start a new holding period
+      (2, vec![test_transfer(&inscription, 2, "bc1qone", "bc1qone", 0)]),
+      (3, vec![test_transfer(&inscription, 3, "bc1qone", "bc1qtwo", 5_000)]),
+      (4, vec![test_transfer(&inscription, 4, "bc1qtwo", "bc1qthree", 0)]),
+      // Leaving and coming back twice in one block gives bc1qtwo two periods with the same block and id
+      (5, [("bc1qtwo", "bc1qfour"), ("bc1qfour", "bc1qtwo"), ("bc1qtwo", "bc1qfour"), ("bc1qfour", "bc1qtwo")]
+        .into_iter()
+        .zip(1..)
+        .map(|((from, to), tx_offset)| {
+          let mut transfer = test_transfer(&other, 5, from, to, 0);
+          transfer.tx_offset = tx_offset;
+          transfer.transaction = format!("{tx_offset:064x}");
+          transfer.satpoint = format!("{}:0:0", transfer.transaction);
+          transfer
+        })
+        .collect()),
+      (6, vec![test_transfer(&inscription, 6, "bc1qthree", "burned", 0)]),
+    ];
+    for (height, transfers) in blocks {
+      index_test_block(&pool, &TestBlock {
+        height,
+        inscriptions: if height == 1 { vec![inscription.clone(), other.clone()] } else { Vec::new() },
+        galleries: Vec::new(),
+        transfers,
+      }).await;
+    }
+
+    let provenance = get_inscription_provenance(pool.clone(), inscription.id.clone()).await.unwrap();
+    let periods: Vec<_> = provenance
+      .iter()
+      .map(|period| (period.address.as_str(), period.acquired_by.clone(), period.price, period.acquired_block, period.held_blocks))
+      .collect();
+    assert_eq!(periods, vec![
+      ("bc1qone", Acquisition::Mint, None, 1, Some(2)),
+      ("bc1qtwo", Acquisition::Sale, Some(5_000), 3, Some(1)),
+      ("bc1qthree", Acquisition::Transfer, None, 4, Some(2)),
+      ("burned", Acquisition::Burn, None, 6, None),
+    ]);
+    assert_eq!(provenance[1].acquired_from, "bc1qone");
+    assert_eq!(provenance[1].held_ms, Some(600_000));
+
+    let params = |cursor: Option<String>| {
+      ParsedCollectorHistoryParams::try_from(CollectorHistoryParams { page_number: None, page_size: Some(1), cursor })
+        .unwrap_or_else(|_| panic!("invalid collector history params"))
+    };
+    let mut cursor = Some(String::new());
+    let mut periods = Vec::new();
+    while let Some(page) = cursor.take() {
+      let Paginated::Page { items, next_cursor } = get_collector_history(pool.clone(), "bc1qtwo".into(), params(Some(page)), &HiddenParams::default()).await.unwrap() else {
+        panic!("expected a page")
+      };
+      assert_eq!(items.len(), 1);
+      periods.extend(items.into_iter().map(|period| (period.id, period.acquired_block, period.acquired_transaction, period.acquired_by)));
+      cursor = next_cursor;
+    }
+    assert_eq!(periods, vec![
+      (other.id.clone(), 5, format!("{:064x}", 4), Acquisition::Transfer),
+      (other.id.clone(), 5, format!("{:064x}", 2), Acquisition::Transfer),
+      (inscription.id.clone(), 3, format!("{:064x}", 3), Acquisition::Sale),
+      (other.id.clone(), 1, format!("{:064x}", 1), Acquisition::Mint),
+    ]);
+  }
+}
@@ -1,22 +1,23 @@
 use super::*;
-use rust_decimal::{prelude::FromPrimitive, Decimal};
+use crate::index::entry::{RuneActivityEntry, RuneActivityKind};
 
+// u128 amounts can exceed Decimal's 96 bits, so they're passed as text and cast to numeric in postgres
 pub struct RuneRow {
   block: i64,
   tx_index: i64,
-  burned: Decimal,
+  burned: String,
   divisibility: i64,
   etching: String,
-  mints: Decimal,
+  mints: String,
   number: i64,
-  premine: Decimal,
+  premine: String,
   spaced_rune: String,
   unspaced_rune: String,
   rune_u128: String,
   spacers: i64,
   symbol: Option<String>,
-  mint_amount: Option<Decimal>,
-  mint_cap: Option<Decimal>,
+  mint_amount: Option<String>,
+  mint_cap: Option<String>,
   mint_height_lower: Option<i64>,
   mint_height_upper: Option<i64>,
   mint_offset_lower: Option<i64>,
@@ -26,15 +27,122 @@ pub struct RuneRow {
   parent: Option<String>,
 }
 
-pub async fn process_runes(index: Arc<Index>, tx: &deadpool_postgres::Transaction<'_>, block_number: u32) -> anyhow::Result<()> {
+pub struct RuneBalanceRow {
+  outpoint: String,
+  rune_block: i64,
+  rune_tx_index: i64,
+  amount: String,
+  address: Option<String>,
+  block: i64,
+  tx_index: i64,
+}
+
+pub struct RuneTransferRow {
+  block: i64,
+  tx_index: i64,
+  txid: String,
+  event_type: String,
+  rune_block: i64,
+  rune_tx_index: i64,
+  amount: String,
+  outpoint: Option<String>,
+  address: Option<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct RuneSummary {
+  rune_id: String,
+  block: i64,
+  tx_index: i64,
+  number: Option<i64>,
+  spaced_rune: Option<String>,
+  unspaced_rune: Option<String>,
+  divisibility: Option<i64>,
+  symbol: Option<String>,
+  etching: Option<String>,
+  premine: Option<String>,
+  mints: Option<String>,
+  burned: Option<String>,
+  mint_amount: Option<String>,
+  mint_cap: Option<String>,
+  mint_height_lower: Option<i64>,
+  mint_height_upper: Option<i64>,
+  mint_offset_lower: Option<i64>,
+  mint_offset_upper: Option<i64>,
+  timestamp: Option<i64>,
+  turbo: Option<bool>,
+  parent: Option<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct RuneHolder {
+  spaced_rune: Option<String>,
+  rune_holder_count: Option<i64>,
+  address: Option<String>,
+  balance: Option<String>,
+  outpoint_count: Option<i64>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct RuneActivity {
+  block: i64,
+  tx_index: i64,
+  txid: String,
+  event_type: String,
+  rune_id: String,
+  spaced_rune: Option<String>,
+  amount: Option<String>,
+  outpoint: Option<String>,
+  address: Option<String>,
+}
+
+// Matches $1 against the spaced name, the bare name or the BLOCK:TX rune id
+pub(crate) const RUNE_MATCH_CLAUSE: &str = "(r.spaced_rune = $1 OR r.unspaced_rune = translate($1, '•.', '') OR concat(r.block, ':', r.tx_index) = $1)";
+
+// Rune rows for one block, read from the index ahead of the block transaction
+pub struct RuneBlock {
+  etchings: Vec<RuneRow>,
+  balances: Vec<RuneBalanceRow>,
+  transfers: Vec<RuneTransferRow>,
+  spent: Vec<(String, String)>,
+}
+
+pub fn extract_runes(index: Arc<Index>, settings: Settings, block_number: u32) -> anyhow::Result<RuneBlock> {
+  let etchings = extract_rune_etchings(index.clone(), block_number)?;
+  let (balances, transfers, spent) = extract_rune_activity(index, settings, block_number)?;
+  Ok(RuneBlock { etchings, balances, transfers, spent })
+}
+
+pub async fn insert_runes(tx: &deadpool_postgres::Transaction<'_>, runes: RuneBlock, block_number: u32) -> anyhow::Result<()> {
   let start_time = Instant::now();
-  let spaced_runes = index.get_runes_in_block(block_number as u64)
+  let etching_count = runes.etchings.len();
+  if etching_count > 0 {
+    bulk_insert_runes(tx, runes.etchings).await
+      .with_context(|| format!("Error bulk inserting runes for block {}", block_number))?;
+    log::info!("Block {}: Indexed {} runes in {:?}", block_number, etching_count, start_time.elapsed());
+  }
+  let start_time = Instant::now();
+  let activity_count = runes.transfers.len() + runes.spent.len();
+  if activity_count > 0 {
+    // Balances created in this block can be spent later in the same block, so insert before marking spends
+    bulk_insert_rune_balances(tx, runes.balances).await
+      .with_context(|| format!("Error bulk inserting rune balances for block {}", block_number))?;
+    bulk_insert_rune_transfers(tx, runes.transfers).await
+      .with_context(|| format!("Error bulk inserting rune transfers for block {}", block_number))?;
+    mark_rune_balances_spent(tx, block_number, runes.spent).await
+      .with_context(|| format!("Error marking spent rune balances for block {}", block_number))?;
+    log::info!("Block {}: Indexed {} rune balance changes in {:?}", block_number, activity_count, start_time.elapsed());
+  }
+  Ok(())
+}
+
+fn extract_rune_etchings(index: Arc<Index>, block_number: u32) -> anyhow::Result<Vec<RuneRow>> {
+  let spaced_runes = index.get_runes_in_block(u64::from(block_number))
     .with_context(|| format!("Error getting runes in block {}", block_number))?;
   if spaced_runes.is_empty() {
-    log::info!("No runes found in block {}, indexing next block", block_number);
-    return Ok(());
+    log::debug!("No runes etched in block {}", block_number);
+    return Ok(Vec::new());
   }
-  let len = spaced_runes.len();
   let mut rows = Vec::new();
   for spaced_rune in spaced_runes {
     let full_rune = index.rune(spaced_rune.rune)
@@ -42,39 +150,111 @@ pub async fn process_runes(index: Arc<Index>, tx: &deadpool_postgres::Transactio
       .ok_or_else(|| anyhow::anyhow!("Rune number {} not found", spaced_rune.rune))?;
     let (id, entry, parent) = full_rune;
     let row = RuneRow {
-      block: id.block as i64,
-      tx_index: id.tx as i64,
-      burned: u128_to_decimal(entry.burned),
-      divisibility: entry.divisibility as i64,
+      block: i64::try_from(id.block)?,
+      tx_index: i64::from(id.tx),
+      burned: entry.burned.to_string(),
+      divisibility: i64::from(entry.divisibility),
       etching: entry.etching.to_string(),
-      mints: u128_to_decimal(entry.mints),
-      number: entry.number as i64,
-      premine: u128_to_decimal(entry.premine),
+      mints: entry.mints.to_string(),
+      number: i64::try_from(entry.number)?,
+      premine: entry.premine.to_string(),
       spaced_rune: entry.spaced_rune.to_string(),
       unspaced_rune: entry.spaced_rune.rune.to_string(),
       rune_u128: entry.spaced_rune.rune.0.to_string(),
-      spacers: entry.spaced_rune.spacers as i64,
-      symbol: entry.symbol.and_then(|s| Some(s.to_string())),
-      mint_amount: entry.terms.and_then(|t| t.amount.map(|a| u128_to_decimal(a))),
-      mint_cap: entry.terms.and_then(|t| t.cap.map(|c| u128_to_decimal(c))),
-      mint_height_lower: entry.terms.and_then(|t| t.height.0.map(|h| h as i64)),
-      mint_height_upper: entry.terms.and_then(|t| t.height.1.map(|h| h as i64)),
-      mint_offset_lower: entry.terms.and_then(|t| t.offset.0.map(|o| o as i64)),
-      mint_offset_upper: entry.terms.and_then(|t| t.offset.1.map(|o| o as i64)),
-      timestamp: entry.timestamp as i64,
+      spacers: i64::from(entry.spaced_rune.spacers),
+      symbol: entry.symbol.map(|s| s.to_string()),
+      mint_amount: entry.terms.and_then(|t| t.amount.map(|a| a.to_string())),
+      mint_cap: entry.terms.and_then(|t| t.cap.map(|c| c.to_string())),
+      mint_height_lower: entry.terms.and_then(|t| t.height.0.and_then(|h| i64::try_from(h).ok())),
+      mint_height_upper: entry.terms.and_then(|t| t.height.1.and_then(|h| i64::try_from(h).ok())),
+      mint_offset_lower: entry.terms.and_then(|t| t.offset.0.and_then(|o| i64::try_from(o).ok())),
+      mint_offset_upper: entry.terms.and_then(|t| t.offset.1.and_then(|o| i64::try_from(o).ok())),
+      timestamp: i64::try_from(entry.timestamp)?,
       turbo: entry.turbo,
       parent: parent.map(|p| p.to_string()),
     };
     rows.push(row);
   }
-  bulk_insert_runes(&tx, rows).await
-    .with_context(|| format!("Error bulk inserting runes for block {}", block_number))?;
-  let elapsed = start_time.elapsed();
-  log::info!("Block {}: Indexed {} runes in {:?}", block_number, len, elapsed);
-  Ok(())
+  Ok(rows)
 }
+
+fn extract_rune_activity(index: Arc<Index>, settings: Settings, block_number: u32) -> anyhow::Result<(Vec<RuneBalanceRow>, Vec<RuneTransferRow>, Vec<(String, String)>)> {
+  let activity = index.get_rune_activity_by_block_height(block_number)
+    .with_context(|| format!("Error getting rune activity in block {}", block_number))?;
+  if activity.is_empty() {
+    log::debug!("No rune activity in block {}", block_number);
+    return Ok((Vec::new(), Vec::new(), Vec::new()));
+  }
+  // Receiving outputs may already be spent in the index, so addresses come from the block itself
+  let block_hash = index.block_hash(Some(block_number))
+    .with_context(|| format!("Error getting block hash for block {}", block_number))?
+    .ok_or_else(|| anyhow::anyhow!("No block hash found for block {}", block_number))?;
+  let block = index.get_block_by_hash(block_hash)
+    .with_context(|| format!("Error getting block {}", block_number))?
+    .ok_or_else(|| anyhow::anyhow!("Block {} not found", block_number))?;
+  let tx_map: HashMap<Txid, &Transaction> = block.txdata.iter()
+    .map(|transaction| (transaction.compute_txid(), transaction))
+    .collect();
+  let address = |outpoint: OutPoint| tx_map.get(&outpoint.txid)
+    .and_then(|transaction| transaction.output.get(outpoint.vout as usize))
+    .and_then(|output| settings.chain().address_from_script(&output.script_pubkey).ok())
+    .map(|address| address.to_string());
+  rune_activity_rows(block_number, activity, address)
+}
+
+// Transfers create balances at their receiving outputs and spends mark earlier balances spent, mints and burns are only logged
+fn rune_activity_rows(block_number: u32, activity: Vec<RuneActivityEntry>, address: impl Fn(OutPoint) -> Option<String>) -> anyhow::Result<(Vec<RuneBalanceRow>, Vec<RuneTransferRow>, Vec<(String, String)>)> {
+  let block = i64::from(block_number);
+  let mut balances = Vec::new();
+  let mut transfers = Vec::new();
+  let mut spent = Vec::new();
+  for entry in activity {
+    if entry.kind == RuneActivityKind::Spend {
+      spent.push((entry.outpoint.to_string(), entry.txid.to_string()));
+      continue;
+    }
+    let (outpoint, address) = if entry.kind == RuneActivityKind::Transfer {
+      let address = address(entry.outpoint);
+      balances.push(RuneBalanceRow {
+        outpoint: entry.outpoint.to_string(),
+        rune_block: i64::try_from(entry.rune_id.block)?,
+        rune_tx_index: i64::from(entry.rune_id.tx),
+        amount: entry.amount.to_string(),
+        address: address.clone(),
+        block,
+        tx_index: i64::from(entry.tx_index),
+      });
+      (Some(entry.outpoint.to_string()), address)
+    } else {
+      (None, None)
+    };
+    transfers.push(RuneTransferRow {
+      block,
+      tx_index: i64::from(entry.tx_index),
+      txid: entry.txid.to_string(),
+      event_type: entry.kind.as_str().to_string(),
+      rune_block: i64::try_from(entry.rune_id.block)?,
+      rune_tx_index: i64::from(entry.rune_id.tx),
+      amount: entry.amount.to_string(),
+      outpoint,
+      address,
+    });
+  }
+  Ok((balances, transfers, spent))
+}
+
 pub async fn initialize_runes_tables(pool: deadpool) -> anyhow::Result<()> {
-  create_runes_table(pool).await.context("Error creating runes table")?;
+  create_runes_table(pool.clone()).await.context("Error creating runes table")?;
+  create_rune_balances_table(pool.clone()).await.context("Error creating rune_balances table")?;
+  create_rune_transfers_table(pool).await.context("Error creating rune_transfers table")?;
+  Ok(())
+}
+
+pub async fn rollback_rune_activity(tx: &deadpool_postgres::Transaction<'_>, last_good_block: u32) -> anyhow::Result<()> {
+  let last_good_block = i64::from(last_good_block);
+  tx.execute("DELETE FROM rune_transfers WHERE block > $1", &[&last_good_block]).await?;
+  tx.execute("DELETE FROM rune_balances WHERE block > $1", &[&last_good_block]).await?;
+  tx.execute("UPDATE rune_balances SET spent_block = NULL, spent_txid = NULL WHERE spent_block > $1", &[&last_good_block]).await?;
   Ok(())
 }
 
@@ -115,8 +295,62 @@ async fn create_runes_table(pool: deadpool) -> anyhow::Result<()> {
   Ok(())
 }
 
+async fn create_rune_balances_table(pool: deadpool) -> anyhow::Result<()> {
+  let conn = pool.get().await?;
+  conn.simple_query(r"
+    CREATE TABLE IF NOT EXISTS rune_balances (
+      outpoint varchar(80) not null,
+      rune_block bigint not null,
+      rune_tx_index bigint not null,
+      amount NUMERIC(39, 0),
+      address varchar(100),
+      block bigint not null,
+      tx_index bigint,
+      spent_block bigint,
+      spent_txid varchar(80),
+      CONSTRAINT rune_balances_key PRIMARY KEY (outpoint, rune_block, rune_tx_index)
+    )").await?;
+  conn.simple_query(r"
+    CREATE INDEX IF NOT EXISTS index_rune_balances_unspent ON rune_balances (rune_block, rune_tx_index) WHERE spent_block IS NULL;
+    CREATE INDEX IF NOT EXISTS index_rune_balances_address ON rune_balances (address);
+    CREATE INDEX IF NOT EXISTS index_rune_balances_block ON rune_balances (block);
+    CREATE INDEX IF NOT EXISTS index_rune_balances_spent_block ON rune_balances (spent_block);
+    ").await?;
+  Ok(())
+}
+
+async fn create_rune_transfers_table(pool: deadpool) -> anyhow::Result<()> {
+  let conn = pool.get().await?;
+  conn.simple_query(r"
+    CREATE TABLE IF NOT EXISTS rune_transfers (
+      block bigint not null,
+      tx_index bigint not null,
+      txid varchar(80) not null,
+      event_type varchar(10) not null,
+      rune_block bigint not null,
+      rune_tx_index bigint not null,
+      amount NUMERIC(39, 0),
+      outpoint varchar(80),
+      address varchar(100)
+    )").await?;
+  conn.simple_query(r"
+    CREATE INDEX IF NOT EXISTS index_rune_transfers_block ON rune_transfers (block);
+    CREATE INDEX IF NOT EXISTS index_rune_transfers_rune ON rune_transfers (rune_block, rune_tx_index);
+    CREATE INDEX IF NOT EXISTS index_rune_transfers_txid ON rune_transfers (txid);
+    CREATE INDEX IF NOT EXISTS index_rune_transfers_address ON rune_transfers (address);
+    ").await?;
+  Ok(())
+}
+
 async fn bulk_insert_runes(tx: &deadpool_postgres::Transaction<'_>, data: Vec<RuneRow>) -> anyhow::Result<()> {
-  let copy_stm = r#"COPY runes (
+  tx.simple_query(r"
+    CREATE TEMP TABLE inserts_runes ON COMMIT DROP AS
+    SELECT block, tx_index, burned::text AS burned, divisibility, etching, mints::text AS mints, number, premine::text AS premine,
+      spaced_rune, unspaced_rune, rune_u128, spacers, symbol, mint_amount::text AS mint_amount, mint_cap::text AS mint_cap,
+      mint_height_lower, mint_height_upper, mint_offset_lower, mint_offset_upper, timestamp, turbo, parent
+    FROM runes WITH NO DATA
+  ").await?;
+  let copy_stm = r#"COPY inserts_runes (
     block,
     tx_index,
     burned,
@@ -140,22 +374,22 @@ async fn bulk_insert_runes(tx: &deadpool_postgres::Transaction<'_>, data: Vec<Ru
     turbo,
     parent
   ) FROM STDIN BINARY"#;
-  let col_types = vec![
+  let col_types = [
     Type::INT8,
     Type::INT8,
-    Type::NUMERIC,
+    Type::TEXT,
     Type::INT8,
     Type::VARCHAR,
-    Type::NUMERIC,
+    Type::TEXT,
     Type::INT8,
-    Type::NUMERIC,
+    Type::TEXT,
     Type::VARCHAR,
     Type::VARCHAR,
     Type::VARCHAR,
     Type::INT8,
     Type::VARCHAR,
-    Type::NUMERIC,
-    Type::NUMERIC,
+    Type::TEXT,
+    Type::TEXT,
     Type::INT8,
     Type::INT8,
     Type::INT8,
@@ -168,39 +402,318 @@ async fn bulk_insert_runes(tx: &deadpool_postgres::Transaction<'_>, data: Vec<Ru
   let writer = BinaryCopyInWriter::new(sink, &col_types);
   pin_mut!(writer);
   for m in data {
-    let mut row: Vec<&'_ (dyn ToSql + Sync)> = Vec::new();
-    row.push(&m.block);
-    row.push(&m.tx_index);
-    row.push(&m.burned);
-    row.push(&m.divisibility);
-    row.push(&m.etching);
-    row.push(&m.mints);
-    row.push(&m.number);
-    row.push(&m.premine);
-    row.push(&m.spaced_rune);
-    row.push(&m.unspaced_rune);
-    row.push(&m.rune_u128);
-    row.push(&m.spacers);
-    let clean_symbol = &m.symbol.map(|s| s.replace("\0", ""));
-    row.push(clean_symbol);
-    row.push(&m.mint_amount);
-    row.push(&m.mint_cap);
-    row.push(&m.mint_height_lower);
-    row.push(&m.mint_height_upper);
-    row.push(&m.mint_offset_lower);
-    row.push(&m.mint_offset_upper);
-    row.push(&m.timestamp);
-    row.push(&m.turbo);
-    row.push(&m.parent);
+    let clean_symbol = m.symbol.map(|s| s.replace('\0', ""));
+    let row: Vec<&'_ (dyn ToSql + Sync)> = vec![
+      &m.block,
+      &m.tx_index,
+      &m.burned,
+      &m.divisibility,
+      &m.etching,
+      &m.mints,
+      &m.number,
+      &m.premine,
+      &m.spaced_rune,
+      &m.unspaced_rune,
+      &m.rune_u128,
+      &m.spacers,
+      &clean_symbol,
+      &m.mint_amount,
+      &m.mint_cap,
+      &m.mint_height_lower,
+      &m.mint_height_upper,
+      &m.mint_offset_lower,
+      &m.mint_offset_upper,
+      &m.timestamp,
+      &m.turbo,
+      &m.parent,
+    ];
+    writer.as_mut().write(&row).await?;
+  }
+  writer.finish().await?;
+  tx.simple_query(r"
+    INSERT INTO runes (block, tx_index, burned, divisibility, etching, mints, number, premine, spaced_rune, unspaced_rune, rune_u128,
+      spacers, symbol, mint_amount, mint_cap, mint_height_lower, mint_height_upper, mint_offset_lower, mint_offset_upper, timestamp, turbo, parent)
+    SELECT block, tx_index, burned::numeric, divisibility, etching, mints::numeric, number, premine::numeric, spaced_rune, unspaced_rune, rune_u128,
+      spacers, symbol, mint_amount::numeric, mint_cap::numeric, mint_height_lower, mint_height_upper, mint_offset_lower, mint_offset_upper, timestamp, turbo, parent
+    FROM inserts_runes
+  ").await?;
+  Ok(())
+}
+
+// The u128 amounts are read back as text, since Decimal can't hold more than 96 bits
+const RUNE_SUMMARY_COLUMNS: &str = r"r.block, r.tx_index, r.number, r.spaced_rune, r.unspaced_rune, r.divisibility, r.symbol, r.etching,
+  r.premine::text AS premine, r.mints::text AS mints, r.burned::text AS burned, r.mint_amount::text AS mint_amount, r.mint_cap::text AS mint_cap,
+  r.mint_height_lower, r.mint_height_upper, r.mint_offset_lower, r.mint_offset_upper, r.timestamp, r.turbo, r.parent";
+
+// The rune lists page by page_number, at most 100 to a page
+fn limit_clause(params: &PaginationParams) -> String {
+  let page_size = std::cmp::min(params.page_size.unwrap_or(10), 100);
+  PageRequest::Offset { page_number: params.page_number.unwrap_or(0), page_size }.limit()
+}
+
+pub async fn get_runes(pool: deadpool, params: PaginationParams) -> anyhow::Result<Vec<RuneSummary>> {
+  let conn = pool.get().await?;
+  let query = format!("SELECT {} FROM runes r ORDER BY r.number DESC{}", RUNE_SUMMARY_COLUMNS, limit_clause(&params));
+  let result = conn.query(query.as_str(), &[]).await?;
+  Ok(result.into_iter().map(map_row_to_rune_summary).collect())
+}
+
+pub async fn get_rune(pool: deadpool, spaced_rune: String) -> anyhow::Result<RuneSummary> {
+  let conn = pool.get().await?;
+  let query = format!("SELECT {} FROM runes r WHERE {} LIMIT 1", RUNE_SUMMARY_COLUMNS, RUNE_MATCH_CLAUSE);
+  let result = conn.query_one(query.as_str(), &[&spaced_rune]).await?;
+  Ok(map_row_to_rune_summary(result))
+}
+
+pub async fn get_runes_in_block(pool: deadpool, block: i64, params: PaginationParams) -> anyhow::Result<Vec<RuneSummary>> {
+  let conn = pool.get().await?;
+  let query = format!("SELECT {} FROM runes r WHERE r.block = $1 ORDER BY r.tx_index ASC{}", RUNE_SUMMARY_COLUMNS, limit_clause(&params));
+  let result = conn.query(query.as_str(), &[&block]).await?;
+  Ok(result.into_iter().map(map_row_to_rune_summary).collect())
+}
+
+pub async fn get_rune_holders(pool: deadpool, rune: String, params: PaginationParams) -> anyhow::Result<Vec<RuneHolder>> {
+  let conn = pool.get().await?;
+  let query = format!(r"
+    WITH m AS (SELECT r.block, r.tx_index, r.spaced_rune FROM runes r WHERE {} LIMIT 1)
+    SELECT
+      m.spaced_rune,
+      COUNT(b.address) OVER () AS rune_holder_count,
+      b.address,
+      SUM(b.amount)::text AS balance,
+      COUNT(*) AS outpoint_count
+    FROM m
+    JOIN rune_balances b ON b.rune_block = m.block AND b.rune_tx_index = m.tx_index
+    WHERE b.spent_block IS NULL
+    GROUP BY m.spaced_rune, b.address
+    ORDER BY SUM(b.amount) DESC{}", RUNE_MATCH_CLAUSE, limit_clause(&params));
+  let result = conn.query(query.as_str(), &[&rune]).await?;
+  let mut holders = Vec::new();
+  for row in result {
+    holders.push(RuneHolder {
+      spaced_rune: row.get("spaced_rune"),
+      rune_holder_count: row.get("rune_holder_count"),
+      address: row.get("address"),
+      balance: row.get("balance"),
+      outpoint_count: row.get("outpoint_count"),
+    });
+  }
+  Ok(holders)
+}
+
+pub async fn get_rune_activity(pool: deadpool, rune: String, params: PaginationParams) -> anyhow::Result<Vec<RuneActivity>> {
+  let conn = pool.get().await?;
+  let query = format!(r"
+    WITH m AS (SELECT r.block, r.tx_index, r.spaced_rune FROM runes r WHERE {} LIMIT 1)
+    SELECT t.*, t.amount::text AS amount_text, m.spaced_rune
+    FROM m
+    JOIN rune_transfers t ON t.rune_block = m.block AND t.rune_tx_index = m.tx_index
+    ORDER BY t.block DESC, t.tx_index DESC{}", RUNE_MATCH_CLAUSE, limit_clause(&params));
+  let result = conn.query(query.as_str(), &[&rune]).await?;
+  let mut activity = Vec::new();
+  for row in result {
+    let rune_block: i64 = row.get("rune_block");
+    let rune_tx_index: i64 = row.get("rune_tx_index");
+    activity.push(RuneActivity {
+      block: row.get("block"),
+      tx_index: row.get("tx_index"),
+      txid: row.get("txid"),
+      event_type: row.get("event_type"),
+      rune_id: format!("{}:{}", rune_block, rune_tx_index),
+      spaced_rune: row.get("spaced_rune"),
+      amount: row.get("amount_text"),
+      outpoint: row.get("outpoint"),
+      address: row.get("address"),
+    });
+  }
+  Ok(activity)
+}
+
+fn map_row_to_rune_summary(row: tokio_postgres::Row) -> RuneSummary {
+  let block: i64 = row.get("block");
+  let tx_index: i64 = row.get("tx_index");
+  RuneSummary {
+    rune_id: format!("{}:{}", block, tx_index),
+    block,
+    tx_index,
+    number: row.get("number"),
+    spaced_rune: row.get("spaced_rune"),
+    unspaced_rune: row.get("unspaced_rune"),
+    divisibility: row.get("divisibility"),
+    symbol: row.get("symbol"),
+    etching: row.get("etching"),
+    premine: row.get("premine"),
+    mints: row.get("mints"),
+    burned: row.get("burned"),
+    mint_amount: row.get("mint_amount"),
+    mint_cap: row.get("mint_cap"),
+    mint_height_lower: row.get("mint_height_lower"),
+    mint_height_upper: row.get("mint_height_upper"),
+    mint_offset_lower: row.get("mint_offset_lower"),
+    mint_offset_upper: row.get("mint_offset_upper"),
+    timestamp: row.get("timestamp"),
+    turbo: row.get("turbo"),
+    parent: row.get("parent"),
+  }
+}
+
+async fn bulk_insert_rune_balances(tx: &deadpool_postgres::Transaction<'_>, data: Vec<RuneBalanceRow>) -> anyhow::Result<()> {
+  tx.simple_query(r"
+    CREATE TEMP TABLE inserts_rune_balances ON COMMIT DROP AS
+    SELECT outpoint, rune_block, rune_tx_index, amount::text AS amount, address, block, tx_index FROM rune_balances WITH NO DATA
+  ").await?;
+  let copy_stm = r#"COPY inserts_rune_balances (
+    outpoint,
+    rune_block,
+    rune_tx_index,
+    amount,
+    address,
+    block,
+    tx_index
+  ) FROM STDIN BINARY"#;
+  let col_types = [
+    Type::VARCHAR,
+    Type::INT8,
+    Type::INT8,
+    Type::TEXT,
+    Type::VARCHAR,
+    Type::INT8,
+    Type::INT8,
+  ];
+  let sink = tx.copy_in(copy_stm).await?;
+  let writer = BinaryCopyInWriter::new(sink, &col_types);
+  pin_mut!(writer);
+  for m in data {
+    let row: Vec<&'_ (dyn ToSql + Sync)> = vec![
+      &m.outpoint,
+      &m.rune_block,
+      &m.rune_tx_index,
+      &m.amount,
+      &m.address,
+      &m.block,
+      &m.tx_index,
+    ];
+    writer.as_mut().write(&row).await?;
+  }
+  writer.finish().await?;
+  tx.simple_query(r"
+    INSERT INTO rune_balances (outpoint, rune_block, rune_tx_index, amount, address, block, tx_index)
+    SELECT outpoint, rune_block, rune_tx_index, amount::numeric, address, block, tx_index FROM inserts_rune_balances
+  ").await?;
+  Ok(())
+}
+
+async fn bulk_insert_rune_transfers(tx: &deadpool_postgres::Transaction<'_>, data: Vec<RuneTransferRow>) -> anyhow::Result<()> {
+  tx.simple_query(r"
+    CREATE TEMP TABLE inserts_rune_transfers ON COMMIT DROP AS
+    SELECT block, tx_index, txid, event_type, rune_block, rune_tx_index, amount::text AS amount, outpoint, address FROM rune_transfers WITH NO DATA
+  ").await?;
+  let copy_stm = r#"COPY inserts_rune_transfers (
+    block,
+    tx_index,
+    txid,
+    event_type,
+    rune_block,
+    rune_tx_index,
+    amount,
+    outpoint,
+    address
+  ) FROM STDIN BINARY"#;
+  let col_types = [
+    Type::INT8,
+    Type::INT8,
+    Type::VARCHAR,
+    Type::VARCHAR,
+    Type::INT8,
+    Type::INT8,
+    Type::TEXT,
+    Type::VARCHAR,
+    Type::VARCHAR,
+  ];
+  let sink = tx.copy_in(copy_stm).await?;
+  let writer = BinaryCopyInWriter::new(sink, &col_types);
+  pin_mut!(writer);
+  for m in data {
+    let row: Vec<&'_ (dyn ToSql + Sync)> = vec![
+      &m.block,
+      &m.tx_index,
+      &m.txid,
+      &m.event_type,
+      &m.rune_block,
+      &m.rune_tx_index,
+      &m.amount,
+      &m.outpoint,
+      &m.address,
+    ];
     writer.as_mut().write(&row).await?;
   }
-  let _x = writer.finish().await?;
-  //println!("Finished writing metadata: {:?}", x);
+  writer.finish().await?;
+  tx.simple_query(r"
+    INSERT INTO rune_transfers (block, tx_index, txid, event_type, rune_block, rune_tx_index, amount, outpoint, address)
+    SELECT block, tx_index, txid, event_type, rune_block, rune_tx_index, amount::numeric, outpoint, address FROM inserts_rune_transfers
+  ").await?;
+  Ok(())
+}
+
+async fn mark_rune_balances_spent(tx: &deadpool_postgres::Transaction<'_>, block_number: u32, spent: Vec<(String, String)>) -> anyhow::Result<()> {
+  if spent.is_empty() {
+    return Ok(());
+  }
+  let (outpoints, txids): (Vec<String>, Vec<String>) = spent.into_iter().unique().unzip();
+  tx.execute(r"
+    UPDATE rune_balances b
+    SET spent_block = $1, spent_txid = s.txid
+    FROM unnest($2::varchar[], $3::varchar[]) AS s(outpoint, txid)
+    WHERE b.outpoint = s.outpoint",
+    &[&i64::from(block_number), &outpoints, &txids]
+  ).await?;
   Ok(())
 }
 
-fn u128_to_decimal(u: u128) -> Decimal {
-  let decimal = Decimal::from_u128(u);
-  // return -1 if it overflows beyond 96 bits -- look into bigdecimal package if 128bit really needed
-  decimal.unwrap_or(Decimal::from(-1))
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn entry(tx_index: u32, kind: RuneActivityKind, amount: u128, outpoint: OutPoint) -> RuneActivityEntry {
+    RuneActivityEntry {
+      tx_index,
+      kind,
+      rune_id: RuneId { block: 840000, tx: 1 },
+      amount,
+      txid: Txid::from_byte_array([tx_index as u8; 32]),
+      outpoint,
+    }
+  }
+
+  #[test]
+  fn rune_activity_rows_are_built_per_kind() {
+    let received = OutPoint { txid: Txid::from_byte_array([2; 32]), vout: 1 };
+    let released = OutPoint { txid: Txid::from_byte_array([9; 32]), vout: 0 };
+    let activity = vec![
+      entry(1, RuneActivityKind::Mint, 1000, OutPoint::null()),
+      entry(2, RuneActivityKind::Spend, 0, released),
+      entry(2, RuneActivityKind::Transfer, u128::MAX, received),
+      entry(3, RuneActivityKind::Burn, 5, OutPoint::null()),
+    ];
+    let (balances, transfers, spent) = rune_activity_rows(840100, activity, |outpoint| {
+      (outpoint == received).then(|| "bc1qreceiver".to_string())
+    }).unwrap();
+
+    assert_eq!(balances.len(), 1);
+    assert_eq!(balances[0].outpoint, received.to_string());
+    assert_eq!((balances[0].rune_block, balances[0].rune_tx_index), (840000, 1));
+    assert_eq!(balances[0].amount, u128::MAX.to_string());
+    assert_eq!(balances[0].address.as_deref(), Some("bc1qreceiver"));
+    assert_eq!((balances[0].block, balances[0].tx_index), (840100, 2));
+
+    assert_eq!(
+      transfers.iter().map(|t| (t.tx_index, t.event_type.as_str(), t.amount.as_str())).collect::<Vec<_>>(),
+      vec![(1, "mint", "1000"), (2, "transfer", u128::MAX.to_string().as_str()), (3, "burn", "5")]
+    );
+    assert_eq!(transfers[1].outpoint, Some(received.to_string()));
+    assert_eq!(transfers[1].address.as_deref(), Some("bc1qreceiver"));
+    assert_eq!((transfers[0].outpoint.as_ref(), transfers[2].address.as_ref()), (None, None));
+
+    assert_eq!(spent, vec![(released.to_string(), Txid::from_byte_array([2; 32]).to_string())]);
+  }
+}
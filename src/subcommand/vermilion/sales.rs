@@ -0,0 +1,532 @@
+use super::*;
+
+// Marketplace sales, split out of transfers. A sale is a transfer whose seller signed their inscription input
+// so that the same transaction has to pay them, see get_sale_price. Sales are attributed to a marketplace by
+// the address of the fee output, looked up in the marketplaces table when read.
+
+#[derive(Clone, Serialize, JsonSchema)]
+pub struct Sale {
+  /// Inscription id
+  pub(crate) id: String,
+  number: Option<i64>,
+  block_number: i64,
+  block_timestamp: i64,
+  transaction: String,
+  input_index: i32,
+  seller: String,
+  buyer: String,
+  /// This inscription's share of the bundle price, in sats
+  price: i64,
+  /// Paid to the seller for the sold input, in sats
+  bundle_price: i64,
+  /// Inscriptions sold together in the same input
+  bundle_size: i32,
+  /// Inscriptions bought in the same transaction
+  sweep_size: i32,
+  /// This inscription's share of the transaction fee, in sats
+  tx_fee: i64,
+  /// Address paid the largest output that isn't a seller payment, an inscription or change
+  fee_recipient: Option<String>,
+  /// This inscription's share of the fee output, in sats
+  marketplace_fee: i64,
+  marketplace: Option<String>,
+}
+
+// A transfer of an inscription out of an input the seller was paid for
+pub(crate) struct SoldInput {
+  pub(crate) transfer: usize,
+  pub(crate) input_index: usize,
+}
+
+pub(crate) async fn extract_sales(chain: Chain, fetcher: &Fetcher, tx_map: &HashMap<Txid, Transaction>, transfers: &[Transfer], sold: Vec<SoldInput>) -> anyhow::Result<Vec<Sale>> {
+  if sold.is_empty() {
+    return Ok(Vec::new());
+  }
+  let mut sold_by_tx: BTreeMap<Txid, Vec<SoldInput>> = BTreeMap::new();
+  for sold_input in sold {
+    let txid = transfers[sold_input.transfer].transaction.parse::<Txid>()?;
+    sold_by_tx.entry(txid).or_default().push(sold_input);
+  }
+
+  // Change goes back to the scripts that funded the transaction, so those are needed to pick out the fee output
+  let missing: Vec<Txid> = sold_by_tx
+    .keys()
+    .flat_map(|txid| tx_map[txid].input.iter().map(|input| input.previous_output.txid))
+    .filter(|txid| !tx_map.contains_key(txid))
+    .unique()
+    .collect();
+  let fetched: HashMap<Txid, Transaction> = fetcher
+    .get_transactions(missing)
+    .await
+    .context("Failed to get funding transactions of sales")?
+    .into_iter()
+    .map(|tx| (tx.compute_txid(), tx))
+    .collect();
+
+  let mut sales = Vec::new();
+  for (txid, sold) in sold_by_tx {
+    let tx = &tx_map[&txid];
+    let mut funding = HashSet::new();
+    for input in &tx.input {
+      let prev_tx = tx_map
+        .get(&input.previous_output.txid)
+        .or_else(|| fetched.get(&input.previous_output.txid))
+        .ok_or_else(|| anyhow!("Missing funding transaction {} of sale {}", input.previous_output.txid, txid))?;
+      if let Some(output) = prev_tx.output.get(input.previous_output.vout as usize) {
+        funding.insert(output.script_pubkey.clone());
+      }
+    }
+    sales.extend(transaction_sales(chain, tx, &funding, transfers, &sold));
+  }
+  Ok(sales)
+}
+
+fn transaction_sales(chain: Chain, tx: &Transaction, funding: &HashSet<ScriptBuf>, transfers: &[Transfer], sold: &[SoldInput]) -> Vec<Sale> {
+  let txid = tx.compute_txid().to_string();
+  let mut claimed: HashSet<usize> = transfers
+    .iter()
+    .filter(|transfer| transfer.transaction == txid)
+    .filter_map(|transfer| usize::try_from(transfer.vout).ok())
+    .collect();
+  claimed.extend(sold.iter().filter_map(|sold_input| Vermilion::get_sale_output(tx, sold_input.input_index, 0)));
+  let fee_output = tx
+    .output
+    .iter()
+    .enumerate()
+    .filter(|(vout, output)| {
+      !claimed.contains(vout) && !output.script_pubkey.is_op_return() && output.value.to_sat() > 0 && !funding.contains(&output.script_pubkey)
+    })
+    .max_by_key(|(vout, output)| (output.value, std::cmp::Reverse(*vout)))
+    .map(|(_, output)| output);
+  let fee_recipient = fee_output.and_then(|output| chain.address_from_script(&output.script_pubkey).ok().map(|address| address.to_string()));
+  let fee = fee_output.map_or(0, |output| i64::try_from(output.value.to_sat()).unwrap_or(i64::MAX));
+
+  // Inscriptions paid for by the same output were sold as one bundle for the value of that output. That's one
+  // input for SIGHASH_SINGLE, but every SIGHASH_ALL|ANYONECANPAY input of a transaction is paid by output 1.
+  let mut bundles: BTreeMap<usize, Vec<(usize, &Transfer)>> = BTreeMap::new();
+  for sold_input in sold {
+    let sale_output = Vermilion::get_sale_output(tx, sold_input.input_index, 0).unwrap_or(sold_input.input_index);
+    bundles.entry(sale_output).or_default().push((sold_input.input_index, &transfers[sold_input.transfer]));
+  }
+  let total_price: i64 = bundles.values().map(|bundle| bundle[0].1.price).sum();
+  let sweep_size = i32::try_from(sold.len()).unwrap_or(i32::MAX);
+  let mut sales = Vec::new();
+  for bundle in bundles.into_values() {
+    let bundle_price = bundle[0].1.price;
+    let bundle_size = i32::try_from(bundle.len()).unwrap_or(i32::MAX);
+    for (i, (input_index, transfer)) in bundle.into_iter().enumerate() {
+      // Any remainder goes to the first inscription of the bundle
+      let price = bundle_price / i64::from(bundle_size) + if i == 0 { bundle_price % i64::from(bundle_size) } else { 0 };
+      let marketplace_fee = if total_price == 0 {
+        0
+      } else {
+        i64::try_from(i128::from(fee) * i128::from(price) / i128::from(total_price)).unwrap_or(0)
+      };
+      sales.push(Sale {
+        id: transfer.id.clone(),
+        number: None,
+        block_number: transfer.block_number,
+        block_timestamp: transfer.block_timestamp,
+        transaction: txid.clone(),
+        input_index: i32::try_from(input_index).unwrap_or(i32::MAX),
+        seller: transfer.previous_address.clone(),
+        buyer: transfer.address.clone(),
+        price,
+        bundle_price,
+        bundle_size,
+        sweep_size,
+        tx_fee: transfer.tx_fee,
+        fee_recipient: fee_recipient.clone(),
+        marketplace_fee,
+        marketplace: None,
+      });
+    }
+  }
+  // Rounding leftovers of the fee split go to the first sale, so the shares add up to the fee output
+  let leftover = fee - sales.iter().map(|sale| sale.marketplace_fee).sum::<i64>();
+  if let Some(first) = sales.first_mut() {
+    first.marketplace_fee += leftover;
+  }
+  sales
+}
+
+pub(crate) async fn initialize_sales_tables(pool: deadpool) -> anyhow::Result<()> {
+  let conn = pool.get().await?;
+  conn.simple_query(
+    r"CREATE TABLE IF NOT EXISTS sales (
+      sale_id bigserial primary key,
+      id varchar(80) not null,
+      block_number bigint not null,
+      block_timestamp bigint,
+      transaction varchar(80) not null,
+      input_index int not null,
+      seller varchar(100),
+      buyer varchar(100),
+      price bigint not null,
+      bundle_price bigint not null,
+      bundle_size int not null,
+      sweep_size int not null,
+      tx_fee bigint,
+      fee_recipient varchar(100),
+      marketplace_fee bigint,
+      UNIQUE (id, transaction)
+    )").await?;
+  conn.simple_query(r"
+    CREATE INDEX IF NOT EXISTS index_sales_id ON sales (id, sale_id);
+    CREATE INDEX IF NOT EXISTS index_sales_block ON sales (block_number);
+    CREATE INDEX IF NOT EXISTS index_sales_seller ON sales (seller, sale_id);
+    CREATE INDEX IF NOT EXISTS index_sales_buyer ON sales (buyer, sale_id);
+  ").await?;
+  // Maintained by hand, new entries apply to sales already indexed
+  conn.simple_query(
+    r"CREATE TABLE IF NOT EXISTS marketplaces (
+      fee_recipient varchar(100) not null primary key,
+      marketplace text not null
+    )").await?;
+  Ok(())
+}
+
+pub(crate) async fn bulk_insert_sales(tx: &deadpool_postgres::Transaction<'_>, sales: Vec<Sale>) -> anyhow::Result<()> {
+  let copy_stm = r#"COPY sales (
+    id,
+    block_number,
+    block_timestamp,
+    transaction,
+    input_index,
+    seller,
+    buyer,
+    price,
+    bundle_price,
+    bundle_size,
+    sweep_size,
+    tx_fee,
+    fee_recipient,
+    marketplace_fee) FROM STDIN BINARY"#;
+  let col_types = vec![
+    Type::VARCHAR,
+    Type::INT8,
+    Type::INT8,
+    Type::VARCHAR,
+    Type::INT4,
+    Type::VARCHAR,
+    Type::VARCHAR,
+    Type::INT8,
+    Type::INT8,
+    Type::INT4,
+    Type::INT4,
+    Type::INT8,
+    Type::VARCHAR,
+    Type::INT8,
+  ];
+  let sink = tx.copy_in(copy_stm).await?;
+  let writer = BinaryCopyInWriter::new(sink, &col_types);
+  pin_mut!(writer);
+  for m in sales {
+    let row: Vec<&'_ (dyn ToSql + Sync)> = vec![
+      &m.id,
+      &m.block_number,
+      &m.block_timestamp,
+      &m.transaction,
+      &m.input_index,
+      &m.seller,
+      &m.buyer,
+      &m.price,
+      &m.bundle_price,
+      &m.bundle_size,
+      &m.sweep_size,
+      &m.tx_fee,
+      &m.fee_recipient,
+      &m.marketplace_fee,
+    ];
+    writer.as_mut().write(&row).await?;
+  }
+  writer.finish().await?;
+  Ok(())
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct SalesQueryParams {
+  /// Sort order for the sales
+  #[schemars(description = "Sort order, newest first by default")]
+  sort_by: Option<SaleSortBy>,
+
+  /// Page number for pagination (0-based)
+  #[schemars(description = "Page number for pagination, starting from 0", example = "0", range(min = 0))]
+  page_number: Option<usize>,
+
+  /// Number of items per page (max 100)
+  #[schemars(description = "Number of items per page, maximum 100", example = "20", range(min = 1, max = 100))]
+  page_size: Option<usize>,
+
+  /// Cursor for keyset pagination
+  #[schemars(description = "Opaque cursor for keyset pagination. Pass an empty cursor for the first page, then the next_cursor of each response. Takes precedence over page_number")]
+  #[serde(default, deserialize_with = "deserialize_cursor")]
+  cursor: Option<String>,
+}
+
+pub(crate) struct ParsedSalesQueryParams {
+  keyset: Keyset,
+  page: PageRequest,
+}
+
+impl SalesQueryParams {
+  // Each list gets its own cursors
+  pub(crate) fn parse(self, list: &str) -> Result<ParsedSalesQueryParams, ApiError> {
+    let sort_by = self.sort_by.unwrap_or(SaleSortBy::Newest);
+    let (column, descending) = match sort_by {
+      SaleSortBy::Newest => ("s.sale_id", true),
+      SaleSortBy::Oldest => ("s.sale_id", false),
+      SaleSortBy::HighestPrice => ("s.price", true),
+      SaleSortBy::LowestPrice => ("s.price", false),
+    };
+    let keyset = Keyset::new(list, sort_by, column, descending).tie_break("s.sale_id", KeyType::Int8);
+    let page = PageRequest::new(self.page_number, std::cmp::min(self.page_size.unwrap_or(20), 100), self.cursor.as_deref(), &keyset)?;
+    Ok(ParsedSalesQueryParams { keyset, page })
+  }
+}
+
+pub(crate) enum SalesOf {
+  Inscription(String),
+  Address(String),
+  Collection(String),
+}
+
+// Sales are numbered in the order they were indexed, so newest first is by sale_id
+fn sales_query(of: &SalesOf, params: &ParsedSalesQueryParams, hidden: &HiddenParams, sql_params: &mut SqlParams) -> String {
+  let condition = match of {
+    SalesOf::Inscription(id) => format!("s.id = {}", sql_params.push(id.clone())),
+    SalesOf::Address(address) => format!("(s.seller = {address} OR s.buyer = {address})", address = sql_params.push(address.clone())),
+    SalesOf::Collection(symbol) => format!(
+      "s.id IN (SELECT c.id FROM collections c WHERE c.collection_symbol = {})",
+      sql_params.push(symbol.clone())
+    ),
+  };
+  let mut query = format!(
+    "SELECT s.*, o.number, m.marketplace FROM sales s \
+    LEFT JOIN ordinals o ON o.id = s.id \
+    LEFT JOIN marketplaces m ON m.fee_recipient = s.fee_recipient \
+    WHERE {condition}"
+  );
+  if let Some(visible) = hidden.visible_ids(&["s.id"], sql_params) {
+    query.push_str(&format!(" AND {visible}"));
+  }
+  if let Some(after) = params.page.filter(&params.keyset, sql_params) {
+    query.push_str(&format!(" AND {after}"));
+  }
+  query.push_str(&params.keyset.order_by());
+  query.push_str(&params.page.limit());
+  query
+}
+
+pub(crate) async fn get_sales(pool: deadpool, of: SalesOf, params: ParsedSalesQueryParams, hidden: &HiddenParams) -> anyhow::Result<Paginated<Sale>> {
+  let conn = pool.get().await?;
+  let mut sql_params = SqlParams::default();
+  let query = sales_query(&of, &params, hidden, &mut sql_params);
+  let rows = conn.query(query.as_str(), &sql_params.values()).await?;
+  Ok(params.page.paginate(&params.keyset, rows, |row| Sale {
+    id: row.get("id"),
+    number: row.get("number"),
+    block_number: row.get("block_number"),
+    block_timestamp: row.get("block_timestamp"),
+    transaction: row.get("transaction"),
+    input_index: row.get("input_index"),
+    seller: row.get("seller"),
+    buyer: row.get("buyer"),
+    price: row.get("price"),
+    bundle_price: row.get("bundle_price"),
+    bundle_size: row.get("bundle_size"),
+    sweep_size: row.get("sweep_size"),
+    tx_fee: row.get("tx_fee"),
+    fee_recipient: row.get("fee_recipient"),
+    marketplace_fee: row.get("marketplace_fee"),
+    marketplace: row.get("marketplace"),
+  }))
+}
+
+#[cfg(test)]
+mod tests {
+  use {super::*, bitcoin::{absolute::LockTime, transaction::Version}};
+
+  // A taproot output script, so it has an address
+  fn script(byte: u8) -> ScriptBuf {
+    let mut bytes = vec![0x51, 0x20];
+    bytes.extend([byte; 32]);
+    ScriptBuf::from_bytes(bytes)
+  }
+
+  fn input(vout: u32, sighash: u8) -> TxIn {
+    let mut sig = vec![1; 64];
+    sig.push(sighash);
+    TxIn {
+      previous_output: OutPoint { txid: Txid::all_zeros(), vout },
+      script_sig: ScriptBuf::new(),
+      sequence: Sequence::MAX,
+      witness: Witness::from_slice(&[sig]),
+    }
+  }
+
+  fn output(sats: u64, script_pubkey: ScriptBuf) -> TxOut {
+    TxOut { value: Amount::from_sat(sats), script_pubkey }
+  }
+
+  fn transfer(tx: &Transaction, id: &str, vout: i32, price: i64) -> Transfer {
+    Transfer {
+      id: id.into(),
+      block_number: 1,
+      block_timestamp: 600_000,
+      satpoint: format!("{}:{vout}:0", tx.compute_txid()),
+      tx_offset: 0,
+      transaction: tx.compute_txid().to_string(),
+      vout,
+      offset: 0,
+      address: "buyer".into(),
+      previous_address: "seller".into(),
+      price,
+      tx_fee: 100,
+      tx_size: 200,
+      is_genesis: false,
+      burn_metadata: None,
+    }
+  }
+
+  #[test]
+  fn sweeps_and_bundles_are_split_into_sales() {
+    // A sweep of two listings, the second holding two inscriptions, with the buyer's change going back to their
+    // funding script
+    let tx = Transaction {
+      version: Version(2),
+      lock_time: LockTime::ZERO,
+      input: vec![input(0, 0x01), input(1, 0x83), input(2, 0x83), input(3, 0x01)],
+      output: vec![
+        output(1_000, script(0)),
+        output(100_000, script(1)),
+        output(50_001, script(2)),
+        output(546, script(3)),
+        output(546, script(3)),
+        output(4_500, script(4)),
+        output(90_000, script(0)),
+      ],
+    };
+    let transfers = vec![
+      transfer(&tx, "ai0", 3, 100_000),
+      transfer(&tx, "bi0", 4, 50_001),
+      transfer(&tx, "ci0", 4, 50_001),
+    ];
+    let sold = vec![
+      SoldInput { transfer: 0, input_index: 1 },
+      SoldInput { transfer: 1, input_index: 2 },
+      SoldInput { transfer: 2, input_index: 2 },
+    ];
+    let funding = [script(0)].into_iter().collect();
+    let sales = transaction_sales(Chain::Regtest, &tx, &funding, &transfers, &sold);
+
+    let summary: Vec<(&str, i32, i64, i64, i32, i32, i64)> = sales
+      .iter()
+      .map(|sale| (sale.id.as_str(), sale.input_index, sale.price, sale.bundle_price, sale.bundle_size, sale.sweep_size, sale.marketplace_fee))
+      .collect();
+    assert_eq!(summary, vec![
+      ("ai0", 1, 100_000, 100_000, 1, 3, 3_001),
+      ("bi0", 2, 25_001, 50_001, 2, 3, 750),
+      ("ci0", 2, 25_000, 50_001, 2, 3, 749),
+    ]);
+    let fee_recipient = Chain::Regtest.address_from_script(&script(4)).unwrap().to_string();
+    assert!(sales.iter().all(|sale| sale.fee_recipient.as_deref() == Some(fee_recipient.as_str())));
+  }
+
+  #[test]
+  fn anyonecanpay_all_inputs_share_one_payment() {
+    // Both SIGHASH_ALL|ANYONECANPAY listings are paid by output 1, which is counted once
+    let tx = Transaction {
+      version: Version(2),
+      lock_time: LockTime::ZERO,
+      input: vec![input(0, 0x01), input(1, 0x81), input(2, 0x81), input(3, 0x01)],
+      output: vec![
+        output(546, script(3)),
+        output(60_000, script(1)),
+        output(546, script(3)),
+        output(2_000, script(4)),
+        output(9_000, script(0)),
+      ],
+    };
+    let transfers = vec![transfer(&tx, "ai0", 0, 60_000), transfer(&tx, "bi0", 2, 60_000)];
+    let sold = vec![SoldInput { transfer: 0, input_index: 1 }, SoldInput { transfer: 1, input_index: 2 }];
+    let sales = transaction_sales(Chain::Regtest, &tx, &[script(0)].into_iter().collect(), &transfers, &sold);
+    let summary: Vec<(&str, i32, i64, i64, i32, i64)> = sales
+      .iter()
+      .map(|sale| (sale.id.as_str(), sale.input_index, sale.price, sale.bundle_price, sale.bundle_size, sale.marketplace_fee))
+      .collect();
+    assert_eq!(summary, vec![("ai0", 1, 30_000, 60_000, 2, 1_000), ("bi0", 2, 30_000, 60_000, 2, 1_000)]);
+  }
+
+  #[test]
+  fn sales_without_a_fee_output() {
+    let tx = Transaction {
+      version: Version(2),
+      lock_time: LockTime::ZERO,
+      input: vec![input(0, 0x01), input(1, 0x83)],
+      output: vec![output(546, script(3)), output(20_000, script(1)), output(5_000, script(0))],
+    };
+    let transfers = vec![transfer(&tx, "ai0", 0, 20_000)];
+    let funding = [script(0)].into_iter().collect();
+    let sales = transaction_sales(Chain::Regtest, &tx, &funding, &transfers, &[SoldInput { transfer: 0, input_index: 1 }]);
+    assert_eq!(sales.len(), 1);
+    assert_eq!(sales[0].fee_recipient, None);
+    assert_eq!(sales[0].marketplace_fee, 0);
+  }
+
+  #[tokio::test]
+  #[ignore]
+  async fn sales_are_listed_by_inscription_address_and_collection() {
+    let pool = super::super::tests::TestDatabase::new("vermilion_sales_test").await;
+    Vermilion::initialize_collection_tables(pool.clone()).await.unwrap();
+
+    let tx = Transaction {
+      version: Version(2),
+      lock_time: LockTime::ZERO,
+      input: vec![input(0, 0x01), input(1, 0x83), input(2, 0x83)],
+      output: vec![
+        output(546, script(3)),
+        output(100_000, script(1)),
+        output(40_000, script(2)),
+        output(546, script(3)),
+        output(2_800, script(4)),
+      ],
+    };
+    let transfers = vec![transfer(&tx, "ai0", 0, 100_000), transfer(&tx, "bi0", 3, 40_000)];
+    let sold = vec![SoldInput { transfer: 0, input_index: 1 }, SoldInput { transfer: 1, input_index: 2 }];
+    let sales = transaction_sales(Chain::Regtest, &tx, &[script(0)].into_iter().collect(), &transfers, &sold);
+    let fee_recipient = sales[0].fee_recipient.clone().unwrap();
+
+    let mut conn = pool.get().await.unwrap();
+    let db_tx = conn.transaction().await.unwrap();
+    undo_journal::start_block(&db_tx, 1, 1).await.unwrap();
+    bulk_insert_sales(&db_tx, sales).await.unwrap();
+    db_tx.commit().await.unwrap();
+    conn.execute("INSERT INTO marketplaces VALUES ($1, 'magic eden')", &[&fee_recipient]).await.unwrap();
+    conn.execute("INSERT INTO collections (id, collection_symbol) VALUES ('bi0', 'pixels')", &[]).await.unwrap();
+
+    let params = |sort_by, cursor: Option<&str>| SalesQueryParams { sort_by, page_number: None, page_size: Some(1), cursor: cursor.map(str::to_string) }
+      .parse("sales")
+      .unwrap_or_else(|_| panic!("invalid sales params"));
+    let ids = |page: &Paginated<Sale>| match page {
+      Paginated::Items(items) | Paginated::Page { items, .. } => items.iter().map(|sale| sale.id.clone()).collect::<Vec<_>>(),
+    };
+
+    let page = get_sales(pool.clone(), SalesOf::Address("buyer".into()), params(None, Some("")), &HiddenParams::default()).await.unwrap();
+    assert_eq!(ids(&page), vec!["bi0"]);
+    let Paginated::Page { next_cursor: Some(next_cursor), .. } = page else { panic!("expected a next cursor") };
+    let page = get_sales(pool.clone(), SalesOf::Address("seller".into()), params(None, Some(&next_cursor)), &HiddenParams::default()).await.unwrap();
+    assert_eq!(ids(&page), vec!["ai0"]);
+    let page = get_sales(pool.clone(), SalesOf::Address("buyer".into()), params(Some(SaleSortBy::LowestPrice), None), &HiddenParams::default()).await.unwrap();
+    assert_eq!(ids(&page), vec!["bi0"]);
+
+    let Paginated::Items(sales) = get_sales(pool.clone(), SalesOf::Inscription("ai0".into()), params(None, None), &HiddenParams::default()).await.unwrap() else { panic!("expected a page") };
+    assert_eq!((sales[0].marketplace.as_deref(), sales[0].marketplace_fee, sales[0].sweep_size), (Some("magic eden"), 2_000, 2));
+    let page = get_sales(pool.clone(), SalesOf::Collection("pixels".into()), params(None, None), &HiddenParams::default()).await.unwrap();
+    assert_eq!(ids(&page), vec!["bi0"]);
+
+    Vermilion::handle_reorg(pool.clone(), 0).await.unwrap();
+    let page = get_sales(pool.clone(), SalesOf::Inscription("ai0".into()), params(None, None), &HiddenParams::default()).await.unwrap();
+    assert!(ids(&page).is_empty());
+  }
+}
@@ -0,0 +1,173 @@
+use super::*;
+use async_trait::async_trait;
+use axum_session::{DatabaseError, DatabasePool};
+
+pub(crate) const SESSIONS_TABLE: &str = "sessions_table";
+
+// Feed sessions (the bands a visitor has already been shown) are kept in postgres so they survive restarts
+// and are shared by every API replica. Expired rows are purged by the session layer on a timer.
+#[derive(Clone)]
+pub(crate) struct SessionPgPool {
+  pool: deadpool,
+}
+
+impl std::fmt::Debug for SessionPgPool {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("SessionPgPool").finish_non_exhaustive()
+  }
+}
+
+impl SessionPgPool {
+  pub(crate) fn new(pool: deadpool) -> Self {
+    Self { pool }
+  }
+
+  async fn client(&self) -> Result<deadpool_postgres::Object, DatabaseError> {
+    self.pool.get().await.map_err(|err| DatabaseError::GenericAquire(err.to_string()))
+  }
+}
+
+fn now() -> i64 {
+  chrono::Utc::now().timestamp()
+}
+
+#[async_trait]
+impl DatabasePool for SessionPgPool {
+  async fn initiate(&self, table_name: &str) -> Result<(), DatabaseError> {
+    self.client().await?.batch_execute(&format!(
+      r#"CREATE TABLE IF NOT EXISTS {table_name} (
+        id varchar(128) not null primary key,
+        expires bigint,
+        session text not null
+      );
+      CREATE INDEX IF NOT EXISTS {table_name}_expires ON {table_name} (expires);"#
+    )).await.map_err(|err| DatabaseError::GenericCreateError(err.to_string()))
+  }
+
+  async fn count(&self, table_name: &str) -> Result<i64, DatabaseError> {
+    let row = self.client().await?
+      .query_one(&format!("SELECT COUNT(*) FROM {table_name}"), &[])
+      .await
+      .map_err(|err| DatabaseError::GenericSelectError(err.to_string()))?;
+    Ok(row.get(0))
+  }
+
+  async fn store(&self, id: &str, session: &str, expires: i64, table_name: &str) -> Result<(), DatabaseError> {
+    self.client().await?.execute(
+      &format!(
+        r#"INSERT INTO {table_name} (id, session, expires) VALUES ($1, $2, $3)
+        ON CONFLICT (id) DO UPDATE SET session = EXCLUDED.session, expires = EXCLUDED.expires"#
+      ),
+      &[&id, &session, &expires]
+    ).await.map_err(|err| DatabaseError::GenericInsertError(err.to_string()))?;
+    Ok(())
+  }
+
+  async fn load(&self, id: &str, table_name: &str) -> Result<Option<String>, DatabaseError> {
+    let row = self.client().await?
+      .query_opt(
+        &format!("SELECT session FROM {table_name} WHERE id = $1 AND (expires IS NULL OR expires > $2)"),
+        &[&id, &now()]
+      )
+      .await
+      .map_err(|err| DatabaseError::GenericSelectError(err.to_string()))?;
+    Ok(row.map(|row| row.get(0)))
+  }
+
+  async fn delete_one_by_id(&self, id: &str, table_name: &str) -> Result<(), DatabaseError> {
+    self.client().await?
+      .execute(&format!("DELETE FROM {table_name} WHERE id = $1"), &[&id])
+      .await
+      .map_err(|err| DatabaseError::GenericDeleteError(err.to_string()))?;
+    Ok(())
+  }
+
+  async fn exists(&self, id: &str, table_name: &str) -> Result<bool, DatabaseError> {
+    let row = self.client().await?
+      .query_opt(
+        &format!("SELECT 1 FROM {table_name} WHERE id = $1 AND (expires IS NULL OR expires > $2)"),
+        &[&id, &now()]
+      )
+      .await
+      .map_err(|err| DatabaseError::GenericSelectError(err.to_string()))?;
+    Ok(row.is_some())
+  }
+
+  async fn delete_by_expiry(&self, table_name: &str) -> Result<Vec<String>, DatabaseError> {
+    let rows = self.client().await?
+      .query(&format!("DELETE FROM {table_name} WHERE expires <= $1 RETURNING id"), &[&now()])
+      .await
+      .map_err(|err| DatabaseError::GenericDeleteError(err.to_string()))?;
+    Ok(rows.iter().map(|row| row.get(0)).collect())
+  }
+
+  async fn delete_all(&self, table_name: &str) -> Result<(), DatabaseError> {
+    self.client().await?
+      .batch_execute(&format!("TRUNCATE {table_name}"))
+      .await
+      .map_err(|err| DatabaseError::GenericDeleteError(err.to_string()))
+  }
+
+  async fn get_ids(&self, table_name: &str) -> Result<Vec<String>, DatabaseError> {
+    let rows = self.client().await?
+      .query(&format!("SELECT id FROM {table_name} WHERE expires IS NULL OR expires > $1"), &[&now()])
+      .await
+      .map_err(|err| DatabaseError::GenericSelectError(err.to_string()))?;
+    Ok(rows.iter().map(|row| row.get(0)).collect())
+  }
+
+  fn auto_handles_expiry(&self) -> bool {
+    false
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::tests::TestDatabase;
+
+  async fn replica(pool: deadpool) -> String {
+    let config = SessionConfig::default()
+      .with_table_name(SESSIONS_TABLE)
+      .with_memory_lifetime(chrono::Duration::zero());
+    let store = SessionStore::new(Some(SessionPgPool::new(pool)), config).await.unwrap();
+    let app = axum::Router::new()
+      .route("/seen", axum::routing::get(|session: Session<SessionPgPool>| async move {
+        let mut seen: Vec<i64> = session.get("seen").unwrap_or_default();
+        seen.push(seen.len() as i64);
+        session.set("seen", seen.clone());
+        Json(seen)
+      }))
+      .layer(SessionLayer::new(store));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/seen", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    url
+  }
+
+  #[tokio::test]
+  #[ignore]
+  async fn sessions_are_shared_between_replicas_and_expire() {
+    let pool = TestDatabase::empty("vermilion_sessions_test").await;
+
+    let (first, second) = (replica(pool.clone()).await, replica(pool.clone()).await);
+    let response = reqwest::get(&first).await.unwrap();
+    let cookies: Vec<String> = response.headers().get_all(http::header::SET_COOKIE).iter()
+      .map(|cookie| cookie.to_str().unwrap().split(';').next().unwrap().to_string())
+      .collect();
+    assert_eq!(response.json::<Vec<i64>>().await.unwrap(), vec![0]);
+    let client = reqwest::Client::new();
+    for (url, expected) in [(&second, vec![0, 1]), (&first, vec![0, 1, 2])] {
+      let seen: Vec<i64> = client.get(url).header(http::header::COOKIE, cookies.join("; ")).send().await.unwrap().json().await.unwrap();
+      assert_eq!(seen, expected);
+    }
+
+    let sessions = SessionPgPool::new(pool.clone());
+    assert_eq!(sessions.count(SESSIONS_TABLE).await.unwrap(), 1);
+    sessions.store("stale", "{}", now() - 1, SESSIONS_TABLE).await.unwrap();
+    assert_eq!(sessions.load("stale", SESSIONS_TABLE).await.unwrap(), None);
+    assert!(!sessions.exists("stale", SESSIONS_TABLE).await.unwrap());
+    assert_eq!(sessions.delete_by_expiry(SESSIONS_TABLE).await.unwrap(), vec!["stale".to_string()]);
+    assert_eq!(sessions.get_ids(SESSIONS_TABLE).await.unwrap().len(), 1);
+  }
+}
@@ -0,0 +1,398 @@
+use super::*;
+use aide::{generate::GenContext, openapi::Operation, OperationOutput};
+use axum::{http::header, response::Response};
+use std::io::Write;
+
+// Who held a set of inscriptions or a rune at the end of a past block. Inscription owners come from the last
+// transfer of each inscription at or below the height, rune balances from the outputs that were unspent then.
+// Set membership is current: a collection is the inscriptions it has now.
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum SnapshotOf {
+  Collection(String),
+  OnChainCollection(Vec<String>),
+  Gallery(String),
+  Inscriptions(Vec<String>),
+  Rune(String),
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct HolderSnapshot {
+  height: i64,
+  holders: Vec<SnapshotHolder>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SnapshotHolder {
+  address: String,
+  /// Inscriptions held, or outputs holding the rune
+  count: i64,
+  /// Rune balance in the rune's smallest unit, null for inscriptions
+  amount: Option<String>,
+  /// Ids of the inscriptions held, empty for runes
+  inscriptions: Vec<String>,
+}
+
+impl HolderSnapshot {
+  pub(crate) fn to_csv(&self) -> anyhow::Result<Vec<u8>> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(["address", "count", "amount", "inscriptions"])?;
+    for holder in &self.holders {
+      writer.write_record([
+        holder.address.as_str(),
+        holder.count.to_string().as_str(),
+        holder.amount.as_deref().unwrap_or(""),
+        holder.inscriptions.join(" ").as_str(),
+      ])?;
+    }
+    Ok(writer.into_inner()?)
+  }
+}
+
+pub(crate) async fn get_indexed_height(pool: &deadpool) -> anyhow::Result<i64> {
+  let conn = pool.get().await?;
+  let row = conn.query_one("SELECT coalesce(max(block_number), -1) FROM blockstats", &[]).await?;
+  Ok(row.get(0))
+}
+
+pub(crate) async fn get_holder_snapshot(pool: deadpool, of: SnapshotOf, height: i64, hidden: &HiddenParams) -> anyhow::Result<HolderSnapshot> {
+  let conn = pool.get().await?;
+  let holders = if let SnapshotOf::Rune(rune) = &of {
+    let query = format!(
+      r"WITH m AS (SELECT r.block, r.tx_index FROM runes r WHERE {} LIMIT 1)
+      SELECT b.address, count(*) AS count, sum(b.amount)::text AS amount
+      FROM m
+      JOIN rune_balances b ON b.rune_block = m.block AND b.rune_tx_index = m.tx_index
+      WHERE b.block <= $2 AND (b.spent_block IS NULL OR b.spent_block > $2) AND b.address IS NOT NULL
+      GROUP BY b.address
+      ORDER BY sum(b.amount) DESC, b.address",
+      rune_indexer::RUNE_MATCH_CLAUSE
+    );
+    conn.query(query.as_str(), &[rune, &height]).await?
+      .into_iter()
+      .map(|row| SnapshotHolder {
+        address: row.get("address"),
+        count: row.get("count"),
+        amount: row.get("amount"),
+        inscriptions: Vec::new(),
+      })
+      .collect()
+  } else {
+    let mut sql_params = SqlParams::default();
+    let ids = match &of {
+      SnapshotOf::Collection(symbol) => format!("SELECT id FROM collections WHERE collection_symbol = {}", sql_params.push(symbol.clone())),
+      SnapshotOf::OnChainCollection(parents) => format!("SELECT id FROM ordinals WHERE parents = {}", sql_params.push(parents.clone())),
+      SnapshotOf::Gallery(gallery_id) => format!("SELECT inscription_id FROM inscription_galleries WHERE gallery_id = {}", sql_params.push(gallery_id.clone())),
+      SnapshotOf::Inscriptions(ids) => format!("SELECT unnest({}::varchar[])", sql_params.push(ids.clone())),
+      SnapshotOf::Rune(_) => unreachable!(),
+    };
+    let mut transfers = format!("t.id IN ({ids}) AND t.block_number <= {}", sql_params.push(height));
+    if let Some(visible) = hidden.visible_ids(&["t.id"], &mut sql_params) {
+      transfers.push_str(&format!(" AND {visible}"));
+    }
+    // Transfers later in a block come later in the block's transactions. Inscriptions whose last transfer was to a
+    // pseudo address have no holder.
+    let query = format!(
+      r"SELECT o.address, count(*) AS count, array_agg(o.id ORDER BY o.id) AS inscriptions
+      FROM (
+        SELECT DISTINCT ON (t.id) t.id, t.address
+        FROM transfers t
+        WHERE {transfers}
+        ORDER BY t.id, t.block_number DESC, t.tx_offset DESC
+      ) o
+      WHERE {}
+      GROUP BY o.address
+      ORDER BY count(*) DESC, o.address",
+      is_holder_address("o.address")
+    );
+    conn.query(query.as_str(), &sql_params.values()).await?
+      .into_iter()
+      .map(|row| SnapshotHolder {
+        address: row.get("address"),
+        count: row.get("count"),
+        amount: None,
+        inscriptions: row.get("inscriptions"),
+      })
+      .collect()
+  };
+  Ok(HolderSnapshot { height, holders })
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct SnapshotParams {
+  /// Off-chain collection symbol
+  #[schemars(description = "Snapshot holders of the off-chain collection with this symbol")]
+  collection: Option<String>,
+
+  /// Parent inscription ids of an on-chain collection
+  #[schemars(description = "Snapshot holders of the on-chain collection with these comma separated parents")]
+  on_chain_collection: Option<String>,
+
+  /// Gallery inscription id
+  #[schemars(description = "Snapshot holders of the inscriptions in this gallery")]
+  gallery: Option<String>,
+
+  /// Inscription ids
+  #[schemars(description = "Snapshot holders of these comma separated inscriptions")]
+  inscriptions: Option<String>,
+
+  /// Rune name or id
+  #[schemars(description = "Snapshot holders of the rune with this name (with or without spacers) or BLOCK:TX id")]
+  rune: Option<String>,
+
+  /// Block height
+  #[schemars(description = "Ownership at the end of this block, at most the indexed height", range(min = 0))]
+  height: i64,
+
+  /// Response format
+  #[schemars(description = "json (default) or csv")]
+  format: Option<SnapshotFormat>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SnapshotFormat {
+  Json,
+  Csv,
+}
+
+impl SnapshotParams {
+  pub(crate) fn target(&self) -> Result<SnapshotOf, ApiError> {
+    let split = |list: &str| list.split(',').map(|item| item.to_string()).collect();
+    let targets = [
+      self.collection.clone().map(SnapshotOf::Collection),
+      self.on_chain_collection.as_deref().map(|parents| SnapshotOf::OnChainCollection(split(parents))),
+      self.gallery.clone().map(SnapshotOf::Gallery),
+      self.inscriptions.as_deref().map(|ids| SnapshotOf::Inscriptions(split(ids))),
+      self.rune.clone().map(SnapshotOf::Rune),
+    ];
+    match targets.into_iter().flatten().collect::<Vec<_>>().as_slice() {
+      [target] => Ok(target.clone()),
+      _ => Err(ApiError::BadRequest(
+        "Exactly one of collection, on_chain_collection, gallery, inscriptions or rune is required".to_string(),
+      )),
+    }
+  }
+
+  pub(crate) fn height(&self, indexed_height: i64) -> Result<i64, ApiError> {
+    if self.height < 0 || self.height > indexed_height {
+      return Err(ApiError::BadRequest(format!("Height must be between 0 and the indexed height {indexed_height}")));
+    }
+    Ok(self.height)
+  }
+
+  pub(crate) fn is_csv(&self) -> bool {
+    self.format == Some(SnapshotFormat::Csv)
+  }
+}
+
+pub enum SnapshotResponse {
+  Json(HolderSnapshot),
+  Csv(Vec<u8>),
+}
+
+impl IntoResponse for SnapshotResponse {
+  fn into_response(self) -> Response {
+    match self {
+      SnapshotResponse::Json(snapshot) => Json(snapshot).into_response(),
+      SnapshotResponse::Csv(csv) => ([(header::CONTENT_TYPE, "text/csv")], csv).into_response(),
+    }
+  }
+}
+
+impl OperationOutput for SnapshotResponse {
+  type Inner = HolderSnapshot;
+
+  fn operation_response(ctx: &mut GenContext, operation: &mut Operation) -> Option<aide::openapi::Response> {
+    Json::<HolderSnapshot>::operation_response(ctx, operation)
+  }
+
+  fn inferred_responses(ctx: &mut GenContext, operation: &mut Operation) -> Vec<(Option<u16>, aide::openapi::Response)> {
+    Json::<HolderSnapshot>::inferred_responses(ctx, operation)
+  }
+}
+
+#[derive(Debug, Parser, Clone)]
+#[clap(
+group(
+  ArgGroup::new("target")
+    .required(true)
+    .args(&["collection", "on_chain_collection", "gallery", "inscriptions", "rune"]))
+)]
+pub(crate) struct Snapshot {
+  #[arg(long, help = "Snapshot holders of the off-chain collection with symbol <COLLECTION>.")]
+  collection: Option<String>,
+  #[arg(long, value_delimiter = ',', help = "Snapshot holders of the on-chain collection with the comma separated parents <ON_CHAIN_COLLECTION>.")]
+  on_chain_collection: Option<Vec<String>>,
+  #[arg(long, help = "Snapshot holders of the inscriptions in gallery <GALLERY>.")]
+  gallery: Option<String>,
+  #[arg(long, value_delimiter = ',', help = "Snapshot holders of the comma separated <INSCRIPTIONS>.")]
+  inscriptions: Option<Vec<String>>,
+  #[arg(long, help = "Snapshot holders of <RUNE>, by name or BLOCK:TX id.")]
+  rune: Option<String>,
+  #[arg(long, help = "Snapshot ownership at the end of block <HEIGHT>.")]
+  height: u32,
+  #[arg(long, help = "Print holders as CSV instead of JSON.")]
+  csv: bool,
+}
+
+impl Snapshot {
+  fn target(&self) -> SnapshotOf {
+    if let Some(symbol) = &self.collection {
+      SnapshotOf::Collection(symbol.clone())
+    } else if let Some(parents) = &self.on_chain_collection {
+      SnapshotOf::OnChainCollection(parents.clone())
+    } else if let Some(gallery_id) = &self.gallery {
+      SnapshotOf::Gallery(gallery_id.clone())
+    } else if let Some(ids) = &self.inscriptions {
+      SnapshotOf::Inscriptions(ids.clone())
+    } else {
+      SnapshotOf::Rune(self.rune.clone().unwrap_or_default())
+    }
+  }
+
+  pub(crate) fn run(&self, settings: Settings) -> SubcommandResult {
+    let rt = tokio::runtime::Builder::new_current_thread()
+      .enable_all()
+      .build()?;
+    let snapshot = rt.block_on(async {
+      let pool = Vermilion::get_deadpool(settings).await?;
+      let indexed_height = get_indexed_height(&pool).await?;
+      anyhow::ensure!(i64::from(self.height) <= indexed_height, "height {} is above the indexed height {indexed_height}", self.height);
+      // Operators see hidden inscriptions
+      get_holder_snapshot(pool, self.target(), i64::from(self.height), &HiddenParams::default()).await
+    })?;
+    if self.csv {
+      io::stdout().write_all(&snapshot.to_csv()?)?;
+      return Ok(None);
+    }
+    Ok(Some(Box::new(snapshot)))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use {
+    super::*,
+    super::super::tests::{index_test_block, TestDatabase, test_inscription, test_transfer, TestBlock},
+  };
+
+  #[test]
+  fn cli_requires_exactly_one_target() {
+    let parse = |args: &[&str]| Snapshot::try_parse_from([&["snapshot"], args].concat());
+    assert_eq!(
+      parse(&["--on-chain-collection", "ai0,bi0", "--height", "5"]).unwrap().target(),
+      SnapshotOf::OnChainCollection(vec!["ai0".into(), "bi0".into()])
+    );
+    assert_eq!(parse(&["--rune", "840000:3", "--height", "5"]).unwrap().target(), SnapshotOf::Rune("840000:3".into()));
+    assert!(parse(&["--height", "5"]).is_err());
+    assert!(parse(&["--collection", "pixels", "--rune", "840000:3", "--height", "5"]).is_err());
+  }
+
+  #[test]
+  fn height_must_be_indexed() {
+    let params = |height: i64| serde_json::from_value::<SnapshotParams>(serde_json::json!({ "rune": "840000:3", "height": height })).unwrap();
+    assert!(matches!(params(0).height(5), Ok(0)));
+    assert!(matches!(params(5).height(5), Ok(5)));
+    assert!(matches!(params(-1).height(5), Err(ApiError::BadRequest(_))));
+    assert!(matches!(params(6).height(5), Err(ApiError::BadRequest(_))));
+    assert!(matches!(params(0).height(-1), Err(ApiError::BadRequest(_))));
+  }
+
+  #[test]
+  fn csv_has_a_row_per_holder() {
+    let snapshot = HolderSnapshot {
+      height: 5,
+      holders: vec![
+        SnapshotHolder { address: "bc1qone".into(), count: 2, amount: None, inscriptions: vec!["ai0".into(), "bi0".into()] },
+        SnapshotHolder { address: "bc1qtwo".into(), count: 1, amount: Some("1000".into()), inscriptions: Vec::new() },
+      ],
+    };
+    assert_eq!(
+      String::from_utf8(snapshot.to_csv().unwrap()).unwrap(),
+      "address,count,amount,inscriptions\nbc1qone,2,,ai0 bi0\nbc1qtwo,1,1000,\n"
+    );
+  }
+
+  #[tokio::test]
+  #[ignore]
+  async fn holder_snapshot_at_past_heights() {
+    let pool = TestDatabase::new("vermilion_holder_snapshot_test").await;
+
+    let first = test_inscription(0, 1, &"a".repeat(64), Vec::new(), None);
+    let second = test_inscription(1, 1, &"b".repeat(64), Vec::new(), None);
+    pool.get().await.unwrap().execute(
+      "INSERT INTO collections (id, collection_symbol) VALUES ($1, 'pixels'), ($2, 'pixels')",
+      &[&first.id, &second.id],
+    ).await.unwrap();
+    index_test_block(&pool, &TestBlock {
+      height: 1,
+      inscriptions: vec![first.clone(), second.clone()],
+      galleries: Vec::new(),
+      transfers: vec![test_transfer(&first, 1, "", "bc1qone", 0), test_transfer(&second, 1, "", "bc1qone", 0)],
+    }).await;
+    index_test_block(&pool, &TestBlock {
+      height: 2,
+      inscriptions: Vec::new(),
+      galleries: Vec::new(),
+      transfers: vec![test_transfer(&second, 2, "bc1qone", "bc1qtwo", 1_000)],
+    }).await;
+
+    let holders = |snapshot: HolderSnapshot| {
+      snapshot.holders.into_iter().map(|holder| (holder.address, holder.count, holder.inscriptions)).collect::<Vec<_>>()
+    };
+    let collection = SnapshotOf::Collection("pixels".into());
+    assert_eq!(holders(get_holder_snapshot(pool.clone(), collection.clone(), 1, &HiddenParams::default()).await.unwrap()), vec![
+      ("bc1qone".to_string(), 2, vec![first.id.clone(), second.id.clone()]),
+    ]);
+    assert_eq!(holders(get_holder_snapshot(pool.clone(), collection.clone(), 2, &HiddenParams::default()).await.unwrap()), vec![
+      ("bc1qone".to_string(), 1, vec![first.id.clone()]),
+      ("bc1qtwo".to_string(), 1, vec![second.id.clone()]),
+    ]);
+    assert!(holders(get_holder_snapshot(pool.clone(), collection.clone(), 0, &HiddenParams::default()).await.unwrap()).is_empty());
+    let inscriptions = SnapshotOf::Inscriptions(vec![second.id.clone()]);
+    assert_eq!(holders(get_holder_snapshot(pool.clone(), inscriptions.clone(), 2, &HiddenParams::default()).await.unwrap()), vec![
+      ("bc1qtwo".to_string(), 1, vec![second.id.clone()]),
+    ]);
+    let hidden = HiddenParams { ids: vec![second.id.clone()], sha256s: Vec::new() };
+    assert_eq!(holders(get_holder_snapshot(pool.clone(), collection.clone(), 2, &hidden).await.unwrap()), vec![
+      ("bc1qone".to_string(), 1, vec![first.id.clone()]),
+    ]);
+    assert!(holders(get_holder_snapshot(pool.clone(), inscriptions, 2, &hidden).await.unwrap()).is_empty());
+
+    // Burned inscriptions and outputs without an address have no holder
+    index_test_block(&pool, &TestBlock {
+      height: 3,
+      inscriptions: Vec::new(),
+      galleries: Vec::new(),
+      transfers: vec![
+        test_transfer(&first, 3, "bc1qone", "burned", 0),
+        test_transfer(&second, 3, "bc1qtwo", "script is not a p2pkh, p2sh or witness program", 0),
+      ],
+    }).await;
+    assert_eq!(get_indexed_height(&pool).await.unwrap(), 3);
+    assert!(holders(get_holder_snapshot(pool.clone(), collection.clone(), 3, &HiddenParams::default()).await.unwrap()).is_empty());
+    assert_eq!(holders(get_holder_snapshot(pool.clone(), collection.clone(), 2, &HiddenParams::default()).await.unwrap()).len(), 2);
+
+    // A rune etched in block 1, split across two outputs in block 2 and one of them spent in block 3
+    pool.get().await.unwrap().simple_query(r"
+      INSERT INTO runes (block, tx_index, spaced_rune, unspaced_rune) VALUES (1, 0, 'A•RUNE', 'ARUNE');
+      INSERT INTO rune_balances (outpoint, rune_block, rune_tx_index, amount, address, block, spent_block) VALUES
+        ('e:0', 1, 0, 100, 'bc1qone', 1, 2),
+        ('f:0', 1, 0, 60, 'bc1qone', 2, NULL),
+        ('f:1', 1, 0, 40, 'bc1qtwo', 2, 3),
+        ('f:2', 1, 0, 5, NULL, 2, NULL);
+    ").await.unwrap();
+    let visible = HiddenParams::default();
+    let rune = |height| get_holder_snapshot(pool.clone(), SnapshotOf::Rune("A•RUNE".into()), height, &visible);
+    let amounts = |snapshot: HolderSnapshot| {
+      snapshot.holders.into_iter().map(|holder| (holder.address, holder.count, holder.amount.unwrap())).collect::<Vec<_>>()
+    };
+    assert_eq!(amounts(rune(1).await.unwrap()), vec![("bc1qone".to_string(), 1, "100".to_string())]);
+    assert_eq!(amounts(rune(2).await.unwrap()), vec![
+      ("bc1qone".to_string(), 1, "60".to_string()),
+      ("bc1qtwo".to_string(), 1, "40".to_string()),
+    ]);
+    assert_eq!(amounts(rune(3).await.unwrap()), vec![("bc1qone".to_string(), 1, "60".to_string())]);
+  }
+}
@@ -2,9 +2,9 @@ use super::*;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct User {
-  user_id: Option<i64>,
+  pub(crate) user_id: Option<i64>,
   user_name: String,
-  user_addresses: Vec<String>,
+  pub(crate) user_addresses: Vec<String>,
   user_picture: Option<String>,
   user_bio: Option<String>,
   user_twitter: Option<String>,
@@ -15,7 +15,7 @@ pub struct User {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Follow {
-  follower_id: i64,
+  pub(crate) follower_id: i64,
   following_id: i64,
   created_at: Option<i64>,
 }
@@ -23,15 +23,15 @@ pub struct Follow {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Like {  
   inscription_id: String,
-  user_id: i64,
+  pub(crate) user_id: i64,
   created_at: Option<i64>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Comment {
-  comment_id: i64,
+  pub(crate) comment_id: i64,
   inscription_id: String,
-  user_id: i64,
+  pub(crate) user_id: i64,
   comment: String,
   parent_comment_id: Option<i64>,
   created_at: Option<i64>,
@@ -39,8 +39,8 @@ pub struct Comment {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PlaylistInfo {
-  playlist_id: Option<i64>,
-  user_id: i64,
+  pub(crate) playlist_id: Option<i64>,
+  pub(crate) user_id: i64,
   playlist_name: String,
   playlist_picture: Option<String>,
   playlist_description: Option<String>,
@@ -52,7 +52,7 @@ pub struct PlaylistInfo {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PlaylistInscription {
-  playlist_id: i64,
+  pub(crate) playlist_id: i64,
   inscription_id: String,
   added_at: Option<i64>,
 }
@@ -64,6 +64,7 @@ pub async fn initialize_social_tables(pool: deadpool) -> anyhow::Result<()> {
   create_comments_table(pool.clone()).await.context("Failed to create comments table")?;
   create_playlist_info_table(pool.clone()).await.context("Failed to create playlist info table")?;
   create_playlist_inscriptions_table(pool.clone()).await.context("Failed to create playlist inscriptions table")?;
+  create_auth_challenges_table(pool.clone()).await.context("Failed to create auth challenges table")?;
   Ok(())
 }
 
@@ -151,6 +152,18 @@ async fn create_playlist_inscriptions_table(pool: deadpool) -> anyhow::Result<()
   Ok(())
 }
 
+async fn create_auth_challenges_table(pool: deadpool) -> anyhow::Result<()> {
+  let conn = pool.get().await?;
+  conn.simple_query(r"
+    CREATE TABLE IF NOT EXISTS auth_challenges (
+      nonce varchar(64) PRIMARY KEY,
+      address varchar(100) NOT NULL,
+      message text NOT NULL,
+      expires_at bigint NOT NULL
+    )").await?;
+  Ok(())
+}
+
 pub async fn insert_user(pool: &deadpool, user: &User) -> anyhow::Result<i64> {
   let conn = pool.get().await?;
   let row = conn.query_one(r"
@@ -290,6 +303,63 @@ pub async fn delete_playlist_inscription(pool: &deadpool, playlist_id: i64, insc
   Ok(())
 }
 
+// Login challenges, stored so any API server can finish a login another one started
+pub async fn insert_auth_challenge(pool: &deadpool, nonce: &str, address: &str, message: &str, expires_at: i64, now: i64) -> anyhow::Result<()> {
+  let conn = pool.get().await?;
+  conn.execute("DELETE FROM auth_challenges WHERE expires_at < $1", &[&now]).await?;
+  conn.execute(r"
+      INSERT INTO auth_challenges (nonce, address, message, expires_at)
+      VALUES ($1, $2, $3, $4)
+  ", &[&nonce, &address, &message, &expires_at]).await?;
+  Ok(())
+}
+
+// Challenges can only be used once, returns the address and message if it hasn't expired
+pub async fn take_auth_challenge(pool: &deadpool, nonce: &str, now: i64) -> anyhow::Result<Option<(String, String)>> {
+  let conn = pool.get().await?;
+  let row = conn.query_opt(
+    "DELETE FROM auth_challenges WHERE nonce = $1 RETURNING address, message, expires_at",
+    &[&nonce]
+  ).await?;
+  Ok(row
+    .filter(|row| row.get::<_, i64>("expires_at") >= now)
+    .map(|row| (row.get("address"), row.get("message"))))
+}
+
+// Ownership checks for the mutating routes
+pub async fn user_has_address(pool: &deadpool, user_id: i64, address: &str) -> anyhow::Result<bool> {
+  let conn = pool.get().await?;
+  let row = conn.query_one(
+    "SELECT EXISTS(SELECT 1 FROM users WHERE user_id = $1 AND $2 = ANY(user_addresses))",
+    &[&user_id, &address]
+  ).await?;
+  Ok(row.get(0))
+}
+
+pub async fn get_user_id_by_address(pool: &deadpool, address: &str) -> anyhow::Result<Option<i64>> {
+  let conn = pool.get().await?;
+  let row = conn.query_opt("SELECT user_id FROM users WHERE $1 = ANY(user_addresses) LIMIT 1", &[&address]).await?;
+  Ok(row.map(|row| row.get(0)))
+}
+
+pub async fn get_user_addresses(pool: &deadpool, user_id: i64) -> anyhow::Result<Option<Vec<String>>> {
+  let conn = pool.get().await?;
+  let row = conn.query_opt("SELECT user_addresses FROM users WHERE user_id = $1", &[&user_id]).await?;
+  Ok(row.map(|row| row.get::<_, Option<Vec<String>>>(0).unwrap_or_default()))
+}
+
+pub async fn get_comment_user_id(pool: &deadpool, comment_id: i64) -> anyhow::Result<Option<i64>> {
+  let conn = pool.get().await?;
+  let row = conn.query_opt("SELECT user_id FROM comments WHERE comment_id = $1", &[&comment_id]).await?;
+  Ok(row.and_then(|row| row.get(0)))
+}
+
+pub async fn get_playlist_user_id(pool: &deadpool, playlist_id: i64) -> anyhow::Result<Option<i64>> {
+  let conn = pool.get().await?;
+  let row = conn.query_opt("SELECT user_id FROM playlist_info WHERE playlist_id = $1", &[&playlist_id]).await?;
+  Ok(row.and_then(|row| row.get(0)))
+}
+
 pub async fn get_user(pool: &deadpool, user_id: i64) -> anyhow::Result<User> {
   let conn = pool.get().await?;
   let row = conn.query_one("SELECT * FROM users WHERE user_id = $1", &[&user_id]).await?;
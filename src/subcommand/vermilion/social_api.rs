@@ -1,5 +1,6 @@
 use super::*;
 use self::social::*;
+use self::social_auth::*;
 use axum::{
   routing::get,
   routing::post,
@@ -9,7 +10,9 @@ use axum::{
 
 //API
 pub fn social_router() -> Router<ApiServerConfig> {
-  let app = Router::new()
+  Router::new()
+    .route("/social/auth/challenge", post(create_challenge_handler))
+    .route("/social/auth/login", post(login_handler))
     .route("/social/user", post(create_user_handler))    
     .route("/social/user/{user_id}", get(get_user_handler))
     .route("/social/user/{user_id}", put(update_user_handler))
@@ -31,11 +34,123 @@ pub fn social_router() -> Router<ApiServerConfig> {
     .route("/social/playlist_inscription", post(create_playlist_inscription_handler))
     .route("/social/playlist_inscription/{playlist_id}", get(get_playlist_inscriptions_handler))
     .route("/social/playlist_inscription/{playlist_id}/{inscription_id}", delete(delete_playlist_inscription_handler))
-    .route("/social/playlists/{user_id}", get(get_playlists_handler));
-  app
+    .route("/social/playlists/{user_id}", get(get_playlists_handler))
  }
 
-async fn create_user_handler(State(server_config): State<ApiServerConfig>, Json(user): Json<User>) -> impl axum::response::IntoResponse {
+async fn create_challenge_handler(State(server_config): State<ApiServerConfig>, Json(request): Json<ChallengeRequest>) -> impl axum::response::IntoResponse {
+  if server_config.access_token_secret.is_none() {
+    return (StatusCode::SERVICE_UNAVAILABLE, "Social login is disabled, access_token_secret is not set").into_response();
+  }
+  let address = match parse_address(&request.address, server_config.chain) {
+    Ok(address) => address,
+    Err(error) => {
+      return (StatusCode::BAD_REQUEST, format!("Invalid address {}: {}", request.address, error)).into_response();
+    }
+  };
+  let nonce = hex::encode(rand::random::<[u8; 16]>());
+  let now = now();
+  let expires_at = now + CHALLENGE_LIFETIME_SECONDS;
+  let message = challenge_message(&address, &nonce, expires_at);
+  match insert_auth_challenge(&server_config.deadpool, &nonce, &address, &message, expires_at, now).await {
+    Ok(_) => (),
+    Err(error) => {
+      log::warn!("Error creating /auth/challenge: {}", error);
+      return (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "Error creating challenge",
+      ).into_response();
+    }
+  };
+  (StatusCode::CREATED, Json(Challenge { address, nonce, message, expires_at })).into_response()
+}
+
+async fn login_handler(State(server_config): State<ApiServerConfig>, Json(request): Json<LoginRequest>) -> impl axum::response::IntoResponse {
+  let Some(secret) = server_config.access_token_secret.as_deref() else {
+    return (StatusCode::SERVICE_UNAVAILABLE, "Social login is disabled, access_token_secret is not set").into_response();
+  };
+  let now = now();
+  let (address, message) = match take_auth_challenge(&server_config.deadpool, &request.nonce, now).await {
+    Ok(Some(challenge)) => challenge,
+    Ok(None) => return (StatusCode::UNAUTHORIZED, "Unknown or expired challenge").into_response(),
+    Err(error) => {
+      log::warn!("Error getting /auth/login challenge: {}", error);
+      return (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "Error logging in",
+      ).into_response();
+    }
+  };
+  if !verify_signature(&address, &message, &request.signature) {
+    return (StatusCode::UNAUTHORIZED, format!("Invalid signature for {}", address)).into_response();
+  }
+  let user_id = match get_user_id_by_address(&server_config.deadpool, &address).await {
+    Ok(user_id) => user_id,
+    Err(error) => {
+      log::warn!("Error getting /auth/login user: {}", error);
+      return (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "Error logging in",
+      ).into_response();
+    }
+  };
+  let expires_at = now + ACCESS_TOKEN_LIFETIME_SECONDS;
+  let access_token = issue_access_token(secret, &address, expires_at);
+  Json(Login { access_token, address, user_id, expires_at }).into_response()
+}
+
+// Mutating routes may only touch resources belonging to a user with the signed in address.
+// None is a resource that doesn't exist.
+async fn authorize(server_config: &ApiServerConfig, token: &AccessToken, user_id: anyhow::Result<Option<i64>>) -> Result<(), Response<Body>> {
+  let owned = match user_id {
+    Ok(Some(user_id)) => user_has_address(&server_config.deadpool, user_id, &token.address).await.map(|owned| (user_id, owned)),
+    Ok(None) => return Err((StatusCode::NOT_FOUND, "Not found").into_response()),
+    Err(error) => Err(error),
+  };
+  match owned {
+    Ok((_, true)) => Ok(()),
+    Ok((user_id, false)) => Err((StatusCode::FORBIDDEN, format!("{} is not an address of user {}", token.address, user_id)).into_response()),
+    Err(error) => {
+      log::warn!("Error checking social permissions: {}", error);
+      Err((StatusCode::INTERNAL_SERVER_ERROR, "Error checking permissions").into_response())
+    }
+  }
+}
+
+// Addresses are proven by signing in with them, so a user can't list one that the signed in address isn't
+fn check_user_addresses(server_config: &ApiServerConfig, token: &AccessToken, user: &mut User, allowed: &[String]) -> Result<(), Response<Body>> {
+  let mut addresses = Vec::new();
+  for address in &user.user_addresses {
+    let address = parse_address(address, server_config.chain)
+      .map_err(|error| (StatusCode::BAD_REQUEST, format!("Invalid address {}: {}", address, error)).into_response())?;
+    if address != token.address && !allowed.contains(&address) {
+      return Err((StatusCode::FORBIDDEN, format!("Sign in as {} to add it to a user", address)).into_response());
+    }
+    if !addresses.contains(&address) {
+      addresses.push(address);
+    }
+  }
+  if !addresses.contains(&token.address) {
+    return Err((StatusCode::BAD_REQUEST, format!("user_addresses must include {}", token.address)).into_response());
+  }
+  user.user_addresses = addresses;
+  Ok(())
+}
+
+async fn create_user_handler(token: AccessToken, State(server_config): State<ApiServerConfig>, Json(mut user): Json<User>) -> impl axum::response::IntoResponse {
+  if let Err(response) = check_user_addresses(&server_config, &token, &mut user, &[]) {
+    return response;
+  }
+  match get_user_id_by_address(&server_config.deadpool, &token.address).await {
+    Ok(None) => (),
+    Ok(Some(user_id)) => return (StatusCode::CONFLICT, format!("{} already belongs to user {}", token.address, user_id)).into_response(),
+    Err(error) => {
+      log::warn!("Error creating /user: {}", error);
+      return (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "Error creating user",
+      ).into_response();
+    }
+  };
   match insert_user(&server_config.deadpool, &user).await {
     Ok(_) => StatusCode::CREATED,
     Err(error) => {
@@ -48,7 +163,10 @@ async fn create_user_handler(State(server_config): State<ApiServerConfig>, Json(
   }.into_response()
 }
 
-async fn create_follow_handler(State(server_config): State<ApiServerConfig>, Json(follow): Json<Follow>) -> impl axum::response::IntoResponse {
+async fn create_follow_handler(token: AccessToken, State(server_config): State<ApiServerConfig>, Json(follow): Json<Follow>) -> impl axum::response::IntoResponse {
+  if let Err(response) = authorize(&server_config, &token, Ok(Some(follow.follower_id))).await {
+    return response;
+  }
   match insert_follow(&server_config.deadpool, &follow).await {
     Ok(_) => StatusCode::CREATED,
     Err(error) => {
@@ -61,7 +179,10 @@ async fn create_follow_handler(State(server_config): State<ApiServerConfig>, Jso
   }.into_response()
 }
 
-async fn create_like_handler(State(server_config): State<ApiServerConfig>, Json(like): Json<Like>) -> impl axum::response::IntoResponse {
+async fn create_like_handler(token: AccessToken, State(server_config): State<ApiServerConfig>, Json(like): Json<Like>) -> impl axum::response::IntoResponse {
+  if let Err(response) = authorize(&server_config, &token, Ok(Some(like.user_id))).await {
+    return response;
+  }
   match insert_like(&server_config.deadpool, &like).await {
     Ok(_) => StatusCode::CREATED,
     Err(error) => {
@@ -74,7 +195,10 @@ async fn create_like_handler(State(server_config): State<ApiServerConfig>, Json(
   }.into_response()
 }
 
-async fn create_comment_handler(State(server_config): State<ApiServerConfig>, Json(comment): Json<Comment>) -> impl axum::response::IntoResponse {
+async fn create_comment_handler(token: AccessToken, State(server_config): State<ApiServerConfig>, Json(comment): Json<Comment>) -> impl axum::response::IntoResponse {
+  if let Err(response) = authorize(&server_config, &token, Ok(Some(comment.user_id))).await {
+    return response;
+  }
   match insert_comment(&server_config.deadpool, &comment).await {
     Ok(_) => StatusCode::CREATED,
     Err(error) => {
@@ -87,7 +211,10 @@ async fn create_comment_handler(State(server_config): State<ApiServerConfig>, Js
   }.into_response()
 }
 
-async fn create_playlist_info_handler(State(server_config): State<ApiServerConfig>, Json(playlist_info): Json<PlaylistInfo>) -> impl axum::response::IntoResponse {
+async fn create_playlist_info_handler(token: AccessToken, State(server_config): State<ApiServerConfig>, Json(playlist_info): Json<PlaylistInfo>) -> impl axum::response::IntoResponse {
+  if let Err(response) = authorize(&server_config, &token, Ok(Some(playlist_info.user_id))).await {
+    return response;
+  }
   match insert_playlist_info(&server_config.deadpool, &playlist_info).await {
     Ok(_) => StatusCode::CREATED,
     Err(error) => {
@@ -100,7 +227,10 @@ async fn create_playlist_info_handler(State(server_config): State<ApiServerConfi
   }.into_response()
 }
 
-async fn create_playlist_inscription_handler(State(server_config): State<ApiServerConfig>, Json(playlist_inscription): Json<PlaylistInscription>) -> impl axum::response::IntoResponse {
+async fn create_playlist_inscription_handler(token: AccessToken, State(server_config): State<ApiServerConfig>, Json(playlist_inscription): Json<PlaylistInscription>) -> impl axum::response::IntoResponse {
+  if let Err(response) = authorize(&server_config, &token, get_playlist_user_id(&server_config.deadpool, playlist_inscription.playlist_id).await).await {
+    return response;
+  }
   match insert_playlist_inscription(&server_config.deadpool, &playlist_inscription).await {
     Ok(_) => StatusCode::CREATED,
     Err(error) => {
@@ -211,7 +341,10 @@ async fn get_playlist_inscriptions_handler(Path(playlist_id): Path<i64>, State(s
   Json(inscriptions).into_response()
 }
 
-async fn delete_user_handler(Path(user_id): Path<i64>, State(server_config): State<ApiServerConfig>) -> impl axum::response::IntoResponse {
+async fn delete_user_handler(token: AccessToken, Path(user_id): Path<i64>, State(server_config): State<ApiServerConfig>) -> impl axum::response::IntoResponse {
+  if let Err(response) = authorize(&server_config, &token, Ok(Some(user_id))).await {
+    return response;
+  }
   match delete_user(&server_config.deadpool, user_id).await {
     Ok(_) => (),
     Err(error) => {
@@ -225,7 +358,10 @@ async fn delete_user_handler(Path(user_id): Path<i64>, State(server_config): Sta
   StatusCode::NO_CONTENT.into_response()
 }
 
-async fn delete_follow_handler(Path(follower_id): Path<i64>, Path(following_id): Path<i64>, State(server_config): State<ApiServerConfig>) -> impl axum::response::IntoResponse {
+async fn delete_follow_handler(token: AccessToken, Path((follower_id, following_id)): Path<(i64, i64)>, State(server_config): State<ApiServerConfig>) -> impl axum::response::IntoResponse {
+  if let Err(response) = authorize(&server_config, &token, Ok(Some(follower_id))).await {
+    return response;
+  }
   match delete_follow(&server_config.deadpool, follower_id, following_id).await {
     Ok(_) => (),
     Err(error) => {
@@ -239,7 +375,10 @@ async fn delete_follow_handler(Path(follower_id): Path<i64>, Path(following_id):
   StatusCode::NO_CONTENT.into_response()
 }
 
-async fn delete_like_handler(Path(inscription_id): Path<String>, Path(user_id): Path<i64>, State(server_config): State<ApiServerConfig>) -> impl axum::response::IntoResponse {
+async fn delete_like_handler(token: AccessToken, Path((inscription_id, user_id)): Path<(String, i64)>, State(server_config): State<ApiServerConfig>) -> impl axum::response::IntoResponse {
+  if let Err(response) = authorize(&server_config, &token, Ok(Some(user_id))).await {
+    return response;
+  }
   match delete_like(&server_config.deadpool, inscription_id.clone(), user_id).await {
     Ok(_) => (),
     Err(error) => {
@@ -253,7 +392,10 @@ async fn delete_like_handler(Path(inscription_id): Path<String>, Path(user_id):
   StatusCode::NO_CONTENT.into_response()
 }
 
-async fn delete_comment_handler(Path(comment_id): Path<i64>, State(server_config): State<ApiServerConfig>) -> impl axum::response::IntoResponse {
+async fn delete_comment_handler(token: AccessToken, Path(comment_id): Path<i64>, State(server_config): State<ApiServerConfig>) -> impl axum::response::IntoResponse {
+  if let Err(response) = authorize(&server_config, &token, get_comment_user_id(&server_config.deadpool, comment_id).await).await {
+    return response;
+  }
   match delete_comment(&server_config.deadpool, comment_id).await {
     Ok(_) => (),
     Err(error) => {
@@ -267,7 +409,10 @@ async fn delete_comment_handler(Path(comment_id): Path<i64>, State(server_config
   StatusCode::NO_CONTENT.into_response()
 }
 
-async fn delete_playlist_info_handler(Path(playlist_id): Path<i64>, State(server_config): State<ApiServerConfig>) -> impl axum::response::IntoResponse {
+async fn delete_playlist_info_handler(token: AccessToken, Path(playlist_id): Path<i64>, State(server_config): State<ApiServerConfig>) -> impl axum::response::IntoResponse {
+  if let Err(response) = authorize(&server_config, &token, get_playlist_user_id(&server_config.deadpool, playlist_id).await).await {
+    return response;
+  }
   match delete_playlist_info(&server_config.deadpool, playlist_id).await {
     Ok(_) => (),
     Err(error) => {
@@ -281,7 +426,10 @@ async fn delete_playlist_info_handler(Path(playlist_id): Path<i64>, State(server
   StatusCode::NO_CONTENT.into_response()
 }
 
-async fn delete_playlist_inscription_handler(Path(playlist_id): Path<i64>, Path(inscription_id): Path<String>, State(server_config): State<ApiServerConfig>) -> impl axum::response::IntoResponse {
+async fn delete_playlist_inscription_handler(token: AccessToken, Path((playlist_id, inscription_id)): Path<(i64, String)>, State(server_config): State<ApiServerConfig>) -> impl axum::response::IntoResponse {
+  if let Err(response) = authorize(&server_config, &token, get_playlist_user_id(&server_config.deadpool, playlist_id).await).await {
+    return response;
+  }
   match delete_playlist_inscription(&server_config.deadpool, playlist_id, &inscription_id).await {
     Ok(_) => (),
     Err(error) => {
@@ -295,7 +443,24 @@ async fn delete_playlist_inscription_handler(Path(playlist_id): Path<i64>, Path(
   StatusCode::NO_CONTENT.into_response()
 }
 
-async fn update_user_handler(Path(user_id): Path<i64>, State(server_config): State<ApiServerConfig>, Json(user): Json<User>) -> impl axum::response::IntoResponse {
+async fn update_user_handler(token: AccessToken, Path(user_id): Path<i64>, State(server_config): State<ApiServerConfig>, Json(mut user): Json<User>) -> impl axum::response::IntoResponse {
+  if let Err(response) = authorize(&server_config, &token, Ok(Some(user_id))).await {
+    return response;
+  }
+  let addresses = match get_user_addresses(&server_config.deadpool, user_id).await {
+    Ok(addresses) => addresses.unwrap_or_default(),
+    Err(error) => {
+      log::warn!("Error updating /user: {}", error);
+      return (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("Error updating user {}", user_id),
+      ).into_response();
+    }
+  };
+  if let Err(response) = check_user_addresses(&server_config, &token, &mut user, &addresses) {
+    return response;
+  }
+  user.user_id = Some(user_id);
   match update_user(&server_config.deadpool, &user).await {
     Ok(_) => (),
     Err(error) => {
@@ -309,7 +474,14 @@ async fn update_user_handler(Path(user_id): Path<i64>, State(server_config): Sta
   StatusCode::NO_CONTENT.into_response()
 }
 
-async fn update_comment_handler(Path(comment_id): Path<i64>, State(server_config): State<ApiServerConfig>, Json(comment): Json<Comment>) -> impl axum::response::IntoResponse {
+async fn update_comment_handler(token: AccessToken, Path(comment_id): Path<i64>, State(server_config): State<ApiServerConfig>, Json(mut comment): Json<Comment>) -> impl axum::response::IntoResponse {
+  if let Err(response) = authorize(&server_config, &token, get_comment_user_id(&server_config.deadpool, comment_id).await).await {
+    return response;
+  }
+  if let Err(response) = authorize(&server_config, &token, Ok(Some(comment.user_id))).await {
+    return response;
+  }
+  comment.comment_id = comment_id;
   match update_comment(&server_config.deadpool, &comment).await {
     Ok(_) => (),
     Err(error) => {
@@ -323,7 +495,14 @@ async fn update_comment_handler(Path(comment_id): Path<i64>, State(server_config
   StatusCode::NO_CONTENT.into_response()
 }
 
-async fn update_playlist_info_handler(Path(playlist_id): Path<i64>, State(server_config): State<ApiServerConfig>, Json(playlist_info): Json<PlaylistInfo>) -> impl axum::response::IntoResponse {
+async fn update_playlist_info_handler(token: AccessToken, Path(playlist_id): Path<i64>, State(server_config): State<ApiServerConfig>, Json(mut playlist_info): Json<PlaylistInfo>) -> impl axum::response::IntoResponse {
+  if let Err(response) = authorize(&server_config, &token, get_playlist_user_id(&server_config.deadpool, playlist_id).await).await {
+    return response;
+  }
+  if let Err(response) = authorize(&server_config, &token, Ok(Some(playlist_info.user_id))).await {
+    return response;
+  }
+  playlist_info.playlist_id = Some(playlist_id);
   match update_playlist_info(&server_config.deadpool, &playlist_info).await {
     Ok(_) => (),
     Err(error) => {
@@ -337,4 +516,108 @@ async fn update_playlist_info_handler(Path(playlist_id): Path<i64>, State(server
   StatusCode::NO_CONTENT.into_response()
 }
 
+#[cfg(test)]
+mod tests {
+  use {
+    super::*,
+    super::super::tests::{test_server_config, TestDatabase},
+    axum::http::header,
+    tower::Service,
+  };
 
+  // Address and key from the BIP-322 test vectors
+  const ALICE: &str = "bc1q9vza2e8x573nczrlzms0wvx3gsqjx7vavgkx0l";
+  const ALICE_KEY: &str = "L3VFeEujGtevx9w18HD1fhRbCH67Az2dpCymeRE1SoPK6XQtaN2k";
+  const BOB: &str = "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq";
+
+  fn token(address: &str) -> String {
+    issue_access_token("secret", address, now() + 60)
+  }
+
+  async fn call(pool: &deadpool, method: &str, uri: &str, token: Option<&str>, body: serde_json::Value) -> (StatusCode, String) {
+    let mut request = Request::builder().method(method).uri(uri).header(header::CONTENT_TYPE, "application/json");
+    if let Some(token) = token {
+      request = request.header(header::AUTHORIZATION, format!("Bearer {token}"));
+    }
+    // Routers are always ready, so they can be called without polling readiness first
+    let response = social_router()
+      .with_state(test_server_config(pool))
+      .call(request.body(Body::from(body.to_string())).unwrap())
+      .await
+      .unwrap();
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, String::from_utf8(body.to_vec()).unwrap())
+  }
+
+  async fn create_user(pool: &deadpool, name: &str, address: &str) -> i64 {
+    let user = serde_json::json!({"user_name": name, "user_addresses": [address]});
+    assert_eq!(call(pool, "POST", "/social/user", Some(&token(address)), user).await.0, StatusCode::CREATED);
+    get_user_id_by_address(pool, address).await.unwrap().unwrap()
+  }
+
+  #[tokio::test]
+  #[ignore]
+  async fn mutating_routes_need_a_current_access_token() {
+    let pool = TestDatabase::new("vermilion_social_token_test").await;
+    let alice = create_user(&pool, "alice", ALICE).await;
+    let comment = serde_json::json!({"comment_id": 0, "inscription_id": "ai0", "user_id": alice, "comment": "gm"});
+
+    let expired = issue_access_token("secret", ALICE, now() - 1);
+    let forged = issue_access_token("other secret", ALICE, now() + 60);
+    for token in [None, Some("not a token"), Some(expired.as_str()), Some(forged.as_str())] {
+      assert_eq!(call(&pool, "POST", "/social/comment", token, comment.clone()).await.0, StatusCode::UNAUTHORIZED);
+      assert_eq!(call(&pool, "DELETE", &format!("/social/user/{alice}"), token, serde_json::Value::Null).await.0, StatusCode::UNAUTHORIZED);
+    }
+    assert_eq!(call(&pool, "POST", "/social/comment", Some(&token(ALICE)), comment).await.0, StatusCode::CREATED);
+  }
+
+  #[tokio::test]
+  #[ignore]
+  async fn users_can_only_change_their_own_profile_and_comments() {
+    let pool = TestDatabase::new("vermilion_social_owner_test").await;
+    let alice = create_user(&pool, "alice", ALICE).await;
+    let bob = create_user(&pool, "bob", BOB).await;
+    let comment = |user_id: i64, text: &str| serde_json::json!({"comment_id": 0, "inscription_id": "ai0", "user_id": user_id, "comment": text});
+    assert_eq!(call(&pool, "POST", "/social/comment", Some(&token(ALICE)), comment(alice, "gm")).await.0, StatusCode::CREATED);
+    let comment_id: i64 = pool.get().await.unwrap().query_one("SELECT comment_id FROM comments", &[]).await.unwrap().get(0);
+
+    // Bob can neither post as Alice nor touch her comment, even by claiming it for himself
+    assert_eq!(call(&pool, "POST", "/social/comment", Some(&token(BOB)), comment(alice, "gn")).await.0, StatusCode::FORBIDDEN);
+    for user_id in [alice, bob] {
+      assert_eq!(call(&pool, "PUT", &format!("/social/comment/{comment_id}"), Some(&token(BOB)), comment(user_id, "gn")).await.0, StatusCode::FORBIDDEN);
+    }
+    assert_eq!(call(&pool, "DELETE", &format!("/social/comment/{comment_id}"), Some(&token(BOB)), serde_json::Value::Null).await.0, StatusCode::FORBIDDEN);
+
+    // Nor her profile
+    let profile = serde_json::json!({"user_name": "bob", "user_addresses": [BOB]});
+    assert_eq!(call(&pool, "PUT", &format!("/social/user/{alice}"), Some(&token(BOB)), profile).await.0, StatusCode::FORBIDDEN);
+    assert_eq!(call(&pool, "DELETE", &format!("/social/user/{alice}"), Some(&token(BOB)), serde_json::Value::Null).await.0, StatusCode::FORBIDDEN);
+
+    let (status, comments) = call(&pool, "GET", "/social/comments/ai0", None, serde_json::Value::Null).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(comments.contains(r#""comment":"gm""#));
+    assert_eq!(call(&pool, "GET", &format!("/social/user/{alice}"), None, serde_json::Value::Null).await.0, StatusCode::OK);
+
+    assert_eq!(call(&pool, "DELETE", &format!("/social/comment/{comment_id}"), Some(&token(ALICE)), serde_json::Value::Null).await.0, StatusCode::NO_CONTENT);
+    assert_eq!(call(&pool, "DELETE", &format!("/social/user/{alice}"), Some(&token(ALICE)), serde_json::Value::Null).await.0, StatusCode::NO_CONTENT);
+  }
+
+  #[tokio::test]
+  #[ignore]
+  async fn challenges_can_only_be_used_once() {
+    let pool = TestDatabase::new("vermilion_social_challenge_test").await;
+    let (status, challenge) = call(&pool, "POST", "/social/auth/challenge", None, serde_json::json!({"address": ALICE})).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let challenge: serde_json::Value = serde_json::from_str(&challenge).unwrap();
+    let signature = bip322::sign_simple_encoded(ALICE, challenge["message"].as_str().unwrap(), ALICE_KEY).unwrap();
+    let login = serde_json::json!({"nonce": challenge["nonce"], "signature": signature});
+
+    let (status, body) = call(&pool, "POST", "/social/auth/login", None, login.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    let access_token: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(verify_access_token("secret", access_token["access_token"].as_str().unwrap(), now()), Some(ALICE.to_string()));
+
+    assert_eq!(call(&pool, "POST", "/social/auth/login", None, login).await.0, StatusCode::UNAUTHORIZED);
+  }
+}
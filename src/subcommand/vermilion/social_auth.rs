@@ -0,0 +1,152 @@
+use super::*;
+use api::{bearer_token, constant_time_eq};
+use axum::{extract::FromRequestParts, http::request::Parts};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use bitcoin::address::NetworkUnchecked;
+use bitcoin::hashes::{hmac, sha256, Hash, HashEngine};
+
+pub(crate) const CHALLENGE_LIFETIME_SECONDS: i64 = 10 * 60;
+pub(crate) const ACCESS_TOKEN_LIFETIME_SECONDS: i64 = 7 * 24 * 60 * 60;
+
+pub(crate) fn now() -> i64 {
+  SystemTime::now()
+    .duration_since(SystemTime::UNIX_EPOCH)
+    .map(|duration| duration.as_secs().try_into().unwrap_or(i64::MAX))
+    .unwrap_or_default()
+}
+
+// Addresses are only accepted on the chain the API is serving, and stored in their canonical form
+pub(crate) fn parse_address(address: &str, chain: Chain) -> anyhow::Result<String> {
+  let address = address.parse::<Address<NetworkUnchecked>>()?.require_network(chain.network())?;
+  Ok(address.to_string())
+}
+
+pub(crate) fn challenge_message(address: &str, nonce: &str, expires_at: i64) -> String {
+  format!("Sign in to Vermilion\n\nAddress: {address}\nNonce: {nonce}\nExpires: {expires_at}")
+}
+
+// Accepts both BIP-322 encodings, like `ord verify`: a simple signature's witness or a full signature's to_sign transaction
+pub(crate) fn verify_signature(address: &str, message: &str, signature: &str) -> bool {
+  bip322::verify_simple_encoded(address, message, signature).is_ok()
+    || bip322::verify_full_encoded(address, message, signature).is_ok()
+}
+
+#[derive(Deserialize)]
+pub struct ChallengeRequest {
+  pub(crate) address: String,
+}
+
+#[derive(Serialize)]
+pub struct Challenge {
+  pub(crate) address: String,
+  pub(crate) nonce: String,
+  /// Sign this with BIP-322 and send the signature to /social/auth/login
+  pub(crate) message: String,
+  pub(crate) expires_at: i64,
+}
+
+#[derive(Deserialize)]
+pub struct LoginRequest {
+  pub(crate) nonce: String,
+  /// Base64 BIP-322 signature of the challenge message
+  pub(crate) signature: String,
+}
+
+#[derive(Serialize)]
+pub struct Login {
+  pub(crate) access_token: String,
+  pub(crate) address: String,
+  /// The user with this address, if one has been created
+  pub(crate) user_id: Option<i64>,
+  pub(crate) expires_at: i64,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Claims {
+  address: String,
+  expires_at: i64,
+}
+
+fn mac(secret: &str, payload: &str) -> hmac::Hmac<sha256::Hash> {
+  let mut engine = hmac::HmacEngine::<sha256::Hash>::new(secret.as_bytes());
+  engine.input(payload.as_bytes());
+  hmac::Hmac::from_engine(engine)
+}
+
+// <base64url claims json>.<base64url hmac-sha256 of the first part>
+pub(crate) fn issue_access_token(secret: &str, address: &str, expires_at: i64) -> String {
+  let claims = Claims { address: address.to_string(), expires_at };
+  let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims).unwrap());
+  let signature = URL_SAFE_NO_PAD.encode(mac(secret, &payload).to_byte_array());
+  format!("{payload}.{signature}")
+}
+
+// Returns the address the token was issued to, if it is genuine and hasn't expired
+pub(crate) fn verify_access_token(secret: &str, token: &str, now: i64) -> Option<String> {
+  let (payload, signature) = token.split_once('.')?;
+  let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+  if !constant_time_eq(&signature, mac(secret, payload).as_byte_array()) {
+    return None;
+  }
+  let claims: Claims = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload).ok()?).ok()?;
+  (claims.expires_at > now).then_some(claims.address)
+}
+
+// The signed in address, from `Authorization: Bearer <access token>`
+pub(crate) struct AccessToken {
+  pub(crate) address: String,
+}
+
+impl FromRequestParts<ApiServerConfig> for AccessToken {
+  type Rejection = Response<Body>;
+
+  async fn from_request_parts(parts: &mut Parts, server_config: &ApiServerConfig) -> Result<Self, Self::Rejection> {
+    let Some(secret) = server_config.access_token_secret.as_deref() else {
+      return Err((StatusCode::SERVICE_UNAVAILABLE, "Social login is disabled, access_token_secret is not set").into_response());
+    };
+    match bearer_token(&parts.headers).and_then(|token| verify_access_token(secret, token, now())) {
+      Some(address) => Ok(AccessToken { address }),
+      None => Err((StatusCode::UNAUTHORIZED, "Missing, invalid or expired access token").into_response()),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn access_tokens_round_trip() {
+    let token = issue_access_token("secret", "bc1qone", 100);
+    assert_eq!(verify_access_token("secret", &token, 99), Some("bc1qone".to_string()));
+    assert_eq!(verify_access_token("secret", &token, 100), None);
+    assert_eq!(verify_access_token("other secret", &token, 99), None);
+  }
+
+  #[test]
+  fn tampered_access_tokens_are_rejected() {
+    let token = issue_access_token("secret", "bc1qone", 100);
+    let (_, signature) = token.split_once('.').unwrap();
+    let forged = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&Claims { address: "bc1qtwo".into(), expires_at: 100 }).unwrap());
+    assert_eq!(verify_access_token("secret", &format!("{forged}.{signature}"), 99), None);
+    assert_eq!(verify_access_token("secret", "not a token", 99), None);
+  }
+
+  #[test]
+  fn challenges_are_signed_with_bip322() {
+    let address = "bc1q9vza2e8x573nczrlzms0wvx3gsqjx7vavgkx0l";
+    let message = challenge_message(address, "00", 100);
+    // Key and address from the BIP-322 test vectors
+    let signature = bip322::sign_simple_encoded(address, &message, "L3VFeEujGtevx9w18HD1fhRbCH67Az2dpCymeRE1SoPK6XQtaN2k").unwrap();
+    assert!(verify_signature(address, &message, &signature));
+    assert!(!verify_signature(address, &challenge_message(address, "01", 100), &signature));
+  }
+
+  #[test]
+  fn addresses_must_match_the_chain() {
+    let address = "bc1q9vza2e8x573nczrlzms0wvx3gsqjx7vavgkx0l";
+    assert_eq!(parse_address(address, Chain::Mainnet).unwrap(), address);
+    assert!(parse_address(address, Chain::Testnet).is_err());
+    assert!(parse_address("not an address", Chain::Mainnet).is_err());
+  }
+}
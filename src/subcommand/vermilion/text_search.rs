@@ -0,0 +1,204 @@
+use super::*;
+
+// Must match the expression of the index_metadata_full_text index on ordinals_full_t, which backs ordinals_full_v, for
+// postgres to use it
+const TEXT_VECTOR: &str = "to_tsvector('english', left(o.text, 800000))";
+const HEADLINE_OPTIONS: &str = "StartSel=<mark>, StopSel=</mark>, MaxWords=30, MinWords=10, MaxFragments=2";
+// Snippets are rendered as markup, so the text is escaped before ts_headline adds its <mark> tags. The parser reads
+// the entities as single tokens, so matching words are still highlighted.
+const ESCAPED_TEXT: &str = "replace(replace(replace(replace(replace(left(o.text, 800000), \
+  '&', '&amp;'), '<', '&lt;'), '>', '&gt;'), '\"', '&quot;'), '''', '&#39;')";
+
+#[derive(Deserialize, JsonSchema)]
+pub struct SearchTextParams {
+  /// Search terms
+  #[schemars(
+    description = "Words to search inscription text for. Quote words to match them as a phrase, end a word with * to match it as a prefix",
+    example = "\"pizza day\" bitco*"
+  )]
+  q: String,
+
+  /// Content types to filter by
+  #[schemars(description = "Content types to filter inscriptions by")]
+  #[serde(default, deserialize_with = "deserialize_comma_separated_and_repeated")]
+  content_types: Vec<ContentType>,
+
+  /// Lowest genesis block height
+  #[schemars(description = "Only inscriptions inscribed at or after this block height")]
+  min_genesis_height: Option<i64>,
+
+  /// Highest genesis block height
+  #[schemars(description = "Only inscriptions inscribed at or before this block height")]
+  max_genesis_height: Option<i64>,
+
+  /// Page number for pagination (0-based)
+  #[schemars(description = "Page number for pagination, starting from 0", example = "0", range(min = 0))]
+  page_number: Option<usize>,
+
+  /// Number of items per page (max 100)
+  #[schemars(description = "Number of items per page, maximum 100", example = "20", range(min = 1, max = 100))]
+  page_size: Option<usize>,
+
+  /// Cursor for keyset pagination
+  #[schemars(description = "Opaque cursor for keyset pagination. Pass an empty cursor for the first page, then the next_cursor of each response. Takes precedence over page_number")]
+  #[serde(default, deserialize_with = "deserialize_cursor")]
+  cursor: Option<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct TextSearchResult {
+  #[serde(flatten)]
+  pub(crate) inscription: FullMetadata,
+  /// Relevance of the match, higher is better
+  rank: i64,
+  /// Best matching fragments of the text, HTML escaped, with matches wrapped in <mark></mark>
+  snippet: Option<String>,
+}
+
+pub(crate) struct ParsedSearchTextParams {
+  tsquery: Vec<(&'static str, String)>,
+  inscriptions: ParsedInscriptionQueryParams,
+}
+
+// Plain words must all match, quoted words match as a phrase and words ending in * match as prefixes.
+// Returns the tsquery function and its text for each part, the parts are and-ed together.
+fn tsquery(q: &str) -> Option<Vec<(&'static str, String)>> {
+  let mut parts = Vec::new();
+  let mut words = Vec::new();
+  for (i, segment) in q.split('"').enumerate() {
+    if i % 2 == 1 {
+      if !segment.trim().is_empty() {
+        parts.push(("phraseto_tsquery", segment.trim().to_string()));
+      }
+      continue;
+    }
+    for word in segment.split_whitespace() {
+      match word.strip_suffix('*') {
+        Some(prefix) => {
+          // to_tsquery parses its own operators, so only the word characters are passed on
+          let prefix: String = prefix.chars().filter(|c| c.is_alphanumeric()).collect();
+          if !prefix.is_empty() {
+            parts.push(("to_tsquery", format!("{prefix}:*")));
+          }
+        }
+        None => words.push(word),
+      }
+    }
+  }
+  if !words.is_empty() {
+    parts.insert(0, ("plainto_tsquery", words.join(" ")));
+  }
+  (!parts.is_empty()).then_some(parts)
+}
+
+impl TryFrom<SearchTextParams> for ParsedSearchTextParams {
+  type Error = ApiError;
+
+  fn try_from(params: SearchTextParams) -> Result<Self, Self::Error> {
+    let tsquery = tsquery(&params.q).ok_or_else(|| ApiError::BadRequest("q must contain at least one word".to_string()))?;
+    // Ranks are scaled to integers so results can be paged by cursor like the other inscription lists
+    let keyset = Keyset::new("search_text", "relevance", "o.rank", true).tie_break("o.sequence_number", KeyType::Int8);
+    let page_size = params.page_size.map_or(10, |v| std::cmp::min(v, 100));
+    let page = PageRequest::new(params.page_number, page_size, params.cursor.as_deref(), &keyset)?;
+    Ok(Self {
+      tsquery,
+      inscriptions: ParsedInscriptionQueryParams {
+        content_types: params.content_types,
+        satributes: Vec::new(),
+        charms: Vec::new(),
+        genesis_height: (params.min_genesis_height, params.max_genesis_height),
+        timestamp: (None, None),
+        content_length: (None, None),
+        genesis_fee: (None, None),
+        metaprotocol: None,
+        inscribed_by_address: None,
+        has_parent: None,
+        has_delegate: None,
+        is_recursive: None,
+        is_json: None,
+        spaced_rune: None,
+        keyset,
+        page,
+      },
+    })
+  }
+}
+
+fn search_text_query(params: &ParsedSearchTextParams, hidden: &HiddenParams, sql_params: &mut SqlParams) -> String {
+  let inscriptions = &params.inscriptions;
+  let tsquery = params.tsquery.iter()
+    .map(|(function, text)| format!("{function}('english', {})", sql_params.push(text.clone())))
+    .join(" && ");
+  let tsquery = format!("({tsquery})");
+  // Snippets are only worked out for the rows on the page
+  let mut query = format!(
+    "SELECT o.*, ts_headline('english', {ESCAPED_TEXT}, {tsquery}, '{HEADLINE_OPTIONS}') AS snippet FROM (\
+    SELECT o.* FROM (\
+    SELECT o.*, (ts_rank_cd({TEXT_VECTOR}, {tsquery}, 32) * 1000000)::bigint AS rank FROM ordinals_full_v o WHERE {TEXT_VECTOR} @@ {tsquery}\
+    ) o WHERE 1=1"
+  );
+  Vermilion::push_inscription_filters(&mut query, inscriptions, hidden, sql_params);
+  query.push_str(&inscriptions.keyset.order_by());
+  query.push_str(&inscriptions.page.limit());
+  query.push_str(") o");
+  query.push_str(&inscriptions.keyset.order_by());
+  query
+}
+
+pub(crate) async fn search_text(pool: deadpool, params: ParsedSearchTextParams, hidden: &HiddenParams) -> anyhow::Result<Paginated<TextSearchResult>> {
+  let conn = pool.get().await?;
+  let mut sql_params = SqlParams::default();
+  let query = search_text_query(&params, hidden, &mut sql_params);
+  let result = conn.query(query.as_str(), &sql_params.values()).await?;
+  let inscriptions = &params.inscriptions;
+  Ok(inscriptions.page.paginate(&inscriptions.keyset, result, |row| TextSearchResult {
+    rank: row.get("rank"),
+    snippet: row.get("snippet"),
+    inscription: Vermilion::map_row_to_fullmetadata(row),
+  }))
+}
+
+#[cfg(test)]
+mod tests {
+  use {
+    super::*,
+    super::super::tests::{index_test_block, TestDatabase, test_inscription, TestBlock},
+  };
+
+  #[test]
+  fn tsqueries() {
+    assert_eq!(tsquery("  "), None);
+    assert_eq!(tsquery("*"), None);
+    assert_eq!(tsquery("pizza day"), Some(vec![("plainto_tsquery", "pizza day".into())]));
+    assert_eq!(
+      tsquery("\"it's pizza day\" bitco* one"),
+      Some(vec![("plainto_tsquery", "one".into()), ("phraseto_tsquery", "it's pizza day".into()), ("to_tsquery", "bitco:*".into())])
+    );
+    assert_eq!(tsquery("a&!b*"), Some(vec![("to_tsquery", "ab:*".into())]));
+  }
+
+  #[tokio::test]
+  #[ignore]
+  async fn search_text_snippets_are_escaped() {
+    let pool = TestDatabase::new("vermilion_search_text_test").await;
+
+    let mut inscription = test_inscription(0, 1, &"a".repeat(64), Vec::new(), None);
+    inscription.text = Some("<script>alert('pizza')</script> it's pizza day".into());
+    index_test_block(&pool, &TestBlock { height: 1, inscriptions: vec![inscription], galleries: Vec::new(), transfers: Vec::new() }).await;
+
+    for q in ["pizza", "\"it's pizza day\"", "'); DROP TABLE ordinals; --"] {
+      let uri = format!("/search_text?q={}", urlencoding::encode(q));
+      let mut parts = http::Request::builder().uri(uri).body(()).unwrap().into_parts().0;
+      let Query(search) = <Query<SearchTextParams> as axum::extract::FromRequestParts<()>>::from_request_parts(&mut parts, &()).await.unwrap();
+      let Ok(search) = ParsedSearchTextParams::try_from(search) else { panic!("invalid params") };
+      let Paginated::Items(results) = search_text(pool.clone(), search, &HiddenParams::default()).await.unwrap() else { panic!("expected items") };
+      if q.contains("DROP") {
+        assert!(results.is_empty());
+        continue;
+      }
+      let snippet = results[0].snippet.clone().unwrap();
+      assert!(snippet.contains("<mark>pizza</mark>"), "{snippet}");
+      assert!(!snippet.contains("<script") && !snippet.contains("'"), "{snippet}");
+    }
+  }
+}
@@ -0,0 +1,156 @@
+use super::*;
+
+// Blocks this close to the index tip are journaled, anything deeper is assumed final
+pub(crate) const UNDO_DEPTH: u32 = 100;
+
+// Every table written inside the block transaction, directly or by triggers, with the non-null
+// columns that identify a row. Undo entries are matched on these columns.
+pub(crate) const JOURNALED_TABLES: &[(&str, &[&str])] = &[
+  ("blockstats", &["block_number"]),
+  ("inscription_blockstats", &["block_number"]),
+  ("runes", &["block", "tx_index"]),
+  ("rune_balances", &["outpoint", "rune_block", "rune_tx_index"]),
+  ("rune_transfers", &["block", "tx_index", "txid", "event_type", "rune_block", "rune_tx_index"]),
+  ("ordinals", &["sequence_number"]),
+  ("ordinals_full_t", &["sequence_number"]),
+  ("sat", &["sat"]),
+  ("satributes", &["sat", "satribute"]),
+  ("content", &["sha256"]),
+  ("editions", &["id"]),
+  ("editions_total", &["sha256"]),
+  ("delegates", &["bootleg_id"]),
+  ("delegates_total", &["delegate_id"]),
+  ("inscription_comments", &["comment_id"]),
+  ("inscription_comments_total", &["delegate_id"]),
+  ("inscription_references", &["reference_id", "recursive_id"]),
+  ("inscription_references_total", &["reference_id"]),
+  ("inscription_satributes", &["satribute", "inscription_id"]),
+  ("inscription_satributes_total", &["satribute"]),
+  ("inscription_galleries", &["gallery_id", "inscription_id"]),
+  ("gallery_summary", &["gallery_id"]),
+  ("on_chain_collection_summary", &["parents_hash"]),
+  ("collection_summary", &["collection_symbol"]),
+  ("transfers", &["id", "block_number", "satpoint"]),
+  ("sales", &["id", "transaction"]),
+  ("collection_history", &["collection_kind", "collection_key", "block_number"]),
+  ("collection_history_days", &["collection_kind", "collection_key", "period_start"]),
+  ("collection_holdings", &["collection_kind", "collection_key", "address"]),
+  ("collection_holder_counts", &["collection_kind", "collection_key"]),
+  ("brc20_tickers", &["tick"]),
+  ("brc20_balances", &["tick", "address"]),
+  ("brc20_events", &["inscription_id", "event"]),
+  ("bitmaps", &["bitmap_number"]),
+  ("addresses", &["id"]),
+];
+
+pub(crate) async fn initialize_undo_journal(pool: deadpool) -> anyhow::Result<()> {
+  let conn = pool.get().await?;
+  conn.simple_query(
+    r"CREATE TABLE IF NOT EXISTS undo_journal (
+        id bigserial primary key,
+        block_number bigint not null,
+        table_name varchar(50) not null,
+        op char(1) not null,
+        row_key jsonb not null,
+        old_row jsonb
+      );
+      CREATE INDEX IF NOT EXISTS index_undo_journal_block ON undo_journal (block_number);
+      CREATE TABLE IF NOT EXISTS undo_journal_blocks (
+        block_number bigint not null primary key
+      );").await?;
+  // Row changes are only journaled when the block transaction has set vermilion.block_number.
+  // Inserts record the key, updates and deletes record the full previous row. Inserted rows are never
+  // converted whole, as some (content) carry large payloads that the journal doesn't need.
+  conn.simple_query(r"CREATE OR REPLACE FUNCTION journal_block_change() RETURNS TRIGGER AS $$
+      DECLARE
+        v_block text := current_setting('vermilion.block_number', true);
+        v_columns text;
+        v_key jsonb;
+        v_row jsonb;
+      BEGIN
+        IF v_block IS NULL OR v_block = '' OR current_setting('vermilion.undoing', true) = 'on' THEN
+          RETURN NULL;
+        END IF;
+        IF TG_OP = 'INSERT' THEN
+          SELECT string_agg(format('%L, ($1).%I', k, k), ', ') INTO v_columns FROM unnest(TG_ARGV) AS k;
+          EXECUTE format('SELECT jsonb_build_object(%s)', v_columns) INTO v_key USING NEW;
+        ELSE
+          v_row := to_jsonb(OLD);
+          SELECT jsonb_object_agg(k, v_row->k) INTO v_key FROM unnest(TG_ARGV) AS k;
+        END IF;
+        INSERT INTO undo_journal (block_number, table_name, op, row_key, old_row)
+        VALUES (v_block::bigint, TG_TABLE_NAME, left(TG_OP, 1), v_key, v_row);
+        RETURN NULL;
+      END;
+      $$ LANGUAGE plpgsql;").await?;
+  // Replays the journal newest first. Callers must set vermilion.undoing, which the counter maintenance
+  // triggers check so they don't adjust the restored rows a second time.
+  conn.simple_query(r"CREATE OR REPLACE FUNCTION undo_blocks_after(v_last_good_block bigint) RETURNS bigint AS $$
+      DECLARE
+        r RECORD;
+        v_match text;
+        v_count bigint := 0;
+      BEGIN
+        FOR r IN SELECT * FROM undo_journal WHERE block_number > v_last_good_block ORDER BY id DESC LOOP
+          SELECT string_agg(format('s.%I = k.%I', col, col), ' AND ') INTO v_match FROM jsonb_object_keys(r.row_key) AS col;
+          EXECUTE format(
+            'DELETE FROM %I t WHERE t.ctid = (SELECT s.ctid FROM %I s, jsonb_populate_record(NULL::%I, $1) k WHERE %s LIMIT 1)',
+            r.table_name, r.table_name, r.table_name, v_match
+          ) USING r.row_key;
+          IF r.old_row IS NOT NULL THEN
+            EXECUTE format('INSERT INTO %I SELECT * FROM jsonb_populate_record(NULL::%I, $1)', r.table_name, r.table_name) USING r.old_row;
+          END IF;
+          v_count := v_count + 1;
+        END LOOP;
+        DELETE FROM undo_journal WHERE block_number > v_last_good_block;
+        DELETE FROM undo_journal_blocks WHERE block_number > v_last_good_block;
+        RETURN v_count;
+      END;
+      $$ LANGUAGE plpgsql;").await?;
+  for (table, key) in JOURNALED_TABLES {
+    let args = key.iter().map(|column| format!("'{column}'")).join(", ");
+    conn.simple_query(&format!(
+      r"CREATE OR REPLACE TRIGGER journal_{table}
+      AFTER INSERT OR UPDATE OR DELETE ON {table}
+      FOR EACH ROW
+      EXECUTE FUNCTION journal_block_change({args});")).await?;
+  }
+  Ok(())
+}
+
+// Called at the start of each block transaction. Journaling is skipped for blocks deep enough that
+// they can't be reorged, and entries that have fallen out of the undo window are pruned.
+pub(crate) async fn start_block(tx: &deadpool_postgres::Transaction<'_>, block_number: u32, tip: u32) -> anyhow::Result<()> {
+  if block_number.saturating_add(UNDO_DEPTH) < tip {
+    return Ok(());
+  }
+  tx.execute("SELECT set_config('vermilion.block_number', $1, true)", &[&block_number.to_string()]).await?;
+  tx.execute("INSERT INTO undo_journal_blocks (block_number) VALUES ($1) ON CONFLICT DO NOTHING", &[&i64::from(block_number)]).await?;
+  let horizon = i64::from(block_number.saturating_sub(UNDO_DEPTH));
+  tx.execute("DELETE FROM undo_journal WHERE block_number < $1", &[&horizon]).await?;
+  tx.execute("DELETE FROM undo_journal_blocks WHERE block_number < $1", &[&horizon]).await?;
+  Ok(())
+}
+
+// Restores every journaled table to its state at last_good_block and returns the number of row changes undone.
+// Blocks that were indexed without a journal are left for the caller to remove.
+// The triggers are switched off with a transaction local setting rather than ALTER TABLE, which would lock every
+// journaled table against readers until the reorg commits.
+pub(crate) async fn rollback(tx: &deadpool_postgres::Transaction<'_>, last_good_block: u32) -> anyhow::Result<i64> {
+  tx.execute("SELECT set_config('vermilion.undoing', 'on', true)", &[]).await?;
+  let row = tx.query_one("SELECT undo_blocks_after($1)", &[&i64::from(last_good_block)]).await?;
+  tx.execute("SELECT set_config('vermilion.undoing', 'off', true)", &[]).await?;
+  Ok(row.get(0))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn journaled_tables_are_unique() {
+    let tables = JOURNALED_TABLES.iter().map(|(table, _)| *table).collect::<HashSet<&str>>();
+    assert_eq!(tables.len(), JOURNALED_TABLES.len());
+    assert!(JOURNALED_TABLES.iter().all(|(_, key)| !key.is_empty()));
+  }
+}